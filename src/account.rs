@@ -0,0 +1,216 @@
+use crate::audit;
+use crate::cache;
+use crate::metrics;
+use crate::realtime;
+use crate::retry::{self, RetryPolicy};
+use crate::revisions;
+use crate::structures_ddb::{TABLE_KEY_FOR_TLIST, TABLE_NAME_TLIST};
+use crate::structures_pg::{self};
+use crate::trace;
+use crate::utils;
+use crate::webhooks;
+use chrono::Utc;
+use dynomite::{
+    dynamodb::{DynamoDb, DynamoDbClient},
+    FromAttributes, Item,
+};
+use log::{self, debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+#[path = "./account_test.rs"]
+pub(crate) mod tests_account;
+
+const TABLE_NAME_RECEIPT: &str = "tuser_deletion";
+const TABLE_KEY_FOR_RECEIPT: &str = "user_id";
+const ERR_MSG_RECEIPT_FAILED: &str = "Failed to record the account deletion progress. Try again.";
+const ERR_MSG_DELETION_FAILED: &str = "Failed to delete the account. Try again.";
+
+/// A record of a user account deletion, kept in DDB after the user is gone.
+/// It is saved after every step, so an interrupted deletion can be resumed from where it stopped.
+/// The receipt holds no personal data other than the user id.
+#[derive(Item, Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct AccountDeletionReceipt {
+    #[dynomite(partition_key)]
+    pub user_id: Uuid,
+    pub requested_on_utc: chrono::DateTime<Utc>,
+    #[dynomite(default)]
+    pub completed_on_utc: Option<chrono::DateTime<Utc>>,
+    /// All lists deleted from DDB and PG so far
+    #[dynomite(default)]
+    pub deleted_lids: Vec<Uuid>,
+    /// Number of list revisions deleted so far
+    #[dynomite(default)]
+    pub deleted_revisions: u64,
+    /// Number of webhooks of the user deleted with their secrets and delivery logs
+    #[dynomite(default)]
+    pub deleted_webhooks: u64,
+    /// Number of WebSocket connections of the user deleted with their subscriptions
+    #[dynomite(default)]
+    pub deleted_connections: u64,
+    /// The audit records of the user's lists and account are deleted and the user is removed from the rest
+    #[dynomite(default)]
+    pub audit_deleted: bool,
+    pub user_deleted: bool,
+}
+
+impl AccountDeletionReceipt {
+    /// Creates a new receipt for a deletion that has not started yet. Not saved in the DB.
+    pub(crate) fn new(user_id: Uuid) -> Self {
+        Self {
+            user_id,
+            requested_on_utc: Utc::now(),
+            completed_on_utc: None,
+            deleted_lids: Vec::new(),
+            deleted_revisions: 0,
+            deleted_webhooks: 0,
+            deleted_connections: 0,
+            audit_deleted: false,
+            user_deleted: false,
+        }
+    }
+
+    /// Retrieves the receipt for the user from DDB, if the deletion was ever requested.
+    pub(crate) async fn get_from_ddb(user_id: Uuid, ddb_client: &DynamoDbClient) -> Result<Option<Self>, String> {
+        debug!("AccountDeletionReceipt::get_from_ddb for {}", user_id);
+
+        let get_input = utils::build_ddb_get_input(TABLE_KEY_FOR_RECEIPT, &user_id, TABLE_NAME_RECEIPT);
+        let get = || ddb_client.get_item(get_input.clone());
+        let span = trace::ddb_span("tuser_deletion.get_item", TABLE_NAME_RECEIPT);
+        let get_item_output = match retry::with_retry_in(&RetryPolicy::default(), span, get).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("DDB error {}", e);
+                return Err(ERR_MSG_RECEIPT_FAILED.to_string());
+            }
+        };
+        metrics::record_capacity("tuser_deletion.get_item", get_item_output.consumed_capacity.as_ref());
+
+        match get_item_output.item {
+            Some(output_item) => match AccountDeletionReceipt::from_attrs(output_item) {
+                Ok(v) => Ok(Some(v)),
+                Err(e) => {
+                    error!("Invalid deletion receipt for {}: {}", user_id, e);
+                    Err(ERR_MSG_RECEIPT_FAILED.to_string())
+                }
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Saves the current state of the receipt in DDB.
+    async fn save_in_ddb(&self, ddb_client: &DynamoDbClient) -> Result<(), String> {
+        let put_input = utils::build_ddb_put_input(self.clone().into(), TABLE_NAME_RECEIPT);
+        let put = || ddb_client.put_item(put_input.clone());
+        let span = trace::ddb_span("tuser_deletion.put_item", TABLE_NAME_RECEIPT);
+        match retry::with_retry_in(&RetryPolicy::default(), span, put).await {
+            Ok(v) => metrics::record_capacity("tuser_deletion.put_item", v.consumed_capacity.as_ref()),
+            Err(e) => {
+                error!("Failed to save the deletion receipt for {}: {:?}", self.user_id, e);
+                return Err(ERR_MSG_RECEIPT_FAILED.to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Deletes the user with all their lists, including the ones in the trash, and the history of the lists
+/// from DDB and PG, then the webhooks and WebSocket connections of the user. The user is removed from the audit log,
+/// see `audit::forget_user`.
+/// Every step is recorded in the receipt, which is returned on completion.
+/// It is safe to call it again after a failure - the deletion resumes from the last completed step.
+pub(crate) async fn delete_user_account(
+    user_id: Uuid,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<AccountDeletionReceipt, String> {
+    debug!("delete_user_account for {}", user_id);
+
+    // pick up an earlier attempt or start a new one
+    let mut receipt = match AccountDeletionReceipt::get_from_ddb(user_id, ddb_client).await? {
+        Some(v) => {
+            if v.completed_on_utc.is_some() {
+                debug!("Account {} was already deleted.", user_id);
+                return Ok(v);
+            }
+            debug!("Resuming deletion for {} with {} lists done", user_id, v.deleted_lids.len());
+            v
+        }
+        None => AccountDeletionReceipt::new(user_id),
+    };
+    receipt.save_in_ddb(ddb_client).await?;
+
+    // deleting a list may reveal more, e.g. child lists, so look again until every list returned by PG
+    // has been handled once in this run - lists PG fails to delete are not retried here to avoid an endless loop
+    let mut processed: HashSet<Uuid> = HashSet::new();
+    loop {
+        let mut lists = structures_pg::get_user_lists(user_id, pg_client)
+            .await
+            .map_err(|_| ERR_MSG_DELETION_FAILED.to_string())?
+            .unwrap_or_default();
+        lists.append(
+            &mut structures_pg::get_user_trash(user_id, pg_client)
+                .await
+                .map_err(|_| ERR_MSG_DELETION_FAILED.to_string())?
+                .unwrap_or_default(),
+        );
+        lists.retain(|tl| !processed.contains(&tl.lid));
+        if lists.is_empty() {
+            break;
+        }
+
+        for tl in lists {
+            processed.insert(tl.lid);
+
+            // the history holds copies of the list, so it goes before the list
+            receipt.deleted_revisions += revisions::delete_revisions(tl.lid, ddb_client)
+                .await
+                .map_err(|_| ERR_MSG_DELETION_FAILED.to_string())?;
+
+            // DDB goes first, so the PG record is still there to find the list if this fails
+            let del_input = utils::build_ddb_del_input(TABLE_KEY_FOR_TLIST, tl.lid, TABLE_NAME_TLIST);
+            let delete = || ddb_client.delete_item(del_input.clone());
+            let span = trace::ddb_span("tlist.delete_item", TABLE_NAME_TLIST).with("lid", tl.lid.to_string());
+            let deleted = retry::with_retry_in(&RetryPolicy::default(), span, delete).await;
+            cache::invalidate(tl.lid).await;
+            match deleted {
+                Ok(v) => metrics::record_capacity("tlist.delete_item", v.consumed_capacity.as_ref()),
+                Err(e) => {
+                    error!("Failed to delete lid {} from DDB: {}", tl.lid, e);
+                    return Err(ERR_MSG_DELETION_FAILED.to_string());
+                }
+            }
+            if structures_pg::del_t_list(tl.lid, pg_client).await.is_err() {
+                return Err(ERR_MSG_DELETION_FAILED.to_string());
+            }
+
+            receipt.deleted_lids.push(tl.lid);
+            receipt.save_in_ddb(ddb_client).await?;
+        }
+    }
+    debug!("Lists deleted: {}", receipt.deleted_lids.len());
+
+    // webhooks and WebSocket subscriptions would refer to lists that are gone
+    receipt.deleted_webhooks += webhooks::delete_user_webhooks(user_id, pg_client).await?;
+    receipt.deleted_connections +=
+        realtime::delete_user_connections(user_id, &receipt.deleted_lids, ddb_client).await?;
+    receipt.save_in_ddb(ddb_client).await?;
+
+    // deleting the lists adds to the audit log, so it is cleaned up after them
+    audit::forget_user(user_id, &receipt.deleted_lids, pg_client).await?;
+    receipt.audit_deleted = true;
+    receipt.save_in_ddb(ddb_client).await?;
+
+    // the user goes last
+    if structures_pg::del_t_user(user_id, pg_client).await.is_err() {
+        return Err(ERR_MSG_DELETION_FAILED.to_string());
+    }
+    receipt.user_deleted = true;
+    receipt.completed_on_utc = Some(Utc::now());
+    receipt.save_in_ddb(ddb_client).await?;
+    debug!("Account {} deleted.", user_id);
+
+    Ok(receipt)
+}
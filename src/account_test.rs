@@ -0,0 +1,95 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_account {
+    use crate::account::*;
+    use crate::audit;
+    use crate::revisions;
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use log::{self, debug};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_delete_user_account() {
+        debug!("test_delete_user_account started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new user
+        let user_email = ["test_delete_user_account@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let pg_user = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user");
+
+        // create 2 lists and trash one of them
+        let lids: [Uuid; 2] = [Uuid::new_v4(), Uuid::new_v4()];
+        test_helpers::create_random_list(lids[0], pg_user.user_id, &ddb_client, &pg_client).await;
+        test_helpers::create_random_list(lids[1], pg_user.user_id, &ddb_client, &pg_client).await;
+        LdList::move_to_trash(lids[1], &ddb_client, &pg_client)
+            .await
+            .expect("Failed to trash the list");
+
+        // a webhook for one of the lists
+        let webhook = TWebhook {
+            webhook_id: Uuid::new_v4(),
+            user_id: pg_user.user_id,
+            lid: Some(lids[0]),
+            org_id: None,
+            url: "https://example.com/hook".to_string(),
+            secret: "a-secret".to_string(),
+            created_on_utc: None,
+        };
+        put_t_webhook(&webhook, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to save the webhook");
+
+        // delete the account
+        let receipt = delete_user_account(pg_user.user_id, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to delete the account");
+
+        // check the receipt
+        assert!(receipt.user_deleted);
+        assert!(receipt.completed_on_utc.is_some());
+        assert_eq!(receipt.deleted_lids.len(), 2);
+        assert!(receipt.deleted_lids.contains(&lids[0]));
+        assert!(receipt.deleted_lids.contains(&lids[1]));
+        assert!(receipt.deleted_revisions > 0);
+        assert_eq!(receipt.deleted_webhooks, 1);
+        assert!(receipt.audit_deleted);
+
+        // check nothing is left in PG or DDB
+        assert!(get_t_user(Some(pg_user.user_id), None, &pg_client)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(get_user_webhooks(pg_user.user_id, &pg_client).await.unwrap().is_empty());
+        assert!(audit::get_user_audit_log(pg_user.user_id, None, None, &pg_client)
+            .await
+            .unwrap()
+            .is_empty());
+        for lid in lids.iter() {
+            assert!(get_t_list(*lid, &pg_client).await.unwrap().is_none());
+            assert!(LdList::get_from_ddb(lid, &ddb_client).await.unwrap().is_none());
+            assert!(revisions::get_revisions(*lid, None, &ddb_client)
+                .await
+                .unwrap()
+                .is_empty());
+            assert!(audit::get_list_audit_log(*lid, None, None, &pg_client)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+
+        // a repeated call returns the same receipt
+        let receipt_again = delete_user_account(pg_user.user_id, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to get the receipt");
+        assert_eq!(receipt_again.deleted_lids, receipt.deleted_lids);
+        assert_eq!(receipt_again.completed_on_utc, receipt.completed_on_utc);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::structures_ddb::{LdList, LdListItem};
+use crate::structures_pg::{self, TAudit};
+use chrono::{DateTime, Utc};
+use log::{self, error};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[path = "./audit_test.rs"]
+pub(crate) mod tests_audit;
+
+/// Default number of records returned by the audit log queries
+pub(crate) const ERR_MSG_AUDIT_FAILED: &str = "Failed to get the audit log. Try again.";
+const AUDIT_LOG_LIMIT: i64 = 50;
+const ERR_MSG_FORGET_FAILED: &str = "Failed to remove the user from the audit log. Try again.";
+
+/// Where the next page of the audit log starts: the last record of the previous page. Records made in the same
+/// microsecond are told apart by their ID, so none of them are skipped or repeated between pages.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) struct AuditCursor {
+    pub created_on_utc: DateTime<Utc>,
+    pub audit_id: i64,
+}
+
+impl AuditCursor {
+    /// The cursor for the page after the one ending with `entry`. None if the record was not read from PG.
+    pub(crate) fn after(entry: &TAudit) -> Option<Self> {
+        Some(AuditCursor {
+            created_on_utc: entry.created_on_utc?,
+            audit_id: entry.audit_id?,
+        })
+    }
+}
+
+/// Mutations recorded in the audit log. Stored in PG as the name of the function that made the change.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum AuditAction {
+    PutTList,
+    PutTListItem,
+    DelTList,
+    DelTUser,
+    SaveList,
+    PutListItem,
+    DelListItem,
+    /// An action recorded by a newer version of the code
+    Unknown,
+}
+
+impl AuditAction {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::PutTList => "put_t_list",
+            AuditAction::PutTListItem => "put_t_list_item",
+            AuditAction::DelTList => "del_t_list",
+            AuditAction::DelTUser => "del_t_user",
+            AuditAction::SaveList => "save_in_ddb",
+            AuditAction::PutListItem => "put_list_item_ddb",
+            AuditAction::DelListItem => "del_list_item_ddb",
+            AuditAction::Unknown => "unknown",
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "put_t_list" => Ok(AuditAction::PutTList),
+            "put_t_list_item" => Ok(AuditAction::PutTListItem),
+            "del_t_list" => Ok(AuditAction::DelTList),
+            "del_t_user" => Ok(AuditAction::DelTUser),
+            "save_in_ddb" => Ok(AuditAction::SaveList),
+            "put_list_item_ddb" => Ok(AuditAction::PutListItem),
+            "del_list_item_ddb" => Ok(AuditAction::DelListItem),
+            _ => Err(format!("Unknown audit action: {}", value)),
+        }
+    }
+}
+
+/// Saves the record in PG. Failures are logged and otherwise ignored because the change has already been made.
+pub(crate) async fn record(entry: TAudit, client: &tokio_postgres::Client) {
+    let _ = structures_pg::put_t_audit(&entry, client).await;
+}
+
+/// Returns the audit log of changes made by the user or to the user's account, newest first.
+pub(crate) async fn get_user_audit_log(
+    user_id: Uuid,
+    before: Option<AuditCursor>,
+    limit: Option<i64>,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Vec<TAudit>, String> {
+    structures_pg::get_user_audit(user_id, before, limit.unwrap_or(AUDIT_LOG_LIMIT), pg_client)
+        .await
+        .map_err(|_| ERR_MSG_AUDIT_FAILED.to_string())
+}
+
+/// Returns the audit log of changes to the list and its items, newest first.
+pub(crate) async fn get_list_audit_log(
+    lid: Uuid,
+    before: Option<AuditCursor>,
+    limit: Option<i64>,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Vec<TAudit>, String> {
+    structures_pg::get_list_audit(lid, before, limit.unwrap_or(AUDIT_LOG_LIMIT), pg_client)
+        .await
+        .map_err(|_| ERR_MSG_AUDIT_FAILED.to_string())
+}
+
+/// Removes the user from the audit log when the account is deleted. The records of the user's lists and account
+/// are deleted because their summaries hold the titles and the text of the lists. Changes the user made to lists
+/// of other users are kept for the owners of those lists, without the actor.
+pub(crate) async fn forget_user(
+    user_id: Uuid,
+    lids: &[Uuid],
+    pg_client: &tokio_postgres::Client,
+) -> Result<(), String> {
+    structures_pg::del_user_t_audit(user_id, lids, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_FORGET_FAILED.to_string())
+}
+
+/// Converts a PG record into JSON for the audit log.
+pub(crate) fn to_summary<T: Serialize>(value: &T) -> Option<Value> {
+    match serde_json::to_value(value) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            error!("Cannot convert the audit summary into JSON: {}", e);
+            None
+        }
+    }
+}
+
+/// A short version of the list for the audit log. Items are counted, but not included.
+pub(crate) fn list_summary(list: &LdList) -> Value {
+    json!({
+        "title": list.title,
+        "description": list.description,
+        "tags": list.tags,
+        "items": list.items.as_ref().map(|i| i.len()).unwrap_or_default(),
+        "user_id": list.rel.user_id,
+        "trashed": list.rel.deleted_on_utc.is_some(),
+    })
+}
+
+/// A short version of the list item for the audit log.
+pub(crate) fn item_summary(item: &LdListItem) -> Value {
+    json!({
+        "title": item.title,
+        "description": item.description,
+        "due_on_utc": item.due.as_ref().map(|d| d.due_on_utc),
+        "completed": item.rel.completed_on_utc.is_some(),
+        "trashed": item.rel.deleted_on_utc.is_some(),
+    })
+}
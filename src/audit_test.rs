@@ -0,0 +1,151 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_audit {
+    use crate::audit::*;
+    use crate::context::{self, RequestContext};
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use log::{self, debug};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_audit_action_names() {
+        let actions = [
+            AuditAction::PutTList,
+            AuditAction::PutTListItem,
+            AuditAction::DelTList,
+            AuditAction::DelTUser,
+            AuditAction::SaveList,
+            AuditAction::PutListItem,
+            AuditAction::DelListItem,
+        ];
+        for action in actions.iter() {
+            assert_eq!(action.as_str().parse::<AuditAction>(), Ok(*action));
+        }
+        assert!("drop_table".parse::<AuditAction>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_audit_summaries() {
+        let lid = Uuid::new_v4();
+        let mut list = LdList::new(lid, "Chores".to_string(), Uuid::new_v4());
+        list.items = Some(vec![LdListItem {
+            title: "Vacuum".to_string(),
+            description: None,
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), lid),
+        }]);
+
+        let summary = list_summary(&list);
+        assert_eq!(summary["title"], "Chores");
+        assert_eq!(summary["items"], 1);
+        assert_eq!(summary["trashed"], false);
+
+        let summary = item_summary(&list.items.as_ref().unwrap()[0]);
+        assert_eq!(summary["title"], "Vacuum");
+        assert_eq!(summary["completed"], false);
+
+        // the actor comes from the request context
+        let actor = Uuid::new_v4();
+        let request = RequestContext {
+            actor: Some(actor),
+            ..Default::default()
+        };
+        let entry = context::scope(request, async { TAudit::new(AuditAction::SaveList) }).await;
+        assert_eq!(entry.actor, Some(actor));
+        assert_eq!(TAudit::new(AuditAction::SaveList).actor, None);
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        debug!("test_audit_log started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new user with a list and make all changes on their behalf
+        let user_email = ["test_audit_log@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let pg_user = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user");
+        let user_id = pg_user.user_id;
+        let request = RequestContext {
+            actor: Some(user_id),
+            ..Default::default()
+        };
+        context::scope(request, async {
+            let lid = Uuid::new_v4();
+            let list = test_helpers::create_random_list(lid, user_id, &ddb_client, &pg_client).await;
+            let liid = list.items.as_ref().unwrap()[0].rel.liid;
+            LdListItem::del_list_item_ddb(lid, liid, &ddb_client, &pg_client)
+                .await
+                .expect("Failed to delete the item");
+
+            // the latest record is the item deletion with the item as it was, preceded by the list save
+            let list_log = get_list_audit_log(lid, None, None, &pg_client)
+                .await
+                .expect("No audit log");
+            assert_eq!(list_log[0].action, AuditAction::DelListItem);
+            assert_eq!(list_log[0].liid, Some(liid));
+            assert_eq!(list_log[0].actor, Some(user_id));
+            assert!(list_log[0].before.is_some());
+            assert_eq!(list_log[1].action, AuditAction::SaveList);
+            assert!(list_log.iter().any(|e| e.action == AuditAction::PutTList));
+            assert_eq!(list_log.iter().filter(|e| e.action == AuditAction::PutListItem).count(), 5);
+
+            // paging
+            let older = get_list_audit_log(lid, AuditCursor::after(&list_log[0]), Some(1), &pg_client)
+                .await
+                .expect("No audit log");
+            assert_eq!(older.len(), 1);
+            assert_eq!(older[0].audit_id, list_log[1].audit_id);
+
+            // records made in the same transaction share the time and are paged by ID
+            let mut paged = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = get_list_audit_log(lid, cursor, Some(2), &pg_client)
+                    .await
+                    .expect("No audit log");
+                if page.is_empty() {
+                    break;
+                }
+                cursor = AuditCursor::after(page.last().unwrap());
+                paged.extend(page.into_iter().map(|e| e.audit_id));
+            }
+            assert_eq!(paged, list_log.iter().map(|e| e.audit_id).collect::<Vec<_>>());
+
+            // clean up and check the deletions are logged too
+            LdList::get_from_ddb(&lid, &ddb_client)
+                .await
+                .unwrap()
+                .unwrap()
+                .delete_from_all_dbs(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to delete the list");
+            let user_log = get_user_audit_log(user_id, None, Some(2), &pg_client)
+                .await
+                .expect("No audit log");
+            assert_eq!(user_log[0].action, AuditAction::DelTList);
+
+            // the deleted user is not named in the log
+            del_t_user(user_id, &pg_client)
+                .await
+                .expect("Failed to delete the user");
+            forget_user(user_id, &[lid], &pg_client)
+                .await
+                .expect("Failed to clean up the audit log");
+            assert!(get_user_audit_log(user_id, None, None, &pg_client)
+                .await
+                .expect("No audit log")
+                .is_empty());
+            assert!(get_list_audit_log(lid, None, None, &pg_client)
+                .await
+                .expect("No audit log")
+                .is_empty());
+        })
+        .await;
+    }
+}
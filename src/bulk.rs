@@ -0,0 +1,342 @@
+use crate::audit::{self, AuditAction};
+use crate::cache::ReadConsistency;
+use crate::realtime;
+use crate::structures_ddb::{LdList, LdListItem};
+use crate::structures_pg::{self, TAudit, TListItem};
+use crate::validation::{self, Validate};
+use crate::webhooks::WebhookEvent;
+use dynomite::dynamodb::DynamoDbClient;
+use log::{self, debug, error, info};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[path = "./bulk_test.rs"]
+pub(crate) mod tests_bulk;
+
+const ERR_MSG_LIST_DOES_NOT_EXIST: &str = "The list doesn't exist";
+const ERR_MSG_ITEM_DOES_NOT_EXIST: &str = "The list item doesn't exist";
+const ERR_MSG_WRONG_LIST: &str = "The item belongs to a different list.";
+const ERR_MSG_DUPLICATE_ITEM: &str = "Only one change per item is allowed in a batch.";
+const ERR_MSG_ITEM_ID_TAKEN: &str = "The item ID is already used by an item of another list.";
+const ERR_MSG_INVALID_TARGET: &str = "The list to move the item to doesn't exist or has a different owner.";
+const ERR_MSG_TOO_MANY_CHANGES: &str = "Too many changes in one batch.";
+const ERR_MSG_BULK_FAILED: &str = "Failed to save the changes. Try again.";
+
+/// The number of item changes accepted in a single call. Keeps the list document well within the DDB item size.
+pub(crate) const MAX_ITEM_OPS: usize = 500;
+
+/// A change to a single item of a list.
+#[derive(Debug)]
+pub(crate) enum ItemOp {
+    /// Adds a new item or updates the title, description and due date of an existing one, matched by `rel.liid`.
+    Put(Box<LdListItem>),
+    Delete {
+        liid: Uuid,
+    },
+    /// Moves the item to the end of another list of the same owner.
+    Move {
+        liid: Uuid,
+        to_lid: Uuid,
+    },
+}
+
+/// The outcome of a single item change.
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct ItemOpResult {
+    pub liid: Uuid,
+    /// None if the change was applied.
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct BulkResult {
+    /// One result per change in the order they were requested.
+    pub results: Vec<ItemOpResult>,
+    /// The list after the changes.
+    pub list: Option<LdList>,
+}
+
+impl ItemOp {
+    pub(crate) fn liid(&self) -> Uuid {
+        match self {
+            ItemOp::Put(item) => item.rel.liid,
+            ItemOp::Delete { liid } | ItemOp::Move { liid, .. } => *liid,
+        }
+    }
+}
+
+/// Applies many item changes to the list in one go. Invalid changes are reported per item and skipped.
+/// All `t_list_item` rows of the valid changes are written in a single PG transaction and the list is written
+/// to DDB once, plus once per list items were moved to. Returns an error if nothing could be saved.
+pub(crate) async fn apply_item_ops(
+    lid: Uuid,
+    ops: Vec<ItemOp>,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<BulkResult, String> {
+    info!("apply_item_ops for {} with {} changes", lid, ops.len());
+    if ops.len() > MAX_ITEM_OPS {
+        return Err(ERR_MSG_TOO_MANY_CHANGES.to_string());
+    }
+
+    let mut list = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+        Some(v) => v,
+        None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+    };
+
+    // lists the items are moved to are loaded once each
+    let mut targets: HashMap<Uuid, LdList> = HashMap::new();
+    for to_lid in ops.iter().filter_map(|op| match op {
+        ItemOp::Move { to_lid, .. } => Some(*to_lid),
+        _ => None,
+    }) {
+        if to_lid == lid || targets.contains_key(&to_lid) {
+            continue;
+        }
+        if let Some(target) = LdList::get_from_ddb_with(&to_lid, ReadConsistency::Strong, ddb_client).await? {
+            if target.rel.user_id == list.rel.user_id {
+                targets.insert(to_lid, target);
+            }
+        }
+    }
+
+    // new items must not take over items of other lists
+    let existing: HashSet<Uuid> = list.items.iter().flatten().map(|i| i.rel.liid).collect();
+    let new_liids: Vec<Uuid> = ops
+        .iter()
+        .filter_map(|op| match op {
+            ItemOp::Put(item) if !existing.contains(&item.rel.liid) => Some(item.rel.liid),
+            _ => None,
+        })
+        .collect();
+    let taken: HashSet<Uuid> = if new_liids.is_empty() {
+        HashSet::new()
+    } else {
+        structures_pg::get_t_list_items_by_liid(&new_liids, pg_client)
+            .await
+            .map_err(|_| ERR_MSG_BULK_FAILED.to_string())?
+            .into_iter()
+            .map(|rel| rel.liid)
+            .collect()
+    };
+
+    let (mut results, accepted) = validate_ops(&list, &targets, &taken, ops);
+    if accepted.is_empty() {
+        return Ok(BulkResult {
+            results,
+            list: Some(list),
+        });
+    }
+
+    // the changes are made in memory first, with the rows PG will get, so that the lists can be checked before
+    // anything is written
+    let (pg_items, deleted_liids) = pg_changes(&list, &accepted)?;
+    let pg_rels: HashMap<Uuid, &TListItem> = pg_items.iter().map(|rel| (rel.liid, rel)).collect();
+    let stored = list.clone();
+    let stored_targets = targets.clone();
+
+    // keep the items as they were for the audit log
+    let before: HashMap<Uuid, Value> = list
+        .items
+        .iter()
+        .flatten()
+        .map(|i| (i.rel.liid, audit::item_summary(i)))
+        .collect();
+    let mut events: Vec<(WebhookEvent, Uuid, Uuid)> = Vec::new();
+    let mut created: Vec<Uuid> = Vec::new();
+    let items = list.items.get_or_insert_with(Vec::new);
+    for op in accepted {
+        match op {
+            ItemOp::Put(new_item) => {
+                let liid = new_item.rel.liid;
+                let rel = pg_rels.get(&liid).map(|rel| (*rel).clone());
+                match items.iter_mut().find(|i| i.rel.liid == liid) {
+                    Some(existing) => {
+                        existing.title = new_item.title;
+                        existing.description = new_item.description;
+                        existing.due = new_item.due;
+                        if let Some(rel) = rel {
+                            existing.rel = rel;
+                        }
+                        events.push((WebhookEvent::ItemUpdated, lid, liid));
+                    }
+                    None => {
+                        let rel = rel.unwrap_or_else(|| TListItem::new(liid, lid));
+                        items.push(LdListItem { rel, ..*new_item });
+                        created.push(liid);
+                        events.push((WebhookEvent::ItemCreated, lid, liid));
+                    }
+                }
+            }
+            ItemOp::Delete { liid } => {
+                items.retain(|i| i.rel.liid != liid);
+                events.push((WebhookEvent::ItemDeleted, lid, liid));
+            }
+            ItemOp::Move { liid, to_lid } => {
+                if let Some(position) = items.iter().position(|i| i.rel.liid == liid) {
+                    let mut item = items.remove(position);
+                    if let Some(rel) = pg_rels.get(&liid) {
+                        item.rel = (*rel).clone();
+                    }
+                    if let Some(target) = targets.get_mut(&to_lid) {
+                        target.items.get_or_insert_with(Vec::new).push(item);
+                    }
+                    events.push((WebhookEvent::ItemDeleted, lid, liid));
+                    events.push((WebhookEvent::ItemCreated, to_lid, liid));
+                }
+            }
+        }
+    }
+    validation::validate_changes_for_save(&list, Some(&stored))?;
+    for (to_lid, target) in targets.iter() {
+        validation::validate_changes_for_save(target, stored_targets.get(to_lid))?;
+    }
+
+    // PG in a single call for all items
+    let mut saved_rels: HashMap<Uuid, TListItem> = if pg_items.is_empty() && deleted_liids.is_empty() {
+        HashMap::new()
+    } else {
+        match structures_pg::apply_t_list_items(&pg_items, &deleted_liids, pg_client).await {
+            Ok(v) => v.into_iter().map(|rel| (rel.liid, rel)).collect(),
+            Err(_) => return Err(ERR_MSG_BULK_FAILED.to_string()),
+        }
+    };
+
+    // new items are only saved with the rel PG returned for them
+    for liid in created.into_iter().filter(|liid| !saved_rels.contains_key(liid)) {
+        error!("PG did not return new item {} of {}", liid, lid);
+        list.items.get_or_insert_with(Vec::new).retain(|i| i.rel.liid != liid);
+        events.retain(|(_, _, event_liid)| *event_liid != liid);
+        if let Some(result) = results.iter_mut().find(|r| r.liid == liid) {
+            result.error = Some(ERR_MSG_BULK_FAILED.to_string());
+        }
+    }
+    let lists = std::iter::once(&mut list).chain(targets.values_mut());
+    for item in lists.flat_map(|l| l.items.iter_mut().flatten()) {
+        if let Some(rel) = saved_rels.remove(&item.rel.liid) {
+            item.rel = rel;
+        }
+    }
+
+    // targets are saved first so that a failure leaves a moved item in both lists rather than in none
+    let mut saved_targets: HashMap<Uuid, LdList> = HashMap::new();
+    for (to_lid, target) in targets {
+        if let Some(saved) = target.save_in_ddb(ddb_client, pg_client).await? {
+            saved_targets.insert(to_lid, saved);
+        }
+    }
+    let list = list.save_in_ddb(ddb_client, pg_client).await?;
+    debug!("Bulk changes saved for {}", lid);
+
+    // record every change in the audit log and let collaborators know about it
+    for (event, event_lid, liid) in events {
+        let event_list = if event_lid == lid {
+            list.as_ref()
+        } else {
+            saved_targets.get(&event_lid)
+        };
+        let item = event_list
+            .and_then(|l| l.items.as_ref())
+            .and_then(|items| items.iter().find(|i| i.rel.liid == liid));
+        let action = match event {
+            WebhookEvent::ItemDeleted => AuditAction::DelListItem,
+            _ => AuditAction::PutListItem,
+        };
+        audit::record(
+            TAudit {
+                user_id: event_list.and_then(|l| l.rel.user_id),
+                lid: Some(event_lid),
+                liid: Some(liid),
+                before: before.get(&liid).cloned(),
+                after: item.map(audit::item_summary),
+                ..TAudit::new(action)
+            },
+            pg_client,
+        )
+        .await;
+        realtime::push_item_change(event, event_lid, liid, item, ddb_client).await;
+    }
+
+    Ok(BulkResult { results, list })
+}
+
+/// Checks every change against the list and returns a result per change and the changes that can be applied.
+/// `taken` are the liids of new items that already exist in PG, i.e. in another list.
+pub(crate) fn validate_ops(
+    list: &LdList,
+    targets: &HashMap<Uuid, LdList>,
+    taken: &HashSet<Uuid>,
+    ops: Vec<ItemOp>,
+) -> (Vec<ItemOpResult>, Vec<ItemOp>) {
+    let existing: HashSet<Uuid> = list.items.iter().flatten().map(|i| i.rel.liid).collect();
+    let mut seen: HashSet<Uuid> = HashSet::new();
+    let mut results = Vec::with_capacity(ops.len());
+    let mut accepted = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        let liid = op.liid();
+        let error = if !seen.insert(liid) {
+            Some(ERR_MSG_DUPLICATE_ITEM.to_string())
+        } else {
+            match &op {
+                ItemOp::Put(item) if item.rel.parent_lid != list.lid => Some(ERR_MSG_WRONG_LIST.to_string()),
+                ItemOp::Put(_) if !existing.contains(&liid) && taken.contains(&liid) => {
+                    Some(ERR_MSG_ITEM_ID_TAKEN.to_string())
+                }
+                ItemOp::Put(item) => item.validate().err().map(|errors| validation::error_message(&errors)),
+                _ if !existing.contains(&liid) => Some(ERR_MSG_ITEM_DOES_NOT_EXIST.to_string()),
+                ItemOp::Move { to_lid, .. } if !targets.contains_key(to_lid) => {
+                    Some(ERR_MSG_INVALID_TARGET.to_string())
+                }
+                _ => None,
+            }
+        };
+        let is_valid = error.is_none();
+
+        results.push(ItemOpResult { liid, error });
+        if is_valid {
+            accepted.push(op);
+        }
+    }
+
+    (results, accepted)
+}
+
+/// Returns `t_list_item` rows to upsert and liids to delete for the valid changes. Updates of existing items
+/// only need PG if the due date changed. Fails if a reminder time is out of range.
+fn pg_changes(list: &LdList, ops: &[ItemOp]) -> Result<(Vec<TListItem>, Vec<Uuid>), String> {
+    let existing: HashMap<Uuid, &LdListItem> = list.items.iter().flatten().map(|i| (i.rel.liid, i)).collect();
+    let mut upserts: Vec<TListItem> = Vec::new();
+    let mut deleted: Vec<Uuid> = Vec::new();
+
+    for op in ops {
+        match op {
+            ItemOp::Put(item) => {
+                let mut rel = match existing.get(&item.rel.liid) {
+                    Some(current) if current.due == item.due => continue,
+                    Some(current) => current.rel.clone(),
+                    None => TListItem::new(item.rel.liid, list.lid),
+                };
+                rel.due_on_utc = item.due.as_ref().map(|d| d.due_on_utc);
+                rel.remind_on_utc = match &item.due {
+                    Some(d) => d.remind_on_utc()?,
+                    None => None,
+                };
+                upserts.push(rel);
+            }
+            ItemOp::Delete { liid } => deleted.push(*liid),
+            ItemOp::Move { liid, to_lid } => {
+                if let Some(current) = existing.get(liid) {
+                    upserts.push(TListItem {
+                        parent_lid: *to_lid,
+                        ..current.rel.clone()
+                    });
+                }
+            }
+        }
+    }
+
+    Ok((upserts, deleted))
+}
@@ -0,0 +1,171 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_bulk {
+    use crate::audit::{self, AuditAction};
+    use crate::bulk::*;
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use log::{self, debug};
+    use std::collections::{HashMap, HashSet};
+    use uuid::Uuid;
+
+    fn new_item(title: &str, lid: Uuid) -> LdListItem {
+        LdListItem {
+            title: title.to_string(),
+            description: None,
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), lid),
+        }
+    }
+
+    #[test]
+    fn test_validate_ops() {
+        let user_id = Uuid::new_v4();
+        let lid = Uuid::new_v4();
+        let mut list = LdList::new(lid, "Source".to_string(), user_id);
+        list.items = Some(vec![new_item("One", lid), new_item("Two", lid)]);
+        let liids: Vec<Uuid> = list.items.as_ref().unwrap().iter().map(|i| i.rel.liid).collect();
+        let target_lid = Uuid::new_v4();
+        let mut targets = HashMap::new();
+        targets.insert(target_lid, LdList::new(target_lid, "Target".to_string(), user_id));
+
+        let added = new_item("Three", lid);
+        let added_liid = added.rel.liid;
+        let foreign = new_item("Elsewhere", Uuid::new_v4());
+        let foreign_liid = foreign.rel.liid;
+        let missing_liid = Uuid::new_v4();
+        let untitled = new_item(" ", lid);
+        let untitled_liid = untitled.rel.liid;
+        let stolen = new_item("Taken", lid);
+        let stolen_liid = stolen.rel.liid;
+        let taken: HashSet<Uuid> = vec![stolen_liid, liids[1]].into_iter().collect();
+        let ops = vec![
+            ItemOp::Put(Box::new(added)),
+            ItemOp::Put(Box::new(foreign)),
+            ItemOp::Delete { liid: liids[0] },
+            ItemOp::Move {
+                liid: liids[0],
+                to_lid: target_lid,
+            },
+            ItemOp::Delete { liid: missing_liid },
+            ItemOp::Move {
+                liid: liids[1],
+                to_lid: Uuid::new_v4(),
+            },
+            ItemOp::Put(Box::new(untitled)),
+            ItemOp::Put(Box::new(stolen)),
+        ];
+
+        let (results, accepted) = validate_ops(&list, &targets, &taken, ops);
+        let errors: Vec<(Uuid, bool)> = results.iter().map(|r| (r.liid, r.error.is_some())).collect();
+        assert_eq!(
+            errors,
+            vec![
+                (added_liid, false),
+                (foreign_liid, true),
+                (liids[0], false),
+                (liids[0], true),
+                (missing_liid, true),
+                (liids[1], true),
+                (untitled_liid, true),
+                (stolen_liid, true),
+            ]
+        );
+        assert_eq!(results[6].error.as_deref(), Some("Invalid input. title: cannot be empty."));
+        assert_eq!(
+            results[7].error.as_deref(),
+            Some("The item ID is already used by an item of another list.")
+        );
+        assert_eq!(accepted.iter().map(ItemOp::liid).collect::<Vec<Uuid>>(), vec![added_liid, liids[0]]);
+    }
+
+    #[tokio::test]
+    async fn test_apply_item_ops() {
+        debug!("test_apply_item_ops started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // a user with two lists of 5 items each
+        let user_email = ["test_apply_item_ops@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let user_id = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user")
+            .user_id;
+        let lid = Uuid::new_v4();
+        let list = test_helpers::create_random_list(lid, user_id, &ddb_client, &pg_client).await;
+        let target_lid = Uuid::new_v4();
+        let target = test_helpers::create_random_list(target_lid, user_id, &ddb_client, &pg_client).await;
+        let liids: Vec<Uuid> = list.items.as_ref().unwrap().iter().map(|i| i.rel.liid).collect();
+
+        // add one, rename one, delete one and move one to the other list, plus one invalid change
+        let added = new_item("Added in bulk", lid);
+        let added_liid = added.rel.liid;
+        let mut renamed = new_item("Renamed in bulk", lid);
+        renamed.rel.liid = liids[0];
+        let mut stolen = new_item("Taken from the other list", lid);
+        stolen.rel.liid = target.items.as_ref().unwrap()[0].rel.liid;
+        let ops = vec![
+            ItemOp::Put(Box::new(added)),
+            ItemOp::Put(Box::new(renamed)),
+            ItemOp::Delete { liid: liids[1] },
+            ItemOp::Move {
+                liid: liids[2],
+                to_lid: target_lid,
+            },
+            ItemOp::Delete { liid: Uuid::new_v4() },
+            ItemOp::Put(Box::new(stolen)),
+        ];
+        let result = apply_item_ops(lid, ops, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to apply the changes");
+        assert_eq!(result.results.iter().filter(|r| r.error.is_some()).count(), 2);
+
+        // DDB documents and PG rows reflect the changes
+        let list = LdList::get_from_ddb(&lid, &ddb_client).await.unwrap().unwrap();
+        let items = list.items.as_ref().unwrap();
+        assert_eq!(items.len(), 4);
+        assert!(items.iter().any(|i| i.rel.liid == added_liid));
+        assert!(items.iter().any(|i| i.title == "Renamed in bulk"));
+        assert!(!items.iter().any(|i| i.rel.liid == liids[1] || i.rel.liid == liids[2]));
+        let target = LdList::get_from_ddb(&target_lid, &ddb_client).await.unwrap().unwrap();
+        assert_eq!(target.items.as_ref().unwrap().len(), 6);
+        let pg_items = get_t_list_items(lid, &pg_client)
+            .await
+            .unwrap()
+            .expect("No items in PG");
+        assert_eq!(pg_items.len(), 4);
+        let pg_target_items = get_t_list_items(target_lid, &pg_client)
+            .await
+            .unwrap()
+            .expect("No items in PG");
+        assert!(pg_target_items.iter().any(|i| i.liid == liids[2]));
+        assert_eq!(pg_target_items.len(), 6);
+
+        // every change is in the audit log
+        let log = audit::get_list_audit_log(lid, None, None, &pg_client)
+            .await
+            .expect("No audit log");
+        let logged = |action: AuditAction, liid: Uuid| log.iter().any(|e| e.action == action && e.liid == Some(liid));
+        assert!(logged(AuditAction::PutListItem, added_liid));
+        assert!(logged(AuditAction::PutListItem, liids[0]));
+        assert!(logged(AuditAction::DelListItem, liids[1]));
+        assert!(logged(AuditAction::DelListItem, liids[2]));
+
+        // clean up
+        for lid in [lid, target_lid].iter() {
+            LdList::get_from_ddb(lid, &ddb_client)
+                .await
+                .unwrap()
+                .unwrap()
+                .delete_from_all_dbs(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to delete the list");
+        }
+        del_t_user(user_id, &pg_client)
+            .await
+            .expect("Failed to delete the user");
+    }
+}
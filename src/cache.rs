@@ -0,0 +1,248 @@
+use crate::structures_ddb::LdList;
+use futures::future::{self, BoxFuture};
+use log::{self, debug, error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env::var;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+#[path = "./cache_test.rs"]
+pub(crate) mod tests_cache;
+
+/// Env var with the max number of lists in the in-process cache. The cache is off if it is not set or 0.
+pub(crate) const EV_LIST_CACHE_SIZE: &str = "LIST_CACHE_SIZE";
+/// Env var with the number of seconds a list stays in the in-process cache.
+pub(crate) const EV_LIST_CACHE_TTL_SECS: &str = "LIST_CACHE_TTL_SECS";
+
+/// Lists saved by other Lambda instances do not invalidate this one's cache, so entries expire after a while.
+const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+/// How fresh a list read has to be.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ReadConsistency {
+    /// Any cached copy will do. Falls back to DDB and caches the result.
+    Cached,
+    /// A strongly consistent read from DDB that refreshes the cache. Use it before modifying the list.
+    Strong,
+}
+
+/// A cache of serialized lists by key. Implemented by the in-process `LruCache` and can be implemented for
+/// an external cache shared by all Lambda instances, e.g. ElastiCache. Errors of external caches should be
+/// logged and treated as misses.
+pub(crate) trait ListCache: Send + Sync {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<String>>;
+    fn put<'a>(&'a self, key: &'a str, value: String) -> BoxFuture<'a, ()>;
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()>;
+}
+
+/// Keeps up to `capacity` entries for `ttl` in memory, so they survive between invocations of a warm Lambda.
+/// The least recently used entry is dropped when the cache is full.
+pub(crate) struct LruCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<LruEntries>,
+}
+
+#[derive(Default)]
+struct LruEntries {
+    /// The value, when it expires and when it was last used.
+    values: HashMap<String, (String, Instant, u64)>,
+    /// Keys by the time they were last used, oldest first.
+    used: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl LruCache {
+    pub(crate) fn new(capacity: usize, ttl: Duration) -> Self {
+        LruCache {
+            capacity,
+            ttl,
+            entries: Mutex::new(LruEntries::default()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().values.len()
+    }
+
+    fn get_now(&self, key: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let (value, expires, used) = entries.values.get(key)?.clone();
+        entries.used.remove(&used);
+        if expires <= Instant::now() {
+            entries.values.remove(key);
+            return None;
+        }
+
+        entries.clock += 1;
+        let clock = entries.clock;
+        entries.used.insert(clock, key.to_string());
+        if let Some(entry) = entries.values.get_mut(key) {
+            entry.2 = clock;
+        }
+        Some(value)
+    }
+
+    fn put_now(&self, key: &str, value: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        if let Some((_, _, used)) = entries
+            .values
+            .insert(key.to_string(), (value, Instant::now() + self.ttl, clock))
+        {
+            entries.used.remove(&used);
+        }
+        entries.used.insert(clock, key.to_string());
+
+        while entries.values.len() > self.capacity {
+            let oldest = match entries.used.keys().next() {
+                Some(v) => *v,
+                None => break,
+            };
+            if let Some(key) = entries.used.remove(&oldest) {
+                entries.values.remove(&key);
+            }
+        }
+    }
+
+    fn delete_now(&self, key: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some((_, _, used)) = entries.values.remove(key) {
+            entries.used.remove(&used);
+        }
+    }
+}
+
+impl ListCache for LruCache {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<String>> {
+        Box::pin(future::ready(self.get_now(key)))
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: String) -> BoxFuture<'a, ()> {
+        self.put_now(key, value);
+        Box::pin(future::ready(()))
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, ()> {
+        self.delete_now(key);
+        Box::pin(future::ready(()))
+    }
+}
+
+/// Cache tiers in the order they are read, usually the in-process cache followed by an external one.
+/// Empty if caching is off.
+static CACHES: RwLock<Vec<Arc<dyn ListCache>>> = RwLock::new(Vec::new());
+
+/// Replaces the cache tiers. Pass an empty Vec to turn caching off.
+pub(crate) fn set_caches(caches: Vec<Arc<dyn ListCache>>) {
+    *CACHES.write().unwrap() = caches;
+}
+
+/// Turns on the in-process cache if `LIST_CACHE_SIZE` env var is set. Replaces any other cache tiers.
+pub(crate) fn init_from_env() {
+    let capacity = var(EV_LIST_CACHE_SIZE)
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or_default();
+    let ttl = var(EV_LIST_CACHE_TTL_SECS)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TTL);
+
+    debug!("List cache for {} lists for {:?}", capacity, ttl);
+    if capacity == 0 {
+        set_caches(Vec::new());
+    } else {
+        set_caches(vec![Arc::new(LruCache::new(capacity, ttl))]);
+    }
+}
+
+/// A copy of the tiers, so that no lock is held while waiting for an external cache.
+fn caches() -> Vec<Arc<dyn ListCache>> {
+    CACHES.read().unwrap().clone()
+}
+
+/// Full lists with items, as returned by `get_from_ddb`.
+fn list_key(lid: Uuid) -> String {
+    ["list:", &lid.to_string()].concat()
+}
+
+/// Lists without items, as returned by `get_user_lists`.
+fn summary_key(lid: Uuid) -> String {
+    ["summary:", &lid.to_string()].concat()
+}
+
+pub(crate) async fn get_list(lid: Uuid) -> Option<LdList> {
+    get_from(&caches(), &list_key(lid)).await
+}
+
+pub(crate) async fn put_list(list: &LdList) {
+    put_in(&caches(), &list_key(list.lid), list).await;
+}
+
+pub(crate) async fn get_summary(lid: Uuid) -> Option<LdList> {
+    get_from(&caches(), &summary_key(lid)).await
+}
+
+pub(crate) async fn put_summary(list: &LdList) {
+    put_in(&caches(), &summary_key(list.lid), list).await;
+}
+
+/// Removes all copies of the list. Must be called every time the list is saved or deleted.
+pub(crate) async fn invalidate(lid: Uuid) {
+    for cache in caches() {
+        cache.delete(&list_key(lid)).await;
+        cache.delete(&summary_key(lid)).await;
+    }
+}
+
+/// Reads the value from the first tier that has it and copies it to the tiers above.
+pub(crate) async fn get_from<T: DeserializeOwned>(caches: &[Arc<dyn ListCache>], key: &str) -> Option<T> {
+    for (tier, cache) in caches.iter().enumerate() {
+        let value = match cache.get(key).await {
+            Some(v) => v,
+            None => continue,
+        };
+        let parsed = match serde_json::from_str::<T>(&value) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Invalid cached value for {}: {}", key, e);
+                cache.delete(key).await;
+                continue;
+            }
+        };
+
+        debug!("Cache hit for {} in tier {}", key, tier);
+        for above in &caches[..tier] {
+            above.put(key, value.clone()).await;
+        }
+        return Some(parsed);
+    }
+
+    None
+}
+
+/// Writes the value to all tiers.
+pub(crate) async fn put_in<T: Serialize>(caches: &[Arc<dyn ListCache>], key: &str, value: &T) {
+    if caches.is_empty() {
+        return;
+    }
+    let value = match serde_json::to_string(value) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Cannot cache {}: {}", key, e);
+            return;
+        }
+    };
+    for cache in caches {
+        cache.put(key, value.clone()).await;
+    }
+}
@@ -0,0 +1,68 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_cache {
+    use crate::cache::*;
+    use crate::structures_ddb::LdList;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_lru_cache() {
+        let cache = LruCache::new(2, Duration::from_secs(60));
+        cache.put("a", "1".to_string()).await;
+        cache.put("b", "2".to_string()).await;
+
+        // reading `a` makes `b` the least recently used one, so it goes first
+        assert_eq!(cache.get("a").await, Some("1".to_string()));
+        cache.put("c", "3".to_string()).await;
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("b").await, None);
+        assert_eq!(cache.get("a").await, Some("1".to_string()));
+        assert_eq!(cache.get("c").await, Some("3".to_string()));
+
+        // replacing a value does not grow the cache
+        cache.put("c", "4".to_string()).await;
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("c").await, Some("4".to_string()));
+
+        cache.delete("a").await;
+        assert_eq!(cache.get("a").await, None);
+        assert_eq!(cache.len(), 1);
+
+        // expired entries are gone and a cache of 0 keeps nothing
+        let expired = LruCache::new(10, Duration::from_secs(0));
+        expired.put("a", "1".to_string()).await;
+        assert_eq!(expired.get("a").await, None);
+        assert_eq!(expired.len(), 0);
+        let off = LruCache::new(0, Duration::from_secs(60));
+        off.put("a", "1".to_string()).await;
+        assert_eq!(off.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cache_tiers() {
+        let local = Arc::new(LruCache::new(10, Duration::from_secs(60)));
+        let external = Arc::new(LruCache::new(10, Duration::from_secs(60)));
+        let tiers: Vec<Arc<dyn ListCache>> = vec![local.clone(), external.clone()];
+
+        // lists are written to every tier
+        let list = LdList::new(Uuid::new_v4(), "Cached".to_string(), Uuid::new_v4());
+        put_in(&tiers, "list:1", &list).await;
+        assert_eq!(local.len(), 1);
+        assert_eq!(external.len(), 1);
+
+        // a hit in a lower tier is copied to the tiers above
+        local.delete("list:1").await;
+        let cached: LdList = get_from(&tiers, "list:1").await.expect("The list is not cached");
+        assert_eq!(cached.lid, list.lid);
+        assert_eq!(cached.title, "Cached");
+        assert_eq!(local.len(), 1);
+
+        // values that cannot be read are dropped
+        local.put("list:2", "not a list".to_string()).await;
+        assert!(get_from::<LdList>(&tiers, "list:2").await.is_none());
+        assert!(local.get("list:2").await.is_none());
+        assert!(get_from::<LdList>(&tiers, "list:3").await.is_none());
+    }
+}
@@ -0,0 +1,695 @@
+use crate::list_export::html_escape;
+use crate::structures_ddb::{ItemPatch, LdList, LdListItem, ListPatch};
+use serde_json::Value;
+
+#[path = "./content_test.rs"]
+pub(crate) mod tests_content;
+
+/// Elements that are removed with everything inside them.
+const DROPPED_ELEMENTS: [&str; 11] = [
+    "script", "style", "iframe", "frameset", "object", "applet", "noscript", "template", "textarea", "svg", "math",
+];
+
+/// Formatting elements that are kept. Their attributes are limited to `ALLOWED_ATTRIBUTES`.
+const ALLOWED_ELEMENTS: [&str; 34] = [
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "cite",
+    "code",
+    "dd",
+    "del",
+    "dl",
+    "dt",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "strong",
+    "sub",
+    "sup",
+    "u",
+    "ul",
+];
+
+/// Attributes that are kept on `ALLOWED_ELEMENTS`. `href` is only kept on links with a safe URL.
+const ALLOWED_ATTRIBUTES: [&str; 2] = ["href", "title"];
+
+/// All other HTML elements. Their tags are removed and their text is kept. Tags with other names, e.g.
+/// `<version>`, are text unless they have attributes.
+const HTML_ELEMENTS: [&str; 100] = [
+    "acronym",
+    "address",
+    "area",
+    "article",
+    "aside",
+    "audio",
+    "base",
+    "basefont",
+    "bdi",
+    "bdo",
+    "bgsound",
+    "big",
+    "blink",
+    "body",
+    "button",
+    "canvas",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "data",
+    "datalist",
+    "details",
+    "dfn",
+    "dialog",
+    "dir",
+    "div",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "font",
+    "footer",
+    "form",
+    "frame",
+    "head",
+    "header",
+    "hgroup",
+    "html",
+    "image",
+    "img",
+    "input",
+    "isindex",
+    "keygen",
+    "label",
+    "legend",
+    "link",
+    "listing",
+    "main",
+    "map",
+    "marquee",
+    "menu",
+    "menuitem",
+    "meta",
+    "meter",
+    "multicol",
+    "nav",
+    "nextid",
+    "nobr",
+    "noembed",
+    "noframes",
+    "optgroup",
+    "option",
+    "output",
+    "param",
+    "picture",
+    "plaintext",
+    "portal",
+    "progress",
+    "rb",
+    "rp",
+    "rt",
+    "rtc",
+    "ruby",
+    "samp",
+    "search",
+    "section",
+    "select",
+    "slot",
+    "small",
+    "source",
+    "spacer",
+    "span",
+    "strike",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "title",
+    "tr",
+    "track",
+    "tt",
+    "var",
+    "video",
+    "wbr",
+    "xmp",
+];
+
+/// URL schemes allowed in links. URLs without a scheme are relative and allowed as well.
+const SAFE_URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+/// Adds `rel` to every rendered link so the linked pages get no access to the app.
+const LINK_REL: &str = "nofollow noopener noreferrer";
+
+// ===== Write =====
+
+/// Removes unsafe HTML, control characters and links with unsafe URLs from the title and the description
+/// of the list and all its items. Called before the list is validated and saved.
+pub(crate) fn sanitize_list(list: &mut LdList) {
+    list.title = sanitize_title(&list.title);
+    list.description = list.description.as_deref().map(sanitize_description);
+    for item in list.items.iter_mut().flatten() {
+        sanitize_item(item);
+    }
+}
+
+pub(crate) fn sanitize_item(item: &mut LdListItem) {
+    item.title = sanitize_title(&item.title);
+    item.description = item.description.as_deref().map(sanitize_description);
+}
+
+/// Patches are passed by reference, so a sanitised copy is returned.
+pub(crate) fn sanitized_list_patch(patch: &ListPatch) -> ListPatch {
+    let (title, description) = sanitize_text_fields(&patch.title, &patch.description);
+    ListPatch { title, description }
+}
+
+pub(crate) fn sanitized_item_patch(patch: &ItemPatch) -> ItemPatch {
+    let (title, description) = sanitize_text_fields(&patch.title, &patch.description);
+    ItemPatch { title, description }
+}
+
+fn sanitize_text_fields(
+    title: &Option<String>,
+    description: &Option<Option<String>>,
+) -> (Option<String>, Option<Option<String>>) {
+    let title = title.as_deref().map(sanitize_title);
+    let description = description.as_ref().map(|v| v.as_deref().map(sanitize_description));
+    (title, description)
+}
+
+/// Titles are plain text on a single line. They are not rendered as Markdown, so code spans get no exception.
+pub(crate) fn sanitize_title(title: &str) -> String {
+    let title: String = strip_html(title)
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    title.trim().to_string()
+}
+
+/// Descriptions are Markdown without unsafe HTML. Code spans are kept as they are. Links and images with URLs
+/// other than `SAFE_URL_SCHEMES` are replaced with their text.
+pub(crate) fn sanitize_description(description: &str) -> String {
+    let description: String = strip_html_outside_code(description)
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect();
+    let description = description
+        .lines()
+        .filter(|line| !is_unsafe_link_definition(line))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    remove_unsafe_links(&description).trim_end().to_string()
+}
+
+/// Keeps the tags of `ALLOWED_ELEMENTS` with `ALLOWED_ATTRIBUTES` and removes all other HTML: comments,
+/// elements like `<script>` with their content and the tags of other elements. Autolinks with safe URLs and text
+/// in angle brackets that is not HTML, e.g. `Vec<String>` or `Release <version>`, are kept.
+pub(crate) fn strip_html(value: &str) -> String {
+    let mut stripped = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('<') {
+        stripped.push_str(&rest[..start]);
+        let tag = &rest[start..];
+
+        // a comment runs to `-->` or to the end
+        if tag.starts_with("<!--") {
+            rest = tag.find("-->").map(|end| &tag[end + 3..]).unwrap_or("");
+            continue;
+        }
+        // `a < b` is text, not a tag
+        let is_tag = tag[1..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || ['/', '!', '?'].contains(&c));
+        let end = match tag_end(tag) {
+            Some(v) if is_tag => v,
+            _ => {
+                stripped.push('<');
+                rest = &tag[1..];
+                continue;
+            }
+        };
+        let inner = &tag[1..end];
+        rest = &tag[end + 1..];
+
+        // declarations and processing instructions, e.g. `<!DOCTYPE html>` or `<?xml ...?>`
+        if inner.starts_with(['!', '?']) {
+            continue;
+        }
+
+        // `/` separates the name from the attributes just like whitespace, e.g. `<img/src=x>`
+        let is_closing = inner.starts_with('/');
+        let name_and_attrs = inner.trim_start_matches('/');
+        let name_end = name_and_attrs
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(name_and_attrs.len());
+        let name = name_and_attrs[..name_end].to_lowercase();
+        let attrs = attributes(&name_and_attrs[name_end..]);
+
+        if DROPPED_ELEMENTS.contains(&name.as_str()) {
+            if !is_closing && !inner.ends_with('/') {
+                let closing = ["</", &name].concat();
+                rest = match rest.to_ascii_lowercase().find(&closing) {
+                    Some(v) => rest[v..].find('>').map(|end| &rest[v + end + 1..]).unwrap_or(""),
+                    None => "",
+                };
+            }
+            continue;
+        }
+        if ALLOWED_ELEMENTS.contains(&name.as_str()) {
+            stripped.push_str(&allowed_tag(&name, is_closing, &attrs));
+            continue;
+        }
+        if HTML_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+        // Markdown renders `<scheme:...>` as a link
+        if is_autolink(inner) {
+            if is_safe_url(inner) {
+                stripped.push_str(&tag[..=end]);
+            }
+            continue;
+        }
+        // unknown elements run event handlers as well, so they are only text without attributes
+        if attrs.is_empty() {
+            stripped.push_str(&tag[..=end]);
+        }
+    }
+    stripped.push_str(rest);
+
+    stripped
+}
+
+/// Applies `strip_html` to the text outside of Markdown code spans, so that `` `<b>` `` keeps the tag.
+/// A code span starts and ends with the same number of backticks.
+fn strip_html_outside_code(value: &str) -> String {
+    let mut stripped = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('`') {
+        let ticks = rest[start..].chars().take_while(|c| *c == '`').count();
+        let fence = "`".repeat(ticks);
+        let code_start = start + ticks;
+        let code_end = rest[code_start..]
+            .match_indices(&fence)
+            .map(|(i, _)| code_start + i)
+            .find(|i| !rest[i + ticks..].starts_with('`') && !rest[..*i].ends_with('`'));
+        stripped.push_str(&strip_html(&rest[..start]));
+        match code_end {
+            Some(v) => {
+                stripped.push_str(&rest[start..v + ticks]);
+                rest = &rest[v + ticks..];
+            }
+            None => {
+                stripped.push_str(&fence);
+                rest = &rest[code_start..];
+            }
+        }
+    }
+    stripped.push_str(&strip_html(rest));
+
+    stripped
+}
+
+/// The position of the `>` that ends the tag at the start of the value. `>` in quoted attribute values does not
+/// end the tag.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Rebuilds an allowed tag with only `ALLOWED_ATTRIBUTES`, e.g. `<a href="/lists/1">`.
+fn allowed_tag(name: &str, is_closing: bool, attrs: &[Attribute<'_>]) -> String {
+    if is_closing {
+        return format!("</{}>", name);
+    }
+    let mut tag = ["<", name].concat();
+    for attr in attrs {
+        let attr_name = attr.name.to_lowercase();
+        let is_allowed = match attr_name.as_str() {
+            "href" => name == "a" && is_safe_url(attr.value),
+            v => ALLOWED_ATTRIBUTES.contains(&v),
+        };
+        if is_allowed {
+            tag.push(' ');
+            tag.push_str(attr.text);
+        }
+    }
+    tag.push('>');
+
+    tag
+}
+
+/// An attribute of a tag as it was entered.
+struct Attribute<'a> {
+    name: &'a str,
+    /// Without quotes.
+    value: &'a str,
+    /// The whole attribute, e.g. `href="/lists/1"`.
+    text: &'a str,
+}
+
+/// Splits what follows the tag name into attributes. Whitespace and `/` separate them the way browsers do,
+/// so `<img/src=x/onerror=y>` has two attributes.
+fn attributes(text: &str) -> Vec<Attribute<'_>> {
+    let mut attrs = Vec::new();
+    let mut rest = text;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        let name_len = rest
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '=')
+            .unwrap_or(rest.len())
+            .max(1);
+        let after_name = rest[name_len..].trim_start();
+        let (value, len) = match after_name.strip_prefix('=') {
+            Some(equals) => {
+                let value = equals.trim_start();
+                let value_start = rest.len() - value.len();
+                match value.chars().next() {
+                    Some(q) if q == '"' || q == '\'' => match value[1..].find(q) {
+                        Some(end) => (&value[1..=end], value_start + end + 2),
+                        None => (&value[1..], rest.len()),
+                    },
+                    _ => {
+                        let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                        (&value[..end], value_start + end)
+                    }
+                }
+            }
+            None => ("", name_len),
+        };
+        attrs.push(Attribute {
+            name: &rest[..name_len],
+            value,
+            text: &rest[..len],
+        });
+        rest = &rest[len..];
+    }
+
+    attrs
+}
+
+/// Returns true if the URL is relative or has one of `SAFE_URL_SCHEMES`. Whitespace and entities that browsers
+/// and Markdown renderers would ignore or decode, e.g. `java&#115;cript:`, make the URL unsafe.
+pub(crate) fn is_safe_url(url: &str) -> bool {
+    let url = url.trim();
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) || url.contains("&#") || url.contains("&colon") {
+        return false;
+    }
+
+    // anything before the first `:` that is not followed by a path, query or fragment is a scheme
+    let scheme_end = match url.find(':') {
+        Some(v) => v,
+        None => return true,
+    };
+    if url[..scheme_end].contains(['/', '?', '#']) {
+        return true;
+    }
+    SAFE_URL_SCHEMES.contains(&url[..scheme_end].to_lowercase().as_str())
+}
+
+/// `<https://example.com>` or `<mailto:ana@example.com>`.
+fn is_autolink(inner: &str) -> bool {
+    inner.contains(':') && !inner.contains(char::is_whitespace)
+}
+
+fn is_safe_autolink(inner: &str) -> bool {
+    is_autolink(inner) && is_safe_url(inner)
+}
+
+/// Reference-style link definitions, e.g. `[1]: javascript:alert(1)`.
+fn is_unsafe_link_definition(line: &str) -> bool {
+    let line = line.trim_start();
+    if !line.starts_with('[') {
+        return false;
+    }
+    match line.find("]:") {
+        Some(v) => {
+            let url = line[v + 2..].split_whitespace().next().unwrap_or_default();
+            !is_safe_url(url.trim_start_matches('<').trim_end_matches('>'))
+        }
+        None => false,
+    }
+}
+
+/// Replaces `[text](url)` and `![text](url)` with `text` if the URL is not safe.
+fn remove_unsafe_links(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        let link = match parse_link(&rest[start..]) {
+            Some(v) => v,
+            None => {
+                cleaned.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+
+        let is_image = rest[..start].ends_with('!');
+        if is_safe_url(link.url) {
+            cleaned.push_str(&rest[..start + link.len]);
+        } else {
+            let before = if is_image { &rest[..start - 1] } else { &rest[..start] };
+            cleaned.push_str(before);
+            cleaned.push_str(link.text);
+        }
+        rest = &rest[start + link.len..];
+    }
+    cleaned.push_str(rest);
+
+    cleaned
+}
+
+/// A Markdown link at the start of a string.
+struct Link<'a> {
+    text: &'a str,
+    url: &'a str,
+    /// The length of the whole link in bytes.
+    len: usize,
+}
+
+/// Parses `[text](url)` or `[text](url "title")` on a single line. URLs can have balanced parentheses.
+fn parse_link(value: &str) -> Option<Link<'_>> {
+    let text_end = value.find("](")?;
+    let url_end = text_end + 2 + closing_parenthesis(&value[text_end + 2..])?;
+    if value[..url_end].contains('\n') {
+        return None;
+    }
+    let destination = value[text_end + 2..url_end].trim();
+    let url = destination.split_whitespace().next().unwrap_or_default();
+
+    Some(Link {
+        text: &value[1..text_end],
+        url: url.trim_start_matches('<').trim_end_matches('>'),
+        len: url_end + 1,
+    })
+}
+
+/// The position of the `)` that closes a `(` just before the value.
+fn closing_parenthesis(value: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// ===== Read =====
+
+/// The list as JSON for the front-end with `description_html` next to every description.
+pub(crate) fn to_json_with_html(list: &LdList) -> Result<Value, String> {
+    let mut json = serde_json::to_value(list).map_err(|e| e.to_string())?;
+    json["description_html"] = description_html(&list.description);
+    if let Some(items) = json.get_mut("items").and_then(Value::as_array_mut) {
+        for (json_item, item) in items.iter_mut().zip(list.items.iter().flatten()) {
+            json_item["description_html"] = description_html(&item.description);
+        }
+    }
+
+    Ok(json)
+}
+
+fn description_html(description: &Option<String>) -> Value {
+    match description {
+        Some(v) => Value::from(render_markdown(v)),
+        None => Value::Null,
+    }
+}
+
+/// Renders the safe Markdown subset as HTML: paragraphs, line breaks, bullet and numbered lists, `**bold**`,
+/// `*italic*`, `` `code` `` and links. All other text is escaped and images are replaced with their text,
+/// so the output can be inserted into a page as is.
+pub(crate) fn render_markdown(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut list: Option<&str> = None;
+
+    for line in markdown.lines().map(str::trim) {
+        let item = list_item(line);
+        if line.is_empty() || item.is_some() {
+            close_paragraph(&mut paragraph, &mut html);
+        }
+        if list.is_some() && item.map(|(tag, _)| tag) != list {
+            html.push_str(&format!("</{}>\n", list.unwrap_or_default()));
+            list = None;
+        }
+
+        match item {
+            Some((tag, text)) => {
+                if list.is_none() {
+                    html.push_str(&format!("<{}>\n", tag));
+                    list = Some(tag);
+                }
+                html.push_str(&format!("<li>{}</li>\n", render_inline(text)));
+            }
+            None if !line.is_empty() => paragraph.push(line),
+            None => {}
+        }
+    }
+    close_paragraph(&mut paragraph, &mut html);
+    if let Some(tag) = list {
+        html.push_str(&format!("</{}>\n", tag));
+    }
+
+    html
+}
+
+fn close_paragraph(lines: &mut Vec<&str>, html: &mut String) {
+    if lines.is_empty() {
+        return;
+    }
+    let rendered: Vec<String> = lines.iter().map(|l| render_inline(l)).collect();
+    html.push_str(&format!("<p>{}</p>\n", rendered.join("<br>\n")));
+    lines.clear();
+}
+
+/// Returns `ul` or `ol` and the text of a list item line, e.g. `- milk` or `2. bread`.
+fn list_item(line: &str) -> Option<(&'static str, &str)> {
+    for bullet in ["- ", "* ", "+ "].iter() {
+        if let Some(text) = line.strip_prefix(bullet) {
+            return Some(("ul", text));
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    match digits > 0 && line[digits..].starts_with(". ") {
+        true => Some(("ol", &line[digits + 2..])),
+        false => None,
+    }
+}
+
+/// Renders emphasis, code and links within a line.
+fn render_inline(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        // code spans are not formatted any further
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`') {
+                html.push_str(&format!("<code>{}</code>", html_escape(&rest[1..=end])));
+                rest = &rest[end + 2..];
+                continue;
+            }
+        }
+        if rest.starts_with("**") {
+            if let Some(end) = rest[2..].find("**").filter(|end| *end > 0) {
+                html.push_str(&format!("<strong>{}</strong>", render_inline(&rest[2..end + 2])));
+                rest = &rest[end + 4..];
+                continue;
+            }
+        }
+        if c == '*' || c == '_' {
+            if let Some(end) = rest[1..].find(c).filter(|end| *end > 0) {
+                html.push_str(&format!("<em>{}</em>", render_inline(&rest[1..=end])));
+                rest = &rest[end + 2..];
+                continue;
+            }
+        }
+        // images are not rendered, but their text is
+        let is_image = c == '!' && rest[1..].starts_with('[');
+        let link_start = if is_image { 1 } else { 0 };
+        if c == '[' || is_image {
+            if let Some(link) = parse_link(&rest[link_start..]) {
+                if is_image || !is_safe_url(link.url) {
+                    html.push_str(&render_inline(link.text));
+                } else {
+                    html.push_str(&format!(
+                        "<a href=\"{}\" rel=\"{}\">{}</a>",
+                        html_escape(link.url),
+                        LINK_REL,
+                        render_inline(link.text)
+                    ));
+                }
+                rest = &rest[link_start + link.len..];
+                continue;
+            }
+        }
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let url = &rest[1..end];
+                if is_safe_autolink(url) {
+                    let href = html_escape(url);
+                    html.push_str(&format!("<a href=\"{}\" rel=\"{}\">{}</a>", href, LINK_REL, href));
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        // a backslash makes the next character literal
+        if c == '\\' {
+            if let Some(next) = rest[1..].chars().next().filter(|n| n.is_ascii_punctuation()) {
+                html.push_str(&html_escape(&next.to_string()));
+                rest = &rest[1 + next.len_utf8()..];
+                continue;
+            }
+        }
+
+        html.push_str(&html_escape(&rest[..c.len_utf8()]));
+        rest = &rest[c.len_utf8()..];
+    }
+
+    html
+}
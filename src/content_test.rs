@@ -0,0 +1,189 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_content {
+    use crate::content::*;
+    use crate::structures_ddb::{ItemPatch, LdList, LdListItem};
+    use crate::structures_pg::TListItem;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_sanitize_title() {
+        assert_eq!(sanitize_title("Groceries"), "Groceries");
+        assert_eq!(
+            sanitize_title("<b onclick=\"go()\">Bold</b> & <i>brave</i>"),
+            "<b>Bold</b> & <i>brave</i>"
+        );
+        assert_eq!(sanitize_title("Milk<script>alert(1)</script>"), "Milk");
+        assert_eq!(sanitize_title("Two\nlines\u{0}"), "Two lines");
+        assert_eq!(sanitize_title("1 < 2 > 0"), "1 < 2 > 0");
+        assert_eq!(sanitize_title("Release <version> of Vec<String>"), "Release <version> of Vec<String>");
+        // an empty title is left for validation to reject
+        assert_eq!(sanitize_title("<img src=x onerror=alert(1)>"), "");
+        assert_eq!(sanitize_title("<!-- hidden --><iframe src=x></iframe>"), "");
+    }
+
+    #[test]
+    fn test_sanitize_description() {
+        let description = "**Buy** [milk](https://example.com/milk) and <a href=\"javascript:alert(1)\">bread</a>\n\
+            <!-- hidden -->- eggs <https://example.com>\n\
+            <SCRIPT type=\"text/javascript\">alert('ĥ')</SCRIPT>- tea";
+        assert_eq!(
+            sanitize_description(description),
+            "**Buy** [milk](https://example.com/milk) and <a>bread</a>\n- eggs <https://example.com>\n- tea"
+        );
+
+        // only unsafe attributes are removed, `>` in quotes does not end the tag
+        assert_eq!(
+            sanitize_description("<a title='a > b' onclick=\"go()\" href=\"/lists/1\" STYLE=x>t</a>"),
+            "<a title='a > b' href=\"/lists/1\">t</a>"
+        );
+        assert_eq!(sanitize_description("<!DOCTYPE html><meta http-equiv=refresh>ok"), "ok");
+
+        // `/` separates attributes like whitespace
+        assert_eq!(sanitize_description("<img/src/onerror=alert(1)>"), "");
+        assert_eq!(sanitize_description("<a/href=javascript:alert(1)>x</a>"), "<a>x</a>");
+        assert_eq!(
+            sanitize_description("<a/href='/lists/1'/title=x>y</a>"),
+            "<a href='/lists/1' title=x>y</a>"
+        );
+        assert_eq!(sanitize_description("<x/onclick=alert(1)>hi</x>"), "hi</x>");
+
+        // only allowed elements are kept
+        assert_eq!(
+            sanitize_description("<form action=https://example.com><input name=a><button>Go</button></form>"),
+            "Go"
+        );
+        assert_eq!(sanitize_description("<img src=https://example.com/pixel.gif>seen"), "seen");
+        assert_eq!(sanitize_description("<P ALIGN=center>text</P>"), "<p>text</p>");
+
+        // code spans are kept as they are
+        assert_eq!(
+            sanitize_description("Use `Vec<String>` or ``<script>`x`</script>`` in Release <version>"),
+            "Use `Vec<String>` or ``<script>`x`</script>`` in Release <version>"
+        );
+        assert_eq!(sanitize_description("no `<script>end"), "no `");
+
+        // links and images with unsafe URLs keep only their text
+        assert_eq!(sanitize_description("[click](javascript:alert(1))"), "click");
+        assert_eq!(sanitize_description("[click]( JaVaScRiPt:alert(1) )"), "click");
+        assert_eq!(sanitize_description("[click](java&#115;cript:alert(1))"), "click");
+        assert_eq!(sanitize_description("![cat](data:image/svg+xml;base64,PHN2Zz4=)"), "cat");
+        assert_eq!(sanitize_description("see <javascript:alert(1)>"), "see");
+        assert_eq!(sanitize_description("[1]: vbscript:msgbox\n[go][1]"), "[go][1]");
+        assert_eq!(
+            sanitize_description("[a](/lists/1) [b](#top) [c](mailto:ana@example.com)"),
+            "[a](/lists/1) [b](#top) [c](mailto:ana@example.com)"
+        );
+
+        // saving again changes nothing
+        let sanitized = sanitize_description(description);
+        assert_eq!(sanitize_description(&sanitized), sanitized);
+    }
+
+    #[test]
+    fn test_is_safe_url() {
+        for url in [
+            "https://example.com",
+            "HTTP://example.com",
+            "mailto:a@b.c",
+            "/a/b",
+            "a?b=c:d",
+            "#top",
+        ]
+        .iter()
+        {
+            assert!(is_safe_url(url), "{} is safe", url);
+        }
+        for url in [
+            "javascript:alert(1)",
+            "data:text/html,x",
+            "file:///etc",
+            "java\tscript:x",
+            "x&colon;y",
+        ]
+        .iter()
+        {
+            assert!(!is_safe_url(url), "{} is unsafe", url);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_list() {
+        let lid = Uuid::new_v4();
+        let mut list = LdList::new(lid, "Groceries<style>h1 {}</style>".to_string(), Uuid::new_v4());
+        list.description = Some("[x](javascript:void(0))".to_string());
+        list.items = Some(vec![LdListItem {
+            title: "<u onmouseover=\"go()\">Milk</u>".to_string(),
+            description: Some("<style>*{}</style>2%".to_string()),
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), lid),
+        }]);
+        sanitize_list(&mut list);
+
+        assert_eq!(list.title, "Groceries");
+        assert_eq!(list.description.as_deref(), Some("x"));
+        let item = &list.items.as_ref().unwrap()[0];
+        assert_eq!(item.title, "<u>Milk</u>");
+        assert_eq!(item.description.as_deref(), Some("2%"));
+
+        // a removed description stays removed
+        let patch = sanitized_item_patch(&ItemPatch {
+            title: Some("Bread<!-- hidden -->".to_string()),
+            description: Some(None),
+        });
+        assert_eq!(patch.title.as_deref(), Some("Bread"));
+        assert_eq!(patch.description, Some(None));
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let html = render_markdown(
+            "Buy **fresh** *bread* & `milk`\nfrom [the shop](https://example.com/?a=1&b=\"2\")\n\n\
+             - eggs\n- <b>tea</b>\n\n1. first\n2. second\n\n\
+             ![img](https://example.com/x.png) [bad](javascript:alert(1)) \\*not italic\\*",
+        );
+        assert_eq!(
+            html,
+            "<p>Buy <strong>fresh</strong> <em>bread</em> &amp; <code>milk</code><br>\n\
+             from <a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\" rel=\"nofollow noopener noreferrer\">\
+             the shop</a></p>\n\
+             <ul>\n<li>eggs</li>\n<li>&lt;b&gt;tea&lt;/b&gt;</li>\n</ul>\n\
+             <ol>\n<li>first</li>\n<li>second</li>\n</ol>\n\
+             <p>img bad *not italic*</p>\n"
+        );
+
+        assert_eq!(
+            render_markdown("<https://example.com>"),
+            "<p><a href=\"https://example.com\" rel=\"nofollow noopener noreferrer\">https://example.com</a></p>\n"
+        );
+        assert_eq!(render_markdown(""), "");
+    }
+
+    #[test]
+    fn test_to_json_with_html() {
+        let lid = Uuid::new_v4();
+        let mut list = LdList::new(lid, "Groceries".to_string(), Uuid::new_v4());
+        list.description = Some("**Weekly**".to_string());
+        list.items = Some(vec![
+            LdListItem {
+                title: "Milk".to_string(),
+                description: Some("2% <i>only</i>".to_string()),
+                due: None,
+                rel: TListItem::new(Uuid::new_v4(), lid),
+            },
+            LdListItem {
+                title: "Bread".to_string(),
+                description: None,
+                due: None,
+                rel: TListItem::new(Uuid::new_v4(), lid),
+            },
+        ]);
+
+        let json = to_json_with_html(&list).expect("The list cannot be serialized");
+        assert_eq!(json["title"], "Groceries");
+        assert_eq!(json["description"], "**Weekly**");
+        assert_eq!(json["description_html"], "<p><strong>Weekly</strong></p>\n");
+        assert_eq!(json["items"][0]["description_html"], "<p>2% &lt;i&gt;only&lt;/i&gt;</p>\n");
+        assert!(json["items"][1]["description_html"].is_null());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::retry;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+#[path = "./context_test.rs"]
+pub(crate) mod tests_context;
+
+/// Details of the request being processed that are needed deep inside DB functions, e.g. the author of a change.
+/// It is kept per task, so futures of different requests polled on the same thread do not see each other's
+/// context. The handler runs the request in `scope` and the IDs are attached to every log event. Storage functions
+/// narrow the list and item IDs down to the ones they work on with `scope_list`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestContext {
+    /// The Lambda request ID to correlate log events of the same invocation.
+    pub request_id: Option<String>,
+    /// The user who made the request. None for system tasks like purging the trash.
+    pub actor: Option<Uuid>,
+    /// The list and the item the request is about, if any.
+    pub lid: Option<Uuid>,
+    pub liid: Option<Uuid>,
+    /// When the Lambda runs out of time, less a margin, e.g. `retry::lambda_deadline(remaining)`.
+    /// Retries of DB calls give up after it. None means no limit.
+    pub deadline: Option<Instant>,
+}
+
+impl RequestContext {
+    /// The context of a Lambda invocation. `deadline_ms` is when the invocation times out in milliseconds since
+    /// the epoch, as in the `Lambda-Runtime-Deadline-Ms` header of the runtime API.
+    pub(crate) fn for_invocation(request_id: &str, deadline_ms: u64) -> Self {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        RequestContext {
+            request_id: Some(request_id.to_string()),
+            deadline: Some(retry::lambda_deadline(Duration::from_millis(deadline_ms.saturating_sub(now_ms)))),
+            ..Default::default()
+        }
+    }
+}
+
+tokio::task_local! {
+    static CONTEXT: RequestContext;
+}
+
+/// Runs `f` with `context` as the context of the current request. Code outside of any scope gets the default
+/// context.
+pub(crate) async fn scope<F: Future>(context: RequestContext, f: F) -> F::Output {
+    CONTEXT.scope(context, f).await
+}
+
+/// Runs `f` with the list and the item it works on in a copy of the current context, so log events of storage
+/// functions carry their IDs, also in requests about many lists. The item of the current context is kept if
+/// `liid` is None and the list is the same. `f` is boxed because storage functions nest and their futures would
+/// otherwise add up on the stack.
+pub(crate) async fn scope_list<F: Future>(lid: Uuid, liid: Option<Uuid>, f: F) -> F::Output {
+    let current = get();
+    let liid = liid.or_else(|| current.liid.filter(|_| current.lid == Some(lid)));
+    let context = RequestContext {
+        lid: Some(lid),
+        liid,
+        ..current
+    };
+    scope(context, Box::pin(f)).await
+}
+
+/// Returns a copy of the context of the current request.
+pub(crate) fn get() -> RequestContext {
+    CONTEXT.try_with(|c| c.clone()).unwrap_or_default()
+}
+
+/// The user who made the current request, if known.
+pub(crate) fn actor() -> Option<Uuid> {
+    CONTEXT.try_with(|c| c.actor).unwrap_or_default()
+}
+
+/// The time by which the current request must be done, if known.
+pub(crate) fn deadline() -> Option<Instant> {
+    CONTEXT.try_with(|c| c.deadline).unwrap_or_default()
+}
@@ -0,0 +1,81 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_context {
+    use crate::context::*;
+    use futures::future::join;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+    use uuid::Uuid;
+
+    /// Returns the actor before and after giving way to other futures.
+    async fn actor_across_await() -> (Option<Uuid>, Option<Uuid>) {
+        let before = actor();
+        tokio::time::delay_for(Duration::from_millis(1)).await;
+        (before, actor())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_scopes() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let request = |actor| RequestContext {
+            actor: Some(actor),
+            ..Default::default()
+        };
+
+        // futures polled on the same thread keep their own context
+        let (in_first, in_second) =
+            join(scope(request(first), actor_across_await()), scope(request(second), actor_across_await())).await;
+        assert_eq!(in_first, (Some(first), Some(first)));
+        assert_eq!(in_second, (Some(second), Some(second)));
+
+        // nothing is left outside of the scopes
+        assert_eq!(actor(), None);
+        assert!(get().request_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_scope() {
+        let (lid, other_lid, liid) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let ids = || {
+            let context = get();
+            (context.lid, context.liid)
+        };
+        let request = RequestContext {
+            request_id: Some("request-1".to_string()),
+            lid: Some(lid),
+            ..Default::default()
+        };
+
+        scope(request, async {
+            // the item is kept for the same list and dropped for another one
+            let (in_item, in_list) =
+                scope_list(lid, Some(liid), async { (ids(), scope_list(lid, None, async { ids() }).await) }).await;
+            assert_eq!(in_item, (Some(lid), Some(liid)));
+            assert_eq!(in_list, (Some(lid), Some(liid)));
+            let in_other = scope_list(lid, Some(liid), scope_list(other_lid, None, async { ids() })).await;
+            assert_eq!(in_other, (Some(other_lid), None));
+
+            // the rest of the context is inherited and the IDs of the request are back afterwards
+            let request_id = scope_list(other_lid, None, async { get().request_id }).await;
+            assert_eq!(request_id.as_deref(), Some("request-1"));
+            assert_eq!(ids(), (Some(lid), None));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_invocation_deadline() {
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        // retries stop a margin before the invocation times out
+        let context = RequestContext::for_invocation("request-1", now_ms + 3000);
+        assert_eq!(context.request_id.as_deref(), Some("request-1"));
+        let expected = context.deadline.unwrap();
+        assert!(expected <= Instant::now() + Duration::from_millis(2500));
+        assert!(expected >= Instant::now() + Duration::from_millis(2000));
+        assert_eq!(scope(context, async { deadline() }).await, Some(expected));
+
+        // an invocation that is already out of time allows no retries
+        let late = RequestContext::for_invocation("request-2", now_ms - 1000);
+        assert!(late.deadline.unwrap() <= Instant::now());
+    }
+}
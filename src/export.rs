@@ -0,0 +1,178 @@
+use crate::structures_ddb::{LdList, LdListItem};
+use crate::structures_pg::{self, TList, TListItem, TUser};
+use chrono::Utc;
+use dynomite::dynamodb::DynamoDbClient;
+use log::{self, debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[path = "./export_test.rs"]
+pub(crate) mod tests_export;
+
+/// The version of the export document produced by this code. Bump it on any breaking change to the layout.
+pub(crate) const EXPORT_FORMAT_VERSION: u32 = 1;
+
+const ERR_MSG_USER_NOT_FOUND: &str = "The user doesn't exist";
+const ERR_MSG_INVALID_EXPORT: &str = "The export file is not valid.";
+const ERR_MSG_EXPORT_FAILED: &str = "Failed to export the lists. Try again.";
+
+/// Everything we hold about a user, as a single JSON document they can take away.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct UserExport {
+    pub format_version: u32,
+    pub exported_on_utc: chrono::DateTime<Utc>,
+    pub user: TUser,
+    /// Lists owned by the user with all items and `rel` data, including the trash
+    pub lists: Vec<LdList>,
+    /// Lists owned by other users that this user has access to
+    pub shared_lists: Vec<LdList>,
+}
+
+impl UserExport {
+    /// Serializes the export into a JSON document.
+    pub(crate) fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| {
+            error!("Failed to serialize the export for {}: {}", self.user.user_id, e);
+            ERR_MSG_INVALID_EXPORT.to_string()
+        })
+    }
+
+    /// Reads back a JSON document produced by `to_json`. Documents from newer versions are rejected.
+    pub(crate) fn from_json(json: &str) -> Result<Self, String> {
+        let export: UserExport = serde_json::from_str(json).map_err(|e| {
+            error!("Failed to parse the export: {}", e);
+            ERR_MSG_INVALID_EXPORT.to_string()
+        })?;
+
+        if export.format_version > EXPORT_FORMAT_VERSION {
+            error!("Unsupported export version {}", export.format_version);
+            return Err(ERR_MSG_INVALID_EXPORT.to_string());
+        }
+
+        Ok(export)
+    }
+}
+
+/// Collects the user details and all lists the user owns or has access to.
+pub(crate) async fn export_user_data(
+    user_id: Uuid,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<UserExport, String> {
+    debug!("export_user_data for {}", user_id);
+
+    let user = match structures_pg::get_t_user(Some(user_id), None, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_EXPORT_FAILED.to_string())?
+    {
+        Some(v) => v,
+        None => return Err(ERR_MSG_USER_NOT_FOUND.to_string()),
+    };
+
+    // own lists come with the trash, shared lists without
+    let own = structures_pg::get_all_user_lists(user_id, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_EXPORT_FAILED.to_string())?;
+    let shared = structures_pg::get_user_shared_lists(user_id, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_EXPORT_FAILED.to_string())?;
+    let lists = get_full_lists(own, ddb_client).await?;
+    let shared_lists = get_full_lists(shared, ddb_client).await?;
+    debug!("Exporting {} own and {} shared lists", lists.len(), shared_lists.len());
+
+    Ok(UserExport {
+        format_version: EXPORT_FORMAT_VERSION,
+        exported_on_utc: Utc::now(),
+        user,
+        lists,
+        shared_lists,
+    })
+}
+
+/// Saves the lists from the export in DDB and PG under `user_id`. Shared lists are not imported
+/// because they belong to other users. Every list and item gets a new id, so importing the same export twice
+/// or into another account creates copies instead of taking over the original lists. Returns the imported lists.
+pub(crate) async fn import_user_data(
+    export: UserExport,
+    user_id: Uuid,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Vec<LdList>, String> {
+    debug!("import_user_data for {} with {} lists", user_id, export.lists.len());
+
+    let lists = with_new_ids(export.lists, export.user.user_id, user_id);
+    if lists.is_empty() {
+        return Ok(lists);
+    }
+
+    // save_many_in_ddb re-creates the PG records for the new owner, lists first and then their items
+    LdList::save_many_in_ddb(lists, ddb_client, pg_client).await
+}
+
+/// Copies the exported lists with new lids and liids for `user_id`. Links between the lists and items
+/// of the export are changed to the new ids, links to anything else are kept or dropped if they could be used
+/// to reach lists of other users. Items completed by the exporting user are completed by `user_id`.
+pub(crate) fn with_new_ids(lists: Vec<LdList>, exported_user_id: Uuid, user_id: Uuid) -> Vec<LdList> {
+    let lids: HashMap<Uuid, Uuid> = lists.iter().map(|l| (l.lid, Uuid::new_v4())).collect();
+    let liids: HashMap<Uuid, Uuid> = lists
+        .iter()
+        .flat_map(|l| l.items.iter().flatten())
+        .map(|i| (i.rel.liid, Uuid::new_v4()))
+        .collect();
+    let new_lid = |lid: Option<Uuid>| lid.and_then(|v| lids.get(&v).copied());
+    let new_liid = |liid: Option<Uuid>| liid.and_then(|v| liids.get(&v).copied());
+
+    lists
+        .into_iter()
+        .map(|list| {
+            let lid = lids[&list.lid];
+            let items: Option<Vec<LdListItem>> = list.items.map(|items| {
+                items
+                    .into_iter()
+                    .map(|item| LdListItem {
+                        rel: TListItem {
+                            child_lid: new_lid(item.rel.child_lid),
+                            origin_lid: new_lid(item.rel.origin_lid).or(item.rel.origin_lid),
+                            origin_liid: new_liid(item.rel.origin_liid).or(item.rel.origin_liid),
+                            completed_on_utc: item.rel.completed_on_utc,
+                            completed_by: item
+                                .rel
+                                .completed_by
+                                .map(|v| if v == exported_user_id { user_id } else { v }),
+                            deleted_on_utc: item.rel.deleted_on_utc,
+                            due_on_utc: item.rel.due_on_utc,
+                            remind_on_utc: item.rel.remind_on_utc,
+                            ..TListItem::new(liids[&item.rel.liid], lid)
+                        },
+                        ..item
+                    })
+                    .collect()
+            });
+
+            LdList {
+                lid,
+                items,
+                rel: TList {
+                    deleted_on_utc: list.rel.deleted_on_utc,
+                    is_template: list.rel.is_template,
+                    ..TList::new(lid, user_id)
+                },
+                ..list
+            }
+        })
+        .collect()
+}
+
+/// Retrieves full lists with items from DDB one by one. `batch_get` is not used because it does not return items.
+async fn get_full_lists(t_lists: Vec<TList>, ddb_client: &DynamoDbClient) -> Result<Vec<LdList>, String> {
+    let mut lists: Vec<LdList> = Vec::new();
+    for tl in t_lists {
+        match LdList::get_from_ddb_incl_trash(&tl.lid, ddb_client).await? {
+            Some(v) => lists.push(v),
+            None => error!("List {} is in PG, but not in DDB - DDB is out of sync.", tl.lid),
+        }
+    }
+
+    Ok(lists)
+}
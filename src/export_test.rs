@@ -0,0 +1,114 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_export {
+    use crate::export::*;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    /// Builds an export with one list and one item without touching the DBs.
+    fn build_export() -> UserExport {
+        let user_id = Uuid::new_v4();
+        let lid = Uuid::new_v4();
+        let mut list = LdList::new(lid, "Exported list".to_string(), user_id);
+        list.tags = Some(vec!["one".to_string(), "two".to_string()]);
+        list.items = Some(vec![LdListItem {
+            title: "Exported item".to_string(),
+            description: Some("Item description".to_string()),
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), lid),
+        }]);
+
+        UserExport {
+            format_version: EXPORT_FORMAT_VERSION,
+            exported_on_utc: Utc::now(),
+            user: TUser {
+                user_id,
+                user_email: "test_export@example.com".to_string(),
+                org_id: None,
+                created_on_utc: Utc::now(),
+                validated_on_utc: None,
+            },
+            lists: vec![list],
+            shared_lists: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_export_json_round_trip() {
+        let export = build_export();
+        let json = export.to_json().expect("Failed to serialize the export");
+        let export_read = UserExport::from_json(&json).expect("Failed to read the export back");
+
+        assert_eq!(export_read.format_version, EXPORT_FORMAT_VERSION);
+        assert_eq!(export_read.user.user_id, export.user.user_id);
+        assert_eq!(export_read.user.user_email, export.user.user_email);
+        assert_eq!(export_read.lists.len(), 1);
+        assert_eq!(export_read.lists[0].lid, export.lists[0].lid);
+        assert_eq!(export_read.lists[0].tags, export.lists[0].tags);
+        assert_eq!(
+            export_read.lists[0].items.as_ref().unwrap()[0].rel,
+            export.lists[0].items.as_ref().unwrap()[0].rel
+        );
+        assert!(export_read.shared_lists.is_empty());
+    }
+
+    #[test]
+    fn test_export_rejects_unknown_input() {
+        // a newer version cannot be read
+        let mut export = build_export();
+        export.format_version = EXPORT_FORMAT_VERSION + 1;
+        let json = export.to_json().unwrap();
+        assert!(UserExport::from_json(&json).is_err());
+
+        // neither can something that is not an export
+        assert!(UserExport::from_json("{\"lists\": []}").is_err());
+        assert!(UserExport::from_json("not json").is_err());
+    }
+
+    #[test]
+    fn test_import_with_new_ids() {
+        let export = build_export();
+        let exported_user_id = export.user.user_id;
+        let (lid, child_lid) = (export.lists[0].lid, Uuid::new_v4());
+        let mut lists = export.lists;
+        lists.push(LdList::new(child_lid, "Child list".to_string(), exported_user_id));
+        lists[0].rel.is_template = true;
+        lists[1].rel.deleted_on_utc = Some(Utc::now());
+        {
+            let rel = &mut lists[0].items.as_mut().unwrap()[0].rel;
+            rel.child_lid = Some(child_lid);
+            rel.completed_on_utc = Some(Utc::now());
+            rel.completed_by = Some(exported_user_id);
+            rel.due_on_utc = Some(Utc::now());
+            rel.top_lid = Some(Uuid::new_v4());
+        }
+        let old_rel = lists[0].items.as_ref().unwrap()[0].rel.clone();
+
+        let user_id = Uuid::new_v4();
+        let imported = with_new_ids(lists, exported_user_id, user_id);
+        assert_eq!(imported.len(), 2);
+        let (list, child) = (&imported[0], &imported[1]);
+        assert!(list.lid != lid && child.lid != child_lid);
+        assert_eq!(list.rel.lid, list.lid);
+        assert_eq!(list.rel.user_id, Some(user_id));
+        assert!(list.rel.created_on_utc.is_none());
+        // templates and lists in the trash stay that way
+        assert!(list.rel.is_template && list.rel.deleted_on_utc.is_none());
+        assert!(!child.rel.is_template && child.rel.deleted_on_utc.is_some());
+        assert_eq!(list.title, "Exported list");
+        assert_eq!(list.tags, Some(vec!["one".to_string(), "two".to_string()]));
+
+        // items get new ids and keep links within the export and their status
+        let item = &list.items.as_ref().unwrap()[0];
+        assert_eq!(item.title, "Exported item");
+        assert!(item.rel.liid != old_rel.liid);
+        assert_eq!(item.rel.parent_lid, list.lid);
+        assert_eq!(item.rel.child_lid, Some(child.lid));
+        assert_eq!(item.rel.completed_on_utc, old_rel.completed_on_utc);
+        assert_eq!(item.rel.completed_by, Some(user_id));
+        assert_eq!(item.rel.due_on_utc, old_rel.due_on_utc);
+        assert!(item.rel.top_lid.is_none() && item.rel.created_on_utc.is_none());
+    }
+}
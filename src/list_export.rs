@@ -0,0 +1,347 @@
+use crate::content;
+use crate::structures_ddb::{LdList, LdListItem};
+use chrono::Utc;
+use dynomite::dynamodb::DynamoDbClient;
+use log::{self, debug, error};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[path = "./list_export_test.rs"]
+pub(crate) mod tests_list_export;
+
+const ERR_MSG_LIST_DOES_NOT_EXIST: &str = "The list doesn't exist";
+const ERR_MSG_RENDERING_FAILED: &str = "Failed to render the list.";
+
+/// iCalendar lines must be folded at 75 octets as per https://tools.ietf.org/html/rfc5545#section-3.1
+const ICAL_MAX_LINE_OCTETS: usize = 75;
+
+/// Child lists deeper than this are not rendered, which also guards against circular links
+const MAX_CHILD_LIST_DEPTH: usize = 10;
+
+/// Formats a list can be downloaded in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum ExportFormat {
+    Json,
+    Markdown,
+    Csv,
+    Html,
+    ICalendar,
+}
+
+impl ExportFormat {
+    /// The value for the Content-Type response header.
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Html => "text/html; charset=utf-8",
+            ExportFormat::ICalendar => "text/calendar; charset=utf-8",
+        }
+    }
+
+    /// Picks the best format for the value of an Accept request header, taking `q` weights into account.
+    /// Returns None if none of the accepted types can be produced.
+    pub(crate) fn from_accept(accept: &str) -> Option<Self> {
+        let mut best: Option<(f32, Self)> = None;
+
+        for media_range in accept.split(',') {
+            let mut parts = media_range.split(';').map(|p| p.trim());
+            let media_type = parts.next().unwrap_or_default().to_lowercase();
+
+            // q defaults to 1
+            let q = parts
+                .filter_map(|p| p.strip_prefix("q="))
+                .filter_map(|q| q.parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+
+            let format = match media_type.as_str() {
+                "application/json" | "*/*" | "application/*" => ExportFormat::Json,
+                "text/markdown" | "text/x-markdown" => ExportFormat::Markdown,
+                "text/csv" => ExportFormat::Csv,
+                "text/html" | "text/*" => ExportFormat::Html,
+                "text/calendar" => ExportFormat::ICalendar,
+                _ => continue,
+            };
+
+            // the first of equally weighted types wins
+            if q > 0.0 && best.map(|(best_q, _)| q > best_q).unwrap_or(true) {
+                best = Some((q, format));
+            }
+        }
+
+        best.map(|(_, format)| format)
+    }
+}
+
+/// A list rendered in one of the export formats, ready to be returned from a Lambda endpoint.
+#[derive(Debug)]
+pub(crate) struct RenderedList {
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+/// Loads the list with all its child lists from DDB and renders it in the format picked from the Accept header.
+pub(crate) async fn render_list(
+    lid: Uuid,
+    accept: &str,
+    ddb_client: &DynamoDbClient,
+) -> Result<Option<RenderedList>, String> {
+    debug!("render_list for {} as {}", lid, accept);
+
+    let format = match ExportFormat::from_accept(accept) {
+        Some(v) => v,
+        None => {
+            debug!("No acceptable format in {}", accept);
+            return Ok(None);
+        }
+    };
+
+    let list = match LdList::get_from_ddb(&lid, ddb_client).await? {
+        Some(v) => v,
+        None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+    };
+    let children = list.get_child_lists_from_ddb(ddb_client).await?;
+
+    let body = match format {
+        ExportFormat::Json => content::to_json_with_html(&list).map(|v| v.to_string()).map_err(|e| {
+            error!("Failed to serialize lid {}: {}", lid, e);
+            ERR_MSG_RENDERING_FAILED.to_string()
+        })?,
+        ExportFormat::Markdown => render_markdown(&list, &children),
+        ExportFormat::Csv => render_csv(&list, &children),
+        ExportFormat::Html => render_html(&list, &children),
+        ExportFormat::ICalendar => render_ical(&list, &children),
+    };
+
+    Ok(Some(RenderedList {
+        content_type: format.content_type(),
+        body,
+    }))
+}
+
+// ===== Markdown =====
+
+/// Renders the list as a Markdown bullet list with child lists nested under their parent items.
+pub(crate) fn render_markdown(list: &LdList, children: &HashMap<Uuid, LdList>) -> String {
+    let mut md = format!("# {}\n\n", single_line(&list.title));
+    if let Some(description) = list.description.as_ref() {
+        md.push_str(description.trim());
+        md.push_str("\n\n");
+    }
+    render_markdown_items(list, children, 0, &mut md);
+
+    md
+}
+
+fn render_markdown_items(list: &LdList, children: &HashMap<Uuid, LdList>, depth: usize, md: &mut String) {
+    let indent = "  ".repeat(depth);
+
+    for item in list.visible_items() {
+        md.push_str(&format!("{}- {}\n", indent, single_line(&item.title)));
+
+        // descriptions go on the lines below, indented past the bullet
+        if let Some(description) = item.description.as_ref() {
+            for line in description.lines().filter(|l| !l.trim().is_empty()) {
+                md.push_str(&format!("{}  {}\n", indent, line.trim()));
+            }
+        }
+
+        if let Some(child) = child_list(item, children, depth) {
+            render_markdown_items(child, children, depth + 1, md);
+        }
+    }
+}
+
+// ===== CSV =====
+
+/// Renders all items as CSV rows in the same order as they appear in Markdown.
+/// The `level` column shows how deep the item is nested in child lists.
+pub(crate) fn render_csv(list: &LdList, children: &HashMap<Uuid, LdList>) -> String {
+    let mut csv = String::from("level,title,description\r\n");
+    render_csv_items(list, children, 0, &mut csv);
+
+    csv
+}
+
+fn render_csv_items(list: &LdList, children: &HashMap<Uuid, LdList>, depth: usize, csv: &mut String) {
+    for item in list.visible_items() {
+        csv.push_str(&format!(
+            "{},{},{}\r\n",
+            depth,
+            csv_field(&item.title),
+            csv_field(item.description.as_deref().unwrap_or_default())
+        ));
+
+        if let Some(child) = child_list(item, children, depth) {
+            render_csv_items(child, children, depth + 1, csv);
+        }
+    }
+}
+
+/// Quotes the field if it has any special chars.
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// ===== HTML =====
+
+/// Renders the list as a standalone HTML page with child lists nested under their parent items.
+pub(crate) fn render_html(list: &LdList, children: &HashMap<Uuid, LdList>) -> String {
+    let title = html_escape(&list.title);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    );
+    if let Some(description) = list.description.as_ref() {
+        html.push_str(&format!("<p>{}</p>\n", html_escape(description)));
+    }
+    render_html_items(list, children, 0, &mut html);
+    html.push_str("</body>\n</html>\n");
+
+    html
+}
+
+fn render_html_items(list: &LdList, children: &HashMap<Uuid, LdList>, depth: usize, html: &mut String) {
+    html.push_str("<ul>\n");
+    for item in list.visible_items() {
+        html.push_str(&format!("<li>{}", html_escape(&item.title)));
+        if let Some(description) = item.description.as_ref() {
+            html.push_str(&format!("<p>{}</p>", html_escape(description)));
+        }
+        if let Some(child) = child_list(item, children, depth) {
+            html.push('\n');
+            render_html_items(child, children, depth + 1, html);
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n");
+}
+
+/// Escapes text for use in HTML element content and attribute values.
+pub(crate) fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+// ===== iCalendar =====
+
+/// Renders every item as a VTODO entry with its due time, if any. Items of child lists are linked to their parent item via RELATED-TO.
+pub(crate) fn render_ical(list: &LdList, children: &HashMap<Uuid, LdList>) -> String {
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//ld-lambdas//list export//EN".to_string(),
+        format!("X-WR-CALNAME:{}", ical_escape(&list.title)),
+    ];
+    let dtstamp = ical_date_time(&Utc::now());
+    render_ical_items(list, children, None, 0, &dtstamp, &mut lines);
+    lines.push("END:VCALENDAR".to_string());
+
+    // every line is folded and terminated with CRLF
+    lines.iter().map(|l| ical_fold(l)).collect::<Vec<String>>().join("")
+}
+
+fn render_ical_items(
+    list: &LdList,
+    children: &HashMap<Uuid, LdList>,
+    parent_liid: Option<Uuid>,
+    depth: usize,
+    dtstamp: &str,
+    lines: &mut Vec<String>,
+) {
+    for item in list.visible_items() {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}", item.rel.liid));
+        lines.push(format!("DTSTAMP:{}", dtstamp));
+        if let Some(created_on_utc) = item.rel.created_on_utc.as_ref() {
+            lines.push(format!("CREATED:{}", ical_date_time(created_on_utc)));
+        }
+        lines.push(format!("SUMMARY:{}", ical_escape(&item.title)));
+        if let Some(description) = item.description.as_ref() {
+            lines.push(format!("DESCRIPTION:{}", ical_escape(description)));
+        }
+        if let Some(due_on_utc) = item.rel.due_on_utc.as_ref() {
+            lines.push(format!("DUE:{}", ical_date_time(due_on_utc)));
+        }
+        match item.rel.completed_on_utc.as_ref() {
+            Some(completed_on_utc) => {
+                lines.push("STATUS:COMPLETED".to_string());
+                lines.push(format!("COMPLETED:{}", ical_date_time(completed_on_utc)));
+            }
+            None => lines.push("STATUS:NEEDS-ACTION".to_string()),
+        }
+        if let Some(parent_liid) = parent_liid {
+            lines.push(format!("RELATED-TO:{}", parent_liid));
+        }
+        lines.push("END:VTODO".to_string());
+
+        if let Some(child) = child_list(item, children, depth) {
+            render_ical_items(child, children, Some(item.rel.liid), depth + 1, dtstamp, lines);
+        }
+    }
+}
+
+fn ical_date_time(value: &chrono::DateTime<Utc>) -> String {
+    value.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes TEXT values as per https://tools.ietf.org/html/rfc5545#section-3.3.11
+fn ical_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+/// Folds a content line into chunks of no more than 75 octets, without splitting UTF-8 chars.
+fn ical_fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut line_octets = 0usize;
+    for c in line.chars() {
+        if line_octets + c.len_utf8() > ICAL_MAX_LINE_OCTETS {
+            // continuation lines start with a space, which counts towards the limit
+            folded.push_str("\r\n ");
+            line_octets = 1;
+        }
+        folded.push(c);
+        line_octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+
+    folded
+}
+
+// ===== helpers =====
+
+/// Returns the child list of the item, if there is one and it's not too deep.
+fn child_list<'a>(item: &LdListItem, children: &'a HashMap<Uuid, LdList>, depth: usize) -> Option<&'a LdList> {
+    if depth >= MAX_CHILD_LIST_DEPTH {
+        return None;
+    }
+    item.rel.child_lid.and_then(|lid| children.get(&lid))
+}
+
+/// Replaces line breaks with spaces for values that must fit on one line.
+fn single_line(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
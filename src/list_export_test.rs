@@ -0,0 +1,135 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_list_export {
+    use crate::list_export::*;
+    use crate::list_import::parse_markdown;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use chrono::{TimeZone, Utc};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    /// Builds a list with 2 items, the 2nd item has a child list with 1 item.
+    fn build_lists() -> (LdList, HashMap<Uuid, LdList>) {
+        let user_id = Uuid::new_v4();
+        let lid = Uuid::new_v4();
+        let child_lid = Uuid::new_v4();
+
+        let mut child = LdList::new(child_lid, "Child".to_string(), user_id);
+        child.items = Some(vec![LdListItem {
+            title: "Nested <b>item</b>".to_string(),
+            description: None,
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), child_lid),
+        }]);
+
+        let mut list = LdList::new(lid, "Groceries & more".to_string(), user_id);
+        list.description = Some("For the weekend".to_string());
+        let mut parent_rel = TListItem::new(Uuid::new_v4(), lid);
+        parent_rel.child_lid = Some(child_lid);
+        list.items = Some(vec![
+            LdListItem {
+                title: "Milk, 2 bottles".to_string(),
+                description: Some("Full fat\nNot \"lite\"".to_string()),
+                due: None,
+                rel: TListItem::new(Uuid::new_v4(), lid),
+            },
+            LdListItem {
+                title: "Bakery".to_string(),
+                description: None,
+                due: None,
+                rel: parent_rel,
+            },
+        ]);
+
+        let mut children = HashMap::new();
+        children.insert(child_lid, child);
+        (list, children)
+    }
+
+    #[test]
+    fn test_export_format_from_accept() {
+        assert_eq!(ExportFormat::from_accept("text/csv"), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_accept("text/calendar"), Some(ExportFormat::ICalendar));
+        assert_eq!(ExportFormat::from_accept("*/*"), Some(ExportFormat::Json));
+        assert_eq!(
+            ExportFormat::from_accept("text/html;q=0.5, text/markdown;q=0.9, */*;q=0.1"),
+            Some(ExportFormat::Markdown)
+        );
+        assert_eq!(
+            ExportFormat::from_accept("text/html, application/xhtml+xml, application/xml;q=0.9"),
+            Some(ExportFormat::Html)
+        );
+        assert_eq!(ExportFormat::from_accept("image/png"), None);
+        assert_eq!(ExportFormat::from_accept("text/csv;q=0"), None);
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let (list, children) = build_lists();
+        let md = render_markdown(&list, &children);
+        assert_eq!(
+            md,
+            "# Groceries & more\n\nFor the weekend\n\n\
+             - Milk, 2 bottles\n  Full fat\n  Not \"lite\"\n\
+             - Bakery\n  - Nested <b>item</b>\n"
+        );
+
+        // it can be imported back with the same structure
+        let lists = parse_markdown(String::new(), &md, Uuid::new_v4());
+        assert_eq!(lists.len(), 2);
+        assert_eq!(lists[0].title, list.title);
+        assert_eq!(lists[0].items.as_ref().unwrap().len(), 2);
+        assert_eq!(lists[1].items.as_ref().unwrap()[0].title, "Nested <b>item</b>");
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let (list, children) = build_lists();
+        let csv = render_csv(&list, &children);
+        assert_eq!(
+            csv,
+            "level,title,description\r\n\
+             0,\"Milk, 2 bottles\",\"Full fat\nNot \"\"lite\"\"\"\r\n\
+             0,Bakery,\r\n\
+             1,Nested <b>item</b>,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_render_html() {
+        let (list, children) = build_lists();
+        let html = render_html(&list, &children);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Groceries &amp; more</title>"));
+        assert!(html.contains("<li>Nested &lt;b&gt;item&lt;/b&gt;</li>"));
+        assert!(!html.contains("<b>"));
+        assert_eq!(html.matches("<ul>").count(), 2);
+    }
+
+    #[test]
+    fn test_render_ical() {
+        let (mut list, children) = build_lists();
+        list.items.as_mut().unwrap()[0].title = ["Long title ", "x".repeat(100).as_str()].concat();
+        list.items.as_mut().unwrap()[1].rel.due_on_utc = Some(Utc.ymd(2020, 7, 4).and_hms(9, 30, 0));
+        let ical = render_ical(&list, &children);
+
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ical.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ical.matches("BEGIN:VTODO").count(), 3);
+        assert!(ical.contains("DESCRIPTION:Full fat\\nNot \"lite\"\r\n"));
+        assert_eq!(ical.matches("STATUS:NEEDS-ACTION").count(), 3);
+        assert_eq!(ical.matches("DUE:").count(), 1);
+        assert!(ical.contains("DUE:20200704T093000Z\r\n"));
+
+        // the nested item points at its parent
+        let parent_liid = list.items.as_ref().unwrap()[1].rel.liid;
+        assert!(ical.contains(&format!("RELATED-TO:{}\r\n", parent_liid)));
+
+        // no line is longer than 75 octets and long lines are folded
+        for line in ical.split("\r\n") {
+            assert!(line.len() <= 75);
+        }
+        assert!(ical.contains("\r\n x"));
+    }
+}
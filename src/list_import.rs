@@ -0,0 +1,274 @@
+use crate::structures_ddb::{LdList, LdListItem};
+use crate::structures_pg::TListItem;
+use dynomite::dynamodb::DynamoDbClient;
+use log::{self, debug, error};
+use uuid::Uuid;
+
+#[path = "./list_import_test.rs"]
+pub(crate) mod tests_list_import;
+
+const ERR_MSG_NOTHING_TO_IMPORT: &str = "There are no list items to import.";
+
+/// Number of spaces a tab is worth when working out Markdown nesting
+const MD_TAB_WIDTH: usize = 4;
+
+/// Formats a list can be pasted in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum ImportFormat {
+    /// Bullet and checkbox lists. Nested bullets become child lists.
+    Markdown,
+    /// Title and description columns with an optional header row.
+    Csv,
+    /// One item per line.
+    Text,
+}
+
+/// Parses the input and saves it as a new list with all its child lists in bulk. Returns the top list.
+pub(crate) async fn import_list(
+    format: ImportFormat,
+    title: String,
+    input: &str,
+    user_id: Uuid,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<LdList, String> {
+    debug!("import_list as {:?} for {}", format, user_id);
+
+    let lists = match format {
+        ImportFormat::Markdown => parse_markdown(title, input, user_id),
+        ImportFormat::Csv => vec![parse_csv(title, input, user_id)],
+        ImportFormat::Text => vec![parse_text(title, input, user_id)],
+    };
+
+    // an empty list is most likely a user error
+    if lists[0].items.as_ref().map(|i| i.is_empty()).unwrap_or(true) {
+        return Err(ERR_MSG_NOTHING_TO_IMPORT.to_string());
+    }
+
+    let mut saved = LdList::save_many_in_ddb(lists, ddb_client, pg_client).await?;
+    debug!("Imported {} lists", saved.len());
+
+    // the top list is always the first one
+    Ok(saved.remove(0))
+}
+
+/// Parses a Markdown list into a list of lists. The first one is the top list. Items nested under another item
+/// go into a child list linked via `rel.child_lid` of that item. A `# heading` before the first item replaces
+/// the title. Lines that are not list items are added to the description of the item above them.
+pub(crate) fn parse_markdown(title: String, input: &str, user_id: Uuid) -> Vec<LdList> {
+    let mut lists = vec![LdList::new(Uuid::new_v4(), title, user_id)];
+
+    // (indent, index in `lists`) of the list items are currently added to
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+
+    for line in input.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (indent, text) = split_md_indent(line);
+        let item_text = match strip_md_bullet(text) {
+            Some(v) => v,
+            None => {
+                // a heading before any items names the list
+                if stack.is_empty() && text.starts_with('#') {
+                    lists[0].title = text.trim_start_matches('#').trim().to_string();
+                } else if let Some((_, list_idx)) = stack.last() {
+                    append_to_last_description(&mut lists[*list_idx], text.trim());
+                }
+                continue;
+            }
+        };
+
+        // work out which list the item goes into
+        let list_idx = match stack.last().cloned() {
+            None => {
+                stack.push((indent, 0));
+                0
+            }
+            Some((top_indent, top_idx)) if indent > top_indent && has_items(&lists[top_idx]) => {
+                // start a child list under the last item of the current list
+                let child_lid = Uuid::new_v4();
+                let parent_item = lists[top_idx].items.as_mut().unwrap().last_mut().unwrap();
+                parent_item.rel.child_lid = Some(child_lid);
+                let child_title = parent_item.title.clone();
+                lists.push(LdList::new(child_lid, child_title, user_id));
+                stack.push((indent, lists.len() - 1));
+                lists.len() - 1
+            }
+            Some(_) => {
+                // go back up to the list this indent belongs to
+                while stack.len() > 1 && indent < stack.last().unwrap().0 {
+                    stack.pop();
+                }
+                stack.last().unwrap().1
+            }
+        };
+
+        let lid = lists[list_idx].lid;
+        push_item(&mut lists[list_idx], new_item(lid, item_text.to_string(), None));
+    }
+
+    lists
+}
+
+/// Parses CSV with `title` and `description` columns into a list. If the first row has no `title` column
+/// the first column is the title and the second is the description.
+pub(crate) fn parse_csv(title: String, input: &str, user_id: Uuid) -> LdList {
+    let mut list = LdList::new(Uuid::new_v4(), title, user_id);
+    let mut rows = parse_csv_rows(input).into_iter().peekable();
+
+    // use the header row to find the columns, if there is one
+    let (mut title_col, mut descr_col) = (0usize, Some(1usize));
+    if let Some(header) = rows.peek() {
+        let header: Vec<String> = header.iter().map(|h| h.trim().to_lowercase()).collect();
+        if let Some(col) = header.iter().position(|h| h == "title") {
+            title_col = col;
+            descr_col = header.iter().position(|h| h == "description");
+            rows.next();
+        }
+    }
+
+    for row in rows {
+        let item_title = match row.get(title_col).map(|t| t.trim()) {
+            Some(v) if !v.is_empty() => v.to_string(),
+            _ => continue,
+        };
+        let description = descr_col
+            .and_then(|c| row.get(c))
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty());
+
+        let lid = list.lid;
+        push_item(&mut list, new_item(lid, item_title, description));
+    }
+
+    list
+}
+
+/// Parses plain text into a list with one item per non-empty line.
+pub(crate) fn parse_text(title: String, input: &str, user_id: Uuid) -> LdList {
+    let mut list = LdList::new(Uuid::new_v4(), title, user_id);
+
+    for line in input.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let lid = list.lid;
+        push_item(&mut list, new_item(lid, line.to_string(), None));
+    }
+
+    list
+}
+
+/// Splits a CSV document into rows of fields. Supports quoted fields with commas, line breaks and `""`.
+fn parse_csv_rows(input: &str) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.is_empty() => in_quotes = true,
+            ',' if !in_quotes => row.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    // the last line may have no line break
+    if in_quotes {
+        error!("Unterminated quote in CSV input");
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+/// Returns the indent in spaces and the rest of the line.
+fn split_md_indent(line: &str) -> (usize, &str) {
+    let mut indent = 0usize;
+    for (i, c) in line.char_indices() {
+        match c {
+            ' ' => indent += 1,
+            '\t' => indent += MD_TAB_WIDTH,
+            _ => return (indent, &line[i..]),
+        }
+    }
+
+    (indent, "")
+}
+
+/// Returns the item text if the line is a bullet (`-`, `*`, `+`) or a numbered (`1.`, `1)`) list item.
+/// Checkbox markers are removed.
+fn strip_md_bullet(text: &str) -> Option<&str> {
+    let rest = if let Some(rest) = text.strip_prefix(|c| c == '-' || c == '*' || c == '+') {
+        rest
+    } else {
+        let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        text[digits..].strip_prefix(|c| c == '.' || c == ')')?
+    };
+
+    // a bullet must be followed by a space, otherwise it's something like `**bold**`
+    if !rest.starts_with(' ') {
+        return None;
+    }
+    let rest = rest.trim_start();
+
+    // drop `[ ]` / `[x]` checkboxes
+    let rest = match rest.get(..3) {
+        Some("[ ]") | Some("[x]") | Some("[X]") => rest[3..].trim_start(),
+        _ => rest,
+    };
+
+    match rest.is_empty() {
+        true => None,
+        false => Some(rest.trim_end()),
+    }
+}
+
+fn has_items(list: &LdList) -> bool {
+    list.items.as_ref().map(|i| !i.is_empty()).unwrap_or(false)
+}
+
+fn new_item(lid: Uuid, title: String, description: Option<String>) -> LdListItem {
+    LdListItem {
+        title,
+        description,
+        due: None,
+        rel: TListItem::new(Uuid::new_v4(), lid),
+    }
+}
+
+fn push_item(list: &mut LdList, item: LdListItem) {
+    list.items.get_or_insert_with(Vec::new).push(item);
+}
+
+fn append_to_last_description(list: &mut LdList, text: &str) {
+    if let Some(item) = list.items.as_mut().and_then(|i| i.last_mut()) {
+        match item.description.as_mut() {
+            Some(d) => {
+                d.push('\n');
+                d.push_str(text);
+            }
+            None => item.description = Some(text.to_string()),
+        }
+    }
+}
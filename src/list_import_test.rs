@@ -0,0 +1,89 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_list_import {
+    use crate::list_import::*;
+    use crate::structures_ddb::*;
+    use uuid::Uuid;
+
+    /// Returns item titles of the list for easy comparison.
+    fn titles(list: &LdList) -> Vec<&str> {
+        list.items
+            .as_ref()
+            .map(|items| items.iter().map(|i| i.title.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_parse_markdown_nested() {
+        let input = "# Release steps\n\
+                     \n\
+                     - [ ] Build\n\
+                     - [x] Test\n\
+                     \x20 * Unit tests\n\
+                     \x20   more detail\n\
+                     \x20 * Integration tests\n\
+                     \x20     1. Postgres\n\
+                     \x20     2) DynamoDB\n\
+                     - Deploy\n\
+                     **not an item**\n";
+        let user_id = Uuid::new_v4();
+        let lists = parse_markdown("Pasted".to_string(), input, user_id);
+
+        // top list with the heading as its title
+        assert_eq!(lists.len(), 3);
+        assert_eq!(lists[0].title, "Release steps");
+        assert_eq!(titles(&lists[0]), vec!["Build", "Test", "Deploy"]);
+        assert_eq!(lists[0].rel.user_id, Some(user_id));
+
+        // `Test` has a child list
+        let test_item = &lists[0].items.as_ref().unwrap()[1];
+        assert_eq!(test_item.rel.child_lid, Some(lists[1].lid));
+        assert_eq!(lists[1].title, "Test");
+        assert_eq!(titles(&lists[1]), vec!["Unit tests", "Integration tests"]);
+        assert_eq!(lists[1].items.as_ref().unwrap()[0].rel.parent_lid, lists[1].lid);
+
+        // continuation lines go into the description
+        assert_eq!(lists[1].items.as_ref().unwrap()[0].description, Some("more detail".to_string()));
+
+        // numbered items nested 2 levels deep
+        let integration_item = &lists[1].items.as_ref().unwrap()[1];
+        assert_eq!(integration_item.rel.child_lid, Some(lists[2].lid));
+        assert_eq!(titles(&lists[2]), vec!["Postgres", "DynamoDB"]);
+
+        // the last non-item line is a description of `Deploy`
+        assert_eq!(lists[0].items.as_ref().unwrap()[2].description, Some("**not an item**".to_string()));
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        // with a header in a different column order and quoted fields
+        let input = "description,title\r\n\
+                     \"Has, a comma\",First\r\n\
+                     \"Has \"\"quotes\"\"\nand a line break\",Second\r\n\
+                     ,Third\r\n\
+                     no title,\n";
+        let list = parse_csv("CSV".to_string(), input, Uuid::new_v4());
+        assert_eq!(titles(&list), vec!["First", "Second", "Third"]);
+        let items = list.items.as_ref().unwrap();
+        assert_eq!(items[0].description, Some("Has, a comma".to_string()));
+        assert_eq!(items[1].description, Some("Has \"quotes\"\nand a line break".to_string()));
+        assert_eq!(items[2].description, None);
+
+        // without a header
+        let list = parse_csv("CSV".to_string(), "One,Descr 1\nTwo", Uuid::new_v4());
+        assert_eq!(titles(&list), vec!["One", "Two"]);
+        assert_eq!(list.items.as_ref().unwrap()[0].description, Some("Descr 1".to_string()));
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let list = parse_text("Text".to_string(), "  One\n\n\tTwo  \r\nThree", Uuid::new_v4());
+        assert_eq!(titles(&list), vec!["One", "Two", "Three"]);
+        for item in list.items.as_ref().unwrap() {
+            assert_eq!(item.rel.parent_lid, list.lid);
+        }
+
+        // nothing to import
+        assert!(parse_text("Text".to_string(), "\n  \n", Uuid::new_v4()).items.is_none());
+    }
+}
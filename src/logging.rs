@@ -0,0 +1,142 @@
+use crate::context::{self, RequestContext};
+use chrono::{SecondsFormat, Utc};
+use log::{self, LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+use std::env::var;
+use std::io::Write;
+use std::str::FromStr;
+
+#[path = "./logging_test.rs"]
+pub(crate) mod tests_logging;
+
+/// Env var with the log level, e.g. `debug` or `warn`. Overrides the level passed to `init`.
+pub(crate) const EV_LOG_LEVEL: &str = "LOG_LEVEL";
+
+/// Replaces sensitive values in log messages.
+const REDACTED: &str = "<redacted>";
+
+/// Fields whose values are never logged, whether they appear in `{:?}` output of a struct or in JSON.
+const REDACTED_FIELDS: [&str; 2] = ["user_email", "secret"];
+
+/// Writes every log event as a single line of JSON to stdout, where CloudWatch picks it up.
+struct JsonLogger;
+
+static LOGGER: JsonLogger = JsonLogger;
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        println!("{}", log_event(record, &context::get()));
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Installs the JSON logger with the level from `LOG_LEVEL` env var or `default_level` if it is not set or invalid.
+/// Safe to call more than once.
+pub(crate) fn init(default_level: log::Level) {
+    let level = var(EV_LOG_LEVEL)
+        .ok()
+        .and_then(|v| LevelFilter::from_str(v.trim()).ok())
+        .unwrap_or_else(|| default_level.to_level_filter());
+
+    let installed = log::set_logger(&LOGGER).is_ok();
+    log::set_max_level(level);
+    log::debug!("JSON logger at {} level, newly installed: {}", level, installed);
+}
+
+/// Builds the JSON log event with the IDs of the current request. IDs that are not known are left out.
+pub(crate) fn log_event(record: &Record, context: &RequestContext) -> Value {
+    let mut event = json!({
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": redact(&record.args().to_string()),
+    });
+
+    let ids = [
+        ("request_id", context.request_id.clone()),
+        ("user_id", context.actor.map(|v| v.to_string())),
+        ("lid", context.lid.map(|v| v.to_string())),
+        ("liid", context.liid.map(|v| v.to_string())),
+    ];
+    for (name, value) in ids.iter() {
+        if let Some(value) = value {
+            event[name] = Value::from(value.as_str());
+        }
+    }
+
+    event
+}
+
+/// Removes the values of `REDACTED_FIELDS` and any email addresses from the message.
+pub(crate) fn redact(message: &str) -> String {
+    let mut redacted = message.to_string();
+    for field in REDACTED_FIELDS.iter() {
+        // `{:?}` output of structs and JSON
+        for prefix in [format!("{}: \"", field), format!("\"{}\":\"", field)].iter() {
+            redacted = redact_quoted(&redacted, prefix);
+        }
+    }
+    redact_emails(&redacted)
+}
+
+/// Replaces the quoted value after every `prefix` up to the closing quote.
+fn redact_quoted(message: &str, prefix: &str) -> String {
+    let mut redacted = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find(prefix) {
+        let value_start = start + prefix.len();
+        let value_end = match rest[value_start..].find('"') {
+            Some(v) => value_start + v,
+            None => break,
+        };
+        redacted.push_str(&rest[..value_start]);
+        redacted.push_str(REDACTED);
+        rest = &rest[value_end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+/// Replaces anything that looks like `name@domain.tld`.
+fn redact_emails(message: &str) -> String {
+    let is_local = |c: char| c.is_ascii_alphanumeric() || "._%+-".contains(c);
+    let is_domain = |c: char| c.is_ascii_alphanumeric() || ".-".contains(c);
+
+    let mut redacted = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(at) = rest.find('@') {
+        let start = rest[..at]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_local(*c))
+            .last()
+            .map(|(i, _)| i)
+            .unwrap_or(at);
+        let end = rest[at + 1..]
+            .find(|c: char| !is_domain(c))
+            .map(|i| at + 1 + i)
+            .unwrap_or_else(|| rest.len());
+        let domain = rest[at + 1..end].trim_end_matches('.');
+
+        if start < at && domain.contains('.') {
+            redacted.push_str(&rest[..start]);
+            redacted.push_str(REDACTED);
+            rest = &rest[at + 1 + domain.len()..];
+        } else {
+            redacted.push_str(&rest[..=at]);
+            rest = &rest[at + 1..];
+        }
+    }
+    redacted.push_str(rest);
+    redacted
+}
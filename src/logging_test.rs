@@ -0,0 +1,62 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_logging {
+    use crate::context::RequestContext;
+    use crate::logging::*;
+    use log::Level;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_redact() {
+        // emails anywhere in the message
+        assert_eq!(redact("ld_put_tuser for max@example.com"), "ld_put_tuser for <redacted>");
+        assert_eq!(
+            redact("Invite a.b+lists@mail.example.co.uk, then c@d.io."),
+            "Invite <redacted>, then <redacted>."
+        );
+
+        // field values in Debug output and in JSON
+        assert_eq!(
+            redact(r#"TUser { user_id: 1, user_email: "Ana", org_id: None }"#),
+            r#"TUser { user_id: 1, user_email: "<redacted>", org_id: None }"#
+        );
+        assert_eq!(
+            redact(r#"{"url":"https://example.com/hook","secret":"9f86d081"}"#),
+            r#"{"url":"https://example.com/hook","secret":"<redacted>"}"#
+        );
+
+        // things that are not emails are left alone
+        assert_eq!(redact("Rows: 1"), "Rows: 1");
+        assert_eq!(redact("@mention and a@localhost and 50@"), "@mention and a@localhost and 50@");
+        assert_eq!(redact("Müller:ana@example.com"), "Müller:<redacted>");
+    }
+
+    #[test]
+    fn test_log_event() {
+        let lid = Uuid::new_v4();
+        let context = RequestContext {
+            request_id: Some("8f5c3a1e-request".to_string()),
+            lid: Some(lid),
+            ..Default::default()
+        };
+        let event = log_event(
+            &log::Record::builder()
+                .args(format_args!("Rows: {} for {}", 1, "max@example.com"))
+                .level(Level::Debug)
+                .target("pgsql_zero::structures_pg")
+                .build(),
+            &context,
+        );
+
+        assert_eq!(event["level"], "DEBUG");
+        assert_eq!(event["target"], "pgsql_zero::structures_pg");
+        assert_eq!(event["message"], "Rows: 1 for <redacted>");
+        assert_eq!(event["request_id"], "8f5c3a1e-request");
+        assert_eq!(event["lid"], lid.to_string());
+        assert!(event["timestamp"].is_string());
+
+        // unknown IDs are left out rather than logged as null
+        assert!(event.get("user_id").is_none());
+        assert!(event.get("liid").is_none());
+    }
+}
@@ -1,24 +1,64 @@
 use log::debug;
-use simple_logger;
 use tokio;
 use tokio_postgres::Error;
 use uuid::Uuid;
 
 //use dynamodb_data;
+mod account;
+mod audit;
+mod bulk;
+mod cache;
+mod content;
+mod context;
+mod export;
+mod list_export;
+mod list_import;
+mod logging;
+mod metrics;
+mod realtime;
+mod retry;
+mod revisions;
+mod schedule;
+mod search;
+mod stream;
 mod structures_ddb;
 mod structures_pg;
+mod tags;
+mod templates;
+mod trace;
 mod utils;
+mod validation;
+mod webhooks;
+
+/// Time limit of a test run, the same as the default Lambda timeout.
+const MAIN_TIMEOUT_MS: u64 = 3000;
 
 #[tokio::main] // By default, tokio_postgres uses the tokio crate as its runtime.
 async fn main() -> Result<(), Error> {
     utils::log_init(log::Level::Debug);
+    cache::init_from_env();
     debug!("main started");
     debug!("");
 
     // prepare some constants
     let user_id = uuid::Uuid::parse_str("dbc44eaa-364f-4a4f-b25e-15218c7928a7").unwrap();
     let lid = Uuid::new_v4();
+
+    // every log event of the request carries the request, the user and the list IDs and retries stop before
+    // the invocation would time out
+    let deadline_ms = chrono::Utc::now().timestamp_millis() as u64 + MAIN_TIMEOUT_MS;
+    let request = context::RequestContext {
+        actor: Some(user_id),
+        lid: Some(lid),
+        ..context::RequestContext::for_invocation(&Uuid::new_v4().to_string(), deadline_ms)
+    };
+    context::scope(request, run(user_id, lid)).await
+}
+
+/// Runs a test request for the user within the request context.
+async fn run(user_id: Uuid, lid: Uuid) -> Result<(), Error> {
     let list_title = "My test list X".to_string();
+    let request_span = trace::start_request("main");
 
     // prepare DDB and PG connections
     let ddb_client = rusoto_dynamodb::DynamoDbClient::new(rusoto_core::Region::UsEast1);
@@ -36,17 +76,17 @@ async fn main() -> Result<(), Error> {
     let ddb_list_saved = ddb_list_saved.unwrap();
     assert!(ddb_list_saved.is_some());
 
-    // update the list - add description
-    let mut list_to_update = ddb_list_saved.unwrap();
+    // update the list - add description without rewriting the rest of it
     let new_descr = "Updated description".to_string();
-    list_to_update.description = Some(new_descr.clone());
-    let list_updated = list_to_update.save_in_ddb(&ddb_client, &pg_client).await;
+    let patch = structures_ddb::ListPatch {
+        description: Some(Some(new_descr.clone())),
+        ..Default::default()
+    };
+    let list_updated = structures_ddb::LdList::patch_in_ddb(lid, &patch, &ddb_client, &pg_client).await;
 
     // check if updated successfully
     assert!(list_updated.is_ok());
     let list_updated = list_updated.unwrap();
-    assert!(list_updated.is_some());
-    let list_updated = list_updated.unwrap();
     assert!(list_updated.description.is_some());
     assert_eq!(list_updated.description.unwrap(), new_descr);
 
@@ -55,6 +95,7 @@ async fn main() -> Result<(), Error> {
     let list_item_from_ui = structures_ddb::LdListItem {
         title: "New item 1".to_string(),
         description: Some("Some long description 1".to_string()),
+        due: None,
         rel: structures_pg::TListItem::new(liid_1.clone(), lid.clone()),
     };
     let list_item_1 =
@@ -72,6 +113,7 @@ async fn main() -> Result<(), Error> {
     let list_item_from_ui = structures_ddb::LdListItem {
         title: "New item 2".to_string(),
         description: Some("Some long description 2".to_string()),
+        due: None,
         rel: structures_pg::TListItem::new(liid_2.clone(), lid.clone()),
     };
     let list_item_2 =
@@ -88,6 +130,7 @@ async fn main() -> Result<(), Error> {
     let list_item_from_ui = structures_ddb::LdListItem {
         title: "New item 1 - still".to_string(),
         description: Some("Some long description - modified".to_string()),
+        due: None,
         rel: structures_pg::TListItem::new(liid_1.clone(), lid.clone()),
     };
     let list_item_1a =
@@ -102,6 +145,16 @@ async fn main() -> Result<(), Error> {
     assert_ne!(list_item_1a.title, list_item_1.title); // checks if the title changed
     assert_ne!(list_item_1a.description, list_item_1.description); // checks if the description changed
 
+    // download the list in the format picked from the Accept header
+    let rendered = list_export::render_list(lid, "text/markdown, application/json;q=0.5", &ddb_client).await;
+
+    // check if the list was rendered as Markdown
+    assert!(rendered.is_ok());
+    let rendered = rendered.unwrap().unwrap();
+    assert_eq!(rendered.content_type, "text/markdown; charset=utf-8");
+    assert!(rendered.body.contains("- New item 2"));
+    debug!("Rendered list:\n{}", rendered.body);
+
     // delete items one by one
     let list_del_1 =
         structures_ddb::LdListItem::del_list_item_ddb(lid, liid_1.clone(), &ddb_client, &pg_client)
@@ -112,6 +165,10 @@ async fn main() -> Result<(), Error> {
         assert_ne!(item_remaining.rel.liid, liid_1);
     }
 
+    drop(request_span);
+    metrics::flush();
+    trace::flush();
+
     Ok(())
 }
 
@@ -0,0 +1,158 @@
+use chrono::Utc;
+use rusoto_dynamodb::ConsumedCapacity;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env::var;
+use std::time::Duration;
+
+#[path = "./metrics_test.rs"]
+pub(crate) mod tests_metrics;
+
+/// Env var with the CloudWatch namespace for the metrics.
+pub(crate) const EV_METRICS_NAMESPACE: &str = "METRICS_NAMESPACE";
+const DEFAULT_NAMESPACE: &str = "ld-lambdas";
+
+/// CloudWatch accepts up to 100 values per metric in a single EMF document.
+const MAX_VALUES_PER_METRIC: usize = 100;
+
+const METRIC_LATENCY: &str = "Latency";
+const METRIC_ERRORS: &str = "Errors";
+const METRIC_CONSUMED_CAPACITY: &str = "ConsumedCapacity";
+
+/// Metrics of the current request, keyed by the operation, e.g. `ld_get_tlist` or `tlist.put_item`.
+/// Kept per thread and written out by `flush`.
+#[derive(Debug, Default)]
+pub(crate) struct Recorded {
+    latency_ms: BTreeMap<String, Vec<f64>>,
+    consumed_capacity: BTreeMap<String, Vec<f64>>,
+    /// The number of failed attempts by operation and error kind.
+    errors: BTreeMap<(String, String), u32>,
+}
+
+thread_local! {
+    pub(crate) static RECORDED: RefCell<Recorded> = RefCell::new(Recorded::default());
+}
+
+/// Records how long a PG function or a DDB operation took, including retries.
+pub(crate) fn record_latency(operation: &str, latency: Duration) {
+    let full = RECORDED.with(|r| {
+        let mut r = r.borrow_mut();
+        let values = r.latency_ms.entry(operation.to_string()).or_default();
+        values.push(latency.as_secs_f64() * 1000.0);
+        values.len() >= MAX_VALUES_PER_METRIC
+    });
+    if full {
+        flush();
+    }
+}
+
+/// Records a failed attempt of the operation. `kind` is the error, e.g. `ProvisionedThroughputExceeded`.
+pub(crate) fn record_error(operation: &str, kind: &str) {
+    RECORDED.with(|r| {
+        *r.borrow_mut()
+            .errors
+            .entry((operation.to_string(), kind.to_string()))
+            .or_default() += 1
+    });
+}
+
+/// Records the capacity units DDB reported for the operation. Requests must set `return_consumed_capacity`
+/// for DDB to report them.
+pub(crate) fn record_capacity<'a>(operation: &str, capacity: impl IntoIterator<Item = &'a ConsumedCapacity>) {
+    let units: f64 = capacity.into_iter().filter_map(|c| c.capacity_units).sum();
+    let full = RECORDED.with(|r| {
+        let mut r = r.borrow_mut();
+        let values = r.consumed_capacity.entry(operation.to_string()).or_default();
+        values.push(units);
+        values.len() >= MAX_VALUES_PER_METRIC
+    });
+    if full {
+        flush();
+    }
+}
+
+/// Writes all recorded metrics to stdout in CloudWatch Embedded Metric Format and resets them.
+/// Called by the handler at the end of every request and whenever a metric has collected 100 values.
+pub(crate) fn flush() {
+    let recorded = RECORDED.with(|r| r.replace(Recorded::default()));
+    let namespace = var(EV_METRICS_NAMESPACE).unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string());
+    for document in emf_documents(recorded, &namespace, Utc::now().timestamp_millis()) {
+        println!("{}", document);
+    }
+}
+
+/// Builds one EMF document per operation with its latency and capacity values and one per operation and error
+/// kind with the error count. CloudWatch turns the lists of values into statistics and percentiles.
+pub(crate) fn emf_documents(recorded: Recorded, namespace: &str, timestamp: i64) -> Vec<Value> {
+    let Recorded {
+        latency_ms,
+        mut consumed_capacity,
+        errors,
+    } = recorded;
+    let mut documents = Vec::new();
+
+    for (operation, latency) in latency_ms {
+        let mut metrics = vec![(METRIC_LATENCY, "Milliseconds", latency)];
+        if let Some(capacity) = consumed_capacity.remove(&operation) {
+            metrics.push((METRIC_CONSUMED_CAPACITY, "Count", capacity));
+        }
+        documents.push(emf_document(namespace, timestamp, &[("Operation", &operation)], metrics));
+    }
+    for (operation, capacity) in consumed_capacity {
+        let metrics = vec![(METRIC_CONSUMED_CAPACITY, "Count", capacity)];
+        documents.push(emf_document(namespace, timestamp, &[("Operation", &operation)], metrics));
+    }
+    for ((operation, kind), count) in errors {
+        let metrics = vec![(METRIC_ERRORS, "Count", vec![count as f64])];
+        let dimensions = [("Operation", operation.as_str()), ("ErrorKind", kind.as_str())];
+        documents.push(emf_document(namespace, timestamp, &dimensions, metrics));
+    }
+
+    documents
+}
+
+/// A single EMF document with one dimension set. See
+/// https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html
+fn emf_document(
+    namespace: &str,
+    timestamp: i64,
+    dimensions: &[(&str, &str)],
+    metrics: Vec<(&str, &str, Vec<f64>)>,
+) -> Value {
+    let mut document = Map::new();
+    document.insert(
+        "_aws".to_string(),
+        json!({
+            "Timestamp": timestamp,
+            "CloudWatchMetrics": [{
+                "Namespace": namespace,
+                "Dimensions": [dimensions.iter().map(|(name, _)| *name).collect::<Vec<&str>>()],
+                "Metrics": metrics.iter().map(|(name, unit, _)| json!({"Name": name, "Unit": unit})).collect::<Vec<Value>>(),
+            }],
+        }),
+    );
+    for (name, value) in dimensions {
+        document.insert(name.to_string(), Value::from(*value));
+    }
+    for (name, _, values) in metrics {
+        let value = match values.as_slice() {
+            [single] => Value::from(*single),
+            _ => Value::from(values),
+        };
+        document.insert(name.to_string(), value);
+    }
+
+    Value::Object(document)
+}
+
+/// Returns the name of the PG function called by the statement, e.g. `ld_get_tlist` for
+/// `select * from ld_get_tlist($1::UUID)`, or the whole statement if it doesn't call a function.
+pub(crate) fn pg_operation(statement: &str) -> &str {
+    statement
+        .find(" from ")
+        .map(|i| &statement[i + " from ".len()..])
+        .and_then(|rest| rest.find('(').map(|end| rest[..end].trim()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or(statement)
+}
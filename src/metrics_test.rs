@@ -0,0 +1,60 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_metrics {
+    use crate::metrics::*;
+    use rusoto_dynamodb::ConsumedCapacity;
+    use std::time::Duration;
+
+    fn capacity(units: f64) -> ConsumedCapacity {
+        ConsumedCapacity {
+            capacity_units: Some(units),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_emf_documents() {
+        // metrics are kept per thread, so this test sees only its own
+        record_latency("tlist.get_item", Duration::from_millis(12));
+        record_latency("tlist.get_item", Duration::from_millis(30));
+        record_capacity("tlist.get_item", Some(&capacity(0.5)));
+        record_capacity("tlist.batch_write_item", [capacity(1.0), capacity(2.0)].iter());
+        record_error("ld_get_tlist", "40001");
+        record_error("ld_get_tlist", "40001");
+
+        let recorded = RECORDED.with(|r| r.replace(Recorded::default()));
+        let documents = emf_documents(recorded, "test-ns", 1_600_000_000_000);
+        assert_eq!(documents.len(), 3);
+
+        // latency and capacity of the same operation share a document
+        let get = &documents[0];
+        assert_eq!(get["Operation"], "tlist.get_item");
+        assert_eq!(get["Latency"], serde_json::json!([12.0, 30.0]));
+        assert_eq!(get["ConsumedCapacity"], 0.5);
+        let aws = &get["_aws"];
+        assert_eq!(aws["Timestamp"], 1_600_000_000_000i64);
+        assert_eq!(aws["CloudWatchMetrics"][0]["Namespace"], "test-ns");
+        assert_eq!(aws["CloudWatchMetrics"][0]["Dimensions"], serde_json::json!([["Operation"]]));
+        assert_eq!(aws["CloudWatchMetrics"][0]["Metrics"][0]["Unit"], "Milliseconds");
+
+        // capacity of batches is summed per call
+        assert_eq!(documents[1]["Operation"], "tlist.batch_write_item");
+        assert_eq!(documents[1]["ConsumedCapacity"], 3.0);
+
+        // errors are counted by kind
+        let errors = &documents[2];
+        assert_eq!(errors["Errors"], 2.0);
+        assert_eq!(errors["ErrorKind"], "40001");
+        assert_eq!(
+            errors["_aws"]["CloudWatchMetrics"][0]["Dimensions"],
+            serde_json::json!([["Operation", "ErrorKind"]])
+        );
+    }
+
+    #[test]
+    fn test_pg_operation() {
+        assert_eq!(pg_operation("select * from ld_get_tlist($1::UUID)"), "ld_get_tlist");
+        assert_eq!(pg_operation("select * from ld_add_tlist ($1::UUID, $2)"), "ld_add_tlist");
+        assert_eq!(pg_operation("select 1"), "select 1");
+    }
+}
@@ -0,0 +1,613 @@
+use crate::metrics;
+use crate::retry::{self, RetryPolicy};
+use crate::structures_ddb::LdListItem;
+use crate::structures_pg;
+use crate::trace;
+use crate::utils;
+use crate::webhooks::WebhookEvent;
+use chrono::{DateTime, Duration, Utc};
+use dynomite::{
+    dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, UpdateItemError},
+    Attribute, FromAttributes, Item,
+};
+use futures::stream::{self, StreamExt};
+use log::{self, debug, error, info, warn};
+use rusoto_apigatewaymanagementapi::{
+    ApiGatewayManagementApi, ApiGatewayManagementApiClient, DeleteConnectionRequest, PostToConnectionError,
+    PostToConnectionRequest,
+};
+use rusoto_core::{Region, RusotoError};
+use serde::{Deserialize, Serialize};
+use std::env::var;
+use uuid::Uuid;
+
+#[path = "./realtime_test.rs"]
+pub(crate) mod tests_realtime;
+
+const TABLE_NAME_TWS_CONNECTION: &str = "tws_connection";
+const TABLE_KEY_FOR_TWS_CONNECTION: &str = "connection_id";
+const TABLE_NAME_TLIST_WS: &str = "tlist_ws";
+const TABLE_KEY_FOR_TLIST_WS: &str = "lid";
+const TABLE_SORT_KEY_FOR_TLIST_WS: &str = "connection_id";
+const ERR_MSG_INVALID_MESSAGE: &str = "Invalid message. Expected {\"action\": \"subscribe\", \"lid\": \"...\"}.";
+const ERR_MSG_NOT_CONNECTED: &str = "The connection is not registered. Reconnect and try again.";
+const ERR_MSG_NO_ACCESS: &str = "The list doesn't exist or is not shared with you.";
+const ERR_MSG_TOO_MANY_SUBSCRIPTIONS: &str = "Too many lists subscribed to on this connection.";
+const ERR_MSG_WS_FAILED: &str = "Failed to process the message. Try again.";
+const ERR_MSG_DELETE_CONNECTIONS_FAILED: &str = "Failed to delete the WebSocket connections. Try again.";
+
+/// Env var with the URL of the API Gateway management API for posting to connections,
+/// e.g. `https://{api-id}.execute-api.{region}.amazonaws.com/{stage}`. No changes are pushed if it is not set.
+const EV_WS_API_ENDPOINT: &str = "WS_API_ENDPOINT";
+
+/// API Gateway closes WebSocket connections after 2 hours. DDB deletes records left behind via TTL on `expires_on`.
+const CONNECTION_TTL_HOURS: i64 = 3;
+
+/// The number of times a change to the subscriptions of a connection is tried if it conflicts with another one.
+const UPDATE_ATTEMPTS: usize = 3;
+
+/// The number of lists a single connection can subscribe to.
+pub(crate) const MAX_SUBSCRIPTIONS: usize = 50;
+
+/// The number of connections a change is pushed to. A list is rarely open in more places than this.
+const MAX_SUBSCRIBERS: i64 = 500;
+
+/// The number of connections a change is pushed to at the same time. Keeps the push short without opening
+/// hundreds of requests to API Gateway at once.
+const MAX_CONCURRENT_PUSHES: usize = 25;
+
+/// A WebSocket event as Lambda receives it from API Gateway.
+#[derive(Deserialize, Debug)]
+pub(crate) struct WsEvent {
+    #[serde(rename = "requestContext")]
+    pub request_context: WsRequestContext,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct WsRequestContext {
+    /// `$connect`, `$disconnect` or the `action` of the message
+    #[serde(rename = "routeKey")]
+    pub route_key: String,
+    #[serde(rename = "connectionId")]
+    pub connection_id: String,
+    /// Set by the authorizer of `$connect` route
+    #[serde(default)]
+    pub authorizer: Option<WsAuthorizer>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct WsAuthorizer {
+    /// The user ID
+    #[serde(rename = "principalId", default)]
+    pub principal_id: Option<String>,
+}
+
+/// A message sent by the client over an open connection.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub(crate) enum WsMessage {
+    Subscribe { lid: Uuid },
+    Unsubscribe { lid: Uuid },
+}
+
+/// A decoded WebSocket event.
+#[derive(Debug, PartialEq)]
+pub(crate) enum WsRequest {
+    /// The user is None if the authorizer did not identify the user.
+    Connect {
+        connection_id: String,
+        user_id: Option<Uuid>,
+    },
+    Disconnect {
+        connection_id: String,
+    },
+    Message {
+        connection_id: String,
+        message: WsMessage,
+    },
+}
+
+/// The response to API Gateway. The body is sent to the client for messages only.
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct WsResponse {
+    #[serde(rename = "statusCode")]
+    pub status_code: u16,
+    pub body: String,
+}
+
+/// An open connection, stored in DDB table `tws_connection`.
+#[derive(Item, Debug, Clone)]
+pub(crate) struct WsConnection {
+    #[dynomite(partition_key)]
+    pub connection_id: String,
+    pub user_id: Uuid,
+    /// Lists the connection is subscribed to, to remove the subscriptions on disconnect.
+    #[dynomite(default)]
+    pub lids: Vec<Uuid>,
+    pub connected_on_utc: DateTime<Utc>,
+    /// DDB TTL attribute in seconds since the epoch
+    pub expires_on: i64,
+}
+
+/// A connection subscribed to changes of a list, stored in DDB table `tlist_ws`.
+#[derive(Item, Debug, Clone)]
+pub(crate) struct ListSubscription {
+    #[dynomite(partition_key)]
+    pub lid: Uuid,
+    #[dynomite(sort_key)]
+    pub connection_id: String,
+    /// DDB TTL attribute in seconds since the epoch
+    pub expires_on: i64,
+}
+
+/// A change pushed to the subscribers of the list. Uses the same event names as webhooks.
+#[derive(Serialize, Debug)]
+pub(crate) struct ItemChangeMessage<'a> {
+    pub event: WebhookEvent,
+    pub lid: Uuid,
+    pub liid: Uuid,
+    /// The item after the change or as it was before it was deleted.
+    pub item: Option<&'a LdListItem>,
+}
+
+impl WsResponse {
+    fn new(status_code: u16, body: &str) -> Self {
+        Self {
+            status_code,
+            body: body.to_string(),
+        }
+    }
+
+    fn ok() -> Self {
+        Self::new(200, "")
+    }
+}
+
+/// Parses a Lambda event from API Gateway WebSocket API with `$request.body.action` route selection.
+pub(crate) fn parse_request(event: &str) -> Result<WsRequest, String> {
+    let event: WsEvent = serde_json::from_str(event).map_err(|e| {
+        error!("Invalid WebSocket event: {}", e);
+        format!("Invalid WebSocket event: {}", e)
+    })?;
+    let connection_id = event.request_context.connection_id;
+
+    match event.request_context.route_key.as_str() {
+        "$connect" => Ok(WsRequest::Connect {
+            connection_id,
+            user_id: event
+                .request_context
+                .authorizer
+                .and_then(|a| a.principal_id)
+                .and_then(|id| Uuid::parse_str(&id).ok()),
+        }),
+        "$disconnect" => Ok(WsRequest::Disconnect { connection_id }),
+        _ => {
+            let message = event
+                .body
+                .as_deref()
+                .and_then(|body| serde_json::from_str::<WsMessage>(body).ok())
+                .ok_or_else(|| ERR_MSG_INVALID_MESSAGE.to_string())?;
+            Ok(WsRequest::Message { connection_id, message })
+        }
+    }
+}
+
+/// Lambda handler for `$connect`, `$disconnect`, `subscribe` and `unsubscribe` routes.
+pub(crate) async fn handle_ws_event(
+    event: &str,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> WsResponse {
+    let request = match parse_request(event) {
+        Ok(v) => v,
+        Err(e) => return WsResponse::new(400, &e),
+    };
+    debug!("handle_ws_event {:?}", request);
+
+    let result = match request {
+        WsRequest::Connect { user_id: None, .. } => return WsResponse::new(401, "Unauthorized"),
+        WsRequest::Connect {
+            connection_id,
+            user_id: Some(user_id),
+        } => connect(connection_id, user_id, ddb_client).await,
+        WsRequest::Disconnect { connection_id } => disconnect(&connection_id, ddb_client).await,
+        WsRequest::Message {
+            connection_id,
+            message: WsMessage::Subscribe { lid },
+        } => subscribe(&connection_id, lid, ddb_client, pg_client).await,
+        WsRequest::Message {
+            connection_id,
+            message: WsMessage::Unsubscribe { lid },
+        } => unsubscribe(&connection_id, lid, ddb_client).await,
+    };
+
+    result.unwrap_or_else(|e| e)
+}
+
+/// Registers a new connection of the user.
+async fn connect(connection_id: String, user_id: Uuid, ddb_client: &DynamoDbClient) -> Result<WsResponse, WsResponse> {
+    info!("WebSocket connect {} for {}", connection_id, user_id);
+    let now = Utc::now();
+    let connection = WsConnection {
+        connection_id,
+        user_id,
+        lids: Vec::new(),
+        connected_on_utc: now,
+        expires_on: (now + Duration::hours(CONNECTION_TTL_HOURS)).timestamp(),
+    };
+    put_connection(connection, ddb_client).await?;
+
+    Ok(WsResponse::ok())
+}
+
+/// Removes the connection and all its subscriptions.
+async fn disconnect(connection_id: &str, ddb_client: &DynamoDbClient) -> Result<WsResponse, WsResponse> {
+    info!("WebSocket disconnect {}", connection_id);
+    let connection = match get_connection(connection_id, ddb_client).await? {
+        Some(v) => v,
+        None => return Ok(WsResponse::ok()),
+    };
+
+    for lid in connection.lids.iter() {
+        del_subscription(*lid, connection_id, ddb_client).await;
+    }
+    let del_input =
+        utils::build_ddb_del_input_by_str(TABLE_KEY_FOR_TWS_CONNECTION, connection_id, TABLE_NAME_TWS_CONNECTION);
+    let delete = || ddb_client.delete_item(del_input.clone());
+    let span = trace::ddb_span("tws_connection.delete_item", TABLE_NAME_TWS_CONNECTION);
+    match retry::with_retry_in(&RetryPolicy::default(), span, delete).await {
+        Ok(v) => metrics::record_capacity("tws_connection.delete_item", v.consumed_capacity.as_ref()),
+        Err(e) => error!("Failed to delete connection {}: {}", connection_id, e),
+    }
+
+    Ok(WsResponse::ok())
+}
+
+/// Subscribes the connection to item changes of the list. The user must own the list or have it shared with them.
+async fn subscribe(
+    connection_id: &str,
+    lid: Uuid,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<WsResponse, WsResponse> {
+    let connection = get_connection(connection_id, ddb_client)
+        .await?
+        .ok_or_else(|| WsResponse::new(410, ERR_MSG_NOT_CONNECTED))?;
+    info!("WebSocket subscribe {} to {} for {}", connection_id, lid, connection.user_id);
+
+    if connection.lids.contains(&lid) {
+        return Ok(WsResponse::ok());
+    }
+    if connection.lids.len() >= MAX_SUBSCRIPTIONS {
+        return Err(WsResponse::new(400, ERR_MSG_TOO_MANY_SUBSCRIPTIONS));
+    }
+    if !can_read_list(connection.user_id, lid, pg_client).await? {
+        return Err(WsResponse::new(403, ERR_MSG_NO_ACCESS));
+    }
+
+    // the list is added with conditions, so that concurrent messages cannot add it twice or go over the limit
+    let update = utils::UpdateExpressionBuilder::new()
+        .append(
+            "lids",
+            AttributeValue {
+                l: Some(vec![lid.into_attr()]),
+                ..Default::default()
+            },
+        )
+        .condition_exists(TABLE_KEY_FOR_TWS_CONNECTION)
+        .condition_not_contains("lids", lid.into_attr())
+        .condition_size_below("lids", MAX_SUBSCRIPTIONS);
+    if !update_connection(connection_id, update, ddb_client).await? {
+        // the connection was changed by another message or is gone
+        return match get_connection(connection_id, ddb_client).await? {
+            Some(v) if v.lids.contains(&lid) => Ok(WsResponse::ok()),
+            Some(_) => Err(WsResponse::new(400, ERR_MSG_TOO_MANY_SUBSCRIPTIONS)),
+            None => Err(WsResponse::new(410, ERR_MSG_NOT_CONNECTED)),
+        };
+    }
+
+    let subscription = ListSubscription {
+        lid,
+        connection_id: connection_id.to_string(),
+        expires_on: connection.expires_on,
+    };
+    let put_input = utils::build_ddb_put_input(subscription.into(), TABLE_NAME_TLIST_WS);
+    let put = || ddb_client.put_item(put_input.clone());
+    let span = trace::ddb_span("tlist_ws.put_item", TABLE_NAME_TLIST_WS);
+    match retry::with_retry_in(&RetryPolicy::default(), span, put).await {
+        Ok(v) => metrics::record_capacity("tlist_ws.put_item", v.consumed_capacity.as_ref()),
+        Err(e) => {
+            error!("Failed to subscribe {} to {}: {}", connection_id, lid, e);
+            return Err(WsResponse::new(500, ERR_MSG_WS_FAILED));
+        }
+    }
+
+    Ok(WsResponse::ok())
+}
+
+async fn unsubscribe(connection_id: &str, lid: Uuid, ddb_client: &DynamoDbClient) -> Result<WsResponse, WsResponse> {
+    info!("WebSocket unsubscribe {} from {}", connection_id, lid);
+    del_subscription(lid, connection_id, ddb_client).await;
+
+    // the list is removed by its position, which may be changed by a concurrent message before the update
+    for _ in 0..UPDATE_ATTEMPTS {
+        let position = match get_connection(connection_id, ddb_client).await? {
+            Some(connection) => connection.lids.iter().position(|l| *l == lid),
+            None => None,
+        };
+        let path = match position {
+            Some(i) => format!("lids[{}]", i),
+            None => return Ok(WsResponse::ok()),
+        };
+        let update = utils::UpdateExpressionBuilder::new()
+            .remove(&path)
+            .condition_eq(&path, lid.into_attr());
+        if update_connection(connection_id, update, ddb_client).await? {
+            return Ok(WsResponse::ok());
+        }
+    }
+
+    error!("Failed to remove {} from connection {}", lid, connection_id);
+    Err(WsResponse::new(500, ERR_MSG_WS_FAILED))
+}
+
+/// Pushes a change of the item to all connections subscribed to the list, up to `MAX_CONCURRENT_PUSHES` at a
+/// time. Connections that are gone are unsubscribed. Failures are logged, but not returned because the change is already saved.
+/// Called by `LdListItem::put_list_item_ddb` and `LdListItem::del_list_item_ddb`.
+pub(crate) async fn push_item_change(
+    event: WebhookEvent,
+    lid: Uuid,
+    liid: Uuid,
+    item: Option<&LdListItem>,
+    ddb_client: &DynamoDbClient,
+) {
+    let ws_client = match ws_client() {
+        Some(v) => v,
+        None => {
+            debug!("{} is not set - no WebSocket push", EV_WS_API_ENDPOINT);
+            return;
+        }
+    };
+
+    let connection_ids = get_subscribers(lid, ddb_client).await;
+    if connection_ids.is_empty() {
+        return;
+    }
+    let message = match serde_json::to_string(&ItemChangeMessage { event, lid, liid, item }) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Cannot serialize the change of {}: {}", liid, e);
+            return;
+        }
+    };
+    debug!("Pushing {} of {} to {} connections", event.as_str(), liid, connection_ids.len());
+
+    let ws_client = &ws_client;
+    let message = &message;
+    stream::iter(connection_ids)
+        .for_each_concurrent(MAX_CONCURRENT_PUSHES, |connection_id| async move {
+            match ws_client
+                .post_to_connection(PostToConnectionRequest {
+                    connection_id: connection_id.clone(),
+                    data: message.clone().into(),
+                })
+                .await
+            {
+                Ok(_) => {}
+                Err(RusotoError::Service(PostToConnectionError::Gone(_))) => {
+                    debug!("Connection {} is gone", connection_id);
+                    del_subscription(lid, &connection_id, ddb_client).await;
+                }
+                Err(e) => warn!("Failed to push to {}: {}", connection_id, e),
+            }
+        })
+        .await;
+}
+
+/// Removes all connections of the user with their subscriptions and all subscriptions to the lists.
+/// Open connections are closed if `WS_API_ENDPOINT` is set. Called when the user account is deleted.
+/// Returns the number of removed connections.
+pub(crate) async fn delete_user_connections(
+    user_id: Uuid,
+    lids: &[Uuid],
+    ddb_client: &DynamoDbClient,
+) -> Result<u64, String> {
+    debug!("delete_user_connections for {}", user_id);
+
+    // there is no index by user, but the table only has connections of the last few hours
+    let mut input = utils::build_ddb_scan_input(
+        "user_id",
+        &user_id,
+        &[
+            TABLE_KEY_FOR_TWS_CONNECTION,
+            "user_id",
+            "lids",
+            "connected_on_utc",
+            "expires_on",
+        ],
+        TABLE_NAME_TWS_CONNECTION,
+    );
+    let mut connections: Vec<WsConnection> = Vec::new();
+    loop {
+        let scan = || ddb_client.scan(input.clone());
+        let span = trace::ddb_span("tws_connection.scan", TABLE_NAME_TWS_CONNECTION);
+        let output = match retry::with_retry_in(&RetryPolicy::default(), span, scan).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("DDB error {}", e);
+                return Err(ERR_MSG_DELETE_CONNECTIONS_FAILED.to_string());
+            }
+        };
+        metrics::record_capacity("tws_connection.scan", output.consumed_capacity.as_ref());
+        connections.extend(
+            output
+                .items
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|attrs| WsConnection::from_attrs(attrs).ok()),
+        );
+        match output.last_evaluated_key {
+            Some(key) if !key.is_empty() => input.exclusive_start_key = Some(key),
+            _ => break,
+        }
+    }
+
+    let ws_client = ws_client();
+    for connection in connections.iter() {
+        if let Some(ws_client) = ws_client.as_ref() {
+            let request = DeleteConnectionRequest {
+                connection_id: connection.connection_id.clone(),
+            };
+            if let Err(e) = ws_client.delete_connection(request).await {
+                debug!("Failed to close connection {}: {}", connection.connection_id, e);
+            }
+        }
+        disconnect(&connection.connection_id, ddb_client)
+            .await
+            .map_err(|_| ERR_MSG_DELETE_CONNECTIONS_FAILED.to_string())?;
+    }
+
+    // connections of other users to the lists that were shared with them
+    for lid in lids {
+        for connection_id in get_subscribers(*lid, ddb_client).await {
+            del_subscription(*lid, &connection_id, ddb_client).await;
+        }
+    }
+
+    Ok(connections.len() as u64)
+}
+
+/// Returns IDs of connections subscribed to the list.
+pub(crate) async fn get_subscribers(lid: Uuid, ddb_client: &DynamoDbClient) -> Vec<String> {
+    let input = utils::build_ddb_query_input(
+        TABLE_KEY_FOR_TLIST_WS,
+        &lid,
+        &[TABLE_KEY_FOR_TLIST_WS, TABLE_SORT_KEY_FOR_TLIST_WS, "expires_on"],
+        false,
+        MAX_SUBSCRIBERS,
+        TABLE_NAME_TLIST_WS,
+    );
+
+    let query = || ddb_client.query(input.clone());
+    let span = trace::ddb_span("tlist_ws.query", TABLE_NAME_TLIST_WS);
+    match retry::with_retry_in(&RetryPolicy::default(), span, query).await {
+        Ok(output) => {
+            metrics::record_capacity("tlist_ws.query", output.consumed_capacity.as_ref());
+            output
+                .items
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|attrs| ListSubscription::from_attrs(attrs).ok())
+                .map(|s| s.connection_id)
+                .collect()
+        }
+        Err(e) => {
+            error!("DDB error {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// The user can read the list if they own it or it is shared with them. PG errors are returned as 500,
+/// so that the client retries instead of being told it has no access.
+async fn can_read_list(user_id: Uuid, lid: Uuid, pg_client: &tokio_postgres::Client) -> Result<bool, WsResponse> {
+    let failed = |_| WsResponse::new(500, ERR_MSG_WS_FAILED);
+    match structures_pg::get_t_list(lid, pg_client).await.map_err(failed)? {
+        Some(list) if list.user_id == Some(user_id) => Ok(true),
+        Some(_) => Ok(structures_pg::get_user_shared_list(user_id, lid, pg_client)
+            .await
+            .map_err(failed)?
+            .is_some()),
+        None => Ok(false),
+    }
+}
+
+async fn get_connection(connection_id: &str, ddb_client: &DynamoDbClient) -> Result<Option<WsConnection>, WsResponse> {
+    let get_input =
+        utils::build_ddb_get_input_by_str(TABLE_KEY_FOR_TWS_CONNECTION, connection_id, TABLE_NAME_TWS_CONNECTION);
+    let get = || ddb_client.get_item(get_input.clone());
+    let span = trace::ddb_span("tws_connection.get_item", TABLE_NAME_TWS_CONNECTION);
+    match retry::with_retry_in(&RetryPolicy::default(), span, get).await {
+        Ok(output) => {
+            metrics::record_capacity("tws_connection.get_item", output.consumed_capacity.as_ref());
+            Ok(output.item.and_then(|attrs| WsConnection::from_attrs(attrs).ok()))
+        }
+        Err(e) => {
+            error!("DDB error {}", e);
+            Err(WsResponse::new(500, ERR_MSG_WS_FAILED))
+        }
+    }
+}
+
+async fn put_connection(connection: WsConnection, ddb_client: &DynamoDbClient) -> Result<(), WsResponse> {
+    let connection_id = connection.connection_id.clone();
+    let put_input = utils::build_ddb_put_input(connection.into(), TABLE_NAME_TWS_CONNECTION);
+    let put = || ddb_client.put_item(put_input.clone());
+    let span = trace::ddb_span("tws_connection.put_item", TABLE_NAME_TWS_CONNECTION);
+    match retry::with_retry_in(&RetryPolicy::default(), span, put).await {
+        Ok(v) => metrics::record_capacity("tws_connection.put_item", v.consumed_capacity.as_ref()),
+        Err(e) => {
+            error!("Failed to save connection {}: {}", connection_id, e);
+            return Err(WsResponse::new(500, ERR_MSG_WS_FAILED));
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs UpdateItem on the connection. Returns false if a condition of the update failed.
+async fn update_connection(
+    connection_id: &str,
+    update: utils::UpdateExpressionBuilder,
+    ddb_client: &DynamoDbClient,
+) -> Result<bool, WsResponse> {
+    let update_input = utils::build_ddb_update_input_by_str(
+        TABLE_KEY_FOR_TWS_CONNECTION,
+        connection_id,
+        update,
+        TABLE_NAME_TWS_CONNECTION,
+    );
+    let update = || ddb_client.update_item(update_input.clone());
+    let span = trace::ddb_span("tws_connection.update_item", TABLE_NAME_TWS_CONNECTION);
+    match retry::with_retry_in(&RetryPolicy::default(), span, update).await {
+        Ok(v) => {
+            metrics::record_capacity("tws_connection.update_item", v.consumed_capacity.as_ref());
+            Ok(true)
+        }
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => {
+            debug!("UpdateItem condition failed for connection {}", connection_id);
+            Ok(false)
+        }
+        Err(e) => {
+            error!("Failed to update connection {}: {}", connection_id, e);
+            Err(WsResponse::new(500, ERR_MSG_WS_FAILED))
+        }
+    }
+}
+
+async fn del_subscription(lid: Uuid, connection_id: &str, ddb_client: &DynamoDbClient) {
+    let del_input = utils::build_ddb_del_input_with_sort_key(
+        TABLE_KEY_FOR_TLIST_WS,
+        lid,
+        TABLE_SORT_KEY_FOR_TLIST_WS,
+        connection_id,
+        TABLE_NAME_TLIST_WS,
+    );
+    let delete = || ddb_client.delete_item(del_input.clone());
+    let span = trace::ddb_span("tlist_ws.delete_item", TABLE_NAME_TLIST_WS);
+    match retry::with_retry_in(&RetryPolicy::default(), span, delete).await {
+        Ok(v) => metrics::record_capacity("tlist_ws.delete_item", v.consumed_capacity.as_ref()),
+        Err(e) => error!("Failed to unsubscribe {} from {}: {}", connection_id, lid, e),
+    }
+}
+
+/// Returns a client for posting to WebSocket connections or None if the endpoint is not configured.
+fn ws_client() -> Option<ApiGatewayManagementApiClient> {
+    let endpoint = var(EV_WS_API_ENDPOINT).ok()?;
+    Some(ApiGatewayManagementApiClient::new(Region::Custom {
+        name: Region::default().name().to_string(),
+        endpoint,
+    }))
+}
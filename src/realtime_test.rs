@@ -0,0 +1,160 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_realtime {
+    use crate::realtime::*;
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::LdList;
+    use crate::structures_pg::{del_t_user, put_t_user};
+    use log::{self, debug};
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    /// Recorded API Gateway events of a client connecting, subscribing to a list and disconnecting
+    const CONNECT_EVENT: &str = include_str!("./test_fixtures/ws_connect_event.json");
+    const SUBSCRIBE_EVENT: &str = include_str!("./test_fixtures/ws_subscribe_event.json");
+    const DISCONNECT_EVENT: &str = include_str!("./test_fixtures/ws_disconnect_event.json");
+    const CONNECTION_ID: &str = "NvXf1dKwIAMCJhQ=";
+
+    /// Replaces the connection ID, the user ID and the list ID in the fixture.
+    fn with_ids(event: &str, connection_id: &str, user_id: Option<Uuid>, lid: Uuid) -> String {
+        let mut event: Value = serde_json::from_str(event).unwrap();
+        event["requestContext"]["connectionId"] = Value::from(connection_id);
+        if event["requestContext"]["authorizer"].is_object() {
+            event["requestContext"]["authorizer"]["principalId"] = match user_id {
+                Some(v) => Value::from(v.to_string()),
+                None => Value::Null,
+            };
+        }
+        if event["body"].is_string() {
+            event["body"] = Value::from(format!("{{\"action\":\"subscribe\",\"lid\":\"{}\"}}", lid));
+        }
+        event.to_string()
+    }
+
+    #[test]
+    fn test_parse_ws_events() {
+        let user_id = Uuid::parse_str("0b1f6a3e-2c4d-4e8f-9a7b-5c6d7e8f9a0b").unwrap();
+        let lid = Uuid::parse_str("7d6b2b0c-58a4-4d0a-9d1e-3a1f0c2d4e51").unwrap();
+
+        assert_eq!(
+            parse_request(CONNECT_EVENT).unwrap(),
+            WsRequest::Connect {
+                connection_id: CONNECTION_ID.to_string(),
+                user_id: Some(user_id),
+            }
+        );
+        assert_eq!(
+            parse_request(SUBSCRIBE_EVENT).unwrap(),
+            WsRequest::Message {
+                connection_id: CONNECTION_ID.to_string(),
+                message: WsMessage::Subscribe { lid },
+            }
+        );
+        assert_eq!(
+            parse_request(DISCONNECT_EVENT).unwrap(),
+            WsRequest::Disconnect {
+                connection_id: CONNECTION_ID.to_string(),
+            }
+        );
+
+        // connections without a user are accepted by the parser and rejected by the handler
+        let anonymous = with_ids(CONNECT_EVENT, CONNECTION_ID, None, lid);
+        assert_eq!(
+            parse_request(&anonymous).unwrap(),
+            WsRequest::Connect {
+                connection_id: CONNECTION_ID.to_string(),
+                user_id: None,
+            }
+        );
+
+        // messages other than subscribe and unsubscribe
+        let unsubscribe = SUBSCRIBE_EVENT.replace("\\\"subscribe\\\"", "\\\"unsubscribe\\\"");
+        assert!(matches!(
+            parse_request(&unsubscribe).unwrap(),
+            WsRequest::Message {
+                message: WsMessage::Unsubscribe { .. },
+                ..
+            }
+        ));
+        let unknown = SUBSCRIBE_EVENT.replace("\\\"subscribe\\\"", "\\\"delete\\\"");
+        assert!(parse_request(&unknown).is_err());
+        assert!(parse_request("{}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ws_subscriptions() {
+        debug!("test_ws_subscriptions started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // a user with a list and a list of another user
+        let user_email = ["test_ws_subscriptions@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let user_id = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user")
+            .user_id;
+        let other_email = ["test_ws_subscriptions@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let other_id = put_t_user(&other_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user")
+            .user_id;
+        let lid = Uuid::new_v4();
+        test_helpers::create_random_list(lid, user_id, &ddb_client, &pg_client).await;
+        let other_lid = Uuid::new_v4();
+        test_helpers::create_random_list(other_lid, other_id, &ddb_client, &pg_client).await;
+
+        // anonymous connections are rejected
+        let connection_id = Uuid::new_v4().to_string();
+        let event = with_ids(CONNECT_EVENT, &connection_id, None, lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 401);
+
+        // subscribe to own list, but not to someone else's
+        let event = with_ids(CONNECT_EVENT, &connection_id, Some(user_id), lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 200);
+        let event = with_ids(SUBSCRIBE_EVENT, &connection_id, Some(user_id), lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 200);
+        assert_eq!(get_subscribers(lid, &ddb_client).await, vec![connection_id.clone()]);
+        let event = with_ids(SUBSCRIBE_EVENT, &connection_id, Some(user_id), other_lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 403);
+        assert!(get_subscribers(other_lid, &ddb_client).await.is_empty());
+
+        // a connection that was never registered cannot subscribe
+        let event = with_ids(SUBSCRIBE_EVENT, "unknown-connection", Some(user_id), lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 410);
+
+        // disconnecting removes the subscriptions
+        let event = with_ids(DISCONNECT_EVENT, &connection_id, Some(user_id), lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 200);
+        assert!(get_subscribers(lid, &ddb_client).await.is_empty());
+
+        // deleting the account removes the connections of the user with their subscriptions
+        let event = with_ids(CONNECT_EVENT, &connection_id, Some(user_id), lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 200);
+        let event = with_ids(SUBSCRIBE_EVENT, &connection_id, Some(user_id), lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 200);
+        assert_eq!(delete_user_connections(user_id, &[lid], &ddb_client).await, Ok(1));
+        assert!(get_subscribers(lid, &ddb_client).await.is_empty());
+        let event = with_ids(SUBSCRIBE_EVENT, &connection_id, Some(user_id), lid);
+        assert_eq!(handle_ws_event(&event, &ddb_client, &pg_client).await.status_code, 410);
+
+        // clean up
+        for lid in [lid, other_lid].iter() {
+            LdList::get_from_ddb(lid, &ddb_client)
+                .await
+                .unwrap()
+                .unwrap()
+                .delete_from_all_dbs(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to delete the list");
+        }
+        del_t_user(user_id, &pg_client)
+            .await
+            .expect("Failed to delete the user");
+        del_t_user(other_id, &pg_client)
+            .await
+            .expect("Failed to delete the user");
+    }
+}
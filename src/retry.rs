@@ -0,0 +1,346 @@
+use crate::context;
+use crate::logging;
+use crate::metrics;
+use crate::trace::{self, Span, SpanKind};
+use log::{self, debug, warn};
+use rand::Rng;
+use rusoto_core::request::BufferedHttpResponse;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    BatchGetItemError, BatchGetItemOutput, BatchWriteItemError, BatchWriteItemOutput, DeleteItemError,
+    DeleteItemOutput, GetItemError, GetItemOutput, PutItemError, PutItemOutput, QueryError, QueryOutput, ScanError,
+    ScanOutput, UpdateItemError, UpdateItemOutput,
+};
+use std::error::Error;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Row};
+
+#[path = "./retry_test.rs"]
+pub(crate) mod tests_retry;
+
+/// Retries stop this long before the Lambda times out to leave time for returning an error to the caller.
+pub(crate) const DEADLINE_MARGIN: Duration = Duration::from_millis(500);
+
+/// PG errors that go away if the same statement is run again, in addition to all connection exceptions (class 08).
+const RETRYABLE_SQL_STATES: [SqlState; 5] = [
+    SqlState::T_R_SERIALIZATION_FAILURE,
+    SqlState::T_R_DEADLOCK_DETECTED,
+    SqlState::LOCK_NOT_AVAILABLE,
+    SqlState::TOO_MANY_CONNECTIONS,
+    SqlState::CANNOT_CONNECT_NOW,
+];
+
+/// Tells transient errors that are worth another attempt from permanent ones.
+pub(crate) trait Retryable {
+    fn is_retryable(&self) -> bool;
+    /// The kind of error in the error count metrics, e.g. `ConditionalCheckFailed` or SQL state `40001`.
+    fn kind(&self) -> String;
+}
+
+/// The name of the enum variant at the start of `{:?}` output, e.g. `ConditionalCheckFailed` for
+/// `ConditionalCheckFailed("..")`.
+fn variant_name(debug: &str) -> String {
+    debug
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
+
+/// Throttling is not a service error of any operation, so it arrives as an unknown response.
+fn is_throttling(response: &BufferedHttpResponse) -> bool {
+    response.status.as_u16() == 429 || response.body_as_str().contains("ThrottlingException")
+}
+
+/// Throttling and internal errors of DDB operations are transient. Conditional check failures,
+/// missing tables and validation errors are not.
+macro_rules! impl_retryable_ddb_error {
+    ($error:ident: $($variant:ident),+) => {
+        impl Retryable for $error {
+            fn is_retryable(&self) -> bool {
+                matches!(self, $($error::$variant(_))|+)
+            }
+
+            fn kind(&self) -> String {
+                variant_name(&format!("{:?}", self))
+            }
+        }
+    };
+}
+
+impl_retryable_ddb_error!(BatchGetItemError: InternalServerError, ProvisionedThroughputExceeded, RequestLimitExceeded);
+impl_retryable_ddb_error!(BatchWriteItemError: InternalServerError, ProvisionedThroughputExceeded, RequestLimitExceeded);
+impl_retryable_ddb_error!(GetItemError: InternalServerError, ProvisionedThroughputExceeded, RequestLimitExceeded);
+impl_retryable_ddb_error!(QueryError: InternalServerError, ProvisionedThroughputExceeded, RequestLimitExceeded);
+impl_retryable_ddb_error!(ScanError: InternalServerError, ProvisionedThroughputExceeded, RequestLimitExceeded);
+impl_retryable_ddb_error!(
+    DeleteItemError: InternalServerError,
+    ProvisionedThroughputExceeded,
+    RequestLimitExceeded,
+    TransactionConflict
+);
+impl_retryable_ddb_error!(
+    PutItemError: InternalServerError,
+    ProvisionedThroughputExceeded,
+    RequestLimitExceeded,
+    TransactionConflict
+);
+impl_retryable_ddb_error!(
+    UpdateItemError: InternalServerError,
+    ProvisionedThroughputExceeded,
+    RequestLimitExceeded,
+    TransactionConflict
+);
+
+impl<E: Retryable> Retryable for RusotoError<E> {
+    fn is_retryable(&self) -> bool {
+        match self {
+            RusotoError::Service(e) => e.is_retryable(),
+            RusotoError::HttpDispatch(_) => true,
+            RusotoError::Unknown(response) => response.status.is_server_error() || is_throttling(response),
+            _ => false,
+        }
+    }
+
+    fn kind(&self) -> String {
+        match self {
+            RusotoError::Service(e) => e.kind(),
+            RusotoError::Unknown(response) if is_throttling(response) => "ThrottlingException".to_string(),
+            RusotoError::Unknown(response) => format!("Http{}", response.status.as_u16()),
+            RusotoError::HttpDispatch(_) => "HttpDispatch".to_string(),
+            RusotoError::Credentials(_) => "Credentials".to_string(),
+            RusotoError::Validation(_) => "Validation".to_string(),
+            RusotoError::ParseError(_) => "ParseError".to_string(),
+            RusotoError::Blocking => "Blocking".to_string(),
+        }
+    }
+}
+
+impl Retryable for tokio_postgres::Error {
+    fn is_retryable(&self) -> bool {
+        match self.code() {
+            Some(code) => RETRYABLE_SQL_STATES.contains(code) || code.code().starts_with("08"),
+            // I/O errors may go away, but a closed connection stays closed and has no source
+            None => self.source().and_then(|e| e.downcast_ref::<std::io::Error>()).is_some(),
+        }
+    }
+
+    /// The SQL state or, for errors without one, the kind in `{:?}` output, e.g. `Io` or `Closed`.
+    fn kind(&self) -> String {
+        match self.code() {
+            Some(code) => code.code().to_string(),
+            None => variant_name(format!("{:?}", self).split("kind: ").nth(1).unwrap_or_default()),
+        }
+    }
+}
+
+/// A PG error of a statement that must not run twice, e.g. an insert. Only errors that guarantee the statement
+/// was not applied are retryable: a connection error may arrive after the server committed.
+#[derive(Debug)]
+pub(crate) struct NotIdempotent(pub tokio_postgres::Error);
+
+impl Retryable for NotIdempotent {
+    fn is_retryable(&self) -> bool {
+        self.0
+            .code()
+            .filter(|code| RETRYABLE_SQL_STATES.contains(code))
+            .is_some()
+    }
+
+    fn kind(&self) -> String {
+        self.0.kind()
+    }
+}
+
+/// Adds details of a successful result to the span of the call, e.g. the number of rows.
+pub(crate) trait Traced {
+    fn trace(&self, _span: &Span) {}
+}
+
+impl Traced for Vec<Row> {
+    fn trace(&self, span: &Span) {
+        span.set_attribute("db.rows", self.len());
+    }
+}
+
+impl Traced for GetItemOutput {
+    fn trace(&self, span: &Span) {
+        span.set_attribute("db.rows", self.item.iter().count());
+    }
+}
+
+impl Traced for BatchGetItemOutput {
+    fn trace(&self, span: &Span) {
+        let rows: usize = self.responses.iter().flat_map(|r| r.values()).map(Vec::len).sum();
+        span.set_attribute("db.rows", rows);
+    }
+}
+
+impl Traced for QueryOutput {
+    fn trace(&self, span: &Span) {
+        span.set_attribute("db.rows", self.count.unwrap_or_default());
+    }
+}
+
+impl Traced for ScanOutput {
+    fn trace(&self, span: &Span) {
+        span.set_attribute("db.rows", self.count.unwrap_or_default());
+    }
+}
+
+impl Traced for BatchWriteItemOutput {}
+impl Traced for DeleteItemOutput {}
+impl Traced for PutItemOutput {}
+impl Traced for UpdateItemOutput {}
+
+/// How many times and how long to keep trying.
+#[derive(Debug, Clone)]
+pub(crate) struct RetryPolicy {
+    pub max_attempts: u32,
+    /// The longest delay before the 2nd attempt. It doubles with every attempt up to `max_delay`.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// No attempt is started if it would have to wait past this time.
+    pub deadline: Option<Instant>,
+}
+
+impl Default for RetryPolicy {
+    /// 5 attempts within about 1.5s, limited by the deadline of the current request.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            deadline: context::deadline(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns a random delay before the attempt that follows `attempt` between zero and the exponential cap
+    /// ("full jitter"), so that many Lambdas throttled at the same time do not retry in lockstep.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let cap = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        let cap_ms = cap.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(0, cap_ms + 1))
+    }
+}
+
+/// The deadline for retries of a Lambda invocation with `remaining` time left.
+pub(crate) fn lambda_deadline(remaining: Duration) -> Instant {
+    Instant::now() + remaining.checked_sub(DEADLINE_MARGIN).unwrap_or_default()
+}
+
+/// Runs `operation` until it succeeds, fails with a permanent error, runs out of attempts or the next attempt
+/// would start after the deadline. Returns the last error in the last three cases. `name` is the operation
+/// for logging, metrics and tracing: the total latency and every failed attempt are recorded under it.
+pub(crate) async fn with_retry<T, E, F, Fut>(policy: &RetryPolicy, name: &str, operation: F) -> Result<T, E>
+where
+    T: Traced,
+    E: Retryable + std::fmt::Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    with_retry_in(policy, trace::span(name, SpanKind::Client), operation).await
+}
+
+/// Same as `with_retry`, but all attempts are traced in `span`, which ends when this function returns.
+/// The operation is named after the span, e.g. `trace::ddb_span("tlist.get_item", TABLE_NAME_TLIST)`.
+pub(crate) async fn with_retry_in<T, E, F, Fut>(policy: &RetryPolicy, span: Span, operation: F) -> Result<T, E>
+where
+    T: Traced,
+    E: Retryable + std::fmt::Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let started = Instant::now();
+    let result = retry_attempts(policy, &span, operation).await;
+    metrics::record_latency(span.name(), started.elapsed());
+    match &result {
+        Ok(v) => v.trace(&span),
+        Err(e) => span.set_error(&logging::redact(&format!("{:?}", e))),
+    }
+    result
+}
+
+async fn retry_attempts<T, E, F, Fut>(policy: &RetryPolicy, span: &Span, mut operation: F) -> Result<T, E>
+where
+    E: Retryable + std::fmt::Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let name = span.name();
+    let mut attempt = 1;
+    loop {
+        span.set_attribute("retry.attempts", attempt);
+        let e = match operation().await {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+
+        metrics::record_error(name, &e.kind());
+        if !e.is_retryable() {
+            debug!("{} failed with a permanent error: {:?}", name, e);
+            return Err(e);
+        }
+        if attempt >= policy.max_attempts {
+            warn!("{} failed after {} attempts: {:?}", name, attempt, e);
+            return Err(e);
+        }
+        let delay = policy.delay(attempt);
+        if policy.deadline.filter(|d| Instant::now() + delay >= *d).is_some() {
+            warn!("{} failed on attempt {} with no time left to retry: {:?}", name, attempt, e);
+            return Err(e);
+        }
+
+        warn!("{} failed on attempt {}, retrying in {:?}: {:?}", name, attempt, delay, e);
+        tokio::time::delay_for(delay).await;
+        attempt += 1;
+    }
+}
+
+/// PG queries with the default retry policy.
+pub(crate) trait QueryWithRetry {
+    /// For reads and idempotent writes only: connection errors are retried even if the statement was applied.
+    async fn query_with_retry(
+        &self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error>;
+
+    /// For writes that must not be applied twice: only retried if the error guarantees they were not applied.
+    async fn query_write_with_retry(
+        &self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error>;
+}
+
+impl QueryWithRetry for Client {
+    async fn query_with_retry(
+        &self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let span = trace::pg_span(metrics::pg_operation(statement));
+        with_retry_in(&RetryPolicy::default(), span, || self.query(statement, params)).await
+    }
+    async fn query_write_with_retry(
+        &self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let span = trace::pg_span(metrics::pg_operation(statement));
+        with_retry_in(&RetryPolicy::default(), span, || async {
+            self.query(statement, params).await.map_err(NotIdempotent)
+        })
+        .await
+        .map_err(|e| e.0)
+    }
+}
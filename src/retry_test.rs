@@ -0,0 +1,218 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_retry {
+    use crate::retry::*;
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::LdList;
+    use dynomite::Attributes;
+    use hyper::body::Bytes;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server, StatusCode};
+    use rusoto_core::credential::StaticProvider;
+    use rusoto_core::request::BufferedHttpResponse;
+    use rusoto_core::{HttpClient, Region, RusotoError};
+    use rusoto_dynamodb::{DynamoDb, DynamoDbClient, GetItemError, GetItemInput, PutItemError};
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    /// DDB error responses as the service returns them
+    const THROTTLED: (u16, &str) = (
+        400,
+        r#"{"__type":"com.amazonaws.dynamodb.v20120810#ProvisionedThroughputExceededException","message":"Rate exceeded"}"#,
+    );
+    const INTERNAL_ERROR: (u16, &str) = (
+        500,
+        r#"{"__type":"com.amazon.coral.service#InternalServerError","message":"Internal error"}"#,
+    );
+    const NO_TABLE: (u16, &str) = (
+        400,
+        r#"{"__type":"com.amazonaws.dynamodb.v20120810#ResourceNotFoundException","message":"Table not found"}"#,
+    );
+
+    /// Starts a fake DDB endpoint that fails with `faults` in turn, then responds with `success`.
+    /// Returns a client for it and the number of requests it received.
+    fn start_faulty_ddb(faults: Vec<(u16, &'static str)>, success: String) -> (DynamoDbClient, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let faults = Arc::new(Mutex::new(faults.into_iter().collect::<VecDeque<(u16, &str)>>()));
+
+        let counter = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let counter = counter.clone();
+            let faults = faults.clone();
+            let success = success.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_request: Request<Body>| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let (status, body) = match faults.lock().unwrap().pop_front() {
+                        Some((status, body)) => (status, body.to_string()),
+                        None => (200, success.clone()),
+                    };
+                    async move { Ok::<_, Infallible>(Response::builder().status(status).body(Body::from(body)).unwrap()) }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let region = Region::Custom {
+            name: "local".to_string(),
+            endpoint: format!("http://{}", server.local_addr()),
+        };
+        tokio::spawn(server);
+
+        let credentials = StaticProvider::new_minimal("key".to_string(), "secret".to_string());
+        let client = DynamoDbClient::new_with(HttpClient::new().unwrap(), credentials, region);
+        (client, requests)
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(20),
+            deadline: None,
+        }
+    }
+
+    fn get_input() -> GetItemInput {
+        GetItemInput {
+            table_name: "tlist".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            deadline: None,
+        };
+
+        // the delay is random, but never above the doubling cap
+        for _ in 0..50 {
+            assert!(policy.delay(1) <= Duration::from_millis(100));
+            assert!(policy.delay(3) <= Duration::from_millis(400));
+            assert!(policy.delay(9) <= Duration::from_millis(1000));
+            assert!(policy.delay(100) <= Duration::from_millis(1000));
+        }
+        assert!((0..50).map(|_| policy.delay(4)).any(|d| d != policy.delay(4)));
+
+        // the deadline leaves a margin and is never in the past
+        let now = Instant::now();
+        assert!(lambda_deadline(Duration::from_secs(3)) >= now + Duration::from_millis(2500));
+        assert!(lambda_deadline(Duration::from_millis(100)) <= Instant::now());
+    }
+
+    #[test]
+    fn test_classify_ddb_errors() {
+        let retryable: RusotoError<PutItemError> =
+            RusotoError::Service(PutItemError::ProvisionedThroughputExceeded(String::new()));
+        assert!(retryable.is_retryable());
+        let retryable: RusotoError<PutItemError> =
+            RusotoError::Service(PutItemError::TransactionConflict(String::new()));
+        assert!(retryable.is_retryable());
+        let permanent: RusotoError<PutItemError> =
+            RusotoError::Service(PutItemError::ConditionalCheckFailed(String::new()));
+        assert!(!permanent.is_retryable());
+        assert_eq!(permanent.kind(), "ConditionalCheckFailed");
+        let permanent: RusotoError<GetItemError> = RusotoError::Validation("Missing key".to_string());
+        assert!(!permanent.is_retryable());
+        assert_eq!(permanent.kind(), "Validation");
+
+        // throttling comes back as an unknown error
+        let unknown = |status: u16, body: &'static str| -> RusotoError<GetItemError> {
+            RusotoError::Unknown(BufferedHttpResponse {
+                status: StatusCode::from_u16(status).unwrap(),
+                body: Bytes::from(body),
+                headers: Default::default(),
+            })
+        };
+        assert!(unknown(400, r#"{"__type":"ThrottlingException"}"#).is_retryable());
+        assert_eq!(unknown(400, r#"{"__type":"ThrottlingException"}"#).kind(), "ThrottlingException");
+        assert!(unknown(503, "").is_retryable());
+        assert!(!unknown(403, r#"{"__type":"AccessDeniedException"}"#).is_retryable());
+        assert_eq!(unknown(403, r#"{"__type":"AccessDeniedException"}"#).kind(), "Http403");
+    }
+
+    #[tokio::test]
+    async fn test_retry_ddb_faults() {
+        // transient faults are retried until the request succeeds
+        let list = LdList::new(Uuid::new_v4(), "Retried".to_string(), Uuid::new_v4());
+        let doc: Attributes = list.into();
+        let success = serde_json::json!({ "Item": doc }).to_string();
+        let (client, requests) = start_faulty_ddb(vec![INTERNAL_ERROR, THROTTLED], success);
+        let output = with_retry(&fast_policy(), "get_item", || client.get_item(get_input()))
+            .await
+            .expect("Failed to get the item after retries");
+        assert!(output.item.is_some());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // permanent errors are returned straight away
+        let (client, requests) = start_faulty_ddb(vec![NO_TABLE], "{}".to_string());
+        let result = with_retry(&fast_policy(), "get_item", || client.get_item(get_input())).await;
+        assert!(matches!(result, Err(RusotoError::Service(GetItemError::ResourceNotFound(_)))));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // the last error is returned when the attempts run out
+        let (client, requests) = start_faulty_ddb(vec![THROTTLED; 5], "{}".to_string());
+        let result = with_retry(&fast_policy(), "get_item", || client.get_item(get_input())).await;
+        assert!(matches!(
+            result,
+            Err(RusotoError::Service(GetItemError::ProvisionedThroughputExceeded(_)))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // no retries past the deadline
+        let (client, requests) = start_faulty_ddb(vec![THROTTLED; 5], "{}".to_string());
+        let policy = RetryPolicy {
+            deadline: Some(Instant::now()),
+            ..fast_policy()
+        };
+        assert!(with_retry(&policy, "get_item", || client.get_item(get_input()))
+            .await
+            .is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_classify_pg_errors() {
+        let (pg_client, _) = test_helpers::init_db_clients().await;
+
+        // errors in the statement are permanent and not retried
+        let attempts = AtomicUsize::new(0);
+        let result = with_retry(&fast_policy(), "division by zero", || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            pg_client.query("select 1 / 0", &[])
+        })
+        .await;
+        let e = result.unwrap_err();
+        assert!(!e.is_retryable());
+        assert_eq!(e.kind(), "22012");
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let rows = pg_client
+            .query_with_retry("select 1", &[])
+            .await
+            .expect("select 1 failed");
+        assert_eq!(rows.len(), 1);
+
+        // a connection error may arrive after a write was applied, so only reads retry it
+        let e = pg_client
+            .query("do $$ begin raise sqlstate '08006'; end $$", &[])
+            .await
+            .unwrap_err();
+        assert!(e.is_retryable());
+        assert!(!NotIdempotent(e).is_retryable());
+
+        let e = pg_client
+            .query("do $$ begin raise sqlstate '40001'; end $$", &[])
+            .await
+            .unwrap_err();
+        assert!(NotIdempotent(e).is_retryable());
+    }
+}
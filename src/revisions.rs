@@ -0,0 +1,493 @@
+use crate::cache::ReadConsistency;
+use crate::context;
+use crate::metrics;
+use crate::retry::{self, RetryPolicy};
+use crate::structures_ddb::{self, LdList, LdListItem};
+use crate::structures_pg::TListItem;
+use crate::trace;
+use crate::utils;
+use crate::validation;
+use chrono::{DateTime, Duration, Utc};
+use dynomite::{
+    dynamodb::{DynamoDb, DynamoDbClient},
+    Attribute, Attributes, FromAttributes, Item,
+};
+use log::{self, debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env::var;
+use uuid::Uuid;
+
+#[path = "./revisions_test.rs"]
+pub(crate) mod tests_revisions;
+
+const TABLE_NAME_TLIST_REV: &str = "tlist_rev";
+const TABLE_KEY_FOR_TLIST_REV: &str = "lid";
+const TABLE_SORT_KEY_FOR_TLIST_REV: &str = "rev";
+const ERR_MSG_REVISION_DOES_NOT_EXIST: &str = "The revision doesn't exist. It may have expired.";
+const ERR_MSG_LIST_DOES_NOT_EXIST: &str = "The list doesn't exist";
+const ERR_MSG_REVISION_FAILED: &str = "Failed to get the list history. Try again.";
+const ERR_MSG_NO_SNAPSHOT: &str = "The list was too large to keep a copy of it in this revision.";
+const ERR_MSG_DELETE_FAILED: &str = "Failed to delete the list history. Try again.";
+
+/// Env var with the number of days revisions are kept for. DDB deletes them via TTL on `expires_on`.
+const EV_LIST_REVISION_RETENTION_DAYS: &str = "LIST_REVISION_RETENTION_DAYS";
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Before and after values longer than this are cut in the list of changes. The snapshot has them in full.
+const MAX_CHANGE_VALUE_LENGTH: usize = 200;
+
+/// Default number of revisions returned by `get_revisions`
+pub(crate) const REVISIONS_LIMIT: i64 = 50;
+/// Number of revisions looked up at a time by `delete_revisions`
+const DELETE_PAGE_SIZE: i64 = 100;
+
+/// What happened to a field or an item.
+#[derive(Attribute, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A single change between two versions of a list. Item changes have `liid` set.
+#[derive(Item, Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub(crate) struct FieldChange {
+    /// `title`, `description`, `tags`, `trashed` and `items` for the list,
+    /// `item`, `item.title`, `item.description`, `item.due`, `item.completed` and `item.trashed` for items.
+    #[dynomite(partition_key)]
+    pub field: String,
+    #[dynomite(default)]
+    pub liid: Option<Uuid>,
+    pub kind: ChangeKind,
+    #[dynomite(default)]
+    pub before: Option<String>,
+    #[dynomite(default)]
+    pub after: Option<String>,
+}
+
+/// An immutable copy of a list made on every save, stored in DDB table `tlist_rev`.
+#[derive(Item, Debug, Serialize, Deserialize)]
+pub(crate) struct ListRevision {
+    #[dynomite(partition_key)]
+    pub lid: Uuid,
+    /// Time of the save in nanoseconds since the epoch. Identifies the revision within the list.
+    #[dynomite(sort_key)]
+    pub rev: i64,
+    pub saved_on_utc: DateTime<Utc>,
+    /// The user who made the change. None for system changes.
+    #[dynomite(default)]
+    pub author: Option<Uuid>,
+    #[dynomite(default)]
+    pub changes: Vec<FieldChange>,
+    /// The list as it was saved. Not included in `get_revisions` output.
+    #[dynomite(default)]
+    pub snapshot: Option<LdList>,
+    /// DDB TTL attribute in seconds since the epoch
+    #[dynomite(default)]
+    pub expires_on: Option<i64>,
+}
+
+impl ListRevision {
+    /// Creates a revision for the new version of the list. Returns None if nothing changed.
+    pub(crate) fn new(previous: Option<&LdList>, current: LdList) -> Option<Self> {
+        let changes = diff_lists(previous, &current);
+        if changes.is_empty() {
+            return None;
+        }
+
+        let now = Utc::now();
+        Some(Self {
+            lid: current.lid,
+            rev: now.timestamp_nanos(),
+            saved_on_utc: now,
+            author: context::actor(),
+            changes,
+            snapshot: Some(current),
+            expires_on: Some((now + retention()).timestamp()),
+        })
+    }
+}
+
+/// How long revisions are kept for, from `LIST_REVISION_RETENTION_DAYS` env var.
+pub(crate) fn retention() -> Duration {
+    let days = match var(EV_LIST_REVISION_RETENTION_DAYS) {
+        Ok(v) => v.parse::<i64>().unwrap_or_else(|e| {
+            error!("Invalid {}: {} - {}", EV_LIST_REVISION_RETENTION_DAYS, v, e);
+            DEFAULT_RETENTION_DAYS
+        }),
+        Err(_) => DEFAULT_RETENTION_DAYS,
+    };
+
+    Duration::days(days)
+}
+
+/// Saves a revision if the list changed. Failures are logged, but not returned because the list is already saved.
+/// Called by `LdList::save_in_ddb` with the previous and the new versions of the list.
+pub(crate) async fn record_revision(previous: Option<&LdList>, current: LdList, ddb_client: &DynamoDbClient) {
+    let lid = current.lid;
+    let revision = match ListRevision::new(previous, current) {
+        Some(v) => v,
+        None => {
+            debug!("No changes in {} - no revision", lid);
+            return;
+        }
+    };
+
+    let put_input = utils::build_ddb_put_input(fit_in_ddb_item(lid, revision.into()), TABLE_NAME_TLIST_REV);
+    let put = || ddb_client.put_item(put_input.clone());
+    let span = trace::ddb_span("tlist_rev.put_item", TABLE_NAME_TLIST_REV);
+    match retry::with_retry_in(&RetryPolicy::default(), span, put).await {
+        Ok(v) => metrics::record_capacity("tlist_rev.put_item", v.consumed_capacity.as_ref()),
+        Err(e) => error!("Failed to save a revision for {}: {:?}", lid, e),
+    }
+}
+
+/// A revision holds a copy of the list and the changes, so it can be larger than the list itself.
+/// Revisions over the DDB item size limit are saved without the snapshot and with as many changes as fit,
+/// so that the history is kept even if the list cannot be reverted to them.
+fn fit_in_ddb_item(lid: Uuid, mut attrs: Attributes) -> Attributes {
+    if utils::ddb_item_size(&attrs) <= utils::DDB_MAX_ITEM_SIZE {
+        return attrs;
+    }
+    error!("The revision of {} is too large for a snapshot", lid);
+    attrs.remove("snapshot");
+
+    while utils::ddb_item_size(&attrs) > utils::DDB_MAX_ITEM_SIZE {
+        match attrs.get_mut("changes").and_then(|v| v.l.as_mut()) {
+            Some(changes) if !changes.is_empty() => {
+                error!("Dropping some of {} changes from the revision of {}", changes.len(), lid);
+                changes.truncate(changes.len() * 3 / 4);
+            }
+            _ => break,
+        }
+    }
+
+    attrs
+}
+
+/// Saves the first revision of every list in bulk. Called by `LdList::save_many_in_ddb`.
+pub(crate) async fn record_new_lists(lists: Vec<LdList>, ddb_client: &DynamoDbClient) {
+    let revisions: Vec<Attributes> = lists
+        .into_iter()
+        .filter_map(|list| ListRevision::new(None, list))
+        .map(|rev| {
+            let lid = rev.lid;
+            fit_in_ddb_item(lid, rev.into())
+        })
+        .collect();
+
+    if let Err(e) = structures_ddb::batch_put_in_ddb(&revisions, TABLE_NAME_TLIST_REV, ddb_client).await {
+        error!("Failed to save revisions for new lists: {}", e);
+    }
+}
+
+/// Returns up to `limit` revisions of the list, newest first. Snapshots are not included.
+pub(crate) async fn get_revisions(
+    lid: Uuid,
+    limit: Option<i64>,
+    ddb_client: &DynamoDbClient,
+) -> Result<Vec<ListRevision>, String> {
+    debug!("get_revisions for {}", lid);
+
+    let input = utils::build_ddb_query_input(
+        TABLE_KEY_FOR_TLIST_REV,
+        &lid,
+        &["lid", "rev", "saved_on_utc", "author", "changes", "expires_on"],
+        true,
+        limit.unwrap_or(REVISIONS_LIMIT),
+        TABLE_NAME_TLIST_REV,
+    );
+
+    let query = || ddb_client.query(input.clone());
+    let span = trace::ddb_span("tlist_rev.query", TABLE_NAME_TLIST_REV);
+    match retry::with_retry_in(&RetryPolicy::default(), span, query).await {
+        Ok(output) => {
+            metrics::record_capacity("tlist_rev.query", output.consumed_capacity.as_ref());
+            output
+                .items
+                .unwrap_or_default()
+                .into_iter()
+                .map(|attrs| revision_from_attrs(lid, attrs))
+                .collect()
+        }
+        Err(e) => {
+            error!("DDB error {}", e);
+            Err(ERR_MSG_REVISION_FAILED.to_string())
+        }
+    }
+}
+
+/// Returns the list as it was saved in the revision.
+pub(crate) async fn get_list_at_revision(
+    lid: Uuid,
+    rev: i64,
+    ddb_client: &DynamoDbClient,
+) -> Result<Option<LdList>, String> {
+    debug!("get_list_at_revision for {} / {}", lid, rev);
+
+    let get_input = utils::build_ddb_get_input_with_sort_key(
+        TABLE_KEY_FOR_TLIST_REV,
+        &lid,
+        TABLE_SORT_KEY_FOR_TLIST_REV,
+        rev,
+        TABLE_NAME_TLIST_REV,
+    );
+    let get = || ddb_client.get_item(get_input.clone());
+    let span = trace::ddb_span("tlist_rev.get_item", TABLE_NAME_TLIST_REV);
+    match retry::with_retry_in(&RetryPolicy::default(), span, get).await {
+        Ok(output) => match output.item {
+            Some(attrs) => match revision_from_attrs(lid, attrs)?.snapshot {
+                Some(v) => Ok(Some(v)),
+                None => Err(ERR_MSG_NO_SNAPSHOT.to_string()),
+            },
+            None => Ok(None),
+        },
+        Err(e) => {
+            error!("DDB error {}", e);
+            Err(ERR_MSG_REVISION_FAILED.to_string())
+        }
+    }
+}
+
+/// Deletes all revisions of the list before they expire, e.g. when the account of the owner is deleted.
+/// Returns the number of deleted revisions.
+pub(crate) async fn delete_revisions(lid: Uuid, ddb_client: &DynamoDbClient) -> Result<u64, String> {
+    debug!("delete_revisions for {}", lid);
+
+    // the deleted revisions are gone from the next page, so the query always starts from the top
+    let input = utils::build_ddb_query_input(
+        TABLE_KEY_FOR_TLIST_REV,
+        &lid,
+        &[TABLE_KEY_FOR_TLIST_REV, TABLE_SORT_KEY_FOR_TLIST_REV],
+        false,
+        DELETE_PAGE_SIZE,
+        TABLE_NAME_TLIST_REV,
+    );
+    let mut deleted: u64 = 0;
+    loop {
+        let query = || ddb_client.query(input.clone());
+        let span = trace::ddb_span("tlist_rev.query", TABLE_NAME_TLIST_REV);
+        let keys = match retry::with_retry_in(&RetryPolicy::default(), span, query).await {
+            Ok(output) => {
+                metrics::record_capacity("tlist_rev.query", output.consumed_capacity.as_ref());
+                output.items.unwrap_or_default()
+            }
+            Err(e) => {
+                error!("DDB error {}", e);
+                return Err(ERR_MSG_DELETE_FAILED.to_string());
+            }
+        };
+        if keys.is_empty() {
+            break;
+        }
+
+        if let Err(e) = structures_ddb::batch_delete_in_ddb(&keys, TABLE_NAME_TLIST_REV, ddb_client).await {
+            error!("Failed to delete revisions of {}: {}", lid, e);
+            return Err(ERR_MSG_DELETE_FAILED.to_string());
+        }
+        deleted += keys.len() as u64;
+    }
+    debug!("Deleted {} revisions of {}", deleted, lid);
+
+    Ok(deleted)
+}
+
+fn revision_from_attrs(lid: Uuid, attrs: Attributes) -> Result<ListRevision, String> {
+    ListRevision::from_attrs(attrs).map_err(|e| {
+        error!("Invalid revision of {}: {}", lid, e);
+        ERR_MSG_REVISION_FAILED.to_string()
+    })
+}
+
+/// Restores the title, description, tags and items of the list from the revision and saves it as a new revision.
+/// Items deleted since then are re-created in PG with their links and status in the revision. Completion and
+/// trash status of items that still exist are kept as they are now. Lists in the trash cannot be reverted.
+pub(crate) async fn revert_to_revision(
+    lid: Uuid,
+    rev: i64,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Option<LdList>, String> {
+    debug!("revert_to_revision for {} / {}", lid, rev);
+
+    let mut list = match get_list_at_revision(lid, rev, ddb_client).await? {
+        Some(v) => v,
+        None => return Err(ERR_MSG_REVISION_DOES_NOT_EXIST.to_string()),
+    };
+    let current = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+        Some(v) => v,
+        None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+    };
+
+    // PG has the current state of the list and its items
+    list.rel = current.rel.clone();
+
+    // the list is checked as it will be saved before anything is written to PG
+    validation::validate_changes_for_save(&list, Some(&current))?;
+    let mut current_rels: HashMap<Uuid, TListItem> = current
+        .items
+        .into_iter()
+        .flatten()
+        .map(|i| (i.rel.liid, i.rel))
+        .collect();
+
+    // re-create items that were deleted after the revision with their links and status at the time
+    let deleted_rels: Vec<TListItem> = list
+        .items
+        .iter()
+        .flatten()
+        .filter(|i| !current_rels.contains_key(&i.rel.liid))
+        .map(|i| TListItem {
+            parent_lid: lid,
+            ..i.rel.clone()
+        })
+        .collect();
+    if !deleted_rels.is_empty() {
+        debug!("Re-creating {} items", deleted_rels.len());
+        current_rels.extend(LdListItem::create_rels(&deleted_rels, pg_client).await?);
+    }
+
+    for item in list.items.iter_mut().flatten() {
+        if let Some(rel) = current_rels.remove(&item.rel.liid) {
+            item.rel = rel;
+        }
+
+        // due dates are copied to PG
+        if item.due.as_ref().map(|d| d.due_on_utc) != item.rel.due_on_utc {
+            let due = item.due.take();
+            LdListItem::sync_due(item, due, pg_client).await?;
+        }
+    }
+
+    list.save_in_ddb(ddb_client, pg_client).await
+}
+
+/// Lists changes between two versions of a list. `previous` is None for new lists.
+pub(crate) fn diff_lists(previous: Option<&LdList>, current: &LdList) -> Vec<FieldChange> {
+    let mut changes: Vec<FieldChange> = Vec::new();
+
+    let previous = match previous {
+        Some(v) => v,
+        None => {
+            changes.push(change("title", None, None, Some(&current.title)));
+            return changes;
+        }
+    };
+
+    // list fields
+    if previous.title != current.title {
+        changes.push(change("title", None, Some(&previous.title), Some(&current.title)));
+    }
+    if previous.description != current.description {
+        changes.push(change(
+            "description",
+            None,
+            previous.description.as_deref(),
+            current.description.as_deref(),
+        ));
+    }
+    if previous.tags != current.tags {
+        let tags_text = |tags: &Option<Vec<String>>| tags.as_ref().map(|t| t.join(", "));
+        changes.push(change(
+            "tags",
+            None,
+            tags_text(&previous.tags).as_deref(),
+            tags_text(&current.tags).as_deref(),
+        ));
+    }
+    if previous.rel.deleted_on_utc.is_some() != current.rel.deleted_on_utc.is_some() {
+        changes.push(flag_change("trashed", None, current.rel.deleted_on_utc.is_some()));
+    }
+
+    // items
+    let previous_items: HashMap<Uuid, &LdListItem> = previous.items.iter().flatten().map(|i| (i.rel.liid, i)).collect();
+    let current_items: HashMap<Uuid, &LdListItem> = current.items.iter().flatten().map(|i| (i.rel.liid, i)).collect();
+
+    for item in previous.items.iter().flatten() {
+        if !current_items.contains_key(&item.rel.liid) {
+            changes.push(change("item", Some(item.rel.liid), Some(&item.title), None));
+        }
+    }
+    for item in current.items.iter().flatten() {
+        let liid = Some(item.rel.liid);
+        let before = match previous_items.get(&item.rel.liid) {
+            Some(v) => v,
+            None => {
+                changes.push(change("item", liid, None, Some(&item.title)));
+                continue;
+            }
+        };
+
+        if before.title != item.title {
+            changes.push(change("item.title", liid, Some(&before.title), Some(&item.title)));
+        }
+        if before.description != item.description {
+            changes.push(change(
+                "item.description",
+                liid,
+                before.description.as_deref(),
+                item.description.as_deref(),
+            ));
+        }
+        if before.due != item.due {
+            let due_text = |i: &LdListItem| i.due.as_ref().map(|d| d.due_on_utc.to_rfc3339());
+            changes.push(change("item.due", liid, due_text(before).as_deref(), due_text(item).as_deref()));
+        }
+        if before.rel.completed_on_utc.is_some() != item.rel.completed_on_utc.is_some() {
+            changes.push(flag_change("item.completed", liid, item.rel.completed_on_utc.is_some()));
+        }
+        if before.rel.deleted_on_utc.is_some() != item.rel.deleted_on_utc.is_some() {
+            changes.push(flag_change("item.trashed", liid, item.rel.deleted_on_utc.is_some()));
+        }
+    }
+
+    // the same items in a different order
+    let order = |list: &LdList| -> Vec<Uuid> { list.items.iter().flatten().map(|i| i.rel.liid).collect() };
+    let (previous_order, current_order) = (order(previous), order(current));
+    let same_items =
+        previous_items.len() == current_items.len() && current_items.keys().all(|k| previous_items.contains_key(k));
+    if same_items && previous_order != current_order {
+        changes.push(FieldChange {
+            field: "items".to_string(),
+            liid: None,
+            kind: ChangeKind::Modified,
+            before: None,
+            after: None,
+        });
+    }
+
+    changes
+}
+
+/// Creates a change record with the kind worked out from the values.
+fn change(field: &str, liid: Option<Uuid>, before: Option<&str>, after: Option<&str>) -> FieldChange {
+    let kind = match (before, after) {
+        (None, Some(_)) => ChangeKind::Added,
+        (Some(_), None) => ChangeKind::Removed,
+        _ => ChangeKind::Modified,
+    };
+
+    FieldChange {
+        field: field.to_string(),
+        liid,
+        kind,
+        before: before.map(shorten),
+        after: after.map(shorten),
+    }
+}
+
+/// Creates a change record for a flag like `completed` that was set or cleared.
+fn flag_change(field: &str, liid: Option<Uuid>, is_set: bool) -> FieldChange {
+    FieldChange {
+        field: field.to_string(),
+        liid,
+        kind: if is_set { ChangeKind::Added } else { ChangeKind::Removed },
+        before: None,
+        after: None,
+    }
+}
+
+fn shorten(value: &str) -> String {
+    value.chars().take(MAX_CHANGE_VALUE_LENGTH).collect()
+}
@@ -0,0 +1,165 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_revisions {
+    use crate::context::{self, RequestContext};
+    use crate::revisions::*;
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use dynomite::FromAttributes;
+    use log::{self, debug};
+    use uuid::Uuid;
+
+    fn build_list(lid: Uuid, liids: &[Uuid]) -> LdList {
+        let mut list = LdList::new(lid, "Packing".to_string(), Uuid::new_v4());
+        list.items = Some(
+            liids
+                .iter()
+                .map(|liid| LdListItem {
+                    title: ["Item ", liid.to_string().as_str()].concat(),
+                    description: None,
+                    due: None,
+                    rel: TListItem::new(*liid, lid),
+                })
+                .collect(),
+        );
+        list
+    }
+
+    #[test]
+    fn test_diff_lists() {
+        let lid = Uuid::new_v4();
+        let liids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let previous = build_list(lid, &liids[..2]);
+
+        // a new list
+        let changes = diff_lists(None, &previous);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Added);
+
+        // no changes
+        assert!(diff_lists(Some(&previous), &build_list(lid, &liids[..2])).is_empty());
+
+        // list fields and items
+        let mut current = build_list(lid, &liids[1..]);
+        current.title = "Packing for the beach".to_string();
+        current.tags = Some(vec!["travel".to_string()]);
+        {
+            let items = current.items.as_mut().unwrap();
+            items[0].description = Some("x".repeat(500));
+            items[0].rel.completed_on_utc = Some(chrono::Utc::now());
+        }
+        let changes = diff_lists(Some(&previous), &current);
+        let find = |field: &str| changes.iter().find(|c| c.field == field).expect(field);
+
+        assert_eq!(find("title").before, Some("Packing".to_string()));
+        assert_eq!(find("title").after, Some("Packing for the beach".to_string()));
+        assert_eq!(find("tags").kind, ChangeKind::Added);
+        assert_eq!(find("item.description").liid, Some(liids[1]));
+        assert_eq!(find("item.description").after.as_ref().unwrap().len(), 200);
+        assert_eq!(find("item.completed").kind, ChangeKind::Added);
+
+        // one item removed and one added
+        let item_changes: Vec<&FieldChange> = changes.iter().filter(|c| c.field == "item").collect();
+        assert_eq!(item_changes.len(), 2);
+        assert_eq!(item_changes[0].liid, Some(liids[0]));
+        assert_eq!(item_changes[0].kind, ChangeKind::Removed);
+        assert_eq!(item_changes[1].liid, Some(liids[2]));
+        assert_eq!(item_changes[1].kind, ChangeKind::Added);
+
+        // the same items in a different order
+        let mut reordered = build_list(lid, &liids[..2]);
+        reordered.items.as_mut().unwrap().reverse();
+        let changes = diff_lists(Some(&previous), &reordered);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "items");
+    }
+
+    #[tokio::test]
+    async fn test_revision_author() {
+        let actor = Uuid::new_v4();
+        let request = RequestContext {
+            actor: Some(actor),
+            ..Default::default()
+        };
+        let revision = context::scope(request, async { ListRevision::new(None, build_list(Uuid::new_v4(), &[])) })
+            .await
+            .unwrap();
+
+        assert_eq!(revision.author, Some(actor));
+        assert!(revision.snapshot.is_some());
+        assert!(revision.expires_on.unwrap() > revision.saved_on_utc.timestamp());
+    }
+
+    #[test]
+    fn test_large_revision() {
+        let lid = Uuid::new_v4();
+        let liids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let mut list = build_list(lid, &liids);
+        for item in list.items.as_mut().unwrap() {
+            item.description = Some("x".repeat(150 * 1024));
+        }
+
+        // the snapshot does not fit, but the changes do
+        let revision = ListRevision::new(Some(&build_list(lid, &[])), list).unwrap();
+        let attrs = fit_in_ddb_item(lid, revision.into());
+        assert!(crate::utils::ddb_item_size(&attrs) <= crate::utils::DDB_MAX_ITEM_SIZE);
+        let revision = ListRevision::from_attrs(attrs).expect("The revision cannot be read back");
+        assert!(revision.snapshot.is_none());
+        assert_eq!(revision.changes.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_revert_to_revision() {
+        debug!("test_revert_to_revision started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new list and remember its latest revision
+        let user_email = ["test_revert_to_revision@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let pg_user = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user");
+        let lid = Uuid::new_v4();
+        let list = test_helpers::create_random_list(lid, pg_user.user_id, &ddb_client, &pg_client).await;
+        let revisions = get_revisions(lid, None, &ddb_client).await.unwrap();
+        assert_eq!(revisions.len(), 6);
+        assert!(revisions[0].snapshot.is_none());
+        let rev = revisions[0].rev;
+
+        // change the title and delete an item
+        let liid = list.items.as_ref().unwrap()[0].rel.liid;
+        let mut list = LdListItem::del_list_item_ddb(lid, liid, &ddb_client, &pg_client)
+            .await
+            .unwrap()
+            .unwrap();
+        let title = list.title.clone();
+        list.title = "Changed".to_string();
+        list.save_in_ddb(&ddb_client, &pg_client).await.unwrap();
+        assert_eq!(get_revisions(lid, None, &ddb_client).await.unwrap().len(), 8);
+
+        // the old version is still there
+        let old_list = get_list_at_revision(lid, rev, &ddb_client).await.unwrap().unwrap();
+        assert_eq!(old_list.title, title);
+
+        // revert and check the item was re-created in PG
+        let reverted = revert_to_revision(lid, rev, &ddb_client, &pg_client)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reverted.title, title);
+        assert_eq!(reverted.items.as_ref().unwrap().len(), 5);
+        assert!(get_t_list_item(liid, &pg_client).await.unwrap().is_some());
+
+        // clean up
+        reverted
+            .delete_from_all_dbs(&ddb_client, &pg_client)
+            .await
+            .expect("Failed to delete the list");
+        del_t_user(pg_user.user_id, &pg_client)
+            .await
+            .expect("Failed to delete the user");
+    }
+}
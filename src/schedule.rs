@@ -0,0 +1,205 @@
+use crate::structures_ddb::{LdList, LdListItem};
+use crate::structures_pg::{self};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use dynomite::{dynamodb::DynamoDbClient, Attribute, Item};
+use log::{self, debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+#[path = "./schedule_test.rs"]
+pub(crate) mod tests_schedule;
+
+const ERR_MSG_INVALID_TIMEZONE: &str = "Unknown time zone.";
+const ERR_MSG_OUT_OF_RANGE: &str = "The due date is out of range.";
+const ERR_MSG_DUE_ITEMS_FAILED: &str = "Failed to get the due items. Try again.";
+
+/// How often a list item repeats.
+#[derive(Attribute, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// Repeats an item every `interval` days, weeks, months or years.
+#[derive(Item, Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub(crate) struct Recurrence {
+    #[dynomite(partition_key)]
+    pub frequency: Frequency,
+    pub interval: u32,
+}
+
+/// When a list item is due. Part of LdListItem. `due_on_utc` and the reminder time are copied to PG
+/// for the overdue and upcoming item queries.
+#[derive(Item, Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub(crate) struct ItemDue {
+    #[dynomite(partition_key)]
+    pub due_on_utc: chrono::DateTime<Utc>,
+    /// IANA time zone name, e.g. `Pacific/Auckland`. Recurring items keep the same local time in this zone.
+    pub timezone: String,
+    /// Send a reminder this many minutes before the due time.
+    #[dynomite(default)]
+    pub remind_before_minutes: Option<i64>,
+    #[dynomite(default)]
+    pub recurrence: Option<Recurrence>,
+}
+
+impl ItemDue {
+    /// Parses `timezone`.
+    pub(crate) fn tz(&self) -> Result<Tz, String> {
+        self.timezone.parse::<Tz>().map_err(|e| {
+            error!("Invalid time zone {}: {}", self.timezone, e);
+            ERR_MSG_INVALID_TIMEZONE.to_string()
+        })
+    }
+
+    /// When the reminder is due, if there is one. Fails if the time is out of range.
+    pub(crate) fn remind_on_utc(&self) -> Result<Option<chrono::DateTime<Utc>>, String> {
+        let minutes = match self.remind_before_minutes {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        match minutes
+            .checked_mul(60_000)
+            .and_then(|ms| self.due_on_utc.checked_sub_signed(Duration::milliseconds(ms)))
+        {
+            Some(v) => Ok(Some(v)),
+            None => {
+                error!("The reminder {} minutes before {} is out of range", minutes, self.due_on_utc);
+                Err(ERR_MSG_OUT_OF_RANGE.to_string())
+            }
+        }
+    }
+
+    /// Returns a copy moved to the next occurrence for recurring items, or None for one-off items.
+    /// Monthly and yearly recurrences fall back to the last day of the month if the day does not exist,
+    /// e.g. Jan 31 is followed by Feb 28.
+    pub(crate) fn next_occurrence(&self) -> Result<Option<Self>, String> {
+        let recurrence = match self.recurrence.as_ref() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let tz = self.tz()?;
+
+        // the math is done in local time, so the item stays at the same time of day across DST changes
+        let local = tz.from_utc_datetime(&self.due_on_utc.naive_utc()).naive_local();
+        let interval = recurrence.interval.max(1);
+        let next_local = match recurrence.frequency {
+            Frequency::Daily => local.checked_add_signed(Duration::days(interval as i64)),
+            Frequency::Weekly => local.checked_add_signed(Duration::weeks(interval as i64)),
+            Frequency::Monthly => add_months(local, interval),
+            Frequency::Yearly => interval.checked_mul(12).and_then(|months| add_months(local, months)),
+        };
+        let next_local = match next_local {
+            Some(v) => v,
+            None => {
+                error!("The next occurrence of {} is out of range: {:?}", self.due_on_utc, recurrence);
+                return Err(ERR_MSG_OUT_OF_RANGE.to_string());
+            }
+        };
+
+        // a local time that falls into a DST gap is moved forward by an hour
+        let next = tz.from_local_datetime(&next_local).earliest().or_else(|| {
+            next_local
+                .checked_add_signed(Duration::hours(1))
+                .and_then(|v| tz.from_local_datetime(&v).earliest())
+        });
+        let next = match next {
+            Some(v) => v.with_timezone(&Utc),
+            None => {
+                error!("Cannot convert {} in {} to UTC", next_local, self.timezone);
+                return Err(ERR_MSG_INVALID_TIMEZONE.to_string());
+            }
+        };
+
+        Ok(Some(Self {
+            due_on_utc: next,
+            ..self.clone()
+        }))
+    }
+}
+
+/// Adds calendar months, clamping the day to the length of the target month. Returns None if the year is out
+/// of range.
+fn add_months(value: NaiveDateTime, months: u32) -> Option<NaiveDateTime> {
+    let month0 = value.month0().checked_add(months)?;
+    let year = value.year().checked_add((month0 / 12) as i32)?;
+    let month = month0 % 12 + 1;
+
+    // find the last day of the target month
+    let first_of_next_month = match month {
+        12 => NaiveDate::from_ymd_opt(year.checked_add(1)?, 1, 1)?,
+        _ => NaiveDate::from_ymd_opt(year, month + 1, 1)?,
+    };
+    let last_day = first_of_next_month.pred_opt()?.day();
+
+    Some(NaiveDate::from_ymd_opt(year, month, value.day().min(last_day))?.and_time(value.time()))
+}
+
+/// A list item that is due, with the list it belongs to.
+#[derive(Debug, Serialize)]
+pub(crate) struct DueItem {
+    pub lid: Uuid,
+    pub list_title: String,
+    pub item: LdListItem,
+}
+
+/// Items that are not done yet, sorted by due time.
+#[derive(Debug, Serialize)]
+pub(crate) struct DueItems {
+    /// Items due before now
+    pub overdue: Vec<DueItem>,
+    /// Items due within the requested window
+    pub upcoming: Vec<DueItem>,
+}
+
+/// Returns all items of all user lists that are overdue or due within `upcoming_window` from now.
+/// PG finds the items using its due date index and DDB is only read for the lists they belong to.
+pub(crate) async fn get_user_due_items(
+    user_id: Uuid,
+    upcoming_window: Duration,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<DueItems, String> {
+    debug!("get_user_due_items for {}", user_id);
+    let now = Utc::now();
+
+    // find the items in PG
+    let rels = structures_pg::get_user_due_t_list_items(user_id, now + upcoming_window, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_DUE_ITEMS_FAILED.to_string())?;
+    let liids: HashSet<Uuid> = rels.iter().map(|r| r.liid).collect();
+    let mut lids: Vec<Uuid> = rels.iter().map(|r| r.parent_lid).collect();
+    lids.sort();
+    lids.dedup();
+    debug!("Due items: {} in {} lists", liids.len(), lids.len());
+
+    // get the full items from their lists
+    let mut due_items: Vec<DueItem> = Vec::new();
+    for lid in lids {
+        let list: LdList = match LdList::get_from_ddb(&lid, ddb_client).await? {
+            Some(v) => v,
+            None => continue,
+        };
+        for item in list.items.into_iter().flatten() {
+            if liids.contains(&item.rel.liid) && item.due.is_some() && item.rel.deleted_on_utc.is_none() {
+                due_items.push(DueItem {
+                    lid,
+                    list_title: list.title.clone(),
+                    item,
+                });
+            }
+        }
+    }
+
+    // split them into overdue and upcoming, with the most urgent first
+    due_items.sort_by_key(|d| d.item.due.as_ref().map(|due| due.due_on_utc));
+    let (overdue, upcoming): (Vec<DueItem>, Vec<DueItem>) = due_items
+        .into_iter()
+        .partition(|d| d.item.due.as_ref().map(|due| due.due_on_utc < now).unwrap_or(false));
+
+    Ok(DueItems { overdue, upcoming })
+}
@@ -0,0 +1,103 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_schedule {
+    use crate::schedule::*;
+    use chrono::{TimeZone, Utc};
+
+    fn due(y: i32, m: u32, d: u32, h: u32, timezone: &str, frequency: Frequency, interval: u32) -> ItemDue {
+        ItemDue {
+            due_on_utc: Utc.ymd(y, m, d).and_hms(h, 0, 0),
+            timezone: timezone.to_string(),
+            remind_before_minutes: None,
+            recurrence: Some(Recurrence { frequency, interval }),
+        }
+    }
+
+    fn next(due: &ItemDue) -> chrono::DateTime<Utc> {
+        due.next_occurrence().unwrap().unwrap().due_on_utc
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        assert_eq!(
+            next(&due(2020, 3, 1, 9, "UTC", Frequency::Daily, 2)),
+            Utc.ymd(2020, 3, 3).and_hms(9, 0, 0)
+        );
+        assert_eq!(
+            next(&due(2020, 3, 1, 9, "UTC", Frequency::Weekly, 1)),
+            Utc.ymd(2020, 3, 8).and_hms(9, 0, 0)
+        );
+
+        // the day is clamped to the end of a shorter month
+        assert_eq!(
+            next(&due(2020, 1, 31, 9, "UTC", Frequency::Monthly, 1)),
+            Utc.ymd(2020, 2, 29).and_hms(9, 0, 0)
+        );
+        assert_eq!(
+            next(&due(2020, 11, 30, 9, "UTC", Frequency::Monthly, 3)),
+            Utc.ymd(2021, 2, 28).and_hms(9, 0, 0)
+        );
+        assert_eq!(
+            next(&due(2020, 2, 29, 9, "UTC", Frequency::Yearly, 1)),
+            Utc.ymd(2021, 2, 28).and_hms(9, 0, 0)
+        );
+
+        // one-off items have no next occurrence
+        let mut one_off = due(2020, 3, 1, 9, "UTC", Frequency::Daily, 1);
+        one_off.recurrence = None;
+        assert!(one_off.next_occurrence().unwrap().is_none());
+
+        // unknown time zones are reported
+        assert!(due(2020, 3, 1, 9, "Mars/Olympus", Frequency::Daily, 1)
+            .next_occurrence()
+            .is_err());
+    }
+
+    #[test]
+    fn test_next_occurrence_dst() {
+        // 9am NZDT is 20:00 UTC the day before, 9am NZST after DST ends on Apr 5, 2020 is 21:00 UTC
+        assert_eq!(
+            next(&due(2020, 4, 3, 20, "Pacific/Auckland", Frequency::Daily, 2)),
+            Utc.ymd(2020, 4, 5).and_hms(21, 0, 0)
+        );
+
+        // 2:30am on Mar 8, 2020 does not exist in New York and is moved to 3:30am EDT
+        let mut gap = due(2020, 3, 7, 7, "America/New_York", Frequency::Daily, 1);
+        gap.due_on_utc = Utc.ymd(2020, 3, 7).and_hms(7, 30, 0);
+        assert_eq!(next(&gap), Utc.ymd(2020, 3, 8).and_hms(7, 30, 0));
+    }
+
+    #[test]
+    fn test_remind_on_utc() {
+        let mut item_due = due(2020, 3, 1, 9, "UTC", Frequency::Daily, 1);
+        assert!(item_due.remind_on_utc().unwrap().is_none());
+
+        item_due.remind_before_minutes = Some(90);
+        assert_eq!(item_due.remind_on_utc().unwrap(), Some(Utc.ymd(2020, 3, 1).and_hms(7, 30, 0)));
+
+        // the reminder offset is kept for the next occurrence
+        let next_due = item_due.next_occurrence().unwrap().unwrap();
+        assert_eq!(next_due.remind_on_utc().unwrap(), Some(Utc.ymd(2020, 3, 2).and_hms(7, 30, 0)));
+
+        // times out of range are errors
+        item_due.remind_before_minutes = Some(i64::MAX);
+        assert!(item_due.remind_on_utc().is_err());
+        item_due.remind_before_minutes = Some(i64::MIN);
+        assert!(item_due.remind_on_utc().is_err());
+    }
+
+    #[test]
+    fn test_next_occurrence_out_of_range() {
+        for frequency in [
+            Frequency::Daily,
+            Frequency::Weekly,
+            Frequency::Monthly,
+            Frequency::Yearly,
+        ]
+        .iter()
+        {
+            let item_due = due(2020, 3, 1, 9, "UTC", *frequency, u32::MAX);
+            assert!(item_due.next_occurrence().is_err(), "{:?} is out of range", frequency);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::list_export::html_escape;
+use crate::structures_ddb::LdList;
+use crate::structures_pg::{self, PgError, SEARCH_MATCH_END, SEARCH_MATCH_START};
+use log::{self, debug};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[path = "./search_test.rs"]
+pub(crate) mod tests_search;
+
+const ERR_MSG_SEARCH_FAILED: &str = "Failed to search the lists. Try again.";
+
+/// Default number of search results
+pub(crate) const SEARCH_LIMIT: i64 = 20;
+
+/// A list matching the search query.
+#[derive(Debug, Serialize)]
+pub(crate) struct SearchResult {
+    pub lid: Uuid,
+    pub title: String,
+    /// Higher is better. Only comparable within the same search.
+    pub rank: f32,
+    /// HTML-escaped fragments of the list text with the matches wrapped into `<mark>` tags.
+    pub snippet_html: String,
+}
+
+/// Returns lists the user owns or has access to that match the query, best matches first.
+/// The search covers list titles, descriptions and the titles and descriptions of their items.
+pub(crate) async fn search_user_lists(
+    user_id: Uuid,
+    query: &str,
+    limit: Option<i64>,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Vec<SearchResult>, String> {
+    let query = query.trim();
+    debug!("search_user_lists for {}: {}", user_id, query);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let hits = structures_pg::search_user_lists(user_id, query, limit.unwrap_or(SEARCH_LIMIT), pg_client)
+        .await
+        .map_err(|_| ERR_MSG_SEARCH_FAILED.to_string())?;
+    Ok(hits
+        .into_iter()
+        .map(|hit| SearchResult {
+            lid: hit.lid,
+            title: hit.title,
+            rank: hit.rank,
+            snippet_html: highlight_snippet(&hit.snippet),
+        })
+        .collect())
+}
+
+/// Returns the text of the list that goes into the search index, excluding the title:
+/// the list description followed by titles and descriptions of items not in the trash, one per line.
+pub(crate) fn search_body(list: &LdList) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    if let Some(description) = list.description.as_ref() {
+        lines.push(description);
+    }
+    for item in list.visible_items() {
+        lines.push(&item.title);
+        if let Some(description) = item.description.as_ref() {
+            lines.push(description);
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Copies the searchable text of the list to PG. Called by the stream handler for every change of the list.
+pub(crate) async fn index_list(list: &LdList, pg_client: &tokio_postgres::Client) -> Result<(), PgError> {
+    structures_pg::put_t_list_search(list.lid, &list.title, &search_body(list), pg_client).await
+}
+
+/// Converts a snippet from PG into HTML. The text is escaped and the matches are wrapped into `<mark>` tags.
+pub(crate) fn highlight_snippet(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    let mut text = String::new();
+    let mut in_match = false;
+    for c in snippet.chars() {
+        match c {
+            SEARCH_MATCH_START | SEARCH_MATCH_END => {
+                html.push_str(&html_escape(&text));
+                text.clear();
+
+                // unbalanced markers are dropped
+                if c == SEARCH_MATCH_START && !in_match {
+                    html.push_str("<mark>");
+                    in_match = true;
+                } else if c == SEARCH_MATCH_END && in_match {
+                    html.push_str("</mark>");
+                    in_match = false;
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    html.push_str(&html_escape(&text));
+
+    // PG may cut the snippet inside a match
+    if in_match {
+        html.push_str("</mark>");
+    }
+
+    html
+}
@@ -0,0 +1,113 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_search {
+    use crate::search::*;
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::{del_t_user, put_t_user, TListItem};
+    use log::{self, debug};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_highlight_snippet() {
+        assert_eq!(
+            highlight_snippet("Buy \u{2}milk\u{3} & <b>bread</b>"),
+            "Buy <mark>milk</mark> &amp; &lt;b&gt;bread&lt;/b&gt;"
+        );
+
+        // unbalanced markers are dropped or closed
+        assert_eq!(highlight_snippet("a\u{3}b \u{2}c"), "ab <mark>c</mark>");
+        assert_eq!(highlight_snippet("no matches"), "no matches");
+    }
+
+    #[test]
+    fn test_search_body() {
+        let lid = Uuid::new_v4();
+        let mut list = LdList::new(lid, "Title".to_string(), Uuid::new_v4());
+        list.description = Some("About".to_string());
+        let mut trashed = TListItem::new(Uuid::new_v4(), lid);
+        trashed.deleted_on_utc = Some(chrono::Utc::now());
+        list.items = Some(vec![
+            LdListItem {
+                title: "One".to_string(),
+                description: Some("First".to_string()),
+                due: None,
+                rel: TListItem::new(Uuid::new_v4(), lid),
+            },
+            LdListItem {
+                title: "Deleted".to_string(),
+                description: None,
+                due: None,
+                rel: trashed,
+            },
+            LdListItem {
+                title: "Two".to_string(),
+                description: None,
+                due: None,
+                rel: TListItem::new(Uuid::new_v4(), lid),
+            },
+        ]);
+
+        assert_eq!(search_body(&list), "About\nOne\nFirst\nTwo");
+    }
+
+    #[tokio::test]
+    async fn test_search_user_lists() {
+        debug!("test_search_user_lists started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create 2 users with a list each
+        let mut user_ids: Vec<Uuid> = Vec::new();
+        for _ in 0..2 {
+            let user_email = ["test_search_user_lists@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+            let pg_user = put_t_user(&user_email, &pg_client)
+                .await
+                .unwrap()
+                .expect("Failed to create a new user");
+            user_ids.push(pg_user.user_id);
+        }
+        let lids: [Uuid; 2] = [Uuid::new_v4(), Uuid::new_v4()];
+        let search_word = Uuid::new_v4().to_simple().to_string();
+        for (lid, user_id) in lids.iter().zip(user_ids.iter()) {
+            let mut list = test_helpers::create_random_list(*lid, *user_id, &ddb_client, &pg_client).await;
+            list.items.as_mut().unwrap()[0].description = Some(["Contains ", search_word.as_str()].concat());
+            list.save_in_ddb(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to save the list");
+            test_helpers::apply_stream(*lid, &ddb_client, &pg_client).await;
+        }
+
+        // only the list of the 1st user is found
+        let results = search_user_lists(user_ids[0], &search_word, None, &pg_client)
+            .await
+            .expect("Failed to search");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].lid, lids[0]);
+        assert!(results[0].snippet_html.contains("<mark>"));
+
+        // lists in the trash are not searched
+        LdList::move_to_trash(lids[0], &ddb_client, &pg_client)
+            .await
+            .expect("Failed to trash the list");
+        assert!(search_user_lists(user_ids[0], &search_word, None, &pg_client)
+            .await
+            .expect("Failed to search")
+            .is_empty());
+
+        // clean up
+        for (lid, user_id) in lids.iter().zip(user_ids.iter()) {
+            LdList::get_from_ddb_incl_trash(lid, &ddb_client)
+                .await
+                .unwrap()
+                .unwrap()
+                .delete_from_all_dbs(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to delete the list");
+            del_t_user(*user_id, &pg_client)
+                .await
+                .expect("Failed to delete the user");
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::context;
+use crate::search;
+use crate::structures_ddb::LdList;
+use crate::structures_pg;
+use crate::webhooks;
+use dynomite::{dynamodb::AttributeValue, Attributes, FromAttributes};
+use log::{self, debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[path = "./stream_test.rs"]
+pub(crate) mod tests_stream;
+
+/// A batch of DynamoDB Streams records for `tlist` table as Lambda receives it.
+/// The stream must be configured with `NEW_AND_OLD_IMAGES` view type.
+#[derive(Deserialize, Debug)]
+pub(crate) struct StreamEvent {
+    #[serde(rename = "Records")]
+    pub records: Vec<StreamRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct StreamRecord {
+    #[serde(rename = "eventID")]
+    pub event_id: String,
+    #[serde(rename = "eventName")]
+    pub event_name: StreamEventName,
+    pub dynamodb: StreamData,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum StreamEventName {
+    Insert,
+    Modify,
+    Remove,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct StreamData {
+    #[serde(rename = "Keys")]
+    pub keys: HashMap<String, AttributeValue>,
+    #[serde(rename = "NewImage", default)]
+    pub new_image: Option<Attributes>,
+    #[serde(rename = "OldImage", default)]
+    pub old_image: Option<Attributes>,
+    #[serde(rename = "SequenceNumber")]
+    pub sequence_number: String,
+}
+
+/// A change of a list decoded from a stream record.
+#[derive(Debug)]
+pub(crate) struct ListChange {
+    /// The ID of the stream record. It is the same when Lambda retries the record.
+    pub event_id: String,
+    pub lid: Uuid,
+    pub event_name: StreamEventName,
+    /// The list before the change. None for new lists.
+    pub old: Option<LdList>,
+    /// The list after the change. None for deleted lists.
+    pub new: Option<LdList>,
+}
+
+/// Lambda response for partial batch failures. Lambda retries the batch from the first failed record.
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct BatchResponse {
+    #[serde(rename = "batchItemFailures")]
+    pub batch_item_failures: Vec<BatchItemFailure>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct BatchItemFailure {
+    /// The sequence number of the failed record
+    #[serde(rename = "itemIdentifier")]
+    pub item_identifier: String,
+}
+
+/// Parses a Lambda event with DynamoDB Streams records.
+pub(crate) fn parse_event(event: &str) -> Result<StreamEvent, String> {
+    serde_json::from_str(event).map_err(|e| {
+        error!("Invalid stream event: {}", e);
+        format!("Invalid stream event: {}", e)
+    })
+}
+
+/// Decodes the key and the old and new images of the record into LdList structures.
+pub(crate) fn parse_record(record: &StreamRecord) -> Result<ListChange, String> {
+    let lid = record
+        .dynamodb
+        .keys
+        .get("lid")
+        .and_then(|key| key.s.as_ref())
+        .and_then(|key| Uuid::parse_str(key).ok())
+        .ok_or_else(|| format!("Missing or invalid lid in {}", record.event_id))?;
+
+    let to_list = |image: &Option<Attributes>| -> Result<Option<LdList>, String> {
+        match image {
+            Some(attrs) => LdList::from_attrs(attrs.clone())
+                .map(Some)
+                .map_err(|e| format!("Cannot convert the image of {} into LdList: {:?}", lid, e)),
+            None => Ok(None),
+        }
+    };
+    let old = to_list(&record.dynamodb.old_image)?;
+    let new = to_list(&record.dynamodb.new_image)?;
+
+    // both images are needed to keep the derived data correct
+    match (record.event_name, &old, &new) {
+        (StreamEventName::Insert, _, Some(_))
+        | (StreamEventName::Modify, Some(_), Some(_))
+        | (StreamEventName::Remove, Some(_), _) => {}
+        _ => {
+            return Err(format!(
+                "{:?} record {} has no images. Is the stream view type NEW_AND_OLD_IMAGES?",
+                record.event_name, record.event_id
+            ))
+        }
+    }
+
+    Ok(ListChange {
+        event_id: record.event_id.clone(),
+        lid,
+        event_name: record.event_name,
+        old,
+        new,
+    })
+}
+
+/// Lambda handler for `tlist` table stream. Updates the tag and search indexes and item counts in PG and notifies
+/// webhooks for every change, including changes made outside of this code, e.g. by TTL or in the console.
+/// This is the only place the indexes are updated. The audit log is written by the functions making the changes
+/// because only they know who made them.
+/// Processing stops at the first failed record, which is returned for Lambda to retry from.
+pub(crate) async fn handle_stream_event(event: StreamEvent, pg_client: &tokio_postgres::Client) -> BatchResponse {
+    info!("Stream records: {}", event.records.len());
+
+    for record in event.records.iter() {
+        let applied = match parse_record(record) {
+            Ok(change) => apply_change(&change, pg_client).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = applied {
+            error!("{}", e);
+            return BatchResponse {
+                batch_item_failures: vec![BatchItemFailure {
+                    item_identifier: record.dynamodb.sequence_number.clone(),
+                }],
+            };
+        }
+    }
+
+    BatchResponse::default()
+}
+
+/// Brings the data derived from the list up to date with the change. Every step overwrites the derived data,
+/// so the change can be applied again if the record is retried.
+pub(crate) async fn apply_change(change: &ListChange, pg_client: &tokio_postgres::Client) -> Result<(), String> {
+    context::scope_list(change.lid, None, async move {
+        debug!("apply_change {:?} for {}", change.event_name, change.lid);
+        let err = |_| format!("Failed to update the data derived from {}", change.lid);
+
+        match change.new.as_ref() {
+            Some(list) => {
+                structures_pg::put_t_list_tags(list.lid, list.tags.as_deref().unwrap_or_default(), pg_client)
+                    .await
+                    .map_err(err)?;
+                search::index_list(list, pg_client).await.map_err(err)?;
+                let (item_count, done_count) = item_counts(list);
+                structures_pg::put_t_list_counts(list.lid, item_count, done_count, pg_client)
+                    .await
+                    .map_err(err)?;
+            }
+            None => structures_pg::del_t_list_derived(change.lid, pg_client)
+                .await
+                .map_err(err)?,
+        }
+
+        webhooks::notify(change, pg_client).await.map_err(err)?;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the number of items not in the trash and how many of them are done.
+pub(crate) fn item_counts(list: &LdList) -> (i32, i32) {
+    list.visible_items().fold((0, 0), |(total, done), item| {
+        (total + 1, done + item.rel.completed_on_utc.is_some() as i32)
+    })
+}
@@ -0,0 +1,66 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_stream {
+    use crate::stream::*;
+    use uuid::Uuid;
+
+    /// A recorded event with INSERT, MODIFY and REMOVE records of the same list
+    const STREAM_EVENT: &str = include_str!("./test_fixtures/tlist_stream_event.json");
+
+    #[test]
+    fn test_parse_stream_event() {
+        let event = parse_event(STREAM_EVENT).expect("Cannot parse the fixture");
+        assert_eq!(event.records.len(), 3);
+        let lid = Uuid::parse_str("7d6b2b0c-58a4-4d0a-9d1e-3a1f0c2d4e51").unwrap();
+
+        // a new list without items
+        let insert = parse_record(&event.records[0]).unwrap();
+        assert_eq!(insert.event_name, StreamEventName::Insert);
+        assert_eq!(insert.lid, lid);
+        assert!(insert.old.is_none());
+        let new_list = insert.new.as_ref().unwrap();
+        assert_eq!(new_list.title, "Camping");
+        assert!(new_list.items.is_none());
+        assert_eq!(item_counts(new_list), (0, 0));
+
+        // 3 items were added, one of them is done and one is in the trash
+        let modify = parse_record(&event.records[1]).unwrap();
+        assert_eq!(modify.event_name, StreamEventName::Modify);
+        assert!(modify.old.as_ref().unwrap().items.is_none());
+        let new_list = modify.new.as_ref().unwrap();
+        assert_eq!(new_list.items.as_ref().unwrap().len(), 3);
+        assert_eq!(new_list.tags, Some(vec!["outdoors".to_string(), "family".to_string()]));
+        assert_eq!(item_counts(new_list), (2, 1));
+
+        // the list was deleted
+        let remove = parse_record(&event.records[2]).unwrap();
+        assert_eq!(remove.event_name, StreamEventName::Remove);
+        assert!(remove.new.is_none());
+        assert_eq!(
+            remove.old.as_ref().unwrap().rel.user_id.unwrap().to_string(),
+            "0b1f6a3e-2c4d-4e8f-9a7b-5c6d7e8f9a0b"
+        );
+    }
+
+    #[test]
+    fn test_parse_stream_record_errors() {
+        // the stream was configured without old images
+        let mut event = parse_event(STREAM_EVENT).unwrap();
+        event.records[1].dynamodb.old_image = None;
+        assert!(parse_record(&event.records[1])
+            .unwrap_err()
+            .contains("NEW_AND_OLD_IMAGES"));
+
+        // an image that is not an LdList
+        let mut event = parse_event(STREAM_EVENT).unwrap();
+        event.records[0].dynamodb.new_image.as_mut().unwrap().remove("title");
+        assert!(parse_record(&event.records[0]).is_err());
+
+        // a record that is not from `tlist` table
+        let mut event = parse_event(STREAM_EVENT).unwrap();
+        event.records[0].dynamodb.keys.clear();
+        assert!(parse_record(&event.records[0]).unwrap_err().contains("lid"));
+
+        assert!(parse_event("{\"Records\": [{}]}").is_err());
+    }
+}
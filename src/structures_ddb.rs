@@ -1,11 +1,26 @@
-use crate::structures_pg::{self};
+use crate::audit::{self, AuditAction};
+use crate::cache::{self, ReadConsistency};
+use crate::content;
+use crate::context;
+use crate::metrics;
+use crate::realtime;
+use crate::retry::{self, RetryPolicy};
+use crate::revisions;
+use crate::schedule::ItemDue;
+use crate::structures_pg::{self, TAudit};
+use crate::tags;
+use crate::trace::{self, Span, SpanKind};
 use crate::utils;
+use crate::validation::{self, Validate};
+use crate::webhooks::WebhookEvent;
 use dynomite::{
-    dynamodb::{DynamoDb, DynamoDbClient},
-    FromAttributes, Item,
+    dynamodb::{BatchWriteItemInput, DynamoDb, DynamoDbClient, PutItemInput, UpdateItemError, UpdateItemInput},
+    Attribute, Attributes, FromAttributes, Item,
 };
 use log::{self, debug, error};
+use rusoto_core::RusotoError;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use tokio_postgres;
 use uuid::Uuid;
 
@@ -14,22 +29,39 @@ pub(crate) mod tests_ddb;
 
 // DDB structures
 
-const TABLE_NAME_TLIST: &str = "tlist";
-const TABLE_KEY_FOR_TLIST: &str = "lid";
+pub(crate) const TABLE_NAME_TLIST: &str = "tlist";
+pub(crate) const TABLE_KEY_FOR_TLIST: &str = "lid";
 const ERR_MSG_SAVING_ITEM_FAILED: &str = "Failed to save this new list item in the DB.";
+const ERR_MSG_LIST_DOES_NOT_EXIST: &str = "The list doesn't exist";
+const ERR_MSG_ITEM_DOES_NOT_EXIST: &str = "The list item doesn't exist";
+const ERR_MSG_ITEM_MOVED: &str = "The list changed since it was loaded. Reload it and try again.";
+const ERR_MSG_INVALID_DOCUMENT: &str = "The list in the DB is damaged and cannot be read.";
+const ERR_MSG_INDEX_FAILED: &str = "Failed to read or update the list index. Try again.";
+const ERR_MSG_DELETE_FAILED: &str = "Failed to delete the list. Try again.";
+const DDB_BATCH_WRITE_ATTEMPTS: usize = 3;
+
+/// Number of done items out of all items in a list and its child lists.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
 
 /// A single list item. Part of LdList.
-#[derive(Item, Debug, Serialize, Deserialize)]
+#[derive(Item, Debug, Serialize, Deserialize, Clone)]
 pub(crate) struct LdListItem {
     #[dynomite(partition_key)]
     pub title: String,
     #[dynomite(default)]
     pub description: Option<String>,
+    /// Due date, reminder and recurrence. Copied to `rel` for the PG queries.
+    #[dynomite(default)]
+    pub due: Option<ItemDue>,
     pub rel: structures_pg::TListItem,
 }
 
 /// A complete List structure to exchange with the front-end
-#[derive(Item, Debug, Serialize, Deserialize)]
+#[derive(Item, Debug, Serialize, Deserialize, Clone)]
 pub(crate) struct LdList {
     #[dynomite(partition_key)]
     pub lid: Uuid,
@@ -43,6 +75,83 @@ pub(crate) struct LdList {
     pub rel: structures_pg::TList,
 }
 
+/// Changes to the list fields that are saved with UpdateItem without rewriting the items. Fields set to None
+/// are left as they are.
+#[derive(Debug, Default)]
+pub(crate) struct ListPatch {
+    pub title: Option<String>,
+    /// `Some(None)` removes the description.
+    pub description: Option<Option<String>>,
+}
+
+/// Changes to the text of a single item saved with UpdateItem. Due dates and completion are kept in PG as well
+/// and have their own functions.
+#[derive(Debug, Default)]
+pub(crate) struct ItemPatch {
+    pub title: Option<String>,
+    /// `Some(None)` removes the description.
+    pub description: Option<Option<String>>,
+}
+
+impl ListPatch {
+    /// Adds SET / REMOVE actions for the changed fields.
+    pub(crate) fn to_update(&self, update: utils::UpdateExpressionBuilder) -> utils::UpdateExpressionBuilder {
+        text_update(update, "", &self.title, &self.description)
+    }
+
+    pub(crate) fn apply(&self, list: &mut LdList) {
+        if let Some(title) = &self.title {
+            list.title = title.clone();
+        }
+        if let Some(description) = &self.description {
+            list.description = description.clone();
+        }
+    }
+}
+
+impl ItemPatch {
+    /// Adds SET / REMOVE actions for the changed fields of the item at `position` in `items`.
+    pub(crate) fn to_update(
+        &self,
+        position: usize,
+        update: utils::UpdateExpressionBuilder,
+    ) -> utils::UpdateExpressionBuilder {
+        text_update(update, &format!("items[{}].", position), &self.title, &self.description)
+    }
+
+    pub(crate) fn apply(&self, item: &mut LdListItem) {
+        if let Some(title) = &self.title {
+            item.title = title.clone();
+        }
+        if let Some(description) = &self.description {
+            item.description = description.clone();
+        }
+    }
+}
+
+/// Title and description are patched the same way for lists and items.
+fn text_update(
+    update: utils::UpdateExpressionBuilder,
+    prefix: &str,
+    title: &Option<String>,
+    description: &Option<Option<String>>,
+) -> utils::UpdateExpressionBuilder {
+    let update = match title {
+        Some(v) => update.set(&[prefix, "title"].concat(), v.clone().into_attr()),
+        None => update,
+    };
+    match description {
+        Some(Some(v)) => update.set(&[prefix, "description"].concat(), v.clone().into_attr()),
+        Some(None) => update.remove(&[prefix, "description"].concat()),
+        None => update,
+    }
+}
+
+/// Starts a span for a DDB operation on a single list.
+fn ddb_span(operation: &str, lid: Uuid) -> Span {
+    trace::ddb_span(operation, TABLE_NAME_TLIST).with("lid", lid.to_string())
+}
+
 impl LdList {
     /// Create a new LdList struct with no items and only required fields.
     /// It is not saved in the DB.
@@ -60,68 +169,349 @@ impl LdList {
         }
     }
 
-    /// Save itself in DDB, get the latest version back and return it wrapped in Result.
-    /// The `rel` section is saved in PG if none exists.
+    /// Validates the list before it is saved. If it breaks a rule, the saved version is read to let through the
+    /// errors it already had, see `validation::validate_changes`.
+    async fn validate_with_stored(&self, ddb_client: &DynamoDbClient) -> Result<(), String> {
+        if self.validate().is_ok() {
+            return Ok(());
+        }
+        let stored = match self.rel.created_on_utc {
+            Some(_) => LdList::get_from_ddb_incl_trash(&self.lid, ddb_client).await?,
+            None => None,
+        };
+        validation::validate_changes_for_save(self, stored.as_ref())
+    }
+
+    /// Save itself in DDB and return the document as it was written, wrapped in Result. It is not read back,
+    /// so concurrent changes are not included. Use `save_in_ddb_consistent` if they matter.
+    /// The `rel` section is saved in PG if none exists. The tag and search indexes are updated from the DDB stream.
     pub(crate) async fn save_in_ddb(
         mut self,
         ddb_client: &DynamoDbClient,
         pg_client: &tokio_postgres::Client,
     ) -> Result<Option<Self>, String> {
-        // this var will be used a few times
-        let lid = self.lid.clone();
+        context::scope_list(self.lid, None, async move {
+            // this var will be used a few times
+            let lid = self.lid.clone();
 
-        debug!("save_in_ddb for {}", lid);
+            debug!("save_in_ddb for {}", lid);
+            let _span = trace::span("LdList::save_in_ddb", SpanKind::Internal).with("lid", lid.to_string());
+            content::sanitize_list(&mut self);
+            // the tag and search indexes in PG are updated from the DDB stream
+            self.tags = tags::normalize_tags(self.tags.take());
+            self.validate_with_stored(ddb_client).await?;
 
-        // check if it's a brand-new list and needs `rel` section created in PG first
-        if self.rel.created_on_utc.is_none() {
-            let pg_list = structures_pg::put_t_list(&self.rel, &pg_client).await;
+            // check if it's a brand-new list and needs `rel` section created in PG first
+            if self.rel.created_on_utc.is_none() {
+                let pg_list = structures_pg::put_t_list(&self.rel, &pg_client)
+                    .await
+                    .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
 
-            // exit if there is no list
-            if pg_list.is_none() {
-                error!(
-                    "Failed to create a new list for user {} / lid {}",
-                    self.rel.user_id.expect("Missing user_id"),
-                    lid
-                );
-                return Err("Failed to create a new list.".to_string());
+                // exit if there is no list
+                if pg_list.is_none() {
+                    error!(
+                        "Failed to create a new list for user {} / lid {}",
+                        self.rel.user_id.expect("Missing user_id"),
+                        lid
+                    );
+                    return Err("Failed to create a new list.".to_string());
+                }
+
+                // replace the placeholder list with the proper one from PG
+                self.rel = pg_list.unwrap();
             }
 
-            // replace the placeholder list with the proper one from PG
-            self.rel = pg_list.unwrap();
+            // put the item in DDB and get the previous version back for the revision history
+            let saved = self.clone();
+            let doc: Attributes = self.into();
+            let put_input = PutItemInput {
+                return_values: Some("ALL_OLD".to_string()),
+                ..utils::build_ddb_put_input(doc, TABLE_NAME_TLIST)
+            };
+            let put = || ddb_client.put_item(put_input.clone());
+            let put_result = retry::with_retry_in(&RetryPolicy::default(), ddb_span("tlist.put_item", lid), put).await;
+            cache::invalidate(lid).await;
+            let previous_doc = match put_result {
+                Ok(v) => {
+                    metrics::record_capacity("tlist.put_item", v.consumed_capacity.as_ref());
+                    v.attributes
+                }
+                Err(put_err) => {
+                    error!("Failed to put_item {:?}", put_err);
+                    return Err("Failed to save in DDB.".to_string());
+                }
+            };
+            debug!("Item put in DDB.");
+
+            // record the change in the audit log and the revision history
+            let previous = previous_doc.and_then(|attrs| LdList::from_attrs(attrs).ok());
+            audit::record(
+                TAudit {
+                    user_id: saved.rel.user_id,
+                    lid: Some(lid),
+                    before: previous.as_ref().map(audit::list_summary),
+                    after: Some(audit::list_summary(&saved)),
+                    ..TAudit::new(AuditAction::SaveList)
+                },
+                pg_client,
+            )
+            .await;
+            revisions::record_revision(previous.as_ref(), saved.clone(), ddb_client).await;
+
+            // the document we wrote is what DDB has now, so there is no need to read it back
+            Ok(Some(saved))
+        })
+        .await
+    }
+
+    /// Same as `save_in_ddb`, but reads the list back from DDB with a strongly consistent read, even if it is
+    /// in the trash. Costs an extra read, so only use it when changes made by others since the write matter.
+    pub(crate) async fn save_in_ddb_consistent(
+        self,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Self>, String> {
+        let lid = self.lid;
+        self.save_in_ddb(ddb_client, pg_client).await?;
+        LdList::get_from_ddb_incl_trash(&lid, ddb_client).await
+    }
+
+    /// Saves only the changed fields of the list with UpdateItem, leaving the items and any concurrent changes
+    /// to them alone. Returns the updated list or an error if it doesn't exist.
+    pub(crate) async fn patch_in_ddb(
+        lid: Uuid,
+        patch: &ListPatch,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Self, String> {
+        context::scope_list(lid, None, async move {
+            debug!("patch_in_ddb for {}: {:?}", lid, patch);
+            let _span = trace::span("LdList::patch_in_ddb", SpanKind::Internal).with("lid", lid.to_string());
+            let patch = &content::sanitized_list_patch(patch);
+            patch.validate_for_save()?;
+
+            let update = patch.to_update(utils::UpdateExpressionBuilder::new().condition_exists(TABLE_KEY_FOR_TLIST));
+            if update.is_empty() {
+                return LdList::get_from_ddb_incl_trash(&lid, ddb_client)
+                    .await?
+                    .ok_or_else(|| ERR_MSG_LIST_DOES_NOT_EXIST.to_string());
+            }
+            let previous_doc = LdList::update_in_ddb(lid, update, ERR_MSG_LIST_DOES_NOT_EXIST, ddb_client).await?;
+
+            // the update returns the old version, so the new one is the old one with the same changes
+            let previous = LdList::from_doc(lid, previous_doc)?;
+            let mut saved = previous.clone();
+            patch.apply(&mut saved);
+
+            audit::record(
+                TAudit {
+                    user_id: saved.rel.user_id,
+                    lid: Some(lid),
+                    before: Some(audit::list_summary(&previous)),
+                    after: Some(audit::list_summary(&saved)),
+                    ..TAudit::new(AuditAction::SaveList)
+                },
+                pg_client,
+            )
+            .await;
+
+            Ok(LdList::record_patch(previous, saved, ddb_client).await)
+        })
+        .await
+    }
+
+    /// Runs UpdateItem on the list and returns the document as it was before the update.
+    /// Returns `condition_err` if a condition of the update failed.
+    async fn update_in_ddb(
+        lid: Uuid,
+        update: utils::UpdateExpressionBuilder,
+        condition_err: &str,
+        ddb_client: &DynamoDbClient,
+    ) -> Result<Attributes, String> {
+        debug!("UpdateItem for {} with {}", lid, update.update_expression());
+
+        // the old version is needed for the revision history and the audit log
+        let update_input = UpdateItemInput {
+            return_values: Some("ALL_OLD".to_string()),
+            ..utils::build_ddb_update_input(TABLE_KEY_FOR_TLIST, lid, update, TABLE_NAME_TLIST)
+        };
+        let update = || ddb_client.update_item(update_input.clone());
+        let update_result =
+            retry::with_retry_in(&RetryPolicy::default(), ddb_span("tlist.update_item", lid), update).await;
+        cache::invalidate(lid).await;
+        match update_result {
+            Ok(v) => {
+                debug!("Item updated in DDB.");
+                metrics::record_capacity("tlist.update_item", v.consumed_capacity.as_ref());
+                v.attributes.ok_or_else(|| condition_err.to_string())
+            }
+            Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => {
+                debug!("UpdateItem condition failed for {}", lid);
+                Err(condition_err.to_string())
+            }
+            Err(e) => {
+                error!("Failed to update_item {:?}", e);
+                Err("Failed to save in DDB.".to_string())
+            }
         }
+    }
 
-        // put the item in DDB
-        if let Err(put_err) = ddb_client
-            .put_item(utils::build_ddb_put_input(self.into(), TABLE_NAME_TLIST))
-            .await
-        {
-            error!("Failed to put_item {:?}", put_err);
-            return Err("Failed to save in DDB.".to_string());
+    /// Records a revision for a patched list and returns the list.
+    async fn record_patch(previous: LdList, saved: LdList, ddb_client: &DynamoDbClient) -> LdList {
+        revisions::record_revision(Some(&previous), saved.clone(), ddb_client).await;
+        saved
+    }
+
+    /// Converts a document from DDB into LdList. The error is logged with the details.
+    fn from_doc(lid: Uuid, doc: Attributes) -> Result<Self, String> {
+        LdList::from_attrs(doc).map_err(|e| {
+            error!("Error converting DDB list {} into LdList: {:?}", lid, e);
+            ERR_MSG_INVALID_DOCUMENT.to_string()
+        })
+    }
+
+    /// Saves brand-new lists with their items in bulk: one PG call per list, one PG call for all items
+    /// and one DDB call per 25 lists. Items that are completed, due or trashed take a few more PG calls.
+    /// Returns the lists as they were saved.
+    pub(crate) async fn save_many_in_ddb(
+        lists: Vec<Self>,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Vec<Self>, String> {
+        debug!("save_many_in_ddb for {} lists", lists.len());
+        let mut lists = lists;
+
+        // nothing is saved if any of the lists is invalid, the tag and search indexes in PG are updated from
+        // the DDB stream
+        let mut errors: Vec<validation::FieldError> = Vec::new();
+        for (i, list) in lists.iter_mut().enumerate() {
+            content::sanitize_list(list);
+            list.tags = tags::normalize_tags(list.tags.take());
+            list.check(&format!("lists[{}].", i), &mut errors);
+        }
+        if !errors.is_empty() {
+            return Err(validation::error_message(&errors));
+        }
+
+        // create `rel` sections for the lists, which must exist before their items
+        // lists in the trash and templates take one more PG call each
+        for list in lists.iter_mut().filter(|l| l.rel.created_on_utc.is_none()) {
+            let (trashed, is_template) = (list.rel.deleted_on_utc.is_some(), list.rel.is_template);
+            let mut rel = structures_pg::put_t_list(&list.rel, pg_client)
+                .await
+                .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+            if trashed && rel.is_some() {
+                rel = structures_pg::trash_t_list(list.lid, true, pg_client)
+                    .await
+                    .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+            }
+            if is_template && rel.is_some() {
+                rel = structures_pg::put_t_list_template(list.lid, true, pg_client)
+                    .await
+                    .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+            }
+            list.rel = match rel {
+                Some(v) => v,
+                None => {
+                    error!("Failed to create a new list for lid {}", list.lid);
+                    return Err("Failed to create a new list.".to_string());
+                }
+            };
         }
-        debug!("Item put in DDB.");
 
-        // get the same record back from DDB
-        LdList::get_from_ddb(&lid, &ddb_client).await
+        // create `rel` sections for all new items in one go, completed items without a user were completed
+        // by the owner
+        let new_rels: Vec<structures_pg::TListItem> = lists
+            .iter()
+            .flat_map(|l| {
+                l.items
+                    .iter()
+                    .flatten()
+                    .filter(|i| i.rel.created_on_utc.is_none())
+                    .map(move |i| structures_pg::TListItem {
+                        completed_by: i.rel.completed_by.or(l.rel.user_id),
+                        ..i.rel.clone()
+                    })
+            })
+            .collect();
+        if !new_rels.is_empty() {
+            let mut saved_rels = LdListItem::create_rels(&new_rels, pg_client).await?;
+            for item in lists.iter_mut().filter_map(|l| l.items.as_mut()).flatten() {
+                if let Some(rel) = saved_rels.remove(&item.rel.liid) {
+                    item.rel = rel;
+                }
+            }
+        }
+
+        // write the documents to DDB in batches and keep a copy to return to the caller
+        let saved_lists = lists.clone();
+        let docs: Vec<Attributes> = lists.into_iter().map(|l| l.into()).collect();
+        let put_result = batch_put_in_ddb(&docs, TABLE_NAME_TLIST, ddb_client).await;
+        for list in &saved_lists {
+            cache::invalidate(list.lid).await;
+        }
+        put_result?;
+        debug!("Lists put in DDB.");
+
+        // the lists are new, so there is nothing to compare them to
+        revisions::record_new_lists(saved_lists.clone(), ddb_client).await;
+
+        Ok(saved_lists)
     }
 
-    /// Retrieve a single list from DDB by ID. Should not panic.
+    /// Retrieve a single list from the cache or DDB by ID. Lists in the trash are returned as None. Should not panic.
+    /// Use `get_from_ddb_with` and `ReadConsistency::Strong` to read the list before modifying it.
     pub(crate) async fn get_from_ddb(lid: &Uuid, ddb_client: &DynamoDbClient) -> Result<Option<Self>, String> {
+        LdList::get_from_ddb_with(lid, ReadConsistency::Cached, ddb_client).await
+    }
+
+    /// Same as `get_from_ddb`, but only reads from the cache if `consistency` allows it.
+    pub(crate) async fn get_from_ddb_with(
+        lid: &Uuid,
+        consistency: ReadConsistency,
+        ddb_client: &DynamoDbClient,
+    ) -> Result<Option<Self>, String> {
+        let cached = match consistency {
+            ReadConsistency::Cached => cache::get_list(*lid).await,
+            ReadConsistency::Strong => None,
+        };
+        let list = match cached {
+            Some(v) => Some(v),
+            None => LdList::get_from_ddb_incl_trash(lid, ddb_client).await?,
+        };
+
+        match list {
+            Some(list) if list.rel.deleted_on_utc.is_some() => {
+                debug!("List {} is in the trash - returning None.", lid);
+                Ok(None)
+            }
+            list => Ok(list),
+        }
+    }
+
+    /// Retrieve a single list from DDB by ID with a strongly consistent read, including lists in the trash.
+    /// The list is cached for `get_from_ddb`. Should not panic.
+    pub(crate) async fn get_from_ddb_incl_trash(
+        lid: &Uuid,
+        ddb_client: &DynamoDbClient,
+    ) -> Result<Option<Self>, String> {
         // this var will be used a few times
         let lid = lid.clone();
 
-        debug!("get_from_ddb for {}", lid);
+        debug!("get_from_ddb_incl_trash for {}", lid);
 
         // retrieve the latest copy, which may be a bit different from what was saved
-        match ddb_client
-            .get_item(utils::build_ddb_get_input(TABLE_KEY_FOR_TLIST, &lid, TABLE_NAME_TLIST))
-            .await
-        {
+        let get_input = utils::build_ddb_get_input(TABLE_KEY_FOR_TLIST, &lid, TABLE_NAME_TLIST);
+        let get = || ddb_client.get_item(get_input.clone());
+        match retry::with_retry_in(&RetryPolicy::default(), ddb_span("tlist.get_item", lid), get).await {
             Ok(get_item_output) => {
+                metrics::record_capacity("tlist.get_item", get_item_output.consumed_capacity.as_ref());
                 match get_item_output.item {
                     Some(output_item) => {
                         debug!("Raw from DDB: {:?}", output_item);
 
-                        let new_self = LdList::from_attrs(output_item).expect("Error converting DDB list into LdList");
+                        let new_self = LdList::from_doc(lid, output_item)?;
+                        cache::put_list(&new_self).await;
 
                         return Ok(Some(new_self));
                     }
@@ -138,16 +528,29 @@ impl LdList {
         }
     }
 
-    /// Retrieve a single list from DDB by ID. Should not panic.
+    /// Retrieve all lists of the user from the cache or DDB, excluding the trash. Items are not included.
+    /// Should not panic.
     pub(crate) async fn get_all_user_lists_from_ddb(
         user_id: Uuid,
         ddb_client: &DynamoDbClient,
         pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Vec<Self>>, String> {
+        LdList::get_all_user_lists_from_ddb_with(user_id, ReadConsistency::Cached, ddb_client, pg_client).await
+    }
+
+    /// Same as `get_all_user_lists_from_ddb`, but only reads from the cache if `consistency` allows it.
+    pub(crate) async fn get_all_user_lists_from_ddb_with(
+        user_id: Uuid,
+        consistency: ReadConsistency,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
     ) -> Result<Option<Vec<Self>>, String> {
         debug!("get_for_user_from_ddb");
 
         // get the list of list ids from PG
-        let list_ids = structures_pg::get_user_lists(user_id, &pg_client).await;
+        let list_ids = structures_pg::get_user_lists(user_id, &pg_client)
+            .await
+            .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
 
         // check if there is any data
         let list_ids = match list_ids {
@@ -167,32 +570,138 @@ impl LdList {
             }
         };
 
-        // get all user lists from DDB
-        match ddb_client
-            .batch_get_item(utils::build_ddb_get_batch_input(
-                TABLE_KEY_FOR_TLIST,
-                &list_ids,
-                TABLE_NAME_TLIST,
-            ))
+        // get all user lists from DDB and drop any that were trashed after PG was read
+        let lists = LdList::batch_get_from_ddb(&list_ids, consistency, ddb_client).await?;
+        Ok(lists.map(|lists| lists.into_iter().filter(|l| l.rel.deleted_on_utc.is_none()).collect()))
+    }
+
+    /// Returns all lists of the user with the tag, excluding the trash. Items are not included.
+    pub(crate) async fn get_user_lists_by_tag_from_ddb(
+        user_id: Uuid,
+        tag: &str,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Vec<Self>>, String> {
+        debug!("get_user_lists_by_tag_from_ddb for {} / {}", user_id, tag);
+
+        let tag = match tags::normalize_tag(tag) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        // PG has the tag index
+        let list_ids: Vec<Uuid> = structures_pg::get_user_tag_lists(user_id, &tag, false, pg_client)
             .await
-        {
+            .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?
+            .iter()
+            .map(|tl| tl.lid)
+            .collect();
+
+        LdList::batch_get_from_ddb(&list_ids, ReadConsistency::Cached, ddb_client).await
+    }
+
+    /// Returns templates of the user and the user's org, excluding the trash. Items are not included.
+    pub(crate) async fn get_user_templates_from_ddb(
+        user_id: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Vec<Self>>, String> {
+        debug!("get_user_templates_from_ddb for {}", user_id);
+
+        let list_ids: Vec<Uuid> = structures_pg::get_user_templates(user_id, pg_client)
+            .await
+            .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?
+            .iter()
+            .map(|tl| tl.lid)
+            .collect();
+
+        LdList::batch_get_from_ddb(&list_ids, ReadConsistency::Cached, ddb_client).await
+    }
+
+    /// Returns all lists the user moved to trash. Items are not included.
+    pub(crate) async fn get_user_trash_from_ddb(
+        user_id: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Vec<Self>>, String> {
+        debug!("get_user_trash_from_ddb for {}", user_id);
+
+        // get the list of trashed list ids from PG
+        let list_ids: Vec<Uuid> = match structures_pg::get_user_trash(user_id, pg_client).await {
+            Ok(Some(v)) => v.iter().map(|tl| tl.lid).collect(),
+            Ok(None) => {
+                return Ok(None);
+            }
+            Err(_) => return Err(ERR_MSG_INDEX_FAILED.to_string()),
+        };
+
+        LdList::batch_get_from_ddb(&list_ids, ReadConsistency::Cached, ddb_client).await
+    }
+
+    /// Retrieves up to 100 lists from the cache or from DDB in a single request. Items are not included.
+    async fn batch_get_from_ddb(
+        list_ids: &[Uuid],
+        consistency: ReadConsistency,
+        ddb_client: &DynamoDbClient,
+    ) -> Result<Option<Vec<Self>>, String> {
+        if list_ids.is_empty() {
+            return Ok(None);
+        }
+
+        // only the lists that are not cached are read from DDB
+        let mut cached: Vec<LdList> = Vec::new();
+        let mut missing: Vec<Uuid> = Vec::new();
+        for lid in list_ids {
+            match consistency {
+                ReadConsistency::Cached => match cache::get_summary(*lid).await {
+                    Some(v) => cached.push(v),
+                    None => missing.push(*lid),
+                },
+                ReadConsistency::Strong => missing.push(*lid),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(Some(cached));
+        }
+
+        let mut get_input = utils::build_ddb_get_batch_input(TABLE_KEY_FOR_TLIST, &missing, TABLE_NAME_TLIST);
+        if consistency == ReadConsistency::Strong {
+            for keys in get_input.request_items.values_mut() {
+                keys.consistent_read = Some(true);
+            }
+        }
+        let get = || ddb_client.batch_get_item(get_input.clone());
+        let span = trace::ddb_span("tlist.batch_get_item", TABLE_NAME_TLIST).with("db.keys", missing.len());
+        match retry::with_retry_in(&RetryPolicy::default(), span, get).await {
             Ok(get_items_output) => {
+                metrics::record_capacity("tlist.batch_get_item", get_items_output.consumed_capacity.iter().flatten());
                 match get_items_output.responses {
                     Some(mut output_tables) => {
                         debug!("Raw from DDB: {:?}", output_tables);
 
                         // extract the list and convert it into the output format
 
-                        let output_items = output_tables.remove(TABLE_NAME_TLIST).unwrap();
+                        let output_items = output_tables.remove(TABLE_NAME_TLIST).unwrap_or_default();
 
-                        let mut fn_output: Vec<LdList> = Vec::new();
+                        let mut fn_output: Vec<LdList> = cached;
                         for output_item in output_items {
-                            fn_output
-                                .push(LdList::from_attrs(output_item).expect("Error converting DDB list into LdList"));
+                            // the key is only needed to log which document is damaged
+                            let lid = output_item
+                                .get(TABLE_KEY_FOR_TLIST)
+                                .and_then(|v| v.s.as_ref())
+                                .and_then(|v| Uuid::parse_str(v).ok())
+                                .unwrap_or_default();
+                            let list = LdList::from_doc(lid, output_item)?;
+                            cache::put_summary(&list).await;
+                            fn_output.push(list);
                         }
 
                         return Ok(Some(fn_output));
                     }
+                    None if !cached.is_empty() => {
+                        error!("Some user lists are cached, but not in DDB - DDB is out of sync.");
+                        return Ok(Some(cached));
+                    }
                     None => {
                         error!("No user lists found in DDB - DDB is out of sync.");
                         return Ok(None);
@@ -212,111 +721,592 @@ impl LdList {
         ddb_client: &DynamoDbClient,
         pg_client: &tokio_postgres::Client,
     ) -> Result<(), String> {
-        debug!("delete_from_all_dbs for {}", self.lid);
+        context::scope_list(self.lid, None, async move {
+            debug!("delete_from_all_dbs for {}", self.lid);
+            let _span =
+                trace::span("LdList::delete_from_all_dbs", SpanKind::Internal).with("lid", self.lid.to_string());
 
-        // delete from PG
-        structures_pg::del_t_list(self.lid.clone(), &pg_client)
+            // delete from PG
+            structures_pg::del_t_list(self.lid.clone(), &pg_client)
+                .await
+                .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+            debug!("List deleted from PG.");
+
+            // delete from DDB
+            let del_input = utils::build_ddb_del_input(TABLE_KEY_FOR_TLIST, self.lid, TABLE_NAME_TLIST);
+            let delete = || ddb_client.delete_item(del_input.clone());
+            let deleted =
+                retry::with_retry_in(&RetryPolicy::default(), ddb_span("tlist.delete_item", self.lid), delete).await;
+            cache::invalidate(self.lid).await;
+            let deleted = deleted.map_err(|e| {
+                error!("Failed to delete {} from DDB: {:?}", self.lid, e);
+                ERR_MSG_DELETE_FAILED.to_string()
+            })?;
+            metrics::record_capacity("tlist.delete_item", deleted.consumed_capacity.as_ref());
+            debug!("List deleted from DDB.");
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Moves the list to trash in PG and DDB. The list is hidden from the user until it is restored or purged.
+    pub(crate) async fn move_to_trash(
+        lid: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Self>, String> {
+        debug!("move_to_trash for {}", lid);
+        LdList::set_trashed(lid, true, ddb_client, pg_client).await
+    }
+
+    /// Restores the list from trash in PG and DDB and returns it.
+    pub(crate) async fn restore_from_trash(
+        lid: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Self>, String> {
+        debug!("restore_from_trash for {}", lid);
+        LdList::set_trashed(lid, false, ddb_client, pg_client).await
+    }
+
+    /// Sets or clears the trash marker in PG first and then copies the updated `rel` into DDB.
+    async fn set_trashed(
+        lid: Uuid,
+        trashed: bool,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Self>, String> {
+        // get the list from DDB, wherever it is
+        let mut list = match LdList::get_from_ddb_incl_trash(&lid, ddb_client).await? {
+            Some(v) => v,
+            None => {
+                return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string());
+            }
+        };
+
+        // PG is updated first because it is the index for get_user_lists and the trash
+        list.rel = match structures_pg::trash_t_list(lid, trashed, pg_client).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                error!("Failed to update trash status in PG for lid {}", lid);
+                return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string());
+            }
+            Err(_) => return Err(ERR_MSG_INDEX_FAILED.to_string()),
+        };
+
+        list.save_in_ddb(ddb_client, pg_client).await
+    }
+
+    /// Hard-deletes lists and list items that have been in the trash for longer than `retention`.
+    /// Returns the number of purged lists and items. Lists and items that fail to purge are logged and left
+    /// for the next run.
+    pub(crate) async fn purge_trash(
+        retention: chrono::Duration,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<usize, String> {
+        let deleted_before = chrono::Utc::now() - retention;
+        debug!("purge_trash for items deleted before {}", deleted_before);
+        let mut purged = 0usize;
+
+        // purge whole lists first - their items go with them
+        let expired_lists = structures_pg::get_expired_t_lists(deleted_before, pg_client)
             .await
-            .expect("delete_from_all_dbs failed");
-        debug!("List deleted from PG.");
-
-        // delete from DDB
-        ddb_client
-            .delete_item(utils::build_ddb_del_input(
-                TABLE_KEY_FOR_TLIST,
-                self.lid.clone(),
-                TABLE_NAME_TLIST,
-            ))
+            .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+        for tl in expired_lists {
+            let del_input = utils::build_ddb_del_input(TABLE_KEY_FOR_TLIST, tl.lid, TABLE_NAME_TLIST);
+            let delete = || ddb_client.delete_item(del_input.clone());
+            let deleted =
+                retry::with_retry_in(&RetryPolicy::default(), ddb_span("tlist.delete_item", tl.lid), delete).await;
+            cache::invalidate(tl.lid).await;
+            match deleted {
+                Ok(v) => metrics::record_capacity("tlist.delete_item", v.consumed_capacity.as_ref()),
+                Err(e) => {
+                    error!("Failed to purge lid {} from DDB: {}", tl.lid, e);
+                    continue;
+                }
+            }
+            if let Err(e) = structures_pg::del_t_list(tl.lid, pg_client).await {
+                error!("Failed to purge lid {} from PG: {:?}", tl.lid, e);
+                continue;
+            }
+            purged += 1;
+        }
+
+        // purge individual items from lists that are still in use, items of trashed lists go with their list
+        // when it expires
+        let expired_items = structures_pg::get_expired_t_list_items(deleted_before, pg_client)
             .await
-            .expect("Failed to delete from DDB.");
-        debug!("List deleted from DDB.");
+            .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+        let mut trashed_lists: HashMap<Uuid, bool> = HashMap::new();
+        for tli in expired_items {
+            let list_trashed = match trashed_lists.get(&tli.parent_lid) {
+                Some(v) => *v,
+                None => {
+                    let trashed = structures_pg::get_t_list(tli.parent_lid, pg_client)
+                        .await
+                        .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?
+                        .is_none_or(|tl| tl.deleted_on_utc.is_some());
+                    trashed_lists.insert(tli.parent_lid, trashed);
+                    trashed
+                }
+            };
+            if list_trashed {
+                debug!("Skipping liid {} - lid {} is in trash", tli.liid, tli.parent_lid);
+                continue;
+            }
 
-        Ok(())
+            match LdListItem::del_list_item_ddb(tli.parent_lid, tli.liid, ddb_client, pg_client).await {
+                Ok(_) => purged += 1,
+                Err(e) => error!("Failed to purge liid {} from lid {}: {}", tli.liid, tli.parent_lid, e),
+            }
+        }
+
+        debug!("Purged: {}", purged);
+        Ok(purged)
+    }
+
+    /// Counts done and total items in this list and its child lists. Items in the trash are not counted.
+    /// An item with a child list is counted along with the items of the child list.
+    pub(crate) fn progress(&self, children: &HashMap<Uuid, LdList>) -> Progress {
+        let mut progress = Progress { done: 0, total: 0 };
+        let mut visited: HashSet<Uuid> = HashSet::new();
+        self.add_progress(children, &mut visited, &mut progress);
+
+        progress
+    }
+
+    fn add_progress(&self, children: &HashMap<Uuid, LdList>, visited: &mut HashSet<Uuid>, progress: &mut Progress) {
+        // guard against circular links between lists
+        if !visited.insert(self.lid) {
+            return;
+        }
+
+        for item in self.visible_items() {
+            progress.total += 1;
+            if item.rel.completed_on_utc.is_some() {
+                progress.done += 1;
+            }
+            if let Some(child) = item.rel.child_lid.and_then(|lid| children.get(&lid)) {
+                child.add_progress(children, visited, progress);
+            }
+        }
+    }
+
+    /// Loads the list with its child lists from DDB and returns its progress.
+    pub(crate) async fn get_progress_from_ddb(
+        lid: Uuid,
+        ddb_client: &DynamoDbClient,
+    ) -> Result<Option<Progress>, String> {
+        debug!("get_progress_from_ddb for {}", lid);
+
+        let list = match LdList::get_from_ddb(&lid, ddb_client).await? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let children = list.get_child_lists_from_ddb(ddb_client).await?;
+
+        Ok(Some(list.progress(&children)))
+    }
+
+    /// Loads all lists linked from the items of this list via `rel.child_lid`, all the way down.
+    pub(crate) async fn get_child_lists_from_ddb(
+        &self,
+        ddb_client: &DynamoDbClient,
+    ) -> Result<HashMap<Uuid, LdList>, String> {
+        let mut children: HashMap<Uuid, LdList> = HashMap::new();
+        let mut visited: HashSet<Uuid> = HashSet::new();
+        visited.insert(self.lid);
+        let mut to_visit: Vec<Uuid> = self.visible_items().filter_map(|i| i.rel.child_lid).collect();
+
+        while let Some(lid) = to_visit.pop() {
+            if !visited.insert(lid) {
+                continue;
+            }
+            match LdList::get_from_ddb(&lid, ddb_client).await? {
+                Some(child) => {
+                    to_visit.extend(child.visible_items().filter_map(|i| i.rel.child_lid));
+                    children.insert(lid, child);
+                }
+                None => debug!("Child list {} does not exist", lid),
+            }
+        }
+
+        debug!("Child lists loaded: {}", children.len());
+        Ok(children)
+    }
+
+    /// Items that were not moved to trash.
+    pub(crate) fn visible_items(&self) -> impl Iterator<Item = &LdListItem> {
+        self.items.iter().flatten().filter(|i| i.rel.deleted_on_utc.is_none())
+    }
+
+    /// Checks off all items in the list that are not done yet. Recurring items move to their next occurrence
+    /// instead. Child lists are not affected.
+    pub(crate) async fn check_all(
+        lid: Uuid,
+        user_id: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Self>, String> {
+        debug!("check_all for {}", lid);
+
+        let list = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+            Some(v) => v,
+            None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+        };
+        let liids: Vec<Uuid> = list
+            .visible_items()
+            .filter(|i| i.rel.completed_on_utc.is_none())
+            .map(|i| i.rel.liid)
+            .collect();
+        if liids.is_empty() {
+            return Ok(Some(list));
+        }
+
+        LdListItem::complete_in_list(list, &liids, true, user_id, ddb_client, pg_client).await
+    }
+
+    /// Moves all done items to trash.
+    pub(crate) async fn clear_completed(
+        lid: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Self>, String> {
+        debug!("clear_completed for {}", lid);
+
+        let list = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+            Some(v) => v,
+            None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+        };
+        let liids: Vec<Uuid> = list
+            .visible_items()
+            .filter(|i| i.rel.completed_on_utc.is_some())
+            .map(|i| i.rel.liid)
+            .collect();
+        if liids.is_empty() {
+            return Ok(Some(list));
+        }
+
+        let rels = structures_pg::trash_t_list_items(&liids, true, pg_client)
+            .await
+            .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+        list.save_with_rels(rels, ddb_client, pg_client).await
+    }
+
+    /// Replaces `rel` of the matching items with the updated versions from PG and saves the list.
+    async fn save_with_rels(
+        mut self,
+        rels: Vec<structures_pg::TListItem>,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<Self>, String> {
+        let mut rels: HashMap<Uuid, structures_pg::TListItem> = rels.into_iter().map(|r| (r.liid, r)).collect();
+        for item in self.items.iter_mut().flatten() {
+            if let Some(rel) = rels.remove(&item.rel.liid) {
+                item.rel = rel;
+            }
+        }
+
+        self.save_in_ddb(ddb_client, pg_client).await
     }
 }
 
+/// Writes the documents to DDB in batches of 25, retrying any requests DDB left unprocessed.
+pub(crate) async fn batch_put_in_ddb(
+    docs: &[Attributes],
+    table: &str,
+    ddb_client: &DynamoDbClient,
+) -> Result<(), String> {
+    for chunk in docs.chunks(utils::DDB_BATCH_WRITE_LIMIT) {
+        batch_write_in_ddb(utils::build_ddb_batch_put_input(chunk.to_vec(), table), table, ddb_client).await?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the documents with the keys from DDB in batches of 25, retrying any requests DDB left unprocessed.
+pub(crate) async fn batch_delete_in_ddb(
+    keys: &[Attributes],
+    table: &str,
+    ddb_client: &DynamoDbClient,
+) -> Result<(), String> {
+    for chunk in keys.chunks(utils::DDB_BATCH_WRITE_LIMIT) {
+        batch_write_in_ddb(utils::build_ddb_batch_del_input(chunk.to_vec(), table), table, ddb_client).await?;
+    }
+
+    Ok(())
+}
+
+/// Sends a single batch of writes to DDB.
+async fn batch_write_in_ddb(
+    mut batch: BatchWriteItemInput,
+    table: &str,
+    ddb_client: &DynamoDbClient,
+) -> Result<(), String> {
+    // DDB may leave some of the requests unprocessed under load, so they are sent again after a delay
+    let policy = RetryPolicy::default();
+    let operation = [table, ".batch_write_item"].concat();
+    for attempt in 1..=DDB_BATCH_WRITE_ATTEMPTS {
+        if attempt > 1 {
+            tokio::time::delay_for(policy.delay(attempt as u32 - 1)).await;
+        }
+        let write = || ddb_client.batch_write_item(batch.clone());
+        let keys: usize = batch.request_items.values().map(Vec::len).sum();
+        let span = trace::ddb_span(&operation, table).with("db.keys", keys);
+        let output = match retry::with_retry_in(&policy, span, write).await {
+            Ok(v) => {
+                metrics::record_capacity(&operation, v.consumed_capacity.iter().flatten());
+                v
+            }
+            Err(e) => {
+                error!("Failed to batch_write_item {:?}", e);
+                return Err("Failed to save in DDB.".to_string());
+            }
+        };
+        match output.unprocessed_items {
+            Some(unprocessed) if !unprocessed.is_empty() => batch.request_items = unprocessed,
+            _ => return Ok(()),
+        }
+    }
+
+    error!(
+        "DDB left some {} documents unprocessed after {} attempts",
+        table, DDB_BATCH_WRITE_ATTEMPTS
+    );
+    Err("Failed to save in DDB.".to_string())
+}
+
 impl LdListItem {
     /// Add a new or update an existing List Item inside its list. Updates DDB and PG in one go.
+    /// Returns the item as it was saved.
     pub(crate) async fn put_list_item_ddb(
         list_item: LdListItem,
         ddb_client: &DynamoDbClient,
         pg_client: &tokio_postgres::Client,
     ) -> Result<Self, String> {
-        // get the list from DDB
-        let list = LdList::get_from_ddb(&list_item.rel.parent_lid, &ddb_client).await;
+        let (mut list, position) = LdListItem::put_in_list(list_item, ddb_client, pg_client).await?;
+        let items = list.items.as_mut().expect("The saved list has no items");
+        Ok(items.swap_remove(position))
+    }
 
-        // return the error if no list exists or there were problems getting it from DDB
-        if list.is_err() {
-            return Err(list.unwrap_err());
-        }
-        let list = list.unwrap();
-        if list.is_none() {
-            return Err("The list for this item doesn't exist".to_string());
-        }
+    /// Same as `put_list_item_ddb`, but returns the whole list as it was saved to save the caller another read.
+    pub(crate) async fn put_list_item_and_get_list_ddb(
+        list_item: LdListItem,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<LdList, String> {
+        let (list, _) = LdListItem::put_in_list(list_item, ddb_client, pg_client).await?;
+        Ok(list)
+    }
 
-        // this is the actual list struct that will be modified
-        let mut list = list.unwrap();
+    /// Adds or updates the item and saves the list. Returns the saved list and the position of the item in it.
+    async fn put_in_list(
+        list_item: LdListItem,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<(LdList, usize), String> {
+        context::scope_list(list_item.rel.parent_lid, Some(list_item.rel.liid), async move {
+            let _span = trace::span("LdListItem::put_list_item_ddb", SpanKind::Internal)
+                .with("lid", list_item.rel.parent_lid.to_string())
+                .with("liid", list_item.rel.liid.to_string());
+            let mut list_item = list_item;
+            content::sanitize_item(&mut list_item);
+            list_item.validate_for_save()?;
 
-        // make sure there is a container for items
-        if list.items.is_none() {
-            list.items = Some(Vec::new());
-        }
+            // get the list from DDB
+            let list = LdList::get_from_ddb_with(&list_item.rel.parent_lid, ReadConsistency::Strong, &ddb_client).await;
 
-        // try to find the right item in the existing list
-        let mut is_existing_item = false;
-        let ref mut items = list.items.as_mut().unwrap();
-        for mut existing_item in items.into_iter() {
-            if existing_item.rel.liid == list_item.rel.liid {
-                existing_item.title = list_item.title.clone();
-                existing_item.description = list_item.description.clone();
-                is_existing_item = true;
-                break;
+            // return the error if no list exists or there were problems getting it from DDB
+            if list.is_err() {
+                return Err(list.unwrap_err());
             }
-        }
+            let list = list.unwrap();
+            if list.is_none() {
+                return Err("The list for this item doesn't exist".to_string());
+            }
+
+            // this is the actual list struct that will be modified
+            let mut list = list.unwrap();
+
+            // make sure there is a container for items
+            if list.items.is_none() {
+                list.items = Some(Vec::new());
+            }
+
+            // the list is checked as it will be saved before anything is written to PG
+            let mut preview = list.clone();
+            let preview_items = preview.items.get_or_insert_with(Vec::new);
+            match preview_items.iter_mut().find(|i| i.rel.liid == list_item.rel.liid) {
+                Some(existing_item) => {
+                    existing_item.title = list_item.title.clone();
+                    existing_item.description = list_item.description.clone();
+                    existing_item.set_due_fields(list_item.due.clone())?;
+                }
+                None => {
+                    let mut new_item = LdListItem {
+                        title: list_item.title.clone(),
+                        description: list_item.description.clone(),
+                        due: None,
+                        rel: structures_pg::TListItem::new(list_item.rel.liid, list_item.rel.parent_lid),
+                    };
+                    new_item.set_due_fields(list_item.due.clone())?;
+                    preview_items.push(new_item);
+                }
+            }
+            validation::validate_changes_for_save(&preview, Some(&list))?;
+
+            // try to find the right item in the existing list
+            let mut is_existing_item = false;
+            let mut item_before: Option<serde_json::Value> = None;
+            let owner_id = list.rel.user_id;
+            let mut position: Option<usize> = None;
+            let ref mut items = list.items.as_mut().unwrap();
+            for (i, existing_item) in items.iter_mut().enumerate() {
+                if existing_item.rel.liid == list_item.rel.liid {
+                    item_before = Some(audit::item_summary(existing_item));
+                    existing_item.title = list_item.title.clone();
+                    existing_item.description = list_item.description.clone();
+                    if existing_item.due != list_item.due {
+                        existing_item.sync_due(list_item.due.clone(), pg_client).await?;
+                    }
+                    is_existing_item = true;
+                    position = Some(i);
+                    break;
+                }
+            }
+
+            // create a new item if needed
+            if !is_existing_item {
+                // create t_list_item in PG for rel field
+                let new_rel_item_template = structures_pg::TListItem::new(list_item.rel.liid, list_item.rel.parent_lid);
+                let new_rel_item = structures_pg::put_t_list_item(&new_rel_item_template, &pg_client)
+                    .await
+                    .map_err(|_| ERR_MSG_SAVING_ITEM_FAILED.to_string())?;
+                if new_rel_item.is_none() {
+                    error!(
+                        "Failed to create a new t_list_item for liid: {}, lid: {} ",
+                        new_rel_item_template.liid, new_rel_item_template.parent_lid
+                    );
+                    return Err("Failed to save this new list item in the DB.".to_string());
+                }
+
+                // assign t_list_item to rel field
+                let mut new_ddb_item = LdListItem {
+                    title: list_item.title.clone(),
+                    description: list_item.description.clone(),
+                    due: None,
+                    rel: new_rel_item.unwrap(),
+                };
+                if list_item.due.is_some() {
+                    new_ddb_item.sync_due(list_item.due.clone(), pg_client).await?;
+                }
 
-        // create a new item if needed
-        if !is_existing_item {
-            // create t_list_item in PG for rel field
-            let new_rel_item_template = structures_pg::TListItem::new(list_item.rel.liid, list_item.rel.parent_lid);
-            let new_rel_item = structures_pg::put_t_list_item(&new_rel_item_template, &pg_client).await;
-            if new_rel_item.is_none() {
-                error!(
-                    "Failed to create a new t_list_item for liid: {}, lid: {} ",
-                    new_rel_item_template.liid, new_rel_item_template.parent_lid
-                );
-                return Err("Failed to save this new list item in the DB.".to_string());
+                items.push(new_ddb_item);
+                position = Some(items.len() - 1);
             }
+            let position = position.expect("The item was neither found nor added");
 
-            // assign t_list_item to rel field
-            let new_ddb_item = LdListItem {
-                title: list_item.title.clone(),
-                description: list_item.description.clone(),
-                rel: new_rel_item.unwrap(),
+            // update the list in the DB - the document is returned as written, so the item is where we put it
+            let list_updated = match list.save_in_ddb(&ddb_client, &pg_client).await? {
+                Some(v) => v,
+                None => return Err(ERR_MSG_SAVING_ITEM_FAILED.to_string()),
+            };
+            let item_updated = match list_updated.items.as_ref().and_then(|items| items.get(position)) {
+                Some(v) => v,
+                None => return Err(ERR_MSG_SAVING_ITEM_FAILED.to_string()),
             };
 
-            items.push(new_ddb_item);
-        }
+            audit::record(
+                TAudit {
+                    user_id: owner_id,
+                    lid: Some(item_updated.rel.parent_lid),
+                    liid: Some(item_updated.rel.liid),
+                    before: item_before,
+                    after: Some(audit::item_summary(item_updated)),
+                    ..TAudit::new(AuditAction::PutListItem)
+                },
+                pg_client,
+            )
+            .await;
+            let event = if is_existing_item {
+                WebhookEvent::ItemUpdated
+            } else {
+                WebhookEvent::ItemCreated
+            };
+            realtime::push_item_change(
+                event,
+                item_updated.rel.parent_lid,
+                item_updated.rel.liid,
+                Some(item_updated),
+                ddb_client,
+            )
+            .await;
 
-        // update the list in the DB
-        let list_updated = list.save_in_ddb(&ddb_client, &pg_client).await;
+            Ok((list_updated, position))
+        })
+        .await
+    }
 
-        if list_updated.is_err() {
-            return Err(list_updated.unwrap_err());
-        };
+    /// Saves only the changed text of the item at `position` in its list with UpdateItem. Fails without saving
+    /// if the item is no longer at that position, e.g. because of a concurrent change. Returns the updated list.
+    pub(crate) async fn patch_in_ddb(
+        lid: Uuid,
+        liid: Uuid,
+        position: usize,
+        patch: &ItemPatch,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<LdList, String> {
+        context::scope_list(lid, Some(liid), async move {
+            debug!("patch_in_ddb for {} / {} at {}: {:?}", lid, liid, position, patch);
+            let _span = trace::span("LdListItem::patch_in_ddb", SpanKind::Internal)
+                .with("lid", lid.to_string())
+                .with("liid", liid.to_string());
+            let patch = &content::sanitized_item_patch(patch);
+            patch.validate_for_save()?;
 
-        // extract and return the item as it is in the DB
-        let list_updated = list_updated.unwrap();
-        if let Some(items) = list_updated.unwrap().items {
-            // try to find the matching item in the updated list to return back
-            for item_updated in items.into_iter() {
-                if item_updated.rel.liid == list_item.rel.liid {
-                    return Ok(item_updated);
-                }
+            let item_path = format!("items[{}]", position);
+            let update = patch.to_update(
+                position,
+                utils::UpdateExpressionBuilder::new()
+                    .condition_eq(&[&item_path, ".rel.liid"].concat(), liid.into_attr()),
+            );
+            if update.is_empty() {
+                return LdList::get_from_ddb_incl_trash(&lid, ddb_client)
+                    .await?
+                    .ok_or_else(|| ERR_MSG_LIST_DOES_NOT_EXIST.to_string());
             }
-        };
+            let previous_doc = LdList::update_in_ddb(lid, update, ERR_MSG_ITEM_MOVED, ddb_client).await?;
+
+            // the update returns the old version, so the new one is the old one with the same changes
+            let previous = LdList::from_doc(lid, previous_doc)?;
+            let mut saved = previous.clone();
+            let item = match saved.items.as_mut().and_then(|items| items.get_mut(position)) {
+                Some(v) => v,
+                None => return Err(ERR_MSG_ITEM_DOES_NOT_EXIST.to_string()),
+            };
+            let item_before = audit::item_summary(item);
+            patch.apply(item);
+
+            audit::record(
+                TAudit {
+                    user_id: saved.rel.user_id,
+                    lid: Some(lid),
+                    liid: Some(liid),
+                    before: Some(item_before),
+                    after: Some(audit::item_summary(item)),
+                    ..TAudit::new(AuditAction::PutListItem)
+                },
+                pg_client,
+            )
+            .await;
+            realtime::push_item_change(WebhookEvent::ItemUpdated, lid, liid, Some(item), ddb_client).await;
 
-        // something went wrong - the items we saved is not there
-        Err(ERR_MSG_SAVING_ITEM_FAILED.to_string())
+            Ok(LdList::record_patch(previous, saved, ddb_client).await)
+        })
+        .await
     }
 
     /// Delete the list item from PG and DDB and returns the list without the item.
@@ -326,40 +1316,334 @@ impl LdListItem {
         ddb_client: &DynamoDbClient,
         pg_client: &tokio_postgres::Client,
     ) -> Result<Option<LdList>, String> {
-        // delete the list item from PG
-        structures_pg::del_t_list_item(liid.clone(), &pg_client).await;
+        context::scope_list(lid, Some(liid), async move {
+            let _span = trace::span("LdListItem::del_list_item_ddb", SpanKind::Internal)
+                .with("lid", lid.to_string())
+                .with("liid", liid.to_string());
 
-        // get the list from DDB
-        let list = LdList::get_from_ddb(&lid, &ddb_client).await;
+            // delete the list item from PG
+            structures_pg::del_t_list_item(liid.clone(), &pg_client)
+                .await
+                .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?;
+
+            // get the list from DDB
+            let list = LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, &ddb_client).await;
+
+            // return the error if no list exists or there were problems getting it from DDB
+            if list.is_err() {
+                return Err(list.unwrap_err());
+            }
+            let list = list.unwrap();
+            if list.is_none() {
+                return Err("The list for this item doesn't exist".to_string());
+            }
+
+            // this is the actual list struct that will be modified
+            let mut list = list.unwrap();
 
-        // return the error if no list exists or there were problems getting it from DDB
-        if list.is_err() {
-            return Err(list.unwrap_err());
+            // return the list if there are no items
+            if list.items.is_none() {
+                return Ok(Some(list));
+            }
+
+            // try to find the right item in the existing list
+            // and remove it by index
+            let mut removed_item: Option<LdListItem> = None;
+            let ref mut items = list.items.as_mut().unwrap();
+            for i in 0..items.len() {
+                if items[i].rel.liid == liid {
+                    removed_item = Some(items.remove(i));
+                    break;
+                }
+            }
+            let owner_id = list.rel.user_id;
+
+            // update the list in the DB
+            let list_updated = list.save_in_ddb(&ddb_client, &pg_client).await;
+
+            // the item is gone from PG even if DDB failed
+            audit::record(
+                TAudit {
+                    user_id: owner_id,
+                    lid: Some(lid),
+                    liid: Some(liid),
+                    before: removed_item.as_ref().map(audit::item_summary),
+                    ..TAudit::new(AuditAction::DelListItem)
+                },
+                pg_client,
+            )
+            .await;
+
+            if list_updated.is_ok() && removed_item.is_some() {
+                realtime::push_item_change(WebhookEvent::ItemDeleted, lid, liid, removed_item.as_ref(), ddb_client)
+                    .await;
+            }
+
+            list_updated
+        })
+        .await
+    }
+
+    /// Checks off the items as done by `user_id` or clears their completion. Recurring items are not completed,
+    /// but moved to their next due date instead. Returns the updated list.
+    pub(crate) async fn set_completed(
+        lid: Uuid,
+        liids: &[Uuid],
+        completed: bool,
+        user_id: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<LdList>, String> {
+        debug!("set_completed for {} items in {} / {}", liids.len(), lid, completed);
+
+        let list = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+            Some(v) => v,
+            None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+        };
+
+        // only items of this list can be changed
+        let liids: Vec<Uuid> = list
+            .visible_items()
+            .filter(|i| liids.contains(&i.rel.liid))
+            .map(|i| i.rel.liid)
+            .collect();
+        if liids.is_empty() {
+            return Err(ERR_MSG_ITEM_DOES_NOT_EXIST.to_string());
         }
-        let list = list.unwrap();
-        if list.is_none() {
-            return Err("The list for this item doesn't exist".to_string());
+
+        LdListItem::complete_in_list(list, &liids, completed, user_id, ddb_client, pg_client).await
+    }
+
+    /// Completes or un-completes the items of the list and saves it. Recurring items are moved to their next
+    /// occurrence instead of being completed. Used by `set_completed` and `LdList::check_all`.
+    pub(crate) async fn complete_in_list(
+        mut list: LdList,
+        liids: &[Uuid],
+        completed: bool,
+        user_id: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<LdList>, String> {
+        // move recurring items to their next occurrence and complete the rest
+        let mut next_dues: HashMap<Uuid, ItemDue> = HashMap::new();
+        let mut to_complete: Vec<Uuid> = Vec::new();
+        for item in list.items.iter().flatten().filter(|i| liids.contains(&i.rel.liid)) {
+            let next_due = match (completed, item.due.as_ref()) {
+                (true, Some(due)) => due.next_occurrence()?,
+                _ => None,
+            };
+            match next_due {
+                Some(v) => {
+                    next_dues.insert(item.rel.liid, v);
+                }
+                None => to_complete.push(item.rel.liid),
+            }
         }
 
-        // this is the actual list struct that will be modified
-        let mut list = list.unwrap();
+        // the list is checked with the new due dates before anything is written to PG
+        let mut preview = list.clone();
+        for item in preview.items.iter_mut().flatten() {
+            if let Some(due) = next_dues.get(&item.rel.liid) {
+                item.set_due_fields(Some(due.clone()))?;
+            }
+        }
+        validation::validate_changes_for_save(&preview, Some(&list))?;
 
-        // return the list if there are no items
-        if list.items.is_none() {
-            return Ok(Some(list));
+        for item in list.items.iter_mut().flatten() {
+            if let Some(due) = next_dues.remove(&item.rel.liid) {
+                item.sync_due(Some(due), pg_client).await?;
+            }
         }
 
-        // try to find the right item in the existing list
-        // and remove it by index
-        let ref mut items = list.items.as_mut().unwrap();
-        for i in 0..items.len() {
-            if items[i].rel.liid == liid {
-                items.remove(i);
-                break;
+        let rels = match to_complete.is_empty() {
+            true => Vec::new(),
+            false => structures_pg::complete_t_list_items(&to_complete, completed, user_id, pg_client)
+                .await
+                .map_err(|_| ERR_MSG_INDEX_FAILED.to_string())?,
+        };
+        list.save_with_rels(rels, ddb_client, pg_client).await
+    }
+
+    /// Sets or clears the due date of the item. Returns the updated list.
+    pub(crate) async fn set_due(
+        lid: Uuid,
+        liid: Uuid,
+        due: Option<ItemDue>,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<LdList>, String> {
+        debug!("set_due for {} / {}", lid, liid);
+
+        let mut list = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+            Some(v) => v,
+            None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+        };
+
+        let position = match list.items.iter().flatten().position(|i| i.rel.liid == liid) {
+            Some(v) => v,
+            None => return Err(ERR_MSG_ITEM_DOES_NOT_EXIST.to_string()),
+        };
+
+        // the list is checked with the new due date before anything is written to PG
+        let mut preview = list.clone();
+        if let Some(item) = preview.items.as_mut().and_then(|items| items.get_mut(position)) {
+            item.set_due_fields(due.clone())?;
+        }
+        validation::validate_changes_for_save(&preview, Some(&list))?;
+
+        if let Some(item) = list.items.as_mut().and_then(|items| items.get_mut(position)) {
+            item.sync_due(due, pg_client).await?;
+        }
+        list.save_in_ddb(ddb_client, pg_client).await
+    }
+
+    /// Creates `rel` sections in PG for items that are new to it, e.g. from a template, an export or a revision,
+    /// keeping their links, due dates, completion and trash status. Completion is recorded for `completed_by` at
+    /// the time of the call and is skipped for items without it. Returns the saved rels by liid.
+    pub(crate) async fn create_rels(
+        rels: &[structures_pg::TListItem],
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<HashMap<Uuid, structures_pg::TListItem>, String> {
+        let mut saved: HashMap<Uuid, structures_pg::TListItem> =
+            match structures_pg::put_t_list_items(rels, pg_client).await {
+                Ok(v) => v.into_iter().map(|r| (r.liid, r)).collect(),
+                Err(_) => return Err(ERR_MSG_SAVING_ITEM_FAILED.to_string()),
+            };
+
+        // completion is set per user who completed the items
+        let mut completed: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for rel in rels.iter().filter(|r| r.completed_on_utc.is_some()) {
+            if let Some(user_id) = rel.completed_by {
+                completed.entry(user_id).or_default().push(rel.liid);
             }
         }
+        for (user_id, liids) in completed {
+            let updated = structures_pg::complete_t_list_items(&liids, true, user_id, pg_client)
+                .await
+                .map_err(|_| ERR_MSG_SAVING_ITEM_FAILED.to_string())?;
+            saved.extend(updated.into_iter().map(|r| (r.liid, r)));
+        }
+
+        for rel in rels.iter().filter(|r| r.due_on_utc.is_some()) {
+            match structures_pg::put_t_list_item_due(rel.liid, rel.due_on_utc, rel.remind_on_utc, pg_client).await {
+                Ok(Some(v)) => saved.insert(v.liid, v),
+                _ => return Err(ERR_MSG_SAVING_ITEM_FAILED.to_string()),
+            };
+        }
+
+        let trashed: Vec<Uuid> = rels
+            .iter()
+            .filter(|r| r.deleted_on_utc.is_some())
+            .map(|r| r.liid)
+            .collect();
+        if !trashed.is_empty() {
+            let updated = structures_pg::trash_t_list_items(&trashed, true, pg_client)
+                .await
+                .map_err(|_| ERR_MSG_SAVING_ITEM_FAILED.to_string())?;
+            saved.extend(updated.into_iter().map(|r| (r.liid, r)));
+        }
+
+        Ok(saved)
+    }
+
+    /// Copies the due and reminder times into the item without saving them anywhere.
+    pub(crate) fn set_due_fields(&mut self, due: Option<ItemDue>) -> Result<(), String> {
+        self.rel.due_on_utc = due.as_ref().map(|d| d.due_on_utc);
+        self.rel.remind_on_utc = match &due {
+            Some(d) => d.remind_on_utc()?,
+            None => None,
+        };
+        self.due = due;
+
+        Ok(())
+    }
+
+    /// Saves the due and reminder times in PG and copies them into the item. DDB is not updated.
+    pub(crate) async fn sync_due(
+        &mut self,
+        due: Option<ItemDue>,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<(), String> {
+        let due_on_utc = due.as_ref().map(|d| d.due_on_utc);
+        let remind_on_utc = match &due {
+            Some(d) => d.remind_on_utc()?,
+            None => None,
+        };
+
+        self.rel = match structures_pg::put_t_list_item_due(self.rel.liid, due_on_utc, remind_on_utc, pg_client).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                error!("Failed to update the due date in PG for liid {}", self.rel.liid);
+                return Err(ERR_MSG_SAVING_ITEM_FAILED.to_string());
+            }
+            Err(_) => return Err(ERR_MSG_SAVING_ITEM_FAILED.to_string()),
+        };
+        self.due = due;
+
+        Ok(())
+    }
+
+    /// Moves the list item to trash in PG and DDB. The item stays in the list with `rel.deleted_on_utc` set.
+    /// Returns the updated list.
+    pub(crate) async fn move_to_trash(
+        lid: Uuid,
+        liid: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<LdList>, String> {
+        debug!("move_to_trash for {} / {}", lid, liid);
+        LdListItem::set_trashed(lid, liid, true, ddb_client, pg_client).await
+    }
+
+    /// Restores the list item from trash in PG and DDB. Returns the updated list.
+    pub(crate) async fn restore_from_trash(
+        lid: Uuid,
+        liid: Uuid,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<LdList>, String> {
+        debug!("restore_from_trash for {} / {}", lid, liid);
+        LdListItem::set_trashed(lid, liid, false, ddb_client, pg_client).await
+    }
+
+    /// Sets or clears the trash marker in PG first and then copies the updated `rel` into the DDB list.
+    async fn set_trashed(
+        lid: Uuid,
+        liid: Uuid,
+        trashed: bool,
+        ddb_client: &DynamoDbClient,
+        pg_client: &tokio_postgres::Client,
+    ) -> Result<Option<LdList>, String> {
+        // get the list from DDB
+        let mut list = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+            Some(v) => v,
+            None => {
+                return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string());
+            }
+        };
+
+        // find the item in the list
+        let item = match list
+            .items
+            .as_mut()
+            .and_then(|items| items.iter_mut().find(|i| i.rel.liid == liid))
+        {
+            Some(v) => v,
+            None => {
+                return Err(ERR_MSG_ITEM_DOES_NOT_EXIST.to_string());
+            }
+        };
+
+        // update PG and copy the new `rel` into DDB
+        item.rel = match structures_pg::trash_t_list_item(liid, trashed, pg_client).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                error!("Failed to update trash status in PG for liid {}", liid);
+                return Err(ERR_MSG_ITEM_DOES_NOT_EXIST.to_string());
+            }
+            Err(_) => return Err(ERR_MSG_INDEX_FAILED.to_string()),
+        };
 
-        // update the list in the DB
-        list.save_in_ddb(&ddb_client, &pg_client).await
+        list.save_in_ddb(ddb_client, pg_client).await
     }
 }
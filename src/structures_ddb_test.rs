@@ -1,8 +1,10 @@
 // Use cargo test -- --nocapture to get the full logging output
 #[cfg(test)]
-mod tests_ddb {
+pub(crate) mod tests_ddb {
+    use crate::schedule::{Frequency, ItemDue, Recurrence};
     use crate::structures_ddb::*;
     use crate::structures_pg::*;
+    use chrono::{Duration, Utc};
     use log::{self, debug};
     use uuid::Uuid;
 
@@ -22,6 +24,7 @@ mod tests_ddb {
         .concat();
         let pg_user = put_t_user(&user_email, &pg_client)
             .await
+            .unwrap()
             .expect("Failed to create a new user");
 
         // prepare some constants
@@ -59,6 +62,7 @@ mod tests_ddb {
         let list_item_from_ui = LdListItem {
             title: "New item 1".to_string(),
             description: Some("Some long description 1".to_string()),
+            due: None,
             rel: TListItem::new(liid_1.clone(), lid.clone()),
         };
         let list_item_1 = LdListItem::put_list_item_ddb(list_item_from_ui, &ddb_client, &pg_client).await;
@@ -74,6 +78,7 @@ mod tests_ddb {
         let list_item_from_ui = LdListItem {
             title: "New item 2".to_string(),
             description: Some("Some long description 2".to_string()),
+            due: None,
             rel: TListItem::new(liid_2.clone(), lid.clone()),
         };
         let list_item_2 = LdListItem::put_list_item_ddb(list_item_from_ui, &ddb_client, &pg_client).await;
@@ -88,6 +93,7 @@ mod tests_ddb {
         let list_item_from_ui = LdListItem {
             title: "New item 1 - still".to_string(),
             description: Some("Some long description - modified".to_string()),
+            due: None,
             rel: TListItem::new(liid_1.clone(), lid.clone()),
         };
         let list_item_1a = LdListItem::put_list_item_ddb(list_item_from_ui, &ddb_client, &pg_client).await;
@@ -100,6 +106,20 @@ mod tests_ddb {
         assert_ne!(list_item_1a.title, list_item_1.title); // checks if the title changed
         assert_ne!(list_item_1a.description, list_item_1.description); // checks if the description changed
 
+        // add a 3rd item and get the whole list back
+        let liid_3 = Uuid::new_v4();
+        let list_item_from_ui = LdListItem {
+            title: "New item 3".to_string(),
+            description: None,
+            due: None,
+            rel: TListItem::new(liid_3, lid),
+        };
+        let list_3 = LdListItem::put_list_item_and_get_list_ddb(list_item_from_ui, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to add the 3rd item");
+        assert_eq!(list_3.items.as_ref().unwrap().len(), 3);
+        assert!(list_3.items.as_ref().unwrap().iter().any(|i| i.rel.liid == liid_3));
+
         // delete items one by one
         let list_del_1 = LdListItem::del_list_item_ddb(lid, liid_1.clone(), &ddb_client, &pg_client).await;
 
@@ -128,6 +148,7 @@ mod tests_ddb {
         .concat();
         let pg_user = put_t_user(&user_email, &pg_client)
             .await
+            .unwrap()
             .expect("Failed to create a new user");
 
         // create user lists
@@ -166,6 +187,270 @@ mod tests_ddb {
         assert!(list3.delete_from_all_dbs(&ddb_client, &pg_client).await.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_dynamodb_patch() {
+        debug!("test_dynamodb_patch started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new user with a list
+        let user_email = ["test_dynamodb_patch@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let pg_user = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user");
+        let lid = Uuid::new_v4();
+        let list = test_helpers::create_random_list(lid, pg_user.user_id, &ddb_client, &pg_client).await;
+        let liid = list.items.as_ref().unwrap()[1].rel.liid;
+
+        // change the title and remove the description - items stay as they are
+        let patch = ListPatch {
+            title: Some("Patched title".to_string()),
+            description: Some(None),
+        };
+        let patched = LdList::patch_in_ddb(lid, &patch, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to patch the list");
+        assert_eq!(patched.title, "Patched title");
+        assert!(patched.description.is_none());
+        let from_ddb = LdList::get_from_ddb(&lid, &ddb_client).await.unwrap().unwrap();
+        assert_eq!(from_ddb.title, "Patched title");
+        assert!(from_ddb.description.is_none());
+        assert_eq!(from_ddb.items.as_ref().unwrap().len(), 5);
+
+        // patch an item by its position
+        let patch = ItemPatch {
+            title: Some("Patched item".to_string()),
+            ..Default::default()
+        };
+        let patched = LdListItem::patch_in_ddb(lid, liid, 1, &patch, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to patch the item");
+        assert_eq!(patched.items.as_ref().unwrap()[1].title, "Patched item");
+        let from_ddb = LdList::get_from_ddb(&lid, &ddb_client).await.unwrap().unwrap();
+        assert_eq!(from_ddb.items.as_ref().unwrap()[1].title, "Patched item");
+
+        // a wrong position fails without changing anything
+        assert!(LdListItem::patch_in_ddb(lid, liid, 0, &patch, &ddb_client, &pg_client)
+            .await
+            .is_err());
+        assert!(LdList::patch_in_ddb(Uuid::new_v4(), &ListPatch::default(), &ddb_client, &pg_client)
+            .await
+            .is_err());
+
+        // clean up
+        from_ddb
+            .delete_from_all_dbs(&ddb_client, &pg_client)
+            .await
+            .expect("Failed to delete the list");
+        assert!(del_t_user(pg_user.user_id, &pg_client).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_dynamodb_trash_restore() {
+        debug!("test_dynamodb_trash_restore started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new user
+        let user_email = [
+            "test_dynamodb_trash_restore@",
+            Uuid::new_v4().to_string().as_str(),
+            ".com",
+        ]
+        .concat();
+        let pg_user = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user");
+
+        // create a list with a few items
+        let lid = Uuid::new_v4();
+        let list = test_helpers::create_random_list(lid, pg_user.user_id, &ddb_client, &pg_client).await;
+        let liid = list.items.as_ref().unwrap()[0].rel.liid;
+
+        // trash an item - it stays in the list with the marker set
+        let list_item_trashed = LdListItem::move_to_trash(lid, liid, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to trash the item")
+            .unwrap();
+        let item = list_item_trashed
+            .items
+            .unwrap()
+            .into_iter()
+            .find(|i| i.rel.liid == liid);
+        assert!(item.unwrap().rel.deleted_on_utc.is_some());
+
+        // restore the item
+        let list_item_restored = LdListItem::restore_from_trash(lid, liid, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to restore the item")
+            .unwrap();
+        let item = list_item_restored
+            .items
+            .unwrap()
+            .into_iter()
+            .find(|i| i.rel.liid == liid);
+        assert!(item.unwrap().rel.deleted_on_utc.is_none());
+
+        // trash the list - it should be hidden from the normal calls
+        let list_trashed = LdList::move_to_trash(lid, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to trash the list")
+            .unwrap();
+        assert!(list_trashed.rel.deleted_on_utc.is_some());
+        assert!(LdList::get_from_ddb(&lid, &ddb_client).await.unwrap().is_none());
+        assert!(LdList::get_all_user_lists_from_ddb(pg_user.user_id, &ddb_client, &pg_client)
+            .await
+            .unwrap()
+            .is_none());
+
+        // it should be in the trash
+        let trash = LdList::get_user_trash_from_ddb(pg_user.user_id, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to get the trash")
+            .unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].lid, lid);
+
+        // restore the list
+        let list_restored = LdList::restore_from_trash(lid, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to restore the list")
+            .unwrap();
+        assert!(list_restored.rel.deleted_on_utc.is_none());
+        assert!(LdList::get_from_ddb(&lid, &ddb_client).await.unwrap().is_some());
+        assert!(LdList::get_user_trash_from_ddb(pg_user.user_id, &ddb_client, &pg_client)
+            .await
+            .unwrap()
+            .is_none());
+
+        // trash it again and purge with zero retention
+        LdList::move_to_trash(lid, &ddb_client, &pg_client).await.unwrap();
+        let purged = LdList::purge_trash(chrono::Duration::zero(), &ddb_client, &pg_client)
+            .await
+            .expect("Failed to purge the trash");
+        assert!(purged >= 1);
+        assert!(get_t_list(lid, &pg_client).await.unwrap().is_none());
+
+        // clean up
+        assert!(del_t_user(pg_user.user_id, &pg_client).await.is_ok());
+    }
+
+    #[test]
+    fn test_list_progress() {
+        let user_id = Uuid::new_v4();
+        let (lid, child_lid) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // build an item that is done, trashed or has a child list
+        let new_item = |lid: Uuid, done: bool| LdListItem {
+            title: "Item".to_string(),
+            description: None,
+            due: None,
+            rel: TListItem {
+                completed_on_utc: if done { Some(chrono::Utc::now()) } else { None },
+                completed_by: if done { Some(user_id) } else { None },
+                ..TListItem::new(Uuid::new_v4(), lid)
+            },
+        };
+
+        // 1 of 2 done in the child list
+        let mut child = LdList::new(child_lid, "Child".to_string(), user_id);
+        child.items = Some(vec![new_item(child_lid, true), new_item(child_lid, false)]);
+
+        // 2 of 3 done in the top list + a trashed item that is not counted
+        let mut list = LdList::new(lid, "Top".to_string(), user_id);
+        let mut parent_item = new_item(lid, false);
+        parent_item.rel.child_lid = Some(child_lid);
+        let mut trashed_item = new_item(lid, true);
+        trashed_item.rel.deleted_on_utc = Some(chrono::Utc::now());
+        list.items = Some(vec![new_item(lid, true), new_item(lid, true), parent_item, trashed_item]);
+
+        let mut children = std::collections::HashMap::new();
+        assert_eq!(list.progress(&children), Progress { done: 2, total: 3 });
+        children.insert(child_lid, child);
+        assert_eq!(list.progress(&children), Progress { done: 3, total: 5 });
+
+        // an empty list has nothing to do
+        let empty = LdList::new(Uuid::new_v4(), "Empty".to_string(), user_id);
+        assert_eq!(empty.progress(&children), Progress { done: 0, total: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_dynamodb_check_all_clear_completed() {
+        debug!("test_dynamodb_check_all_clear_completed started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new user
+        let user_email = [
+            "test_dynamodb_check_all_clear_completed@",
+            Uuid::new_v4().to_string().as_str(),
+            ".com",
+        ]
+        .concat();
+        let pg_user = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user");
+
+        // create a list with 5 items
+        let lid = Uuid::new_v4();
+        let list = test_helpers::create_random_list(lid, pg_user.user_id, &ddb_client, &pg_client).await;
+        let liid = list.items.as_ref().unwrap()[0].rel.liid;
+
+        // check off a single item
+        let list = LdListItem::set_completed(lid, &[liid], true, pg_user.user_id, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to check off the item")
+            .unwrap();
+        let item = list.visible_items().find(|i| i.rel.liid == liid).unwrap();
+        assert!(item.rel.completed_on_utc.is_some());
+        assert_eq!(item.rel.completed_by, Some(pg_user.user_id));
+        assert_eq!(
+            LdList::get_progress_from_ddb(lid, &ddb_client).await.unwrap(),
+            Some(Progress { done: 1, total: 5 })
+        );
+
+        // check off the rest, a recurring item moves to its next occurrence instead
+        let recurring_liid = list.items.as_ref().unwrap()[1].rel.liid;
+        let due_on_utc = Utc::now() + Duration::days(1);
+        let due = ItemDue {
+            due_on_utc,
+            timezone: "UTC".to_string(),
+            remind_before_minutes: None,
+            recurrence: Some(Recurrence {
+                frequency: Frequency::Daily,
+                interval: 1,
+            }),
+        };
+        LdListItem::set_due(lid, recurring_liid, Some(due), &ddb_client, &pg_client)
+            .await
+            .expect("Failed to set the due date");
+        let list = LdList::check_all(lid, pg_user.user_id, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to check off all items")
+            .unwrap();
+        assert_eq!(list.progress(&Default::default()), Progress { done: 4, total: 5 });
+        let item = list.visible_items().find(|i| i.rel.liid == recurring_liid).unwrap();
+        assert!(item.rel.completed_on_utc.is_none());
+        assert_eq!(item.due.as_ref().unwrap().due_on_utc, due_on_utc + Duration::days(1));
+
+        // clear the done ones
+        let list = LdList::clear_completed(lid, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to clear completed items")
+            .unwrap();
+        assert_eq!(list.visible_items().count(), 1);
+
+        // clean up
+        assert!(del_t_user(pg_user.user_id, &pg_client).await.is_ok());
+        assert!(list.delete_from_all_dbs(&ddb_client, &pg_client).await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_dynamodb_del_user() {
         debug!("test_dynamodb_del_user started");
@@ -177,22 +462,24 @@ mod tests_ddb {
         let user_email = "test_dynamodb_del_user@example.com".to_string();
         let pg_user = put_t_user(&user_email, &pg_client)
             .await
+            .unwrap()
             .expect("Failed to create a new user");
 
         // check the user was created
-        let pg_user_read = get_t_user(None, Some(user_email.clone()), &pg_client).await;
+        let pg_user_read = get_t_user(None, Some(user_email.clone()), &pg_client).await.unwrap();
         assert!(pg_user_read.is_some());
 
         // delete the user
         assert!(del_t_user(pg_user.user_id.clone(), &pg_client).await.is_ok());
 
         // check the user was deleted
-        let pg_user_read = get_t_user(None, Some(user_email.clone()), &pg_client).await;
+        let pg_user_read = get_t_user(None, Some(user_email.clone()), &pg_client).await.unwrap();
         assert!(pg_user_read.is_none());
     }
 
     #[cfg(test)]
-    mod test_helpers {
+    pub(crate) mod test_helpers {
+        use crate::stream;
         use crate::structures_ddb::*;
         use crate::structures_pg::*;
         use crate::utils;
@@ -234,6 +521,7 @@ mod tests_ddb {
                 let list_item_from_ui = LdListItem {
                     title: [i.to_string().as_str(), ": ", generate_random_string(15).as_str()].concat(),
                     description: Some(generate_random_string(15)),
+                    due: None,
                     rel: TListItem::new(Uuid::new_v4(), lid.clone()),
                 };
                 LdListItem::put_list_item_ddb(list_item_from_ui, &ddb_client, &pg_client)
@@ -245,6 +533,24 @@ mod tests_ddb {
             LdList::get_from_ddb(&lid, &ddb_client).await.unwrap().unwrap()
         }
 
+        /// Updates the PG indexes from the list in DDB the way the stream handler does after every save.
+        pub(crate) async fn apply_stream(
+            lid: Uuid,
+            ddb_client: &rusoto_dynamodb::DynamoDbClient,
+            pg_client: &tokio_postgres::Client,
+        ) {
+            let change = stream::ListChange {
+                event_id: Uuid::new_v4().to_string(),
+                lid,
+                event_name: stream::StreamEventName::Modify,
+                old: None,
+                new: LdList::get_from_ddb_incl_trash(&lid, ddb_client).await.unwrap(),
+            };
+            stream::apply_change(&change, pg_client)
+                .await
+                .expect("Failed to apply the change");
+        }
+
         /// Creates Postgres and DynamoDB connection clients in one sweep.
         pub(crate) async fn init_db_clients() -> (tokio_postgres::Client, rusoto_dynamodb::DynamoDbClient) {
             utils::log_init(log::Level::Debug);
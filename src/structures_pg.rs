@@ -1,7 +1,11 @@
+use crate::audit::{self, AuditAction, AuditCursor};
+use crate::context;
+use crate::retry::QueryWithRetry;
 use chrono::Utc;
 use dynomite::Item;
 use log::{self, debug, error};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tokio_postgres::{Client, Row};
 use uuid::Uuid;
 
@@ -15,6 +19,12 @@ pub(crate) enum PgError {
     Logged,
 }
 
+/// Logs a PG error that persisted after retries and converts it into `PgError::Logged`.
+fn query_failed(function: &str, e: tokio_postgres::Error) -> PgError {
+    error!("{} query failed: {:?}", function, e);
+    PgError::Logged
+}
+
 /// Corresponds to table t_list_item
 #[derive(Item, Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub(crate) struct TListItem {
@@ -30,10 +40,25 @@ pub(crate) struct TListItem {
     pub org_id: Option<Uuid>,
     pub created_on_utc: Option<chrono::DateTime<Utc>>,
     pub validated_on_utc: Option<chrono::DateTime<Utc>>,
+    /// Set when the item is moved to trash. The item is purged after the retention period.
+    #[dynomite(default)]
+    pub deleted_on_utc: Option<chrono::DateTime<Utc>>,
+    /// Set when the item is checked off as done.
+    #[dynomite(default)]
+    pub completed_on_utc: Option<chrono::DateTime<Utc>>,
+    /// The user who checked off the item.
+    #[dynomite(default)]
+    pub completed_by: Option<Uuid>,
+    /// A copy of `LdListItem.due` for the overdue and upcoming item queries.
+    #[dynomite(default)]
+    pub due_on_utc: Option<chrono::DateTime<Utc>>,
+    /// When the reminder for the item is due.
+    #[dynomite(default)]
+    pub remind_on_utc: Option<chrono::DateTime<Utc>>,
 }
 
 /// Corresponds to table t_list
-#[derive(Item, Serialize, Deserialize, Debug)]
+#[derive(Item, Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct TList {
     #[dynomite(partition_key)]
     pub lid: Uuid,
@@ -41,6 +66,12 @@ pub(crate) struct TList {
     pub org_id: Option<Uuid>,
     pub created_on_utc: Option<chrono::DateTime<Utc>>,
     pub validated_on_utc: Option<chrono::DateTime<Utc>>,
+    /// Set when the list is moved to trash. The list is purged after the retention period.
+    #[dynomite(default)]
+    pub deleted_on_utc: Option<chrono::DateTime<Utc>>,
+    /// Templates are instantiated into new lists by the owner and members of the owner's org.
+    #[dynomite(default)]
+    pub is_template: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -52,6 +83,83 @@ pub(crate) struct TUser {
     pub validated_on_utc: Option<chrono::DateTime<Utc>>,
 }
 
+/// A tag with the number of user lists it is used in. Returned by ld_get_user_tags.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct TTagCount {
+    pub tag: String,
+    pub list_count: i64,
+}
+
+/// Corresponds to table t_audit. One record per mutation of a list, an item or a user.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct TAudit {
+    /// Assigned by PG
+    pub audit_id: Option<i64>,
+    /// The user who made the change. None for system tasks.
+    pub actor: Option<Uuid>,
+    pub action: AuditAction,
+    /// The user the change applies to, e.g. the owner of the list.
+    pub user_id: Option<Uuid>,
+    pub lid: Option<Uuid>,
+    pub liid: Option<Uuid>,
+    /// Summaries of the changed record before and after the change.
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    /// Assigned by PG
+    pub created_on_utc: Option<chrono::DateTime<Utc>>,
+}
+
+/// A list matching a search query. Returned by ld_search_user_lists.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct TSearchHit {
+    pub lid: Uuid,
+    pub title: String,
+    pub rank: f32,
+    /// Fragments of the list text with the matches wrapped into `SEARCH_MATCH_START` / `SEARCH_MATCH_END`.
+    pub snippet: String,
+}
+
+/// Corresponds to table t_webhook. A URL notified of changes to a single list or to all lists of an org.
+/// Exactly one of `lid` and `org_id` is set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct TWebhook {
+    pub webhook_id: Uuid,
+    /// The user who registered the webhook.
+    pub user_id: Uuid,
+    pub lid: Option<Uuid>,
+    pub org_id: Option<Uuid>,
+    pub url: String,
+    /// The key for signing payloads. Returned to the user once, at registration.
+    pub secret: String,
+    /// Assigned by PG
+    pub created_on_utc: Option<chrono::DateTime<Utc>>,
+}
+
+/// Corresponds to table t_webhook_delivery. One record per delivery attempt.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct TWebhookDelivery {
+    /// Assigned by PG
+    pub delivery_id: Option<i64>,
+    pub webhook_id: Uuid,
+    /// The same for all attempts to deliver the same event.
+    pub event_id: Uuid,
+    pub event: String,
+    /// Starts with 1.
+    pub attempt: i32,
+    /// The HTTP status returned by the receiver. None if there was no response.
+    pub status_code: Option<i32>,
+    /// The reason the attempt failed, if it did.
+    pub error: Option<String>,
+    pub duration_ms: i64,
+    /// Assigned by PG
+    pub created_on_utc: Option<chrono::DateTime<Utc>>,
+}
+
+/// Marks the start of a match in search snippets. A control char that cannot be typed in by the user.
+pub(crate) const SEARCH_MATCH_START: char = '\u{2}';
+/// Marks the end of a match in search snippets.
+pub(crate) const SEARCH_MATCH_END: char = '\u{3}';
+
 // ===== From<&Row> trait implementation =====
 
 impl From<&Row> for TListItem {
@@ -69,6 +177,11 @@ impl From<&Row> for TListItem {
             org_id: row.get("org_id"),
             created_on_utc: row.get("created_on_utc"),
             validated_on_utc: row.get("validated_on_utc"),
+            deleted_on_utc: row.get("deleted_on_utc"),
+            completed_on_utc: row.get("completed_on_utc"),
+            completed_by: row.get("completed_by"),
+            due_on_utc: row.get("due_on_utc"),
+            remind_on_utc: row.get("remind_on_utc"),
         }
     }
 }
@@ -82,6 +195,8 @@ impl From<&Row> for TList {
             org_id: row.get("org_id"),
             created_on_utc: row.get("created_on_utc"),
             validated_on_utc: row.get("validated_on_utc"),
+            deleted_on_utc: row.get("deleted_on_utc"),
+            is_template: row.get("is_template"),
         }
     }
 }
@@ -99,8 +214,97 @@ impl From<&Row> for TUser {
     }
 }
 
+impl From<&Row> for TTagCount {
+    /// Creates a new structure from tokio_postgres::Row
+    fn from(row: &Row) -> Self {
+        Self {
+            tag: row.get("tag"),
+            list_count: row.get("list_count"),
+        }
+    }
+}
+
+impl From<&Row> for TAudit {
+    /// Creates a new structure from tokio_postgres::Row
+    fn from(row: &Row) -> Self {
+        let action: String = row.get("action");
+        Self {
+            audit_id: row.get("audit_id"),
+            actor: row.get("actor"),
+            action: action.parse().unwrap_or(AuditAction::Unknown),
+            user_id: row.get("user_id"),
+            lid: row.get("lid"),
+            liid: row.get("liid"),
+            before: row.get("before"),
+            after: row.get("after"),
+            created_on_utc: row.get("created_on_utc"),
+        }
+    }
+}
+
+impl From<&Row> for TSearchHit {
+    /// Creates a new structure from tokio_postgres::Row
+    fn from(row: &Row) -> Self {
+        Self {
+            lid: row.get("lid"),
+            title: row.get("title"),
+            rank: row.get("rank"),
+            snippet: row.get("snippet"),
+        }
+    }
+}
+
+impl From<&Row> for TWebhook {
+    /// Creates a new structure from tokio_postgres::Row
+    fn from(row: &Row) -> Self {
+        Self {
+            webhook_id: row.get("webhook_id"),
+            user_id: row.get("user_id"),
+            lid: row.get("lid"),
+            org_id: row.get("org_id"),
+            url: row.get("url"),
+            secret: row.get("secret"),
+            created_on_utc: row.get("created_on_utc"),
+        }
+    }
+}
+
+impl From<&Row> for TWebhookDelivery {
+    /// Creates a new structure from tokio_postgres::Row
+    fn from(row: &Row) -> Self {
+        Self {
+            delivery_id: row.get("delivery_id"),
+            webhook_id: row.get("webhook_id"),
+            event_id: row.get("event_id"),
+            event: row.get("event"),
+            attempt: row.get("attempt"),
+            status_code: row.get("status_code"),
+            error: row.get("error"),
+            duration_ms: row.get("duration_ms"),
+            created_on_utc: row.get("created_on_utc"),
+        }
+    }
+}
+
 // ===== struct::new() implementation =====
 
+impl TAudit {
+    /// Creates a new record for the action by the actor of the current request. Not saved in the DB.
+    pub(crate) fn new(action: AuditAction) -> Self {
+        Self {
+            audit_id: None,
+            actor: context::actor(),
+            action,
+            user_id: None,
+            lid: None,
+            liid: None,
+            before: None,
+            after: None,
+            created_on_utc: None,
+        }
+    }
+}
+
 impl TList {
     /// Creates a new object with only the required fields set.
     /// Not saved in the DB.
@@ -111,6 +315,8 @@ impl TList {
             org_id: None,
             created_on_utc: None,
             validated_on_utc: None,
+            deleted_on_utc: None,
+            is_template: false,
         }
     }
 }
@@ -130,6 +336,11 @@ impl TListItem {
             org_id: None,
             created_on_utc: None,
             validated_on_utc: None,
+            deleted_on_utc: None,
+            completed_on_utc: None,
+            completed_by: None,
+            due_on_utc: None,
+            remind_on_utc: None,
         }
     }
 }
@@ -137,40 +348,54 @@ impl TListItem {
 // ===== GET / PUT / DEL PG data  =====
 
 /// Returns a single t_list_item from PG as a structure.
-pub(crate) async fn get_t_list_item(liid: Uuid, client: &Client) -> Option<TListItem> {
+pub(crate) async fn get_t_list_item(liid: Uuid, client: &Client) -> Result<Option<TListItem>, PgError> {
     debug!("get_t_list_item for {}", liid);
 
     // get the data from PG
     let rows = client
-        .query("select * from ld_get_tlistitem($1::UUID)", &[&liid])
+        .query_with_retry("select * from ld_get_tlistitem($1::UUID)", &[&liid])
         .await
-        .expect("ld_get_tlistitem query failed");
+        .map_err(|e| query_failed("ld_get_tlistitem", e))?;
 
     // check if the result makes sense
     let row_count = rows.len();
     debug!("Rows: {}", row_count);
     match row_count {
-        1 => Some(TListItem::from(&rows[0])),
+        1 => Ok(Some(TListItem::from(&rows[0]))),
         0 => {
             debug!("no rows - returning None.");
-            return None;
+            Ok(None)
         }
         _ => {
             error!("ld_get_tlistitem returned multiple rows ({}) for {}", row_count, liid);
-            Some(TListItem::from(&rows[0]))
+            Ok(Some(TListItem::from(&rows[0])))
         }
     }
 }
 
+/// Returns the items with any of the liids, whatever list they are in. Liids that don't exist are skipped.
+pub(crate) async fn get_t_list_items_by_liid(liids: &[Uuid], client: &Client) -> Result<Vec<TListItem>, PgError> {
+    debug!("get_t_list_items_by_liid for {} items", liids.len());
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_tlistitems_by_liid($1::UUID[])", &[&liids])
+        .await
+        .map_err(|e| query_failed("ld_get_tlistitems_by_liid", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TListItem::from).collect())
+}
+
 /// Returns the full list of items per list
-pub(crate) async fn get_t_list_items(lid: Uuid, client: &Client) -> Option<Vec<TListItem>> {
+pub(crate) async fn get_t_list_items(lid: Uuid, client: &Client) -> Result<Option<Vec<TListItem>>, PgError> {
     debug!("get_t_list_items for {}", lid);
 
     // get the data from PG
     let rows = client
-        .query("select * from ld_get_tlistitems($1::UUID)", &[&lid])
+        .query_with_retry("select * from ld_get_tlistitems($1::UUID)", &[&lid])
         .await
-        .expect("ld_get_tlistitems query failed");
+        .map_err(|e| query_failed("ld_get_tlistitems", e))?;
 
     // check if the result makes sense
     let row_count = rows.len();
@@ -179,44 +404,48 @@ pub(crate) async fn get_t_list_items(lid: Uuid, client: &Client) -> Option<Vec<T
     // exit early if no data was fetched
     if row_count == 0 {
         debug!("no rows - returning None.");
-        return None;
+        return Ok(None);
     };
 
     // collect the rows in a vector
     let x: Vec<TListItem> = rows.iter().map(|r| TListItem::from(r)).collect();
     debug!("Rows collected: {}", x.len());
-    Some(x)
+    Ok(Some(x))
 }
 
 /// Returns a single t_list from PG as a structure.
-pub(crate) async fn get_t_list(lid: Uuid, client: &Client) -> Option<TList> {
+pub(crate) async fn get_t_list(lid: Uuid, client: &Client) -> Result<Option<TList>, PgError> {
     debug!("get_t_list for {}", lid);
 
     // get the data from PG
     let rows = client
-        .query("select * from ld_get_tlist($1::UUID)", &[&lid])
+        .query_with_retry("select * from ld_get_tlist($1::UUID)", &[&lid])
         .await
-        .expect("ld_get_tlist query failed");
+        .map_err(|e| query_failed("ld_get_tlist", e))?;
 
     // check if the result makes sense
     let row_count = rows.len();
     debug!("Rows: {}", row_count);
     match row_count {
-        1 => Some(TList::from(&rows[0])),
+        1 => Ok(Some(TList::from(&rows[0]))),
         0 => {
             debug!("no rows - returning None.");
-            return None;
+            Ok(None)
         }
         _ => {
             error!("ld_get_tlistitem returned multiple rows ({}) for {}", row_count, lid);
-            Some(TList::from(&rows[0]))
+            Ok(Some(TList::from(&rows[0])))
         }
     }
 }
 
 /// Returns a single t_user from PG as a structure. Use either 1 param + None or both params from the same user.
 /// The DB will return nothing if both params do not match on the same user.
-pub(crate) async fn get_t_user(user_id: Option<Uuid>, user_email: Option<String>, client: &Client) -> Option<TUser> {
+pub(crate) async fn get_t_user(
+    user_id: Option<Uuid>,
+    user_email: Option<String>,
+    client: &Client,
+) -> Result<Option<TUser>, PgError> {
     debug!(
         "get_t_list for user_id {} / email {}",
         user_id.clone().unwrap_or_else(|| Uuid::default()),
@@ -225,150 +454,702 @@ pub(crate) async fn get_t_user(user_id: Option<Uuid>, user_email: Option<String>
 
     // get the data from PG
     let rows = client
-        .query(
+        .query_with_retry(
             "select * from ld_get_tuser($1::UUID, $2::varchar)",
             &[&user_id, &user_email],
         )
         .await
-        .expect("ld_get_tlist query failed");
+        .map_err(|e| query_failed("ld_get_tuser", e))?;
 
     // check if the result makes sense
     let row_count = rows.len();
     debug!("Rows: {}", row_count);
     match row_count {
-        1 => Some(TUser::from(&rows[0])),
+        1 => Ok(Some(TUser::from(&rows[0]))),
         0 => {
             debug!("no rows - returning None.");
-            None
+            Ok(None)
         }
         _ => {
             error!("ld_get_tuser returned multiple rows {}", row_count);
-            None
+            Ok(None)
         }
     }
 }
 
 /// Returns top N recent lists for the specified user.
 /// Either `id` or `email` must be specified and belong to the same user if both are present.
-pub(crate) async fn get_user_lists(user_id: Uuid, client: &Client) -> Option<Vec<TList>> {
+pub(crate) async fn get_user_lists(user_id: Uuid, client: &Client) -> Result<Option<Vec<TList>>, PgError> {
     debug!("get_user_lists for user_id {}", user_id);
 
     // get the data from PG
     let rows = client
-        .query(
+        .query_with_retry(
             "select * from ld_get_user_lists($1::UUID)",
             &[&user_id],
         )
         .await
-        .expect("ld_get_user_lists query failed");
+        .map_err(|e| query_failed("ld_get_user_lists", e))?;
 
     // check if the result makes sense before returning it
     let row_count = rows.len();
     debug!("Rows: {}", row_count);
-    match row_count {
+
+    // trashed lists are hidden from the user, even if the DB function returned them
+    let lists: Vec<TList> = rows
+        .iter()
+        .map(|r| TList::from(r))
+        .filter(|l| l.deleted_on_utc.is_none())
+        .collect();
+    match lists.len() {
         0 => {
             debug!("no rows - returning None.");
-            None
+            Ok(None)
         }
-        _ => Some(rows.iter().map(|r| TList::from(r)).collect()),
+        _ => Ok(Some(lists)),
     }
 }
 
+/// Returns all lists the user moved to trash and has not purged yet.
+pub(crate) async fn get_user_trash(user_id: Uuid, client: &Client) -> Result<Option<Vec<TList>>, PgError> {
+    debug!("get_user_trash for user_id {}", user_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_user_trash($1::UUID)", &[&user_id])
+        .await
+        .map_err(|e| query_failed("ld_get_user_trash", e))?;
+
+    // check if the result makes sense before returning it
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    Ok(match row_count {
+        0 => {
+            debug!("no rows - returning None.");
+            None
+        }
+        _ => Some(rows.iter().map(TList::from).collect()),
+    })
+}
+
+/// Returns all lists owned by the user, including the ones in the trash, with no limit on the number of lists.
+pub(crate) async fn get_all_user_lists(user_id: Uuid, client: &Client) -> Result<Vec<TList>, PgError> {
+    debug!("get_all_user_lists for user_id {}", user_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_user_all_lists($1::UUID)", &[&user_id])
+        .await
+        .map_err(|e| query_failed("ld_get_user_all_lists", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TList::from).collect())
+}
+
+/// Returns lists owned by other users that are shared with this user, e.g. lists of the same org.
+pub(crate) async fn get_user_shared_lists(user_id: Uuid, client: &Client) -> Result<Vec<TList>, PgError> {
+    debug!("get_user_shared_lists for user_id {}", user_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_user_shared_lists($1::UUID)", &[&user_id])
+        .await
+        .map_err(|e| query_failed("ld_get_user_shared_lists", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TList::from).filter(|l| l.deleted_on_utc.is_none()).collect())
+}
+
+/// Returns the list if it is owned by another user and shared with this user. A point query for access checks
+/// that would otherwise load all shared lists.
+pub(crate) async fn get_user_shared_list(user_id: Uuid, lid: Uuid, client: &Client) -> Result<Option<TList>, PgError> {
+    debug!("get_user_shared_list for user_id {} / {}", user_id, lid);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_user_shared_tlist($1::UUID, $2::UUID)", &[&user_id, &lid])
+        .await
+        .map_err(|e| query_failed("ld_get_user_shared_tlist", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TList::from).find(|l| l.deleted_on_utc.is_none()))
+}
+
+/// Returns all lists that were moved to trash before `deleted_before` and are due to be purged.
+pub(crate) async fn get_expired_t_lists(
+    deleted_before: chrono::DateTime<Utc>,
+    client: &Client,
+) -> Result<Vec<TList>, PgError> {
+    debug!("get_expired_t_lists before {}", deleted_before);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_expired_tlists($1::TIMESTAMPTZ)", &[&deleted_before])
+        .await
+        .map_err(|e| query_failed("ld_get_expired_tlists", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TList::from).collect())
+}
+
+/// Returns all list items that were moved to trash before `deleted_before` and are due to be purged.
+pub(crate) async fn get_expired_t_list_items(
+    deleted_before: chrono::DateTime<Utc>,
+    client: &Client,
+) -> Result<Vec<TListItem>, PgError> {
+    debug!("get_expired_t_list_items before {}", deleted_before);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_expired_tlistitems($1::TIMESTAMPTZ)", &[&deleted_before])
+        .await
+        .map_err(|e| query_failed("ld_get_expired_tlistitems", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TListItem::from).collect())
+}
+
 /// Upserts a single item from a struct to an existing PG list
-pub(crate) async fn put_t_list_item(item: &TListItem, client: &Client) -> Option<TListItem> {
+pub(crate) async fn put_t_list_item(item: &TListItem, client: &Client) -> Result<Option<TListItem>, PgError> {
     debug!("put_t_list_item for {}", item.liid);
 
     // get the data from PG
     let rows = client
-        .query(
+        .query_with_retry(
             "select * from ld_put_tlistitem($1::UUID, $2::UUID)",
             &[&item.parent_lid, &item.liid],
         )
         .await
-        .expect("ld_put_tlistitem query failed");
+        .map_err(|e| query_failed("ld_put_tlistitem", e))?;
 
     // check if the result makes sense
     let row_count = rows.len();
     debug!("Rows: {}", row_count);
-    match row_count {
-        1 => Some(TListItem::from(&rows[0])),
+    let saved = match row_count {
+        1 => TListItem::from(&rows[0]),
         0 => {
             debug!("no rows - returning None.");
-            return None;
+            return Ok(None);
         }
         _ => {
             error!(
                 "ld_put_tlistitem returned multiple rows ({}) for {}",
                 row_count, item.liid
             );
-            Some(TListItem::from(&rows[0]))
+            TListItem::from(&rows[0])
         }
+    };
+
+    audit::record(
+        TAudit {
+            lid: Some(saved.parent_lid),
+            liid: Some(saved.liid),
+            after: audit::to_summary(&saved),
+            ..TAudit::new(AuditAction::PutTListItem)
+        },
+        client,
+    )
+    .await;
+
+    Ok(Some(saved))
+}
+
+/// Upserts many items in a single call. Items may belong to different lists, which must already exist in PG.
+/// Returns the saved items in no particular order.
+pub(crate) async fn put_t_list_items(items: &[TListItem], client: &Client) -> Result<Vec<TListItem>, PgError> {
+    debug!("put_t_list_items for {} items", items.len());
+
+    // the DB function takes the fields as parallel arrays
+    let parent_lids: Vec<Uuid> = items.iter().map(|i| i.parent_lid).collect();
+    let liids: Vec<Uuid> = items.iter().map(|i| i.liid).collect();
+    let child_lids: Vec<Option<Uuid>> = items.iter().map(|i| i.child_lid).collect();
+    let origin_lids: Vec<Option<Uuid>> = items.iter().map(|i| i.origin_lid).collect();
+    let origin_liids: Vec<Option<Uuid>> = items.iter().map(|i| i.origin_liid).collect();
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_put_tlistitems($1::UUID[], $2::UUID[], $3::UUID[], $4::UUID[], $5::UUID[])",
+            &[&parent_lids, &liids, &child_lids, &origin_lids, &origin_liids],
+        )
+        .await
+        .map_err(|e| query_failed("ld_put_tlistitems", e))?;
+
+    // check if the result makes sense
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    if row_count != items.len() {
+        error!("ld_put_tlistitems returned {} rows for {} items", row_count, items.len());
+        return Err(PgError::Logged);
     }
+
+    Ok(rows.iter().map(TListItem::from).collect())
+}
+
+/// Applies many item changes in a single PG transaction: upserts `items` with their `parent_lid` and due dates
+/// and deletes `deleted_liids`. Returns the upserted items in the same order or an error if any of them failed.
+/// The call is not retried after connection errors because it may have been applied.
+pub(crate) async fn apply_t_list_items(
+    items: &[TListItem],
+    deleted_liids: &[Uuid],
+    client: &Client,
+) -> Result<Vec<TListItem>, PgError> {
+    debug!("apply_t_list_items for {} / {} items", items.len(), deleted_liids.len());
+
+    // the DB function takes the fields as parallel arrays
+    let liids: Vec<Uuid> = items.iter().map(|i| i.liid).collect();
+    let parent_lids: Vec<Uuid> = items.iter().map(|i| i.parent_lid).collect();
+    let due_on_utc: Vec<Option<chrono::DateTime<Utc>>> = items.iter().map(|i| i.due_on_utc).collect();
+    let remind_on_utc: Vec<Option<chrono::DateTime<Utc>>> = items.iter().map(|i| i.remind_on_utc).collect();
+
+    // get the data from PG
+    let rows = client
+        .query_write_with_retry(
+            "select * from ld_apply_tlistitems($1::UUID[], $2::UUID[], $3::TIMESTAMPTZ[], $4::TIMESTAMPTZ[], $5::UUID[])",
+            &[&liids, &parent_lids, &due_on_utc, &remind_on_utc, &deleted_liids],
+        )
+        .await
+        .map_err(|e| query_failed("ld_apply_tlistitems", e))?;
+
+    // check if the result makes sense
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    if row_count != items.len() {
+        error!("ld_apply_tlistitems returned {} rows for {} items", row_count, items.len());
+        return Err(PgError::Logged);
+    }
+
+    // return the rows in the order of the input
+    let mut saved: HashMap<Uuid, TListItem> = rows.iter().map(TListItem::from).map(|i| (i.liid, i)).collect();
+    liids
+        .iter()
+        .map(|liid| saved.remove(liid).ok_or(PgError::Logged))
+        .collect()
 }
 
 /// Upserts a single t_list from struct into PG.
-pub(crate) async fn put_t_list(list: &TList, client: &Client) -> Option<TList> {
+pub(crate) async fn put_t_list(list: &TList, client: &Client) -> Result<Option<TList>, PgError> {
     debug!("put_t_list for {}", list.lid);
 
     // get the data from PG
     let rows = client
-        .query(
+        .query_with_retry(
             "select * from ld_put_tlist($1::UUID, $2::UUID)",
             &[&list.lid, &list.user_id],
         )
         .await
-        .expect("ld_put_tlist query failed");
+        .map_err(|e| query_failed("ld_put_tlist", e))?;
 
     // check if the result makes sense
     let row_count = rows.len();
     debug!("Rows: {}", row_count);
-    match row_count {
-        1 => Some(TList::from(&rows[0])),
+    let saved = match row_count {
+        1 => TList::from(&rows[0]),
         0 => {
             debug!("no rows - returning None.");
-            return None;
+            return Ok(None);
         }
         _ => {
             error!("ld_put_tlist returned multiple rows ({}) for {}", row_count, list.lid);
-            Some(TList::from(&rows[0]))
+            TList::from(&rows[0])
         }
-    }
+    };
+
+    audit::record(
+        TAudit {
+            user_id: saved.user_id,
+            lid: Some(saved.lid),
+            after: audit::to_summary(&saved),
+            ..TAudit::new(AuditAction::PutTList)
+        },
+        client,
+    )
+    .await;
+
+    Ok(Some(saved))
 }
 
-pub(crate) async fn put_t_user(user_email: &String, client: &Client) -> Option<TUser> {
+pub(crate) async fn put_t_user(user_email: &String, client: &Client) -> Result<Option<TUser>, PgError> {
     debug!("ld_put_tuser for {}", user_email);
 
     // get the data from PG
     let rows = client
-        .query("select * from ld_put_tuser($1::varchar)", &[user_email])
+        .query_write_with_retry("select * from ld_put_tuser($1::varchar)", &[user_email])
         .await
-        .expect("ld_put_tuser query failed");
+        .map_err(|e| query_failed("ld_put_tuser", e))?;
 
     // check if the result makes sense
     let row_count = rows.len();
     debug!("Rows: {}", row_count);
     match row_count {
-        1 => Some(TUser::from(&rows[0])),
+        1 => Ok(Some(TUser::from(&rows[0]))),
         0 => {
             debug!("no rows - returning None.");
-            None
+            Ok(None)
         }
         _ => {
             error!("ld_put_tuser returned multiple rows {}", row_count);
-            None
+            Ok(None)
         }
     }
 }
 
+/// Sets or clears `deleted_on_utc` on a single t_list. Returns the updated list.
+pub(crate) async fn trash_t_list(lid: Uuid, trashed: bool, client: &Client) -> Result<Option<TList>, PgError> {
+    debug!("trash_t_list for {} / {}", lid, trashed);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_trash_tlist($1::UUID, $2::BOOLEAN)", &[&lid, &trashed])
+        .await
+        .map_err(|e| query_failed("ld_trash_tlist", e))?;
+
+    // check if the result makes sense
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    Ok(match row_count {
+        1 => Some(TList::from(&rows[0])),
+        0 => {
+            debug!("no rows - returning None.");
+            None
+        }
+        _ => {
+            error!("ld_trash_tlist returned multiple rows ({}) for {}", row_count, lid);
+            Some(TList::from(&rows[0]))
+        }
+    })
+}
+
+/// Sets or clears `deleted_on_utc` on a single t_list_item. Returns the updated item.
+pub(crate) async fn trash_t_list_item(
+    liid: Uuid,
+    trashed: bool,
+    client: &Client,
+) -> Result<Option<TListItem>, PgError> {
+    debug!("trash_t_list_item for {} / {}", liid, trashed);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_trash_tlistitem($1::UUID, $2::BOOLEAN)", &[&liid, &trashed])
+        .await
+        .map_err(|e| query_failed("ld_trash_tlistitem", e))?;
+
+    // check if the result makes sense
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    Ok(match row_count {
+        1 => Some(TListItem::from(&rows[0])),
+        0 => {
+            debug!("no rows - returning None.");
+            None
+        }
+        _ => {
+            error!("ld_trash_tlistitem returned multiple rows ({}) for {}", row_count, liid);
+            Some(TListItem::from(&rows[0]))
+        }
+    })
+}
+
+/// Moves many items to trash or restores them in a single call. Returns the updated items.
+pub(crate) async fn trash_t_list_items(
+    liids: &[Uuid],
+    trashed: bool,
+    client: &Client,
+) -> Result<Vec<TListItem>, PgError> {
+    debug!("trash_t_list_items for {} items / {}", liids.len(), trashed);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_trash_tlistitems($1::UUID[], $2::BOOLEAN)", &[&liids, &trashed])
+        .await
+        .map_err(|e| query_failed("ld_trash_tlistitems", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TListItem::from).collect())
+}
+
+/// Checks off many items as done by `user_id` or clears their completion in a single call.
+/// Returns the updated items.
+pub(crate) async fn complete_t_list_items(
+    liids: &[Uuid],
+    completed: bool,
+    user_id: Uuid,
+    client: &Client,
+) -> Result<Vec<TListItem>, PgError> {
+    debug!("complete_t_list_items for {} items / {}", liids.len(), completed);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_complete_tlistitems($1::UUID[], $2::BOOLEAN, $3::UUID)",
+            &[&liids, &completed, &user_id],
+        )
+        .await
+        .map_err(|e| query_failed("ld_complete_tlistitems", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TListItem::from).collect())
+}
+
+/// Sets or clears the due and reminder times of a single t_list_item. Returns the updated item.
+pub(crate) async fn put_t_list_item_due(
+    liid: Uuid,
+    due_on_utc: Option<chrono::DateTime<Utc>>,
+    remind_on_utc: Option<chrono::DateTime<Utc>>,
+    client: &Client,
+) -> Result<Option<TListItem>, PgError> {
+    debug!("put_t_list_item_due for {} / {:?}", liid, due_on_utc);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_put_tlistitem_due($1::UUID, $2::TIMESTAMPTZ, $3::TIMESTAMPTZ)",
+            &[&liid, &due_on_utc, &remind_on_utc],
+        )
+        .await
+        .map_err(|e| query_failed("ld_put_tlistitem_due", e))?;
+
+    // check if the result makes sense
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    Ok(match row_count {
+        1 => Some(TListItem::from(&rows[0])),
+        0 => {
+            debug!("no rows - returning None.");
+            None
+        }
+        _ => {
+            error!("ld_put_tlistitem_due returned multiple rows ({}) for {}", row_count, liid);
+            Some(TListItem::from(&rows[0]))
+        }
+    })
+}
+
+/// Returns all items of the user's lists that are not done or trashed and are due before `due_before`,
+/// including the overdue ones, in the order of their due time.
+pub(crate) async fn get_user_due_t_list_items(
+    user_id: Uuid,
+    due_before: chrono::DateTime<Utc>,
+    client: &Client,
+) -> Result<Vec<TListItem>, PgError> {
+    debug!("get_user_due_t_list_items for {} / {}", user_id, due_before);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_get_user_due_tlistitems($1::UUID, $2::TIMESTAMPTZ)",
+            &[&user_id, &due_before],
+        )
+        .await
+        .map_err(|e| query_failed("ld_get_user_due_tlistitems", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TListItem::from).collect())
+}
+
 /// Deletes a single item from an existing PG list
-pub(crate) async fn del_t_list_item(liid: Uuid, client: &Client) {
+pub(crate) async fn del_t_list_item(liid: Uuid, client: &Client) -> Result<(), PgError> {
     debug!("del_t_list_item for {}", liid);
 
     // get the data from PG
     client
-        .query("select * from ld_del_tlistitem($1::UUID)", &[&liid])
+        .query_with_retry("select * from ld_del_tlistitem($1::UUID)", &[&liid])
+        .await
+        .map_err(|e| query_failed("ld_del_tlistitem", e))?;
+
+    Ok(())
+}
+
+/// Replaces all tags of the list with `tags`. The tags must be normalised by the caller. Returns the saved tags.
+pub(crate) async fn put_t_list_tags(lid: Uuid, tags: &[String], client: &Client) -> Result<Vec<String>, PgError> {
+    debug!("put_t_list_tags for {} / {}", lid, tags.len());
+
+    // get the data from PG
+    let rows = match client
+        .query_with_retry("select * from ld_put_tlist_tags($1::UUID, $2::VARCHAR[])", &[&lid, &tags])
+        .await
+    {
+        Ok(v) => v,
+        Err(x) => {
+            error!("Error in put_t_list_tags for {} with {:?}", lid, x);
+            return Err(PgError::Logged);
+        }
+    };
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(|r| r.get("tag")).collect())
+}
+
+/// Replaces the searchable text of the list. `body` is the list description with all item titles and descriptions.
+pub(crate) async fn put_t_list_search(lid: Uuid, title: &str, body: &str, client: &Client) -> Result<(), PgError> {
+    debug!("put_t_list_search for {}", lid);
+
+    if let Err(x) = client
+        .query_with_retry(
+            "select * from ld_put_tlist_search($1::UUID, $2::VARCHAR, $3::TEXT)",
+            &[&lid, &title, &body],
+        )
+        .await
+    {
+        error!("Error in put_t_list_search for {} with {:?}", lid, x);
+        return Err(PgError::Logged);
+    }
+
+    Ok(())
+}
+
+/// Saves the number of items in the list and how many of them are done. Does nothing if the list is not in PG.
+pub(crate) async fn put_t_list_counts(
+    lid: Uuid,
+    item_count: i32,
+    done_count: i32,
+    client: &Client,
+) -> Result<(), PgError> {
+    debug!("put_t_list_counts for {}: {} / {}", lid, done_count, item_count);
+
+    if let Err(x) = client
+        .query_with_retry(
+            "select * from ld_put_tlist_counts($1::UUID, $2::INTEGER, $3::INTEGER)",
+            &[&lid, &item_count, &done_count],
+        )
+        .await
+    {
+        error!("Error in put_t_list_counts for {} with {:?}", lid, x);
+        return Err(PgError::Logged);
+    }
+
+    Ok(())
+}
+
+/// Removes the tags, the searchable text and the item counts of a list that was deleted from DDB.
+/// The list itself is not affected.
+pub(crate) async fn del_t_list_derived(lid: Uuid, client: &Client) -> Result<(), PgError> {
+    debug!("del_t_list_derived for {}", lid);
+
+    if let Err(x) = client
+        .query_with_retry("select * from ld_del_tlist_derived($1::UUID)", &[&lid])
+        .await
+    {
+        error!("Error in del_t_list_derived for {} with {:?}", lid, x);
+        return Err(PgError::Logged);
+    }
+
+    Ok(())
+}
+
+/// Returns up to `limit` lists the user can access that match the query, best matches first.
+/// The query uses web search syntax, e.g. `milk -soy "whole grain"`. Lists in the trash are not included.
+pub(crate) async fn search_user_lists(
+    user_id: Uuid,
+    query: &str,
+    limit: i64,
+    client: &Client,
+) -> Result<Vec<TSearchHit>, PgError> {
+    debug!("search_user_lists for {}", user_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_search_user_lists($1::UUID, $2::VARCHAR, $3::BIGINT)",
+            &[&user_id, &query, &limit],
+        )
+        .await
+        .map_err(|e| query_failed("ld_search_user_lists", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TSearchHit::from).collect())
+}
+
+/// Returns all lists of the user with the tag. Lists in the trash are included only if `incl_trash` is true.
+pub(crate) async fn get_user_tag_lists(
+    user_id: Uuid,
+    tag: &str,
+    incl_trash: bool,
+    client: &Client,
+) -> Result<Vec<TList>, PgError> {
+    debug!("get_user_tag_lists for {} / {}", user_id, tag);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_get_user_tag_lists($1::UUID, $2::VARCHAR, $3::BOOLEAN)",
+            &[&user_id, &tag, &incl_trash],
+        )
+        .await
+        .map_err(|e| query_failed("ld_get_user_tag_lists", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TList::from).collect())
+}
+
+/// Returns up to `limit` tags of the user starting with `prefix`, most used first.
+/// Lists in the trash are not counted.
+pub(crate) async fn get_user_tags(
+    user_id: Uuid,
+    prefix: &str,
+    limit: i64,
+    client: &Client,
+) -> Result<Vec<TTagCount>, PgError> {
+    debug!("get_user_tags for {} / {}", user_id, prefix);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_get_user_tags($1::UUID, $2::VARCHAR, $3::BIGINT)",
+            &[&user_id, &prefix, &limit],
+        )
+        .await
+        .map_err(|e| query_failed("ld_get_user_tags", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TTagCount::from).collect())
+}
+
+/// Marks the list as a template or as a regular list. Returns the updated list.
+pub(crate) async fn put_t_list_template(
+    lid: Uuid,
+    is_template: bool,
+    client: &Client,
+) -> Result<Option<TList>, PgError> {
+    debug!("put_t_list_template for {} / {}", lid, is_template);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_put_tlist_template($1::UUID, $2::BOOLEAN)",
+            &[&lid, &is_template],
+        )
+        .await
+        .map_err(|e| query_failed("ld_put_tlist_template", e))?;
+
+    // check if the result makes sense
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    Ok(match row_count {
+        1 => Some(TList::from(&rows[0])),
+        0 => {
+            debug!("no rows - returning None.");
+            None
+        }
+        _ => {
+            error!("ld_put_tlist_template returned multiple rows ({}) for {}", row_count, lid);
+            Some(TList::from(&rows[0]))
+        }
+    })
+}
+
+/// Returns templates owned by the user and templates of the user's org, excluding the trash.
+pub(crate) async fn get_user_templates(user_id: Uuid, client: &Client) -> Result<Vec<TList>, PgError> {
+    debug!("get_user_templates for {}", user_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_user_templates($1::UUID)", &[&user_id])
         .await
-        .expect("ld_del_tlistitem query failed");
+        .map_err(|e| query_failed("ld_get_user_templates", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TList::from).collect())
 }
 
 /// Deletes a single list with all child items in PG. Other linked lists are not affected.
@@ -376,13 +1157,21 @@ pub(crate) async fn del_t_list(lid: Uuid, client: &Client) -> Result<(), PgError
     debug!("ld_del_tlist for {}", lid);
 
     // delete the data from PG
-    match client.query("select * from ld_del_tlist($1::UUID)", &[&lid]).await {
-        Err(x) => {
-            error!("Error in del_t_list for {} with {:?}", lid, x);
-            return Err(PgError::Logged);
-        }
-        _ => return Ok(()),
+    if let Err(x) = client.query_with_retry("select * from ld_del_tlist($1::UUID)", &[&lid]).await {
+        error!("Error in del_t_list for {} with {:?}", lid, x);
+        return Err(PgError::Logged);
     }
+
+    audit::record(
+        TAudit {
+            lid: Some(lid),
+            ..TAudit::new(AuditAction::DelTList)
+        },
+        client,
+    )
+    .await;
+
+    Ok(())
 }
 
 /// Delete a single user from PG.
@@ -390,11 +1179,232 @@ pub(crate) async fn del_t_user(user_id: Uuid, client: &Client) -> Result<(), PgE
     debug!("ld_del_tuser for {}", user_id);
 
     // get the data from PG
-    match client.query("select * from ld_del_tuser($1::UUID)", &[&user_id]).await {
-        Err(x) => {
-            error!("Error in del_t_user for {} with {:?}", user_id, x);
-            return Err(PgError::Logged);
+    if let Err(x) = client.query_with_retry("select * from ld_del_tuser($1::UUID)", &[&user_id]).await {
+        error!("Error in del_t_user for {} with {:?}", user_id, x);
+        return Err(PgError::Logged);
+    }
+
+    // no audit record, so that the log does not name the deleted user - the deletion receipt is the record
+
+    Ok(())
+}
+
+/// Saves a single audit record. Returns an error instead of panicking so that a failed audit write
+/// does not undo a mutation that was already made.
+pub(crate) async fn put_t_audit(entry: &TAudit, client: &Client) -> Result<(), PgError> {
+    debug!("put_t_audit for {}", entry.action.as_str());
+
+    if let Err(x) = client
+        .query_write_with_retry(
+            "select * from ld_put_taudit($1::UUID, $2::VARCHAR, $3::UUID, $4::UUID, $5::UUID, $6::JSONB, $7::JSONB)",
+            &[
+                &entry.actor,
+                &entry.action.as_str(),
+                &entry.user_id,
+                &entry.lid,
+                &entry.liid,
+                &entry.before,
+                &entry.after,
+            ],
+        )
+        .await
+    {
+        error!("Error in put_t_audit for {:?} with {:?}", entry, x);
+        return Err(PgError::Logged);
+    }
+
+    Ok(())
+}
+
+/// Removes the user from the audit log: deletes the records of the lists and their items, the records applying
+/// to the user and clears the actor in the records of changes the user made to lists of other users.
+pub(crate) async fn del_user_t_audit(user_id: Uuid, lids: &[Uuid], client: &Client) -> Result<(), PgError> {
+    debug!("del_user_t_audit for {} with {} lists", user_id, lids.len());
+
+    client
+        .query_with_retry("select * from ld_del_user_taudit($1::UUID, $2::UUID[])", &[&user_id, &lids])
+        .await
+        .map_err(|e| query_failed("ld_del_user_taudit", e))?;
+
+    Ok(())
+}
+
+/// Returns up to `limit` audit records made by the user or applying to the user, newest first.
+/// Only records that come after `before` in that order are returned, if set, for paging.
+pub(crate) async fn get_user_audit(
+    user_id: Uuid,
+    before: Option<AuditCursor>,
+    limit: i64,
+    client: &Client,
+) -> Result<Vec<TAudit>, PgError> {
+    debug!("get_user_audit for {}", user_id);
+    let before_on_utc = before.map(|c| c.created_on_utc);
+    let before_id = before.map(|c| c.audit_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_get_user_taudit($1::UUID, $2::TIMESTAMPTZ, $3::BIGINT, $4::BIGINT)",
+            &[&user_id, &before_on_utc, &before_id, &limit],
+        )
+        .await
+        .map_err(|e| query_failed("ld_get_user_taudit", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TAudit::from).collect())
+}
+
+/// Returns up to `limit` audit records of the list and its items, newest first.
+/// Only records that come after `before` in that order are returned, if set, for paging.
+pub(crate) async fn get_list_audit(
+    lid: Uuid,
+    before: Option<AuditCursor>,
+    limit: i64,
+    client: &Client,
+) -> Result<Vec<TAudit>, PgError> {
+    debug!("get_list_audit for {}", lid);
+    let before_on_utc = before.map(|c| c.created_on_utc);
+    let before_id = before.map(|c| c.audit_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_get_list_taudit($1::UUID, $2::TIMESTAMPTZ, $3::BIGINT, $4::BIGINT)",
+            &[&lid, &before_on_utc, &before_id, &limit],
+        )
+        .await
+        .map_err(|e| query_failed("ld_get_list_taudit", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TAudit::from).collect())
+}
+
+/// Saves a new webhook registration. Returns the saved record.
+pub(crate) async fn put_t_webhook(webhook: &TWebhook, client: &Client) -> Result<Option<TWebhook>, PgError> {
+    debug!("put_t_webhook for {}", webhook.webhook_id);
+
+    // get the data from PG
+    let rows = client
+        .query_write_with_retry(
+            "select * from ld_put_twebhook($1::UUID, $2::UUID, $3::UUID, $4::UUID, $5::VARCHAR, $6::VARCHAR)",
+            &[
+                &webhook.webhook_id,
+                &webhook.user_id,
+                &webhook.lid,
+                &webhook.org_id,
+                &webhook.url,
+                &webhook.secret,
+            ],
+        )
+        .await
+        .map_err(|e| query_failed("ld_put_twebhook", e))?;
+
+    // check if the result makes sense
+    let row_count = rows.len();
+    debug!("Rows: {}", row_count);
+    Ok(match row_count {
+        1 => Some(TWebhook::from(&rows[0])),
+        0 => {
+            debug!("no rows - returning None.");
+            None
         }
-        _ => return Ok(()),
+        _ => {
+            error!("ld_put_twebhook returned multiple rows ({}) for {}", row_count, webhook.webhook_id);
+            Some(TWebhook::from(&rows[0]))
+        }
+    })
+}
+
+/// Deletes the webhook if it was registered by the user. The delivery log is deleted with it.
+pub(crate) async fn del_t_webhook(webhook_id: Uuid, user_id: Uuid, client: &Client) -> Result<(), PgError> {
+    debug!("del_t_webhook for {}", webhook_id);
+
+    if let Err(x) = client
+        .query_with_retry("select * from ld_del_twebhook($1::UUID, $2::UUID)", &[&webhook_id, &user_id])
+        .await
+    {
+        error!("Error in del_t_webhook for {} with {:?}", webhook_id, x);
+        return Err(PgError::Logged);
     }
+
+    Ok(())
+}
+
+/// Returns all webhooks registered by the user.
+pub(crate) async fn get_user_webhooks(user_id: Uuid, client: &Client) -> Result<Vec<TWebhook>, PgError> {
+    debug!("get_user_webhooks for {}", user_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_user_twebhooks($1::UUID)", &[&user_id])
+        .await
+        .map_err(|e| query_failed("ld_get_user_twebhooks", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TWebhook::from).collect())
+}
+
+/// Returns webhooks registered for the list or for the org the list belongs to.
+pub(crate) async fn get_list_webhooks(
+    lid: Uuid,
+    org_id: Option<Uuid>,
+    client: &Client,
+) -> Result<Vec<TWebhook>, PgError> {
+    debug!("get_list_webhooks for {} / {:?}", lid, org_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry("select * from ld_get_list_twebhooks($1::UUID, $2::UUID)", &[&lid, &org_id])
+        .await
+        .map_err(|e| query_failed("ld_get_list_twebhooks", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TWebhook::from).collect())
+}
+
+/// Adds a delivery attempt to the delivery log.
+pub(crate) async fn put_t_webhook_delivery(delivery: &TWebhookDelivery, client: &Client) -> Result<(), PgError> {
+    debug!("put_t_webhook_delivery for {} / {}", delivery.webhook_id, delivery.attempt);
+
+    if let Err(x) = client
+        .query_write_with_retry(
+            "select * from ld_put_twebhookdelivery($1::UUID, $2::UUID, $3::VARCHAR, $4::INT, $5::INT, $6::VARCHAR, $7::BIGINT)",
+            &[
+                &delivery.webhook_id,
+                &delivery.event_id,
+                &delivery.event,
+                &delivery.attempt,
+                &delivery.status_code,
+                &delivery.error,
+                &delivery.duration_ms,
+            ],
+        )
+        .await
+    {
+        error!("Error in put_t_webhook_delivery for {:?} with {:?}", delivery, x);
+        return Err(PgError::Logged);
+    }
+
+    Ok(())
+}
+
+/// Returns up to `limit` delivery attempts for the webhook, newest first.
+pub(crate) async fn get_webhook_deliveries(
+    webhook_id: Uuid,
+    limit: i64,
+    client: &Client,
+) -> Result<Vec<TWebhookDelivery>, PgError> {
+    debug!("get_webhook_deliveries for {}", webhook_id);
+
+    // get the data from PG
+    let rows = client
+        .query_with_retry(
+            "select * from ld_get_twebhookdelivery($1::UUID, $2::BIGINT)",
+            &[&webhook_id, &limit],
+        )
+        .await
+        .map_err(|e| query_failed("ld_get_twebhookdelivery", e))?;
+
+    debug!("Rows: {}", rows.len());
+    Ok(rows.iter().map(TWebhookDelivery::from).collect())
 }
@@ -17,16 +17,16 @@ mod tests_pg {
 
         // create a new user
         let user_email = ["test_postgres_functions@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
-        let pg_user = put_t_user(&user_email, &client).await;
+        let pg_user = put_t_user(&user_email, &client).await.unwrap();
         assert!(pg_user.is_some());
 
         // get the user with diff input combos
         let pg_user = pg_user.unwrap(); // it's safe to unwrap after the assert! for Some().
-        let pg_user_g1 = get_t_user(None, Some(pg_user.user_email.clone()), &client).await;
-        let pg_user_g2 = get_t_user(Some(pg_user.user_id.clone()), None, &client).await;
-        let pg_user_g3 = get_t_user(Some(pg_user.user_id.clone()), Some(pg_user.user_email.clone()), &client).await;
-        let pg_user_g4 = get_t_user(None, None, &client).await;
-        let pg_user_g5 = get_t_user(Some(Uuid::new_v4()), Some(pg_user.user_email.clone()), &client).await;
+        let pg_user_g1 = get_t_user(None, Some(pg_user.user_email.clone()), &client).await.unwrap();
+        let pg_user_g2 = get_t_user(Some(pg_user.user_id.clone()), None, &client).await.unwrap();
+        let pg_user_g3 = get_t_user(Some(pg_user.user_id.clone()), Some(pg_user.user_email.clone()), &client).await.unwrap();
+        let pg_user_g4 = get_t_user(None, None, &client).await.unwrap();
+        let pg_user_g5 = get_t_user(Some(Uuid::new_v4()), Some(pg_user.user_email.clone()), &client).await.unwrap();
         assert!(pg_user_g1.is_some());
         assert!(pg_user_g2.is_some());
         assert!(pg_user_g3.is_some());
@@ -35,7 +35,7 @@ mod tests_pg {
 
         // create a new list
         let pg_list = TList::new(Uuid::new_v4(), pg_user.user_id.clone());
-        let list_put = put_t_list(&pg_list, &client).await;
+        let list_put = put_t_list(&pg_list, &client).await.unwrap();
         debug!("list created: {:?}", list_put);
 
         // create new items
@@ -44,24 +44,24 @@ mod tests_pg {
         let pg_list_item_3 = TListItem::new(Uuid::new_v4(), pg_list.lid);
         debug!("dummy item created: {:?}", pg_list_item_1);
 
-        let pg_list_item_1p = put_t_list_item(&pg_list_item_1, &client).await;
-        let pg_list_item_2p = put_t_list_item(&pg_list_item_2, &client).await;
-        let pg_list_item_3p = put_t_list_item(&pg_list_item_3, &client).await;
+        let pg_list_item_1p = put_t_list_item(&pg_list_item_1, &client).await.unwrap();
+        let pg_list_item_2p = put_t_list_item(&pg_list_item_2, &client).await.unwrap();
+        let pg_list_item_3p = put_t_list_item(&pg_list_item_3, &client).await.unwrap();
         debug!("pg item created: {:?}", pg_list_item_1p);
 
         // get single item
-        let pg_list_item_1g = get_t_list_item(pg_list_item_1p.clone().unwrap().liid, &client).await;
+        let pg_list_item_1g = get_t_list_item(pg_list_item_1p.clone().unwrap().liid, &client).await.unwrap();
         debug!("pg item retrieved: {:?}", pg_list_item_1g);
 
         // get all items for the list
-        let items_get = get_t_list_items(pg_list.lid, &client).await;
+        let items_get = get_t_list_items(pg_list.lid, &client).await.unwrap();
         debug!(
             "pg items retrieved: {}",
             items_get.as_ref().map(|itg| itg.len()).unwrap_or_else(|| 0)
         );
 
         // get a single list
-        let list_get = get_t_list(pg_list.lid, &client).await;
+        let list_get = get_t_list(pg_list.lid, &client).await.unwrap();
         debug!("pg list retrieved: {:?}", list_get);
 
         // assert
@@ -75,24 +75,24 @@ mod tests_pg {
         assert_eq!(items_get, Some(vec!(p1, p2, p3)));
 
         // test deletion of a single item
-        del_t_list_item(pg_list_item_1.liid, &client).await;
-        let pg_list_item_1d = get_t_list_item(pg_list_item_1.liid, &client).await;
-        let pg_list_item_2d = get_t_list_item(pg_list_item_2.liid, &client).await;
+        del_t_list_item(pg_list_item_1.liid, &client).await.unwrap();
+        let pg_list_item_1d = get_t_list_item(pg_list_item_1.liid, &client).await.unwrap();
+        let pg_list_item_2d = get_t_list_item(pg_list_item_2.liid, &client).await.unwrap();
         assert!(pg_list_item_1d.is_none());
         assert!(pg_list_item_2d.is_some());
 
         // delete the list and all the other items with it - there should be none left
         del_t_list(pg_list_item_1.parent_lid, &client).await.expect("del_t_list failed");
-        let pg_list_item_2d = get_t_list_item(pg_list_item_2.liid, &client).await;
-        let pg_list_item_3d = get_t_list_item(pg_list_item_3.liid, &client).await;
-        let list_d = get_t_list(pg_list_item_1.parent_lid, &client).await;
+        let pg_list_item_2d = get_t_list_item(pg_list_item_2.liid, &client).await.unwrap();
+        let pg_list_item_3d = get_t_list_item(pg_list_item_3.liid, &client).await.unwrap();
+        let list_d = get_t_list(pg_list_item_1.parent_lid, &client).await.unwrap();
         assert!(pg_list_item_2d.is_none());
         assert!(pg_list_item_3d.is_none());
         assert!(list_d.is_none());
 
         // delete the user
         del_t_user(pg_user.user_id.clone(), &client).await.expect("del_t_list failed");
-        let pg_user_d1 = get_t_user(Some(pg_user.user_id.clone()), None, &client).await;
+        let pg_user_d1 = get_t_user(Some(pg_user.user_id.clone()), None, &client).await.unwrap();
         assert!(pg_user_d1.is_none());
     }
 }
@@ -0,0 +1,114 @@
+use crate::structures_ddb::LdList;
+use crate::structures_pg::{self, TTagCount};
+use dynomite::dynamodb::DynamoDbClient;
+use log::{self, debug, error};
+use uuid::Uuid;
+
+#[path = "./tags_test.rs"]
+pub(crate) mod tests_tags;
+
+const ERR_MSG_INVALID_TAG: &str = "A tag cannot be empty.";
+const ERR_MSG_TAGS_FAILED: &str = "Failed to get the tags. Try again.";
+
+/// Longer tags are cut to this many characters
+pub(crate) const MAX_TAG_LENGTH: usize = 50;
+
+/// Default number of tags returned by autocomplete
+pub(crate) const AUTOCOMPLETE_LIMIT: i64 = 10;
+
+/// Returns the tag in its canonical form: lower case with single spaces between words and no leading or
+/// trailing whitespace. Returns None if there is nothing left.
+pub(crate) fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase();
+    let tag: String = tag.chars().take(MAX_TAG_LENGTH).collect();
+    let tag = tag.trim_end();
+
+    match tag.is_empty() {
+        true => None,
+        false => Some(tag.to_string()),
+    }
+}
+
+/// Normalises all tags and removes duplicates, keeping the order they were added in.
+/// Returns None if there are no tags left.
+pub(crate) fn normalize_tags(tags: Option<Vec<String>>) -> Option<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().flatten().filter_map(|t| normalize_tag(t)) {
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+
+    match normalized.is_empty() {
+        true => None,
+        false => Some(normalized),
+    }
+}
+
+/// Returns tags of the user that start with `prefix` with the number of lists they are used in.
+pub(crate) async fn autocomplete_tags(
+    user_id: Uuid,
+    prefix: &str,
+    limit: Option<i64>,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Vec<TTagCount>, String> {
+    // an empty prefix returns the most used tags
+    let prefix = normalize_tag(prefix).unwrap_or_default();
+    structures_pg::get_user_tags(user_id, &prefix, limit.unwrap_or(AUTOCOMPLETE_LIMIT), pg_client)
+        .await
+        .map_err(|_| ERR_MSG_TAGS_FAILED.to_string())
+}
+
+/// Replaces tag `from` with tag `to` in all lists of the user, including lists in the trash.
+/// If a list already has `to` the two tags are merged into one. Returns the number of lists changed.
+/// PG is updated by the stream handler, so `autocomplete_tags` may return the old tag until the stream catches up.
+pub(crate) async fn rename_user_tag(
+    user_id: Uuid,
+    from: &str,
+    to: &str,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<usize, String> {
+    debug!("rename_user_tag for {}: {} -> {}", user_id, from, to);
+
+    let (from, to) = match (normalize_tag(from), normalize_tag(to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err(ERR_MSG_INVALID_TAG.to_string()),
+    };
+    if from == to {
+        return Ok(0);
+    }
+
+    // PG knows which lists have the tag
+    let lids: Vec<Uuid> = structures_pg::get_user_tag_lists(user_id, &from, true, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_TAGS_FAILED.to_string())?
+        .iter()
+        .map(|l| l.lid)
+        .collect();
+    debug!("Lists to update: {}", lids.len());
+
+    // update the DDB documents one by one; the stream handler copies the new tags to PG asynchronously,
+    // so tag lookups in PG are eventually consistent and may list the old tag for a short while
+    let mut changed = 0usize;
+    for lid in lids {
+        let mut list = match LdList::get_from_ddb_incl_trash(&lid, ddb_client).await? {
+            Some(v) => v,
+            None => {
+                error!("List {} is in PG, but not in DDB", lid);
+                continue;
+            }
+        };
+
+        let tags = list.tags.take().map(|tags| {
+            tags.into_iter()
+                .map(|t| if t == from { to.clone() } else { t })
+                .collect()
+        });
+        list.tags = normalize_tags(tags);
+        list.save_in_ddb(ddb_client, pg_client).await?;
+        changed += 1;
+    }
+
+    Ok(changed)
+}
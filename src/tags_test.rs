@@ -0,0 +1,101 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_tags {
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use crate::tags::*;
+    use log::{self, debug};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_normalize_tags() {
+        assert_eq!(normalize_tag("  Home\tImprovement "), Some("home improvement".to_string()));
+        assert_eq!(normalize_tag("ÄPFEL"), Some("äpfel".to_string()));
+        assert_eq!(normalize_tag(" \n "), None);
+        assert_eq!(normalize_tag(&"x".repeat(100)).unwrap().len(), MAX_TAG_LENGTH);
+
+        // duplicates are removed after case-folding and the order is kept
+        let tags = vec![
+            "Work".to_string(),
+            "home".to_string(),
+            " WORK".to_string(),
+            "".to_string(),
+            "Home".to_string(),
+        ];
+        assert_eq!(normalize_tags(Some(tags)), Some(vec!["work".to_string(), "home".to_string()]));
+        assert_eq!(normalize_tags(Some(vec![" ".to_string()])), None);
+        assert_eq!(normalize_tags(None), None);
+    }
+
+    #[tokio::test]
+    async fn test_tag_index() {
+        debug!("test_tag_index started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new user with 2 tagged lists
+        let user_email = ["test_tag_index@", Uuid::new_v4().to_string().as_str(), ".com"].concat();
+        let pg_user = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user");
+        let user_id = pg_user.user_id;
+        let lids: [Uuid; 2] = [Uuid::new_v4(), Uuid::new_v4()];
+        let tag_sets = [vec!["Groceries", "weekly"], vec!["groceries ", "Costco"]];
+        for (lid, tag_set) in lids.iter().zip(tag_sets.iter()) {
+            let mut list = test_helpers::create_random_list(*lid, user_id, &ddb_client, &pg_client).await;
+            list.tags = Some(tag_set.iter().map(|t| t.to_string()).collect());
+            list.save_in_ddb(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to save the tags");
+            test_helpers::apply_stream(*lid, &ddb_client, &pg_client).await;
+        }
+
+        // both lists are found by the tag in any case
+        let tagged = LdList::get_user_lists_by_tag_from_ddb(user_id, "GROCERIES", &ddb_client, &pg_client)
+            .await
+            .unwrap()
+            .expect("No lists with the tag");
+        assert_eq!(tagged.len(), 2);
+
+        // autocomplete counts the lists per tag
+        let suggestions = autocomplete_tags(user_id, "gro", None, &pg_client).await.unwrap();
+        assert_eq!(
+            suggestions,
+            vec![TTagCount {
+                tag: "groceries".to_string(),
+                list_count: 2
+            }]
+        );
+
+        // merge `weekly` into `costco`
+        let changed = rename_user_tag(user_id, "Weekly", "costco", &ddb_client, &pg_client)
+            .await
+            .expect("Failed to rename the tag");
+        assert_eq!(changed, 1);
+        test_helpers::apply_stream(lids[0], &ddb_client, &pg_client).await;
+        let list = LdList::get_from_ddb(&lids[0], &ddb_client).await.unwrap().unwrap();
+        assert_eq!(list.tags, Some(vec!["groceries".to_string(), "costco".to_string()]));
+        assert!(autocomplete_tags(user_id, "weekly", None, &pg_client)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(autocomplete_tags(user_id, "costco", None, &pg_client).await.unwrap()[0].list_count, 2);
+
+        // clean up
+        for lid in lids.iter() {
+            LdList::get_from_ddb(lid, &ddb_client)
+                .await
+                .unwrap()
+                .unwrap()
+                .delete_from_all_dbs(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to delete the list");
+        }
+        del_t_user(user_id, &pg_client)
+            .await
+            .expect("Failed to delete the user");
+    }
+}
@@ -0,0 +1,175 @@
+use crate::cache::ReadConsistency;
+use crate::structures_ddb::{LdList, LdListItem};
+use crate::structures_pg::{self, TList, TListItem};
+use chrono::Utc;
+use dynomite::dynamodb::DynamoDbClient;
+use log::{self, debug, info};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[path = "./templates_test.rs"]
+pub(crate) mod tests_templates;
+
+const ERR_MSG_LIST_DOES_NOT_EXIST: &str = "The list doesn't exist";
+const ERR_MSG_TEMPLATE_DOES_NOT_EXIST: &str = "The template doesn't exist or is not shared with you.";
+const ERR_MSG_NOT_OWNER: &str = "Only the owner of the list can change it into a template.";
+const ERR_MSG_TEMPLATE_FAILED: &str = "Failed to update the template. Try again.";
+const ERR_MSG_INSTANTIATE_FAILED: &str = "Failed to create the list from the template. Try again.";
+
+/// Placeholders are names in double curly braces, e.g. `{{name}}`.
+const PLACEHOLDER_START: &str = "{{";
+const PLACEHOLDER_END: &str = "}}";
+
+/// A placeholder that is replaced with today's date in UTC, e.g. `2020-06-01`, unless a value is given for it.
+pub(crate) const PLACEHOLDER_DATE: &str = "date";
+
+/// Marks the list of the user as a template or turns it back into a regular list. Returns the updated list.
+pub(crate) async fn set_template(
+    lid: Uuid,
+    user_id: Uuid,
+    is_template: bool,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Option<LdList>, String> {
+    info!("set_template for {} / {}", lid, is_template);
+
+    let mut list = match LdList::get_from_ddb_with(&lid, ReadConsistency::Strong, ddb_client).await? {
+        Some(v) => v,
+        None => return Err(ERR_MSG_LIST_DOES_NOT_EXIST.to_string()),
+    };
+    if list.rel.user_id != Some(user_id) {
+        return Err(ERR_MSG_NOT_OWNER.to_string());
+    }
+    if list.rel.is_template == is_template {
+        return Ok(Some(list));
+    }
+
+    list.rel = match structures_pg::put_t_list_template(lid, is_template, pg_client).await {
+        Ok(Some(v)) => v,
+        _ => return Err(ERR_MSG_TEMPLATE_FAILED.to_string()),
+    };
+    list.save_in_ddb(ddb_client, pg_client).await
+}
+
+/// Creates a new list for the user from a template of the user or the user's org. The list and its items get
+/// new IDs and the items point back to the template via `origin_lid` / `origin_liid`. Placeholders in titles
+/// and descriptions are replaced with `values`. Items in the trash, child lists and due dates are not copied.
+/// Returns the new list.
+pub(crate) async fn instantiate_template(
+    template_lid: Uuid,
+    user_id: Uuid,
+    values: &HashMap<String, String>,
+    ddb_client: &DynamoDbClient,
+    pg_client: &tokio_postgres::Client,
+) -> Result<LdList, String> {
+    info!("instantiate_template {} for {}", template_lid, user_id);
+
+    // templates of other org members are returned by the same PG query
+    let is_available = structures_pg::get_user_templates(user_id, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_INSTANTIATE_FAILED.to_string())?
+        .iter()
+        .any(|t| t.lid == template_lid);
+    if !is_available {
+        return Err(ERR_MSG_TEMPLATE_DOES_NOT_EXIST.to_string());
+    }
+    let template = match LdList::get_from_ddb(&template_lid, ddb_client).await? {
+        Some(v) => v,
+        None => return Err(ERR_MSG_TEMPLATE_DOES_NOT_EXIST.to_string()),
+    };
+
+    let list = new_list_from_template(&template, user_id, values);
+    debug!("New list {} with {} items", list.lid, list.visible_items().count());
+
+    // save_many_in_ddb creates `rel` for all items in a single PG call
+    LdList::save_many_in_ddb(vec![list], ddb_client, pg_client)
+        .await?
+        .pop()
+        .ok_or_else(|| ERR_MSG_TEMPLATE_FAILED.to_string())
+}
+
+/// Copies the template into a new list that is not saved in the DB.
+pub(crate) fn new_list_from_template(template: &LdList, user_id: Uuid, values: &HashMap<String, String>) -> LdList {
+    let lid = Uuid::new_v4();
+    let items: Vec<LdListItem> = template
+        .visible_items()
+        .map(|item| LdListItem {
+            title: fill_placeholders(&item.title, values),
+            description: item.description.as_ref().map(|d| fill_placeholders(d, values)),
+            due: None,
+            rel: TListItem {
+                origin_lid: Some(template.lid),
+                origin_liid: Some(item.rel.liid),
+                ..TListItem::new(Uuid::new_v4(), lid)
+            },
+        })
+        .collect();
+
+    LdList {
+        lid,
+        title: fill_placeholders(&template.title, values),
+        description: template.description.as_ref().map(|d| fill_placeholders(d, values)),
+        tags: template.tags.clone(),
+        items: if items.is_empty() { None } else { Some(items) },
+        rel: TList::new(lid, user_id),
+    }
+}
+
+/// Replaces `{{name}}` placeholders with their values. Whitespace around the name is ignored.
+/// Placeholders without a value are left as they are so that the user can see what is missing.
+pub(crate) fn fill_placeholders(text: &str, values: &HashMap<String, String>) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        let after_start = &rest[start + PLACEHOLDER_START.len()..];
+        let end = match after_start.find(PLACEHOLDER_END) {
+            Some(v) => v,
+            None => break,
+        };
+
+        filled.push_str(&rest[..start]);
+        let name = after_start[..end].trim();
+        match values.get(name) {
+            Some(value) => filled.push_str(value),
+            None if name == PLACEHOLDER_DATE => filled.push_str(&Utc::today().format("%Y-%m-%d").to_string()),
+            None => filled.push_str(&rest[start..start + PLACEHOLDER_START.len() + end + PLACEHOLDER_END.len()]),
+        }
+        rest = &after_start[end + PLACEHOLDER_END.len()..];
+    }
+
+    filled.push_str(rest);
+    filled
+}
+
+/// Returns names of all placeholders used in the template in the order they first appear,
+/// so that the user can be asked for their values before instantiating it.
+pub(crate) fn placeholders(template: &LdList) -> Vec<String> {
+    let texts = std::iter::once(Some(&template.title))
+        .chain(std::iter::once(template.description.as_ref()))
+        .chain(
+            template
+                .visible_items()
+                .flat_map(|i| vec![Some(&i.title), i.description.as_ref()]),
+        )
+        .flatten();
+
+    let mut names: Vec<String> = Vec::new();
+    for text in texts {
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(PLACEHOLDER_START) {
+            rest = &rest[start + PLACEHOLDER_START.len()..];
+            let end = match rest.find(PLACEHOLDER_END) {
+                Some(v) => v,
+                None => break,
+            };
+            let name = rest[..end].trim().to_string();
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+            rest = &rest[end + PLACEHOLDER_END.len()..];
+        }
+    }
+
+    names
+}
@@ -0,0 +1,147 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_templates {
+    use crate::structures_ddb::tests_ddb::tests_ddb::test_helpers;
+    use crate::structures_ddb::*;
+    use crate::structures_pg::*;
+    use crate::templates::*;
+    use chrono::Utc;
+    use log::{self, debug};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_fill_placeholders() {
+        let v = values(&[("name", "Ana"), ("version", "2.1")]);
+        assert_eq!(fill_placeholders("Onboarding: {{name}}", &v), "Onboarding: Ana");
+        assert_eq!(fill_placeholders("Release {{ version }} by {{name}}", &v), "Release 2.1 by Ana");
+
+        // missing values and broken placeholders are left as they are
+        assert_eq!(fill_placeholders("Ask {{manager}} for {{name}}", &v), "Ask {{manager}} for Ana");
+        assert_eq!(fill_placeholders("Tag {{version", &v), "Tag {{version");
+        assert_eq!(fill_placeholders("{{}} and }}{{", &v), "{{}} and }}{{");
+        assert_eq!(fill_placeholders("No placeholders", &v), "No placeholders");
+
+        // the date is filled in unless it is given
+        let today = Utc::today().format("%Y-%m-%d").to_string();
+        assert_eq!(fill_placeholders("Standup {{date}}", &v), ["Standup ", &today].concat());
+        assert_eq!(fill_placeholders("Standup {{date}}", &values(&[("date", "Monday")])), "Standup Monday");
+    }
+
+    #[test]
+    fn test_new_list_from_template() {
+        let template_lid = Uuid::new_v4();
+        let mut template = LdList::new(template_lid, "Onboarding {{name}}".to_string(), Uuid::new_v4());
+        template.description = Some("Starts on {{start}}".to_string());
+        template.tags = Some(vec!["hr".to_string()]);
+        template.rel.is_template = true;
+        let mut trashed = LdListItem {
+            title: "Old step".to_string(),
+            description: None,
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), template_lid),
+        };
+        trashed.rel.deleted_on_utc = Some(Utc::now());
+        template.items = Some(vec![
+            LdListItem {
+                title: "Create an account for {{name}}".to_string(),
+                description: Some("Ask {{ manager }}".to_string()),
+                due: None,
+                rel: TListItem::new(Uuid::new_v4(), template_lid),
+            },
+            trashed,
+        ]);
+        assert_eq!(placeholders(&template), vec!["name", "start", "manager"]);
+
+        let user_id = Uuid::new_v4();
+        let list = new_list_from_template(&template, user_id, &values(&[("name", "Ana"), ("manager", "Bo")]));
+        assert_ne!(list.lid, template_lid);
+        assert_eq!(list.title, "Onboarding Ana");
+        assert_eq!(list.description.as_deref(), Some("Starts on {{start}}"));
+        assert_eq!(list.tags, template.tags);
+        assert_eq!(list.rel.user_id, Some(user_id));
+        assert!(!list.rel.is_template);
+
+        // only the item that is not in the trash is copied with a link back to the template
+        let items = list.items.as_ref().unwrap();
+        assert_eq!(items.len(), 1);
+        let source = &template.items.as_ref().unwrap()[0];
+        assert_eq!(items[0].title, "Create an account for Ana");
+        assert_eq!(items[0].description.as_deref(), Some("Ask Bo"));
+        assert_ne!(items[0].rel.liid, source.rel.liid);
+        assert_eq!(items[0].rel.parent_lid, list.lid);
+        assert_eq!(items[0].rel.origin_lid, Some(template_lid));
+        assert_eq!(items[0].rel.origin_liid, Some(source.rel.liid));
+    }
+
+    #[tokio::test]
+    async fn test_instantiate_template() {
+        debug!("test_instantiate_template started");
+
+        // prepare DDB and PG connections
+        let (pg_client, ddb_client) = test_helpers::init_db_clients().await;
+
+        // create a new user with a list
+        let user_email = [
+            "test_instantiate_template@",
+            Uuid::new_v4().to_string().as_str(),
+            ".com",
+        ]
+        .concat();
+        let user_id = put_t_user(&user_email, &pg_client)
+            .await
+            .unwrap()
+            .expect("Failed to create a new user")
+            .user_id;
+        let template_lid = Uuid::new_v4();
+        let list = test_helpers::create_random_list(template_lid, user_id, &ddb_client, &pg_client).await;
+
+        // it cannot be instantiated until it is a template
+        assert!(instantiate_template(template_lid, user_id, &HashMap::new(), &ddb_client, &pg_client)
+            .await
+            .is_err());
+        let template = set_template(template_lid, user_id, true, &ddb_client, &pg_client)
+            .await
+            .expect("Failed to make a template")
+            .unwrap();
+        assert!(template.rel.is_template);
+        let templates = LdList::get_user_templates_from_ddb(user_id, &ddb_client, &pg_client)
+            .await
+            .unwrap()
+            .expect("No templates");
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].lid, template_lid);
+
+        // the new list is a copy with new IDs linked to the template in PG
+        let new_list = instantiate_template(template_lid, user_id, &HashMap::new(), &ddb_client, &pg_client)
+            .await
+            .expect("Failed to instantiate the template");
+        assert_eq!(new_list.title, list.title);
+        assert!(!new_list.rel.is_template);
+        assert_eq!(new_list.items.as_ref().unwrap().len(), 5);
+        let pg_items = get_t_list_items(new_list.lid, &pg_client)
+            .await
+            .unwrap()
+            .expect("No items in PG");
+        assert_eq!(pg_items.len(), 5);
+        assert!(pg_items.iter().all(|i| i.origin_lid == Some(template_lid)));
+
+        // clean up
+        for lid in [template_lid, new_list.lid].iter() {
+            LdList::get_from_ddb(lid, &ddb_client)
+                .await
+                .unwrap()
+                .unwrap()
+                .delete_from_all_dbs(&ddb_client, &pg_client)
+                .await
+                .expect("Failed to delete the list");
+        }
+        del_t_user(user_id, &pg_client)
+            .await
+            .expect("Failed to delete the user");
+    }
+}
@@ -0,0 +1,327 @@
+use crate::context::{self, RequestContext};
+use log::{self, debug, error};
+use rand::Rng;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env::var;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[path = "./trace_test.rs"]
+pub(crate) mod tests_trace;
+
+/// Env var with the span exporter: `stdout` (default), `none` or `file:<path>`.
+pub(crate) const EV_TRACE_EXPORTER: &str = "TRACE_EXPORTER";
+
+/// Finished spans are kept until `flush` up to this number. The oldest ones are dropped after that.
+const MAX_FINISHED_SPANS: usize = 1000;
+
+/// The instrumentation scope of all spans in OTLP output.
+const SCOPE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Same values as `SpanKind` in OpenTelemetry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SpanKind {
+    Internal = 1,
+    /// The root span of a request.
+    Server = 2,
+    /// A call to PG or DDB.
+    Client = 3,
+}
+
+/// A span as it is exported.
+#[derive(Debug, Clone)]
+pub(crate) struct SpanData {
+    /// 32 hex chars, shared by all spans of the same request.
+    pub trace_id: String,
+    /// 16 hex chars.
+    pub span_id: String,
+    /// None for the root span.
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start: SystemTime,
+    /// None while the span is open.
+    pub end: Option<SystemTime>,
+    pub attributes: BTreeMap<String, Value>,
+    /// The error message if the operation failed.
+    pub error: Option<String>,
+}
+
+/// Open spans from the root down to the current one and spans that ended since the last `flush`.
+/// Kept per thread, so spans of futures polled concurrently on the same thread may end up under the wrong parent.
+#[derive(Debug, Default)]
+pub(crate) struct Spans {
+    open: Vec<SpanData>,
+    finished: Vec<SpanData>,
+}
+
+thread_local! {
+    pub(crate) static SPANS: RefCell<Spans> = RefCell::new(Spans::default());
+}
+
+/// Ends the span when dropped. Any spans started while it is open become its children.
+#[derive(Debug)]
+pub(crate) struct Span {
+    span_id: String,
+    name: String,
+}
+
+impl Span {
+    /// Adds an attribute, e.g. `span.with("db.table", "tlist")`.
+    pub(crate) fn with(self, key: &str, value: impl Into<Value>) -> Self {
+        self.set_attribute(key, value);
+        self
+    }
+
+    /// Adds or replaces an attribute of the open span.
+    pub(crate) fn set_attribute(&self, key: &str, value: impl Into<Value>) {
+        let value = value.into();
+        self.update(|span| {
+            span.attributes.insert(key.to_string(), value);
+        });
+    }
+
+    /// Marks the span as failed.
+    pub(crate) fn set_error(&self, message: &str) {
+        self.update(|span| span.error = Some(message.to_string()));
+    }
+
+    /// The name the span was started with, e.g. `ld_get_tlist`.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn update(&self, change: impl FnOnce(&mut SpanData)) {
+        SPANS.with(|s| {
+            if let Some(span) = s.borrow_mut().open.iter_mut().rev().find(|s| s.span_id == self.span_id) {
+                change(span);
+            }
+        });
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        SPANS.with(|s| {
+            let mut s = s.borrow_mut();
+            let position = match s.open.iter().rposition(|span| span.span_id == self.span_id) {
+                Some(v) => v,
+                None => return,
+            };
+            let mut span = s.open.remove(position);
+            span.end = Some(SystemTime::now());
+            if s.finished.len() >= MAX_FINISHED_SPANS {
+                debug!("Dropping span {} - too many spans since the last flush", s.finished[0].name);
+                s.finished.remove(0);
+            }
+            s.finished.push(span);
+        });
+    }
+}
+
+/// Starts a span under the current one or a new trace if there is no open span.
+pub(crate) fn span(name: &str, kind: SpanKind) -> Span {
+    let mut rng = rand::thread_rng();
+    let span_id = hex::encode(rng.gen::<[u8; 8]>());
+
+    SPANS.with(|s| {
+        let mut s = s.borrow_mut();
+        let (trace_id, parent_span_id) = match s.open.last() {
+            Some(parent) => (parent.trace_id.clone(), Some(parent.span_id.clone())),
+            None => (hex::encode(rng.gen::<[u8; 16]>()), None),
+        };
+        s.open.push(SpanData {
+            trace_id,
+            span_id: span_id.clone(),
+            parent_span_id,
+            name: name.to_string(),
+            kind,
+            start: SystemTime::now(),
+            end: None,
+            attributes: BTreeMap::new(),
+            error: None,
+        });
+    });
+
+    Span {
+        span_id,
+        name: name.to_string(),
+    }
+}
+
+/// Starts the root span of a request with the IDs from the request context. Must be called within `context::scope`.
+/// Spans left open by a previous request are discarded.
+pub(crate) fn start_request(name: &str) -> Span {
+    SPANS.with(|s| s.borrow_mut().open.clear());
+
+    let RequestContext {
+        request_id,
+        actor,
+        lid,
+        liid,
+        ..
+    } = context::get();
+    let span = span(name, SpanKind::Server);
+    let ids = [
+        ("request_id", request_id),
+        ("user_id", actor.map(|v| v.to_string())),
+        ("lid", lid.map(|v| v.to_string())),
+        ("liid", liid.map(|v| v.to_string())),
+    ];
+    for (key, value) in ids.iter() {
+        if let Some(value) = value {
+            span.set_attribute(key, value.as_str());
+        }
+    }
+
+    span
+}
+
+/// Starts a span for a PG function, e.g. `ld_get_tlist`.
+pub(crate) fn pg_span(procedure: &str) -> Span {
+    span(procedure, SpanKind::Client)
+        .with("db.system", "postgresql")
+        .with("db.procedure", procedure)
+}
+
+/// Starts a span for a DDB operation on a table, e.g. `tlist.get_item`.
+pub(crate) fn ddb_span(operation: &str, table: &str) -> Span {
+    span(operation, SpanKind::Client)
+        .with("db.system", "dynamodb")
+        .with("db.table", table)
+}
+
+/// Exports spans in OTLP JSON format.
+pub(crate) trait SpanExporter {
+    fn export(&self, otlp: &Value) -> Result<(), String>;
+}
+
+/// Prints every batch of spans as a single line to stdout.
+pub(crate) struct StdoutExporter;
+
+impl SpanExporter for StdoutExporter {
+    fn export(&self, otlp: &Value) -> Result<(), String> {
+        println!("{}", otlp);
+        Ok(())
+    }
+}
+
+/// Appends every batch of spans as a line to a file, which the OTEL collector can read with its
+/// `otlpjsonfile` receiver.
+pub(crate) struct FileExporter {
+    pub path: String,
+}
+
+impl SpanExporter for FileExporter {
+    fn export(&self, otlp: &Value) -> Result<(), String> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Cannot open {}: {}", self.path, e))?;
+        writeln!(file, "{}", otlp).map_err(|e| format!("Cannot write to {}: {}", self.path, e))
+    }
+}
+
+/// Returns the exporter set in `TRACE_EXPORTER` env var or None if tracing is off.
+pub(crate) fn exporter_from_env() -> Option<Box<dyn SpanExporter>> {
+    let setting = var(EV_TRACE_EXPORTER).unwrap_or_default();
+    match setting.trim() {
+        "" | "stdout" => Some(Box::new(StdoutExporter)),
+        "none" => None,
+        v if v.starts_with("file:") => Some(Box::new(FileExporter {
+            path: v["file:".len()..].to_string(),
+        })),
+        v => {
+            error!("Invalid {} value: {}. Spans go to stdout.", EV_TRACE_EXPORTER, v);
+            Some(Box::new(StdoutExporter))
+        }
+    }
+}
+
+/// Exports the spans that ended since the last call with the exporter from env vars.
+/// Called by the handler at the end of every request, after the root span ended.
+pub(crate) fn flush() {
+    if let Some(exporter) = exporter_from_env() {
+        flush_to(exporter.as_ref());
+    } else {
+        take_finished();
+    }
+}
+
+/// Exports the spans that ended since the last call with `exporter`.
+pub(crate) fn flush_to(exporter: &dyn SpanExporter) {
+    let spans = take_finished();
+    if spans.is_empty() {
+        return;
+    }
+    if let Err(e) = exporter.export(&otlp_json(&spans)) {
+        error!("Failed to export {} spans: {}", spans.len(), e);
+    }
+}
+
+/// Removes and returns the spans that ended since the last call.
+pub(crate) fn take_finished() -> Vec<SpanData> {
+    SPANS.with(|s| std::mem::take(&mut s.borrow_mut().finished))
+}
+
+/// Converts spans into an OTLP `ExportTraceServiceRequest` in its JSON encoding. See
+/// https://github.com/open-telemetry/opentelemetry-proto/blob/main/docs/specification.md#json-protobuf-encoding
+pub(crate) fn otlp_json(spans: &[SpanData]) -> Value {
+    let service_name = var("AWS_LAMBDA_FUNCTION_NAME").unwrap_or_else(|_| SCOPE_NAME.to_string());
+
+    let spans = spans
+        .iter()
+        .map(|span| {
+            let mut otlp = json!({
+                "traceId": span.trace_id,
+                "spanId": span.span_id,
+                "name": span.name,
+                "kind": span.kind as u8,
+                "startTimeUnixNano": unix_nanos(span.start),
+                "endTimeUnixNano": unix_nanos(span.end.unwrap_or(span.start)),
+                "attributes": span.attributes.iter().map(|(k, v)| otlp_attribute(k, v)).collect::<Vec<Value>>(),
+            });
+            if let Some(parent_span_id) = &span.parent_span_id {
+                otlp["parentSpanId"] = Value::from(parent_span_id.as_str());
+            }
+            // the status is left unset for spans that succeeded
+            if let Some(message) = &span.error {
+                otlp["status"] = json!({"code": 2, "message": message});
+            }
+            otlp
+        })
+        .collect::<Vec<Value>>();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {"attributes": [otlp_attribute("service.name", &Value::from(service_name))]},
+            "scopeSpans": [{
+                "scope": {"name": SCOPE_NAME},
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn otlp_attribute(key: &str, value: &Value) -> Value {
+    let value = match value {
+        Value::Bool(v) => json!({ "boolValue": v }),
+        // 64-bit integers are strings in OTLP JSON
+        Value::Number(v) if v.is_i64() || v.is_u64() => json!({ "intValue": v.to_string() }),
+        Value::Number(v) => json!({ "doubleValue": v.as_f64() }),
+        Value::String(v) => json!({ "stringValue": v }),
+        v => json!({ "stringValue": v.to_string() }),
+    };
+    json!({"key": key, "value": value})
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
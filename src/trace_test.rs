@@ -0,0 +1,146 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_trace {
+    use crate::retry::{self, RetryPolicy};
+    use crate::trace::*;
+    use rusoto_core::RusotoError;
+    use rusoto_dynamodb::{GetItemError, GetItemOutput};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn no_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            deadline: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nested_spans() {
+        // spans are kept per thread, so this test sees only its own
+        take_finished();
+        let lid = Uuid::new_v4();
+
+        let root = start_request("test_request");
+        {
+            let _parent = span("LdList::save_in_ddb", SpanKind::Internal).with("lid", lid.to_string());
+            let found: Result<GetItemOutput, RusotoError<GetItemError>> =
+                retry::with_retry_in(&no_retries(), ddb_span("tlist.get_item", "tlist"), || async {
+                    Ok(GetItemOutput::default())
+                })
+                .await;
+            assert!(found.is_ok());
+            let failed: Result<GetItemOutput, RusotoError<GetItemError>> =
+                retry::with_retry(&no_retries(), "tlist.get_item", || async {
+                    Err(RusotoError::Validation("Missing key for user@example.com".to_string()))
+                })
+                .await;
+            assert!(failed.is_err());
+        }
+        drop(root);
+
+        // spans are finished children first
+        let spans = take_finished();
+        let names: Vec<&str> = spans.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "tlist.get_item",
+                "tlist.get_item",
+                "LdList::save_in_ddb",
+                "test_request"
+            ]
+        );
+        let (found, failed, parent, root) = (&spans[0], &spans[1], &spans[2], &spans[3]);
+
+        assert!(spans.iter().all(|s| s.trace_id == root.trace_id && s.end.is_some()));
+        assert_eq!(root.trace_id.len(), 32);
+        assert_eq!(root.kind, SpanKind::Server);
+        assert!(root.parent_span_id.is_none());
+        assert_eq!(parent.parent_span_id.as_ref(), Some(&root.span_id));
+        assert_eq!(found.parent_span_id.as_ref(), Some(&parent.span_id));
+        assert_eq!(failed.parent_span_id.as_ref(), Some(&parent.span_id));
+        assert_eq!(parent.attributes["lid"], lid.to_string());
+
+        // calls record the table, the rows and the attempts
+        assert_eq!(found.kind, SpanKind::Client);
+        assert_eq!(found.attributes["db.system"], "dynamodb");
+        assert_eq!(found.attributes["db.table"], "tlist");
+        assert_eq!(found.attributes["db.rows"], 0);
+        assert_eq!(found.attributes["retry.attempts"], 1);
+        assert!(found.error.is_none());
+        let error = failed.error.as_ref().expect("The failed call has no error");
+        assert!(error.contains("Missing key") && !error.contains("user@example.com"));
+
+        // nothing is left after the spans were taken
+        assert!(take_finished().is_empty());
+    }
+
+    #[test]
+    fn test_otlp_json() {
+        take_finished();
+        {
+            let _root = span("root", SpanKind::Server).with("request_id", "req-1");
+            span("ld_get_tlist", SpanKind::Client)
+                .with("db.rows", 3)
+                .with("cached", false)
+                .set_error("Timed out");
+        }
+        let spans = take_finished();
+
+        let otlp = otlp_json(&spans);
+        let resource_spans = &otlp["resourceSpans"][0];
+        assert!(resource_spans["resource"]["attributes"][0]["value"]["stringValue"].is_string());
+        let scope_spans = &resource_spans["scopeSpans"][0];
+        assert_eq!(scope_spans["scope"]["name"], "pgsql_zero");
+
+        let child = &scope_spans["spans"][0];
+        let root = &scope_spans["spans"][1];
+        assert_eq!(child["name"], "ld_get_tlist");
+        assert_eq!(child["kind"], 3);
+        assert_eq!(child["parentSpanId"], root["spanId"]);
+        assert_eq!(child["traceId"], root["traceId"]);
+        assert_eq!(child["status"], serde_json::json!({"code": 2, "message": "Timed out"}));
+        assert_eq!(
+            child["attributes"],
+            serde_json::json!([
+                {"key": "cached", "value": {"boolValue": false}},
+                {"key": "db.rows", "value": {"intValue": "3"}},
+            ])
+        );
+        assert!(child["startTimeUnixNano"].as_str().unwrap().parse::<u128>().is_ok());
+
+        // successful spans and the root span have no status and no parent
+        assert!(root.get("status").is_none());
+        assert!(root.get("parentSpanId").is_none());
+        assert_eq!(root["kind"], 2);
+    }
+
+    #[test]
+    fn test_file_exporter() {
+        take_finished();
+        let path = std::env::temp_dir().join(format!("spans-{}.jsonl", Uuid::new_v4()));
+        let exporter = FileExporter {
+            path: path.to_string_lossy().to_string(),
+        };
+
+        // one line per flush and nothing if there are no spans
+        drop(span("first", SpanKind::Internal));
+        flush_to(&exporter);
+        flush_to(&exporter);
+        drop(span("second", SpanKind::Internal));
+        flush_to(&exporter);
+
+        let written = std::fs::read_to_string(&path).expect("No spans were written");
+        std::fs::remove_file(&path).expect("Cannot remove the span file");
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|l| serde_json::from_str(l).expect("Invalid JSON line"))
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"], "first");
+        assert_eq!(lines[1]["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"], "second");
+    }
+}
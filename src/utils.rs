@@ -1,12 +1,17 @@
+use crate::logging;
 use log::{debug, error};
 use rusoto_dynamodb::{
-    AttributeValue, BatchGetItemInput, DeleteItemInput, GetItemInput, KeysAndAttributes, PutItemInput,
+    AttributeValue, BatchGetItemInput, BatchWriteItemInput, DeleteItemInput, DeleteRequest, GetItemInput,
+    KeysAndAttributes, PutItemInput, PutRequest, QueryInput, ScanInput, UpdateItemInput, WriteRequest,
 };
 use std::collections::HashMap;
 use std::env::var;
 use tokio_postgres::NoTls;
 use uuid::Uuid;
 
+#[path = "./utils_test.rs"]
+pub(crate) mod tests_utils;
+
 /// Load DB Config from env variables
 pub(crate) fn load_db_config() -> String {
     // list of env vars required to connect to the DB
@@ -30,8 +35,16 @@ pub(crate) fn load_db_config() -> String {
     return conf;
 }
 
+/// All requests ask DDB to report the capacity they consumed for the metrics.
+const RETURN_CONSUMED_CAPACITY: &str = "TOTAL";
+
 /// Builds GetItemInput from the key and the table name
 pub(crate) fn build_ddb_get_input(table_key: &str, key_value: &Uuid, table: &str) -> GetItemInput {
+    build_ddb_get_input_by_str(table_key, &key_value.to_string(), table)
+}
+
+/// Builds GetItemInput for a table with a string key that is not a UUID, e.g. a WebSocket connection ID
+pub(crate) fn build_ddb_get_input_by_str(table_key: &str, key_value: &str, table: &str) -> GetItemInput {
     let mut key: HashMap<String, AttributeValue> = HashMap::new();
     key.insert(
         String::from(table_key),
@@ -42,6 +55,7 @@ pub(crate) fn build_ddb_get_input(table_key: &str, key_value: &Uuid, table: &str
     );
 
     GetItemInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
         key: key,
         table_name: String::from(table),
         consistent_read: Some(true),
@@ -49,10 +63,106 @@ pub(crate) fn build_ddb_get_input(table_key: &str, key_value: &Uuid, table: &str
     }
 }
 
+/// Builds GetItemInput for a table with a partition key and a numeric sort key
+pub(crate) fn build_ddb_get_input_with_sort_key(
+    table_key: &str,
+    key_value: &Uuid,
+    sort_key: &str,
+    sort_value: i64,
+    table: &str,
+) -> GetItemInput {
+    let mut input = build_ddb_get_input(table_key, key_value, table);
+    input.key.insert(
+        String::from(sort_key),
+        AttributeValue {
+            n: Some(sort_value.to_string()),
+            ..Default::default()
+        },
+    );
+
+    input
+}
+
+/// Builds QueryInput for all records with the same partition key. Only `attributes` are returned.
+/// The newest records come first if the sort key is a timestamp and `newest_first` is true.
+pub(crate) fn build_ddb_query_input(
+    table_key: &str,
+    key_value: &Uuid,
+    attributes: &[&str],
+    newest_first: bool,
+    limit: i64,
+    table: &str,
+) -> QueryInput {
+    // use placeholders for all names in case any of them is a reserved word
+    let mut names: HashMap<String, String> = HashMap::new();
+    names.insert("#key".to_string(), String::from(table_key));
+    let mut projection: Vec<String> = Vec::new();
+    for (i, attr) in attributes.iter().enumerate() {
+        let placeholder = format!("#a{}", i);
+        names.insert(placeholder.clone(), attr.to_string());
+        projection.push(placeholder);
+    }
+
+    let mut values: HashMap<String, AttributeValue> = HashMap::new();
+    values.insert(
+        ":key".to_string(),
+        AttributeValue {
+            s: Some(key_value.to_string()),
+            ..Default::default()
+        },
+    );
+
+    QueryInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
+        table_name: String::from(table),
+        key_condition_expression: Some("#key = :key".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        projection_expression: Some(projection.join(", ")),
+        scan_index_forward: Some(!newest_first),
+        limit: Some(limit),
+        consistent_read: Some(true),
+        ..Default::default()
+    }
+}
+
+/// Builds ScanInput for all records with `attribute` equal to `value`. Only `attributes` are returned.
+/// Scans read the whole table, so they are only used for small tables with no index on the attribute.
+/// Set `exclusive_start_key` to read the next page.
+pub(crate) fn build_ddb_scan_input(attribute: &str, value: &Uuid, attributes: &[&str], table: &str) -> ScanInput {
+    let mut names: HashMap<String, String> = HashMap::new();
+    names.insert("#attr".to_string(), String::from(attribute));
+    let mut projection: Vec<String> = Vec::new();
+    for (i, attr) in attributes.iter().enumerate() {
+        let placeholder = format!("#a{}", i);
+        names.insert(placeholder.clone(), attr.to_string());
+        projection.push(placeholder);
+    }
+
+    let mut values: HashMap<String, AttributeValue> = HashMap::new();
+    values.insert(
+        ":value".to_string(),
+        AttributeValue {
+            s: Some(value.to_string()),
+            ..Default::default()
+        },
+    );
+
+    ScanInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
+        table_name: String::from(table),
+        filter_expression: Some("#attr = :value".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        projection_expression: Some(projection.join(", ")),
+        ..Default::default()
+    }
+}
+
 /// Build BatchGetItemInput from a list of keys. Only the first 100 keys are considered
 pub(crate) fn build_ddb_get_batch_input(
     table_key: &str,
-    key_values: &Vec<Uuid>,
+    key_values: &[Uuid],
     table_name: &str,
 ) -> BatchGetItemInput {
     // build a list of UUID keys as a list of hashmaps
@@ -96,20 +206,96 @@ pub(crate) fn build_ddb_get_batch_input(
     );
 
     BatchGetItemInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
         request_items,
-        ..Default::default()
     }
 }
 
 pub(crate) fn build_ddb_put_input(item: HashMap<String, AttributeValue>, table: &str) -> PutItemInput {
     PutItemInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
         item: item,
         table_name: String::from(table),
         ..Default::default()
     }
 }
 
+/// Max number of requests DDB accepts in a single BatchWriteItem call as per
+/// https://docs.aws.amazon.com/amazondynamodb/latest/APIReference/API_BatchWriteItem.html
+pub(crate) const DDB_BATCH_WRITE_LIMIT: usize = 25;
+
+/// Max size of a DDB item, including attribute names, as per
+/// https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/ServiceQuotas.html
+pub(crate) const DDB_MAX_ITEM_SIZE: usize = 400 * 1024;
+
+/// Estimates the size of a DDB item the way DDB counts it towards `DDB_MAX_ITEM_SIZE`: names and values in bytes
+/// plus a few bytes for every list and map. Numbers are counted by their digits, so the estimate errs on the safe side.
+pub(crate) fn ddb_item_size(item: &HashMap<String, AttributeValue>) -> usize {
+    item.iter()
+        .map(|(name, value)| name.len() + ddb_value_size(value))
+        .sum()
+}
+
+fn ddb_value_size(value: &AttributeValue) -> usize {
+    let strings = |v: &Vec<String>| v.iter().map(String::len).sum::<usize>();
+    match value {
+        AttributeValue { s: Some(v), .. } | AttributeValue { n: Some(v), .. } => v.len(),
+        AttributeValue { b: Some(v), .. } => v.len(),
+        AttributeValue { ss: Some(v), .. } | AttributeValue { ns: Some(v), .. } => strings(v),
+        AttributeValue { bs: Some(v), .. } => v.iter().map(|b| b.len()).sum(),
+        AttributeValue { l: Some(v), .. } => 3 + v.iter().map(|v| 1 + ddb_value_size(v)).sum::<usize>(),
+        AttributeValue { m: Some(v), .. } => 3 + v.iter().map(|(k, v)| 1 + k.len() + ddb_value_size(v)).sum::<usize>(),
+        // booleans and nulls
+        _ => 1,
+    }
+}
+
+/// Builds BatchWriteItemInput with a put request per item. The caller must stay within `DDB_BATCH_WRITE_LIMIT`.
+pub(crate) fn build_ddb_batch_put_input(items: Vec<HashMap<String, AttributeValue>>, table: &str) -> BatchWriteItemInput {
+    let write_requests: Vec<WriteRequest> = items
+        .into_iter()
+        .map(|item| WriteRequest {
+            put_request: Some(PutRequest { item }),
+            ..Default::default()
+        })
+        .collect();
+
+    let mut request_items: HashMap<String, Vec<WriteRequest>> = HashMap::new();
+    request_items.insert(String::from(table), write_requests);
+
+    BatchWriteItemInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
+        request_items,
+        ..Default::default()
+    }
+}
+
+/// Builds BatchWriteItemInput with a delete request per key. The caller must stay within `DDB_BATCH_WRITE_LIMIT`.
+pub(crate) fn build_ddb_batch_del_input(keys: Vec<HashMap<String, AttributeValue>>, table: &str) -> BatchWriteItemInput {
+    let write_requests: Vec<WriteRequest> = keys
+        .into_iter()
+        .map(|key| WriteRequest {
+            delete_request: Some(DeleteRequest { key }),
+            ..Default::default()
+        })
+        .collect();
+
+    let mut request_items: HashMap<String, Vec<WriteRequest>> = HashMap::new();
+    request_items.insert(String::from(table), write_requests);
+
+    BatchWriteItemInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
+        request_items,
+        ..Default::default()
+    }
+}
+
 pub(crate) fn build_ddb_del_input(table_key: &str, key_value: Uuid, table: &str) -> DeleteItemInput {
+    build_ddb_del_input_by_str(table_key, &key_value.to_string(), table)
+}
+
+/// Builds DeleteItemInput for a table with a string key that is not a UUID, e.g. a WebSocket connection ID
+pub(crate) fn build_ddb_del_input_by_str(table_key: &str, key_value: &str, table: &str) -> DeleteItemInput {
     let mut key_attr: HashMap<String, AttributeValue> = HashMap::new();
     key_attr.insert(
         String::from(table_key),
@@ -120,12 +306,209 @@ pub(crate) fn build_ddb_del_input(table_key: &str, key_value: Uuid, table: &str)
     );
 
     DeleteItemInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
         key: key_attr,
         table_name: String::from(table),
         ..Default::default()
     }
 }
 
+/// Builds DeleteItemInput for a table with a partition key and a string sort key
+pub(crate) fn build_ddb_del_input_with_sort_key(
+    table_key: &str,
+    key_value: Uuid,
+    sort_key: &str,
+    sort_value: &str,
+    table: &str,
+) -> DeleteItemInput {
+    let mut input = build_ddb_del_input(table_key, key_value, table);
+    input.key.insert(
+        String::from(sort_key),
+        AttributeValue {
+            s: Some(sort_value.to_string()),
+            ..Default::default()
+        },
+    );
+
+    input
+}
+
+/// Collects SET and REMOVE actions for a DDB UpdateItem call. Paths are attribute names separated by dots with
+/// optional list indices, e.g. `description` or `items[2].title`. Names and values always go into placeholders,
+/// so reserved words like `description` are safe to use.
+#[derive(Debug, Default)]
+pub(crate) struct UpdateExpressionBuilder {
+    set: Vec<String>,
+    remove: Vec<String>,
+    conditions: Vec<String>,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl UpdateExpressionBuilder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the attribute at `path` to `value`, creating it if needed.
+    pub(crate) fn set(mut self, path: &str, value: AttributeValue) -> Self {
+        let path = self.path_placeholder(path);
+        let value = self.value_placeholder(value);
+        self.set.push(format!("{} = {}", path, value));
+        self
+    }
+
+    /// Appends the elements of `values`, which must be a list, to the list at `path`, creating the list if needed.
+    pub(crate) fn append(mut self, path: &str, values: AttributeValue) -> Self {
+        let path = self.path_placeholder(path);
+        let empty = self.value_placeholder(AttributeValue {
+            l: Some(Vec::new()),
+            ..Default::default()
+        });
+        let values = self.value_placeholder(values);
+        self.set
+            .push(format!("{} = list_append(if_not_exists({}, {}), {})", path, path, empty, values));
+        self
+    }
+
+    /// Removes the attribute at `path`. Removing a list element shifts the elements after it.
+    pub(crate) fn remove(mut self, path: &str) -> Self {
+        let path = self.path_placeholder(path);
+        self.remove.push(path);
+        self
+    }
+
+    /// The update is only applied if the attribute at `path` exists, e.g. to avoid creating a new document.
+    pub(crate) fn condition_exists(mut self, path: &str) -> Self {
+        let path = self.path_placeholder(path);
+        self.conditions.push(format!("attribute_exists({})", path));
+        self
+    }
+
+    /// The update is only applied if the attribute at `path` equals `value`, e.g. to make sure a list element
+    /// was not moved by a concurrent change.
+    pub(crate) fn condition_eq(mut self, path: &str, value: AttributeValue) -> Self {
+        let path = self.path_placeholder(path);
+        let value = self.value_placeholder(value);
+        self.conditions.push(format!("{} = {}", path, value));
+        self
+    }
+
+    /// The update is only applied if the list at `path` does not contain `value`, e.g. to avoid duplicates.
+    /// A missing list contains nothing.
+    pub(crate) fn condition_not_contains(mut self, path: &str, value: AttributeValue) -> Self {
+        let path = self.path_placeholder(path);
+        let value = self.value_placeholder(value);
+        self.conditions.push(format!("NOT contains({}, {})", path, value));
+        self
+    }
+
+    /// The update is only applied if the list at `path` has fewer than `max` elements. A missing list is empty.
+    pub(crate) fn condition_size_below(mut self, path: &str, max: usize) -> Self {
+        let path = self.path_placeholder(path);
+        let max = self.value_placeholder(AttributeValue {
+            n: Some(max.to_string()),
+            ..Default::default()
+        });
+        self.conditions
+            .push(format!("(attribute_not_exists({}) OR size({}) < {})", path, path, max));
+        self
+    }
+
+    /// True if there is nothing to SET or REMOVE. Conditions alone do not count.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.remove.is_empty()
+    }
+
+    /// Returns the update expression, e.g. `SET #n0 = :v0 REMOVE #n1[2].#n2`.
+    pub(crate) fn update_expression(&self) -> String {
+        let mut clauses: Vec<String> = Vec::new();
+        if !self.set.is_empty() {
+            clauses.push(["SET ", &self.set.join(", ")].concat());
+        }
+        if !self.remove.is_empty() {
+            clauses.push(["REMOVE ", &self.remove.join(", ")].concat());
+        }
+        clauses.join(" ")
+    }
+
+    /// Returns all conditions joined with AND or None if there are none.
+    pub(crate) fn condition_expression(&self) -> Option<String> {
+        if self.conditions.is_empty() {
+            None
+        } else {
+            Some(self.conditions.join(" AND "))
+        }
+    }
+
+    /// Replaces every attribute name in the path with a placeholder, keeping list indices as they are.
+    fn path_placeholder(&mut self, path: &str) -> String {
+        let mut placeholders: Vec<String> = Vec::new();
+        for segment in path.split('.') {
+            let (name, index) = match segment.find('[') {
+                Some(i) => segment.split_at(i),
+                None => (segment, ""),
+            };
+            placeholders.push([self.name_placeholder(name), index.to_string()].concat());
+        }
+        placeholders.join(".")
+    }
+
+    /// The same name gets the same placeholder within one expression.
+    fn name_placeholder(&mut self, name: &str) -> String {
+        if let Some((placeholder, _)) = self.names.iter().find(|(_, v)| v.as_str() == name) {
+            return placeholder.clone();
+        }
+        let placeholder = format!("#n{}", self.names.len());
+        self.names.insert(placeholder.clone(), name.to_string());
+        placeholder
+    }
+
+    fn value_placeholder(&mut self, value: AttributeValue) -> String {
+        let placeholder = format!(":v{}", self.values.len());
+        self.values.insert(placeholder.clone(), value);
+        placeholder
+    }
+}
+
+/// Builds UpdateItemInput from the key and the collected actions. Use `return_values` to get the document back.
+pub(crate) fn build_ddb_update_input(
+    table_key: &str,
+    key_value: Uuid,
+    update: UpdateExpressionBuilder,
+    table: &str,
+) -> UpdateItemInput {
+    build_ddb_update_input_by_str(table_key, &key_value.to_string(), update, table)
+}
+
+/// Builds UpdateItemInput for a table with a string key that is not a UUID, e.g. a WebSocket connection ID
+pub(crate) fn build_ddb_update_input_by_str(
+    table_key: &str,
+    key_value: &str,
+    update: UpdateExpressionBuilder,
+    table: &str,
+) -> UpdateItemInput {
+    let mut key: HashMap<String, AttributeValue> = HashMap::new();
+    key.insert(
+        String::from(table_key),
+        AttributeValue {
+            s: Some(key_value.to_string()),
+            ..Default::default()
+        },
+    );
+
+    UpdateItemInput {
+        return_consumed_capacity: Some(RETURN_CONSUMED_CAPACITY.to_string()),
+        key,
+        table_name: String::from(table),
+        update_expression: Some(update.update_expression()),
+        condition_expression: update.condition_expression(),
+        expression_attribute_names: Some(update.names).filter(|n| !n.is_empty()),
+        expression_attribute_values: Some(update.values).filter(|v| !v.is_empty()),
+        ..Default::default()
+    }
+}
+
 /// Prepare a client for Postgres connection. Panics if cannot connect to the PG DB.
 /// The DB settings come from env vars.
 pub(crate) async fn get_pg_client() -> tokio_postgres::Client {
@@ -147,16 +530,8 @@ pub(crate) async fn get_pg_client() -> tokio_postgres::Client {
     client
 }
 
-/// Initializes `simple_logger` in a safe way to avoid panic on
-/// multiple init calls.
+/// Initializes the JSON logger in a safe way to avoid panic on multiple init calls.
+/// `LOG_LEVEL` env var takes precedence over `level`.
 pub(crate) fn log_init(level: log::Level) {
-    match simple_logger::init_with_level(level) {
-        Err(e) => {
-            debug!("simple_logger::init failed {}", e.to_string());
-        }
-
-        _ => {
-            debug!("simple_logger::init succeeded");
-        }
-    }
+    logging::init(level);
 }
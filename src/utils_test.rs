@@ -0,0 +1,120 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_utils {
+    use crate::utils::*;
+    use rusoto_dynamodb::AttributeValue;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn s(value: &str) -> AttributeValue {
+        AttributeValue {
+            s: Some(value.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_update_expression() {
+        let update = UpdateExpressionBuilder::new()
+            .set("title", s("New title"))
+            .remove("description")
+            .set("items[2].title", s("Item title"))
+            .remove("items[2].description")
+            .condition_exists("lid")
+            .condition_eq("items[2].rel.liid", s("a5d4e1a2"));
+
+        // names are reused, indices are kept and values are in the order they were added
+        assert!(!update.is_empty());
+        assert_eq!(update.update_expression(), "SET #n0 = :v0, #n2[2].#n0 = :v1 REMOVE #n1, #n2[2].#n1");
+        assert_eq!(
+            update.condition_expression().as_deref(),
+            Some("attribute_exists(#n3) AND #n2[2].#n4.#n5 = :v2")
+        );
+
+        let lid = Uuid::new_v4();
+        let input = build_ddb_update_input("lid", lid, update, "tlist");
+        assert_eq!(input.table_name, "tlist");
+        assert_eq!(input.key["lid"].s, Some(lid.to_string()));
+        let names = input.expression_attribute_names.unwrap();
+        assert_eq!(names.len(), 6);
+        assert_eq!(names["#n1"], "description");
+        assert_eq!(names["#n5"], "liid");
+        let values = input.expression_attribute_values.unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[":v1"].s.as_deref(), Some("Item title"));
+    }
+
+    #[test]
+    fn test_update_expression_append() {
+        let list = AttributeValue {
+            l: Some(vec![s("a5d4e1a2")]),
+            ..Default::default()
+        };
+        let update = UpdateExpressionBuilder::new()
+            .append("lids", list)
+            .condition_exists("connection_id")
+            .condition_not_contains("lids", s("a5d4e1a2"))
+            .condition_size_below("lids", 50);
+
+        // a missing list is created and counts as empty
+        assert_eq!(update.update_expression(), "SET #n0 = list_append(if_not_exists(#n0, :v0), :v1)");
+        assert_eq!(
+            update.condition_expression().as_deref(),
+            Some("attribute_exists(#n1) AND NOT contains(#n0, :v2) AND (attribute_not_exists(#n0) OR size(#n0) < :v3)")
+        );
+
+        let input = build_ddb_update_input_by_str("connection_id", "NvXf1dKwIAMCJhQ=", update, "tws_connection");
+        assert_eq!(input.key["connection_id"].s.as_deref(), Some("NvXf1dKwIAMCJhQ="));
+        let values = input.expression_attribute_values.unwrap();
+        assert_eq!(values[":v0"].l.as_ref().map(Vec::len), Some(0));
+        assert_eq!(values[":v3"].n.as_deref(), Some("50"));
+    }
+
+    #[test]
+    fn test_update_expression_remove_only() {
+        let update = UpdateExpressionBuilder::new();
+        assert!(update.is_empty());
+        assert_eq!(update.condition_expression(), None);
+
+        // conditions alone do not make an update
+        let update = UpdateExpressionBuilder::new().condition_exists("lid");
+        assert!(update.is_empty());
+
+        let input = build_ddb_update_input("lid", Uuid::new_v4(), update.remove("tags"), "tlist");
+        assert_eq!(input.update_expression.as_deref(), Some("REMOVE #n1"));
+        assert_eq!(input.condition_expression.as_deref(), Some("attribute_exists(#n0)"));
+        assert!(input.expression_attribute_values.is_none());
+    }
+
+    #[test]
+    fn test_ddb_item_size() {
+        let mut item: HashMap<String, AttributeValue> = HashMap::new();
+        item.insert("lid".to_string(), s("abcd"));
+        assert_eq!(ddb_item_size(&item), 7);
+
+        // lists and maps add 3 bytes and 1 byte per element
+        let tags = AttributeValue {
+            l: Some(vec![s("one"), s("two")]),
+            ..Default::default()
+        };
+        item.insert("tags".to_string(), tags.clone());
+        assert_eq!(ddb_item_size(&item), 7 + 4 + 3 + 4 + 4);
+        let mut rel: HashMap<String, AttributeValue> = HashMap::new();
+        rel.insert("t".to_string(), tags);
+        rel.insert(
+            "ok".to_string(),
+            AttributeValue {
+                bool: Some(true),
+                ..Default::default()
+            },
+        );
+        item.insert(
+            "rel".to_string(),
+            AttributeValue {
+                m: Some(rel),
+                ..Default::default()
+            },
+        );
+        assert_eq!(ddb_item_size(&item), 22 + 3 + 3 + (1 + 1 + 11) + (1 + 2 + 1));
+    }
+}
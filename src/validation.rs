@@ -0,0 +1,331 @@
+use crate::schedule::ItemDue;
+use crate::structures_ddb::{ItemPatch, LdList, LdListItem, ListPatch};
+use crate::structures_pg::{TList, TListItem};
+use crate::tags::{self, MAX_TAG_LENGTH};
+use crate::utils;
+use chrono_tz::Tz;
+use dynomite::Attributes;
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+#[path = "./validation_test.rs"]
+pub(crate) mod tests_validation;
+
+const ERR_MSG_INVALID_INPUT: &str = "Invalid input.";
+const ERR_MSG_REQUIRED: &str = "cannot be empty";
+const ERR_MSG_DUPLICATE_ID: &str = "is used by another item";
+const ERR_MSG_INVALID_TAG_CHARS: &str = "can only contain letters, digits, spaces and - _ & + . #";
+const ERR_MSG_OWN_CHILD: &str = "cannot be the list the item is in";
+const ERR_MSG_NOT_COMPLETED: &str = "requires completed_on_utc";
+const ERR_MSG_NOT_DUE: &str = "requires due_on_utc";
+const ERR_MSG_INVALID_TIMEZONE: &str = "is not a known time zone";
+const ERR_MSG_TOO_LARGE: &str = "The list is too large to save, remove some items or shorten the descriptions";
+
+/// Max length of list and item titles in characters.
+pub(crate) const MAX_TITLE_LENGTH: usize = 250;
+/// Max length of list and item descriptions in characters.
+pub(crate) const MAX_DESCRIPTION_LENGTH: usize = 5000;
+/// Max number of tags per list.
+pub(crate) const MAX_TAGS: usize = 20;
+/// Max number of items per list. The size of the list document is checked separately against the DDB limit.
+pub(crate) const MAX_ITEMS: usize = 1000;
+/// Max number of days, weeks, months or years between occurrences of a recurring item.
+pub(crate) const MAX_RECURRENCE_INTERVAL: u32 = 1000;
+/// Max number of minutes a reminder can be sent before the due time, i.e. a year.
+pub(crate) const MAX_REMIND_BEFORE_MINUTES: i64 = 365 * 24 * 60;
+
+/// Characters allowed in tags in addition to letters and digits.
+const TAG_PUNCTUATION: &str = " -_&+.#";
+
+/// A rule that a field of the input failed.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub(crate) struct FieldError {
+    /// The path to the field, e.g. `items[2].rel.parent_lid`. Empty if the error is about the input as a whole.
+    pub field: String,
+    pub message: String,
+}
+
+/// Checks the fields of a struct before it is saved, so that nothing invalid reaches PG or DDB.
+pub(crate) trait Validate {
+    /// Adds an error for every field that breaks a rule. Field names are prefixed with `path`.
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>);
+
+    /// Returns all errors found or Ok if there are none.
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        self.check("", &mut errors);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Same as `validate`, but with the errors in a single message for functions that return `Result<_, String>`.
+    fn validate_for_save(&self) -> Result<(), String> {
+        self.validate().map_err(|errors| error_message(&errors))
+    }
+}
+
+/// Validates the list as it is about to be saved over `stored`, the version in the DB. Errors that `stored`
+/// already has are not reported, so that lists saved before a rule was added can still be changed, e.g. trashed
+/// or completed, while new input is held to the rules.
+pub(crate) fn validate_changes(list: &LdList, stored: Option<&LdList>) -> Result<(), Vec<FieldError>> {
+    let (errors, stored) = match (list.validate(), stored) {
+        (Ok(()), _) => return Ok(()),
+        (Err(errors), None) => return Err(errors),
+        (Err(errors), Some(stored)) => (errors, stored),
+    };
+
+    // items and tags can change places, so their errors are matched by liid and tag instead of the position
+    let stored_errors: HashSet<(String, String)> = stored
+        .validate()
+        .err()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| (stable_field(stored, &e.field), e.message))
+        .collect();
+    let errors: Vec<FieldError> = errors
+        .into_iter()
+        .filter(|e| !stored_errors.contains(&(stable_field(list, &e.field), e.message.clone())))
+        .collect();
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// Same as `validate_changes`, but with the errors in a single message.
+pub(crate) fn validate_changes_for_save(list: &LdList, stored: Option<&LdList>) -> Result<(), String> {
+    validate_changes(list, stored).map_err(|errors| error_message(&errors))
+}
+
+/// Replaces the position in `items[i]` and `tags[i]` with the liid or the tag, e.g. `items[2].title` becomes
+/// `items[<liid>].title`.
+fn stable_field(list: &LdList, field: &str) -> String {
+    if let Some((i, rest)) = split_index(field, "items[") {
+        if let Some(item) = list.items.as_deref().unwrap_or_default().get(i) {
+            return format!("items[{}{}", item.rel.liid, rest);
+        }
+    }
+    if let Some((i, rest)) = split_index(field, "tags[") {
+        if let Some(tag) = tags::normalize_tags(list.tags.clone()).unwrap_or_default().get(i) {
+            return format!("tags[{}{}", tag, rest);
+        }
+    }
+    field.to_string()
+}
+
+/// Returns the index and the rest of the field after the index, e.g. `(2, "].title")` for `items[2].title`.
+fn split_index<'a>(field: &'a str, prefix: &str) -> Option<(usize, &'a str)> {
+    let rest = field.strip_prefix(prefix)?;
+    let end = rest.find(']')?;
+    let index = rest[..end].parse().ok()?;
+    Some((index, &rest[end..]))
+}
+
+/// Lists all errors in one message, e.g. `Invalid input. title: cannot be empty.`
+pub(crate) fn error_message(errors: &[FieldError]) -> String {
+    let mut message = ERR_MSG_INVALID_INPUT.to_string();
+    for e in errors {
+        match e.field.is_empty() {
+            true => message.push_str(&format!(" {}.", e.message)),
+            false => message.push_str(&format!(" {}: {}.", e.field, e.message)),
+        }
+    }
+    message
+}
+
+/// The rules shared by all structs. Every rule adds an error for `field` under `path` if the value breaks it.
+struct Rules<'a> {
+    path: &'a str,
+    errors: &'a mut Vec<FieldError>,
+}
+
+impl<'a> Rules<'a> {
+    fn new(path: &'a str, errors: &'a mut Vec<FieldError>) -> Self {
+        Rules { path, errors }
+    }
+
+    fn error(&mut self, field: &str, message: &str) {
+        self.errors.push(FieldError {
+            field: [self.path, field].concat(),
+            message: message.to_string(),
+        });
+    }
+
+    /// An error of the struct at `path` as a whole rather than of one of its fields.
+    fn whole_error(&mut self, message: &str) {
+        self.errors.push(FieldError {
+            field: self.path.trim_end_matches('.').to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// The value must have something other than whitespace and be no longer than `max_chars`.
+    fn required_text(&mut self, field: &str, value: &str, max_chars: usize) {
+        if value.trim().is_empty() {
+            self.error(field, ERR_MSG_REQUIRED);
+        } else {
+            self.max_chars(field, value, max_chars);
+        }
+    }
+
+    fn max_chars(&mut self, field: &str, value: &str, max_chars: usize) {
+        if value.chars().count() > max_chars {
+            self.error(field, &format!("cannot be longer than {} characters", max_chars));
+        }
+    }
+
+    fn max_count(&mut self, field: &str, count: usize, max: usize) {
+        if count > max {
+            self.error(field, &format!("cannot have more than {}", max));
+        }
+    }
+
+    fn range(&mut self, field: &str, value: i64, min: i64, max: i64) {
+        if value < min || value > max {
+            self.error(field, &format!("must be between {} and {}", min, max));
+        }
+    }
+
+    fn required<T>(&mut self, field: &str, value: &Option<T>) {
+        if value.is_none() {
+            self.error(field, ERR_MSG_REQUIRED);
+        }
+    }
+
+    /// IDs that are copied between structs must be the same, e.g. `rel.lid` and `lid`.
+    fn same_id(&mut self, field: &str, value: Uuid, expected_field: &str, expected: Uuid) {
+        if value != expected {
+            self.error(field, &format!("must be the same as {}", expected_field));
+        }
+    }
+
+    /// Tags are checked as they are saved, i.e. after `tags::normalize_tags`.
+    fn tags(&mut self, field: &str, tags: &[String]) {
+        self.max_count(field, tags.len(), MAX_TAGS);
+        for (i, tag) in tags.iter().enumerate() {
+            let tag_field = format!("{}[{}]", field, i);
+            self.required_text(&tag_field, tag, MAX_TAG_LENGTH);
+            if !tag.chars().all(|c| c.is_alphanumeric() || TAG_PUNCTUATION.contains(c)) {
+                self.error(&tag_field, ERR_MSG_INVALID_TAG_CHARS);
+            }
+        }
+    }
+
+    fn nested(&mut self, field: &str, value: &dyn Validate) {
+        value.check(&[self.path, field, "."].concat(), self.errors);
+    }
+}
+
+impl Validate for LdList {
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let mut rules = Rules::new(path, errors);
+        rules.required_text("title", &self.title, MAX_TITLE_LENGTH);
+        if let Some(description) = &self.description {
+            rules.max_chars("description", description, MAX_DESCRIPTION_LENGTH);
+        }
+        if let Some(tags) = tags::normalize_tags(self.tags.clone()) {
+            rules.tags("tags", &tags);
+        }
+        rules.nested("rel", &self.rel);
+        rules.same_id("rel.lid", self.rel.lid, "lid", self.lid);
+
+        let items = self.items.as_deref().unwrap_or_default();
+        rules.max_count("items", items.len(), MAX_ITEMS);
+        let mut liids: HashSet<Uuid> = HashSet::new();
+        for (i, item) in items.iter().enumerate() {
+            let item_field = format!("items[{}]", i);
+            rules.nested(&item_field, item);
+            rules.same_id(&[&item_field, ".rel.parent_lid"].concat(), item.rel.parent_lid, "lid", self.lid);
+            if !liids.insert(item.rel.liid) {
+                rules.error(&[&item_field, ".rel.liid"].concat(), ERR_MSG_DUPLICATE_ID);
+            }
+        }
+
+        // DDB rejects larger documents, whatever the number and length of the fields
+        let doc: Attributes = self.clone().into();
+        if utils::ddb_item_size(&doc) > utils::DDB_MAX_ITEM_SIZE {
+            rules.whole_error(ERR_MSG_TOO_LARGE);
+        }
+    }
+}
+
+impl Validate for LdListItem {
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let mut rules = Rules::new(path, errors);
+        rules.required_text("title", &self.title, MAX_TITLE_LENGTH);
+        if let Some(description) = &self.description {
+            rules.max_chars("description", description, MAX_DESCRIPTION_LENGTH);
+        }
+        if let Some(due) = &self.due {
+            rules.nested("due", due);
+        }
+        rules.nested("rel", &self.rel);
+    }
+}
+
+/// The bounds keep the next occurrence and the reminder time within the range of dates.
+impl Validate for ItemDue {
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let mut rules = Rules::new(path, errors);
+        if self.timezone.parse::<Tz>().is_err() {
+            rules.error("timezone", ERR_MSG_INVALID_TIMEZONE);
+        }
+        if let Some(minutes) = self.remind_before_minutes {
+            rules.range("remind_before_minutes", minutes, 0, MAX_REMIND_BEFORE_MINUTES);
+        }
+        if let Some(recurrence) = &self.recurrence {
+            rules.range("recurrence.interval", recurrence.interval as i64, 1, MAX_RECURRENCE_INTERVAL as i64);
+        }
+    }
+}
+
+impl Validate for TList {
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let mut rules = Rules::new(path, errors);
+        rules.required("user_id", &self.user_id);
+    }
+}
+
+impl Validate for TListItem {
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let mut rules = Rules::new(path, errors);
+        if self.child_lid == Some(self.parent_lid) {
+            rules.error("child_lid", ERR_MSG_OWN_CHILD);
+        }
+        if self.completed_by.is_some() && self.completed_on_utc.is_none() {
+            rules.error("completed_by", ERR_MSG_NOT_COMPLETED);
+        }
+        if self.remind_on_utc.is_some() && self.due_on_utc.is_none() {
+            rules.error("remind_on_utc", ERR_MSG_NOT_DUE);
+        }
+    }
+}
+
+/// Only the fields that are changed are checked.
+impl Validate for ListPatch {
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let mut rules = Rules::new(path, errors);
+        if let Some(title) = &self.title {
+            rules.required_text("title", title, MAX_TITLE_LENGTH);
+        }
+        if let Some(Some(description)) = &self.description {
+            rules.max_chars("description", description, MAX_DESCRIPTION_LENGTH);
+        }
+    }
+}
+
+impl Validate for ItemPatch {
+    fn check(&self, path: &str, errors: &mut Vec<FieldError>) {
+        let mut rules = Rules::new(path, errors);
+        if let Some(title) = &self.title {
+            rules.required_text("title", title, MAX_TITLE_LENGTH);
+        }
+        if let Some(Some(description)) = &self.description {
+            rules.max_chars("description", description, MAX_DESCRIPTION_LENGTH);
+        }
+    }
+}
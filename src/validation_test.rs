@@ -0,0 +1,171 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_validation {
+    use crate::schedule::{Frequency, ItemDue, Recurrence};
+    use crate::structures_ddb::{ItemPatch, LdList, LdListItem, ListPatch};
+    use crate::structures_pg::TListItem;
+    use crate::validation::*;
+    use uuid::Uuid;
+
+    fn new_item(title: &str, lid: Uuid) -> LdListItem {
+        LdListItem {
+            title: title.to_string(),
+            description: None,
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), lid),
+        }
+    }
+
+    fn fields(result: Result<(), Vec<FieldError>>) -> Vec<String> {
+        result.err().unwrap_or_default().into_iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn test_validate_list() {
+        let lid = Uuid::new_v4();
+        let mut list = LdList::new(lid, "Groceries".to_string(), Uuid::new_v4());
+        list.description = Some("For the weekend".to_string());
+        list.tags = Some(vec!["food".to_string(), "Costco & co.".to_string()]);
+        list.items = Some(vec![new_item("Milk", lid), new_item("Bread", lid)]);
+        assert!(list.validate().is_ok());
+
+        // every invalid field is reported with its path
+        list.title = " ".to_string();
+        list.description = Some("x".repeat(MAX_DESCRIPTION_LENGTH + 1));
+        list.tags = Some(vec!["ok".to_string(), "no/slashes".to_string(), "".to_string()]);
+        list.rel.lid = Uuid::new_v4();
+        list.rel.user_id = None;
+        let items = list.items.as_mut().unwrap();
+        items[0].title = "y".repeat(MAX_TITLE_LENGTH + 1);
+        items[1].rel.parent_lid = Uuid::new_v4();
+        items.push(new_item("Duplicate", lid));
+        items[2].rel.liid = items[0].rel.liid;
+        assert_eq!(
+            fields(list.validate()),
+            vec![
+                "title",
+                "description",
+                "tags[1]",
+                "rel.user_id",
+                "rel.lid",
+                "items[0].title",
+                "items[1].rel.parent_lid",
+                "items[2].rel.liid",
+            ]
+        );
+
+        // limits on the number of tags and items
+        let mut list = LdList::new(lid, "Big".to_string(), Uuid::new_v4());
+        list.tags = Some((0..=MAX_TAGS).map(|i| format!("tag {}", i)).collect());
+        list.items = Some((0..=MAX_ITEMS).map(|i| new_item(&i.to_string(), lid)).collect());
+        assert_eq!(fields(list.validate()), vec!["tags", "items"]);
+
+        // tags are checked after they are normalised, so the same tag in other case counts once
+        let mut list = LdList::new(lid, "Tags".to_string(), Uuid::new_v4());
+        list.tags = Some((0..MAX_TAGS).map(|i| format!("tag {}", i)).collect());
+        list.tags
+            .as_mut()
+            .unwrap()
+            .extend(vec!["TAG 0".to_string(), "  ".to_string()]);
+        assert!(list.validate().is_ok());
+
+        // the whole document must fit in a DDB item
+        let mut list = LdList::new(lid, "Large".to_string(), Uuid::new_v4());
+        list.items = Some(
+            (0..100)
+                .map(|i| LdListItem {
+                    description: Some("d".repeat(MAX_DESCRIPTION_LENGTH)),
+                    ..new_item(&i.to_string(), lid)
+                })
+                .collect(),
+        );
+        assert_eq!(fields(list.validate()), vec![""]);
+        assert!(list
+            .validate_for_save()
+            .unwrap_err()
+            .starts_with("Invalid input. The list is too large"));
+    }
+
+    #[test]
+    fn test_validate_changes() {
+        let lid = Uuid::new_v4();
+        let mut stored = LdList::new(lid, "Old".to_string(), Uuid::new_v4());
+        stored.tags = Some(vec!["work/home".to_string(), "@home".to_string()]);
+        stored.items = Some(vec![new_item("", lid), new_item("Fine", lid)]);
+        assert_eq!(fields(stored.validate()), vec!["tags[0]", "tags[1]", "items[0].title"]);
+
+        // errors the saved version already has are let through, even if items and tags moved
+        let mut list = stored.clone();
+        list.tags.as_mut().unwrap().reverse();
+        list.items.as_mut().unwrap().reverse();
+        list.items.as_mut().unwrap()[0].rel.completed_on_utc = Some(chrono::Utc::now());
+        assert!(validate_changes(&list, Some(&stored)).is_ok());
+        assert_eq!(fields(validate_changes(&list, None)), vec!["tags[0]", "tags[1]", "items[1].title"]);
+
+        // new input is held to the rules
+        list.tags.as_mut().unwrap().push("a/b".to_string());
+        list.items.as_mut().unwrap()[0].title = String::new();
+        list.items.as_mut().unwrap().push(new_item("", lid));
+        assert_eq!(
+            fields(validate_changes(&list, Some(&stored))),
+            vec!["tags[2]", "items[0].title", "items[2].title"]
+        );
+        assert!(validate_changes_for_save(&list, Some(&stored))
+            .unwrap_err()
+            .starts_with("Invalid input. tags[2]:"));
+    }
+
+    #[test]
+    fn test_validate_items_and_patches() {
+        let lid = Uuid::new_v4();
+        let mut item = new_item("Call the bank", lid);
+        assert!(item.validate().is_ok());
+
+        item.title = String::new();
+        item.rel.child_lid = Some(lid);
+        item.rel.completed_by = Some(Uuid::new_v4());
+        item.rel.remind_on_utc = Some(chrono::Utc::now());
+        assert_eq!(
+            fields(item.validate()),
+            vec!["title", "rel.child_lid", "rel.completed_by", "rel.remind_on_utc"]
+        );
+        let message = item.validate_for_save().unwrap_err();
+        assert!(message.starts_with("Invalid input. title: cannot be empty."));
+
+        // due dates are checked with the item
+        let mut item = new_item("Water the plants", lid);
+        item.due = Some(ItemDue {
+            due_on_utc: chrono::Utc::now(),
+            timezone: "Pacific/Auckland".to_string(),
+            remind_before_minutes: Some(MAX_REMIND_BEFORE_MINUTES),
+            recurrence: Some(Recurrence {
+                frequency: Frequency::Weekly,
+                interval: MAX_RECURRENCE_INTERVAL,
+            }),
+        });
+        assert!(item.validate().is_ok());
+        let due = item.due.as_mut().unwrap();
+        due.timezone = "Mars/Olympus".to_string();
+        due.remind_before_minutes = Some(-1);
+        due.recurrence.as_mut().unwrap().interval = MAX_RECURRENCE_INTERVAL + 1;
+        assert_eq!(
+            fields(item.validate()),
+            vec!["due.timezone", "due.remind_before_minutes", "due.recurrence.interval"]
+        );
+        item.due.as_mut().unwrap().recurrence.as_mut().unwrap().interval = 0;
+        assert!(fields(item.validate()).contains(&"due.recurrence.interval".to_string()));
+
+        // only the changed fields of patches are checked
+        assert!(ListPatch::default().validate().is_ok());
+        let patch = ListPatch {
+            title: Some("".to_string()),
+            description: Some(None),
+        };
+        assert_eq!(fields(patch.validate()), vec!["title"]);
+        let patch = ItemPatch {
+            title: None,
+            description: Some(Some("z".repeat(MAX_DESCRIPTION_LENGTH + 1))),
+        };
+        assert_eq!(fields(patch.validate()), vec!["description"]);
+    }
+}
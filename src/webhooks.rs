@@ -0,0 +1,651 @@
+use crate::context;
+use crate::stream::{ListChange, StreamEventName};
+use crate::structures_ddb::{LdList, LdListItem};
+use crate::structures_pg::{self, PgError, TWebhook, TWebhookDelivery};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use hmac::{Hmac, Mac, NewMac};
+use hyper::client::connect::dns::Name;
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::{Body, Client, Method, Request, Uri};
+use hyper_rustls::HttpsConnector;
+use log::{self, debug, error, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+#[path = "./webhooks_test.rs"]
+pub(crate) mod tests_webhooks;
+
+const ERR_MSG_INVALID_URL: &str = "The webhook URL must be a valid https:// URL.";
+const ERR_MSG_PRIVATE_HOST: &str = "The webhook URL must point at a public host.";
+const ERR_MSG_NOT_ALLOWED: &str = "Webhooks can only be registered for your own lists or your org.";
+const ERR_MSG_WEBHOOK_FAILED: &str = "Failed to save the webhook. Try again.";
+const ERR_MSG_WEBHOOK_DELETE_FAILED: &str = "Failed to delete the webhooks. Try again.";
+const ERR_MSG_WEBHOOK_DOES_NOT_EXIST: &str = "The webhook doesn't exist";
+const ERR_MSG_DELIVERY_LOG_FAILED: &str = "Failed to get the delivery log. Try again.";
+
+/// The header with the HMAC-SHA256 signature of the payload as `t=<unix time>,v1=<hex>`.
+pub(crate) const HEADER_SIGNATURE: &str = "X-Ld-Signature";
+/// The header with the event name, e.g. `item.created`.
+pub(crate) const HEADER_EVENT: &str = "X-Ld-Event";
+/// The header with the event ID. It is the same for all attempts to deliver the event.
+pub(crate) const HEADER_EVENT_ID: &str = "X-Ld-Event-Id";
+/// Signatures older than this are rejected by `verify_signature` to prevent replays.
+pub(crate) const SIGNATURE_TOLERANCE_SECS: i64 = 300;
+/// Default number of records returned from the delivery log
+pub(crate) const DELIVERY_LOG_LIMIT: i64 = 50;
+const MAX_URL_LENGTH: usize = 2048;
+
+pub(crate) type HttpClient = Client<HttpsConnector<HttpConnector<PublicResolver>>>;
+
+/// What the webhook is notified about.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum WebhookScope {
+    /// Changes to a single list
+    List(Uuid),
+    /// Changes to any list of the org
+    Org(Uuid),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum WebhookEvent {
+    #[serde(rename = "list.created")]
+    ListCreated,
+    #[serde(rename = "list.updated")]
+    ListUpdated,
+    #[serde(rename = "list.deleted")]
+    ListDeleted,
+    #[serde(rename = "item.created")]
+    ItemCreated,
+    #[serde(rename = "item.updated")]
+    ItemUpdated,
+    #[serde(rename = "item.deleted")]
+    ItemDeleted,
+}
+
+impl WebhookEvent {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::ListCreated => "list.created",
+            WebhookEvent::ListUpdated => "list.updated",
+            WebhookEvent::ListDeleted => "list.deleted",
+            WebhookEvent::ItemCreated => "item.created",
+            WebhookEvent::ItemUpdated => "item.updated",
+            WebhookEvent::ItemDeleted => "item.deleted",
+        }
+    }
+}
+
+/// The JSON body of a webhook request.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct WebhookPayload {
+    pub event_id: Uuid,
+    pub event: WebhookEvent,
+    pub occurred_on_utc: DateTime<Utc>,
+    pub lid: Uuid,
+    /// Set for item events only.
+    pub liid: Option<Uuid>,
+    /// `LdList` or `LdListItem` after the change, or as it was before it was deleted.
+    pub data: Value,
+}
+
+/// How hard to try delivering a single event to a single webhook.
+#[derive(Debug, Clone)]
+pub(crate) struct DeliveryPolicy {
+    pub max_attempts: i32,
+    /// The delay before the 2nd attempt. It doubles with every attempt after that.
+    pub first_retry_delay: Duration,
+    /// How long to wait for the receiver to respond.
+    pub timeout: Duration,
+    /// How long the deliveries of all events of a change may take together, so that slow receivers cannot hold
+    /// up the stream handler until Lambda times out. Attempts that would not finish in time are not made.
+    pub budget: Duration,
+}
+
+impl Default for DeliveryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            first_retry_delay: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+            budget: Duration::from_secs(20),
+        }
+    }
+}
+
+impl WebhookPayload {
+    /// The event ID is derived from the stream record and the item, so a retried record is delivered with the
+    /// same IDs and receivers can drop the duplicates.
+    fn new(change: &ListChange, event: WebhookEvent, liid: Option<Uuid>, data: Value) -> Self {
+        let liid_name = liid.map(|v| v.to_string()).unwrap_or_default();
+        let name = ["ld:webhook-event:", &change.event_id, ":", &liid_name].concat();
+        Self {
+            event_id: Uuid::new_v5(&Uuid::NAMESPACE_URL, name.as_bytes()),
+            event,
+            occurred_on_utc: Utc::now(),
+            lid: change.lid,
+            liid,
+            data,
+        }
+    }
+}
+
+/// Registers a URL to be notified of changes to the list or to all lists of the org.
+/// The list must be owned by the user and the user must be a member of the org.
+/// The returned record contains the secret for verifying the signatures. It is not shown again.
+pub(crate) async fn register_webhook(
+    user_id: Uuid,
+    scope: WebhookScope,
+    url: &str,
+    pg_client: &tokio_postgres::Client,
+) -> Result<TWebhook, String> {
+    info!("register_webhook for {} / {:?}", user_id, scope);
+    validate_url(url).await?;
+
+    let allowed = match scope {
+        WebhookScope::List(lid) => {
+            structures_pg::get_t_list(lid, pg_client)
+                .await
+                .map_err(|_| ERR_MSG_WEBHOOK_FAILED.to_string())?
+                .and_then(|list| list.user_id)
+                == Some(user_id)
+        }
+        WebhookScope::Org(org_id) => {
+            structures_pg::get_t_user(Some(user_id), None, pg_client)
+                .await
+                .map_err(|_| ERR_MSG_WEBHOOK_FAILED.to_string())?
+                .and_then(|user| user.org_id)
+                == Some(org_id)
+        }
+    };
+    if !allowed {
+        return Err(ERR_MSG_NOT_ALLOWED.to_string());
+    }
+
+    let (lid, org_id) = match scope {
+        WebhookScope::List(lid) => (Some(lid), None),
+        WebhookScope::Org(org_id) => (None, Some(org_id)),
+    };
+    let webhook = TWebhook {
+        webhook_id: Uuid::new_v4(),
+        user_id,
+        lid,
+        org_id,
+        url: url.to_string(),
+        secret: hex::encode(rand::thread_rng().gen::<[u8; 32]>()),
+        created_on_utc: None,
+    };
+
+    match structures_pg::put_t_webhook(&webhook, pg_client).await {
+        Ok(Some(v)) => Ok(v),
+        _ => Err(ERR_MSG_WEBHOOK_FAILED.to_string()),
+    }
+}
+
+/// Deletes a webhook registered by the user.
+pub(crate) async fn delete_webhook(
+    webhook_id: Uuid,
+    user_id: Uuid,
+    pg_client: &tokio_postgres::Client,
+) -> Result<(), String> {
+    info!("delete_webhook {} for {}", webhook_id, user_id);
+    structures_pg::del_t_webhook(webhook_id, user_id, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_WEBHOOK_FAILED.to_string())
+}
+
+/// Returns the latest delivery attempts of a webhook registered by the user, newest first.
+pub(crate) async fn get_delivery_log(
+    webhook_id: Uuid,
+    user_id: Uuid,
+    limit: Option<i64>,
+    pg_client: &tokio_postgres::Client,
+) -> Result<Vec<TWebhookDelivery>, String> {
+    let owned = structures_pg::get_user_webhooks(user_id, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_DELIVERY_LOG_FAILED.to_string())?
+        .iter()
+        .any(|w| w.webhook_id == webhook_id);
+    if !owned {
+        return Err(ERR_MSG_WEBHOOK_DOES_NOT_EXIST.to_string());
+    }
+
+    structures_pg::get_webhook_deliveries(webhook_id, limit.unwrap_or(DELIVERY_LOG_LIMIT), pg_client)
+        .await
+        .map_err(|_| ERR_MSG_DELIVERY_LOG_FAILED.to_string())
+}
+
+/// Deletes all webhooks registered by the user with their secrets and delivery logs.
+/// Called when the user account is deleted. Returns the number of deleted webhooks.
+pub(crate) async fn delete_user_webhooks(user_id: Uuid, pg_client: &tokio_postgres::Client) -> Result<u64, String> {
+    let webhooks = structures_pg::get_user_webhooks(user_id, pg_client)
+        .await
+        .map_err(|_| ERR_MSG_WEBHOOK_DELETE_FAILED.to_string())?;
+    for webhook in webhooks.iter() {
+        structures_pg::del_t_webhook(webhook.webhook_id, user_id, pg_client)
+            .await
+            .map_err(|_| ERR_MSG_WEBHOOK_DELETE_FAILED.to_string())?;
+    }
+
+    Ok(webhooks.len() as u64)
+}
+
+/// Only https URLs of public hosts are accepted, so webhooks cannot be used to reach the internal network.
+pub(crate) async fn validate_url(url: &str) -> Result<(), String> {
+    if url.len() > MAX_URL_LENGTH {
+        return Err(ERR_MSG_INVALID_URL.to_string());
+    }
+    let uri = url.parse::<Uri>().map_err(|_| ERR_MSG_INVALID_URL.to_string())?;
+
+    match (uri.scheme_str(), uri.host()) {
+        (Some("https"), Some(_)) => check_public_host(&uri).await,
+        _ => Err(ERR_MSG_INVALID_URL.to_string()),
+    }
+}
+
+/// Resolves the host of the URL and fails if any of its addresses is not public. Checked again before every
+/// delivery because the DNS records may have changed since the webhook was registered.
+async fn check_public_host(uri: &Uri) -> Result<(), String> {
+    // IPv6 addresses come in brackets
+    let host = uri
+        .host()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(443);
+
+    public_addrs(host, port).await.map(|_| ())
+}
+
+/// Resolves the host and returns its addresses if all of them are public.
+async fn public_addrs(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs = match tokio::net::lookup_host((host, port)).await {
+        Ok(v) => v.collect::<Vec<_>>(),
+        Err(e) => {
+            debug!("Cannot resolve {}: {}", host, e);
+            return Err(ERR_MSG_INVALID_URL.to_string());
+        }
+    };
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public_ip(&addr.ip())) {
+        warn!("Webhook host {} resolves to {:?}", host, addrs);
+        return Err(ERR_MSG_PRIVATE_HOST.to_string());
+    }
+
+    Ok(addrs)
+}
+
+/// The DNS resolver of the webhook HTTP client. It fails if the host resolves to any address that is not public,
+/// so the client only connects to addresses that passed the check. Otherwise the host could resolve to a public
+/// address in `validate_url` and to an internal one when the request is made (DNS rebinding).
+/// IP literals are not resolved by the client. They are checked by `validate_url` before every delivery.
+#[derive(Clone)]
+pub(crate) struct PublicResolver;
+
+impl Service<Name> for PublicResolver {
+    type Response = std::vec::IntoIter<IpAddr>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        Box::pin(async move {
+            // the port is set by the connector
+            let addrs = public_addrs(name.as_str(), 0).await.map_err(std::io::Error::other)?;
+            Ok(addrs.iter().map(|addr| addr.ip()).collect::<Vec<_>>().into_iter())
+        })
+    }
+}
+
+/// False for loopback, private (RFC1918, CGNAT, IPv6 ULA), link-local, multicast and other special addresses,
+/// including benchmarking (198.18/15), IETF protocol assignments (192.0.0/24), reserved (240/4) and
+/// IPv6 prefixes that embed an IPv4 address (NAT64 64:ff9b::/96, 6to4 2002::/16).
+pub(crate) fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let octets = v4.octets();
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || v4.is_documentation()
+                || octets[0] == 0
+                || octets[0] >= 240
+                || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+                || (octets[0] == 198 && octets[1] & 0xfe == 18)
+                || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(v4));
+            }
+            let segments = v6.segments();
+            let first = segments[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || first == 0x2002
+                || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0])
+        }
+    }
+}
+
+/// Sends events for the change to all webhooks registered for the list or its org.
+/// Called by the stream handler, so changes made by any part of the app are covered.
+/// Failed deliveries are retried and logged, but never fail the stream batch. All deliveries stop after
+/// `DeliveryPolicy::budget` or when the Lambda runs out of time, whichever comes first.
+/// Returns an error if the webhooks cannot be looked up, so the record is retried.
+pub(crate) async fn notify(change: &ListChange, pg_client: &tokio_postgres::Client) -> Result<(), PgError> {
+    let events = change_events(change);
+    if events.is_empty() {
+        debug!("No webhook events for {}", change.lid);
+        return Ok(());
+    }
+
+    let org_id = change.new.as_ref().or(change.old.as_ref()).and_then(|l| l.rel.org_id);
+    let webhooks = structures_pg::get_list_webhooks(change.lid, org_id, pg_client).await?;
+    if webhooks.is_empty() {
+        debug!("No webhooks for {}", change.lid);
+        return Ok(());
+    }
+    info!("Webhook events: {}, webhooks: {}", events.len(), webhooks.len());
+
+    // webhooks are notified concurrently, but each gets the events in order
+    let http_client = &http_client();
+    let policy = &DeliveryPolicy::default();
+    let deadline = delivery_deadline(policy);
+    let events = &events;
+    join_all(webhooks.iter().map(|webhook| async move {
+        for payload in events.iter() {
+            deliver(http_client, webhook, payload, policy, deadline, pg_client).await;
+        }
+    }))
+    .await;
+
+    Ok(())
+}
+
+/// When all deliveries must be done: after the budget or at the request deadline, whichever comes first.
+pub(crate) fn delivery_deadline(policy: &DeliveryPolicy) -> Instant {
+    let budget = Instant::now() + policy.budget;
+    match context::deadline() {
+        Some(deadline) if deadline < budget => deadline,
+        _ => budget,
+    }
+}
+
+/// Converts a change of the list into webhook events.
+/// Item events are only sent for lists that existed before and after the change.
+pub(crate) fn change_events(change: &ListChange) -> Vec<WebhookPayload> {
+    match (change.event_name, change.old.as_ref(), change.new.as_ref()) {
+        (StreamEventName::Insert, _, Some(new)) => {
+            vec![WebhookPayload::new(
+                change,
+                WebhookEvent::ListCreated,
+                None,
+                to_json(new),
+            )]
+        }
+        (StreamEventName::Remove, Some(old), _) => {
+            vec![WebhookPayload::new(
+                change,
+                WebhookEvent::ListDeleted,
+                None,
+                to_json(old),
+            )]
+        }
+        (StreamEventName::Modify, Some(old), Some(new)) => {
+            let mut events = Vec::new();
+            if list_fields(old) != list_fields(new) {
+                events.push(WebhookPayload::new(change, WebhookEvent::ListUpdated, None, to_json(new)));
+            }
+
+            let old_items = old
+                .items
+                .iter()
+                .flatten()
+                .map(|item| (item.rel.liid, item))
+                .collect::<HashMap<Uuid, &LdListItem>>();
+            for item in new.items.iter().flatten() {
+                let data = to_json(item);
+                let event = match old_items.get(&item.rel.liid) {
+                    None => WebhookEvent::ItemCreated,
+                    Some(old_item) if to_json(*old_item) != data => WebhookEvent::ItemUpdated,
+                    Some(_) => continue,
+                };
+                events.push(WebhookPayload::new(change, event, Some(item.rel.liid), data));
+            }
+            for item in old.items.iter().flatten() {
+                if !new.items.iter().flatten().any(|i| i.rel.liid == item.rel.liid) {
+                    events.push(WebhookPayload::new(
+                        change,
+                        WebhookEvent::ItemDeleted,
+                        Some(item.rel.liid),
+                        to_json(item),
+                    ));
+                }
+            }
+
+            events
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Sends the event to the webhook and saves all attempts in the delivery log.
+/// Nothing is sent if the host no longer resolves to public addresses.
+async fn deliver(
+    http_client: &HttpClient,
+    webhook: &TWebhook,
+    payload: &WebhookPayload,
+    policy: &DeliveryPolicy,
+    deadline: Instant,
+    pg_client: &tokio_postgres::Client,
+) {
+    let attempts = match validate_url(&webhook.url).await {
+        Ok(_) => send_with_retry(http_client, webhook, payload, policy, deadline).await,
+        Err(e) => vec![TWebhookDelivery {
+            delivery_id: None,
+            webhook_id: webhook.webhook_id,
+            event_id: payload.event_id,
+            event: payload.event.as_str().to_string(),
+            attempt: 1,
+            status_code: None,
+            error: Some(e),
+            duration_ms: 0,
+            created_on_utc: None,
+        }],
+    };
+    for attempt in attempts.iter() {
+        let _ = structures_pg::put_t_webhook_delivery(attempt, pg_client).await;
+    }
+}
+
+/// Posts the payload to the webhook URL until it is accepted, the attempts run out or there is no time left
+/// before `deadline`. Network errors, timeouts, 429 and 5xx responses are retried with exponential backoff.
+/// Other responses are final. Returns the attempts for the delivery log.
+pub(crate) async fn send_with_retry(
+    http_client: &HttpClient,
+    webhook: &TWebhook,
+    payload: &WebhookPayload,
+    policy: &DeliveryPolicy,
+    deadline: Instant,
+) -> Vec<TWebhookDelivery> {
+    let body = match serde_json::to_string(payload) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Cannot serialize webhook payload {}: {}", payload.event_id, e);
+            return Vec::new();
+        }
+    };
+
+    let mut attempts = Vec::new();
+    let mut delay = policy.first_retry_delay;
+    for attempt in 1..=policy.max_attempts {
+        let started = Instant::now();
+        let timeout = policy.timeout.min(deadline.saturating_duration_since(started));
+        if timeout == Duration::from_secs(0) {
+            warn!("No time left to deliver {} to webhook {}", payload.event_id, webhook.webhook_id);
+            break;
+        }
+        let (status_code, error, retry) = match send(http_client, webhook, payload, &body, timeout).await {
+            Ok(status) if (200..300).contains(&status) => (Some(status as i32), None, false),
+            Ok(status) => (
+                Some(status as i32),
+                Some(format!("Responded with {}", status)),
+                status == 429 || status >= 500,
+            ),
+            Err(e) => (None, Some(e), true),
+        };
+
+        if let Some(e) = &error {
+            warn!("Webhook {} attempt {} failed: {}", webhook.webhook_id, attempt, e);
+        }
+        attempts.push(TWebhookDelivery {
+            delivery_id: None,
+            webhook_id: webhook.webhook_id,
+            event_id: payload.event_id,
+            event: payload.event.as_str().to_string(),
+            attempt,
+            status_code,
+            error,
+            duration_ms: started.elapsed().as_millis() as i64,
+            created_on_utc: None,
+        });
+
+        if !retry || attempt == policy.max_attempts || delay >= deadline.saturating_duration_since(Instant::now()) {
+            break;
+        }
+        tokio::time::delay_for(delay).await;
+        delay *= 2;
+    }
+
+    attempts
+}
+
+/// Makes a single signed POST request. Returns the response status.
+async fn send(
+    http_client: &HttpClient,
+    webhook: &TWebhook,
+    payload: &WebhookPayload,
+    body: &str,
+    timeout: Duration,
+) -> Result<u16, String> {
+    let timestamp = Utc::now().timestamp();
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(webhook.url.as_str())
+        .header("Content-Type", "application/json")
+        .header(HEADER_EVENT, payload.event.as_str())
+        .header(HEADER_EVENT_ID, payload.event_id.to_string())
+        .header(HEADER_SIGNATURE, signature_header(&webhook.secret, timestamp, body))
+        .body(Body::from(body.to_string()))
+        .map_err(|e| format!("Invalid request: {}", e))?;
+
+    match tokio::time::timeout(timeout, http_client.request(request)).await {
+        Ok(Ok(response)) => Ok(response.status().as_u16()),
+        Ok(Err(e)) => Err(format!("Request failed: {}", e)),
+        Err(_) => Err(format!("No response in {}ms", timeout.as_millis())),
+    }
+}
+
+/// Returns a client for both http and https URLs. It connects only to public addresses, see `PublicResolver`.
+pub(crate) fn http_client() -> HttpClient {
+    let mut http = HttpConnector::new_with_resolver(PublicResolver);
+    http.enforce_http(false);
+
+    // the same TLS config as in `HttpsConnector::new`
+    let mut tls = rustls::ClientConfig::new();
+    tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    tls.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(store) => store,
+        Err((Some(store), e)) => {
+            warn!("Could not load all certificates: {:?}", e);
+            store
+        }
+        Err((None, e)) => {
+            error!("Cannot load certificates: {:?}", e);
+            rustls::RootCertStore::empty()
+        }
+    };
+
+    Client::builder().build(HttpsConnector::from((http, tls)))
+}
+
+/// Returns the value of the signature header for the body sent at `timestamp`.
+/// The timestamp is signed with the body so that old requests cannot be replayed.
+pub(crate) fn signature_header(secret: &str, timestamp: i64, body: &str) -> String {
+    let mac = signing_mac(secret, timestamp, body);
+    [
+        "t=",
+        &timestamp.to_string(),
+        ",v1=",
+        &hex::encode(mac.finalize().into_bytes()),
+    ]
+    .concat()
+}
+
+/// Checks the signature header of a webhook request the way receivers should.
+pub(crate) fn verify_signature(secret: &str, header: &str, body: &str) -> bool {
+    let mut timestamp = None;
+    let mut signature = None;
+    for part in header.split(',') {
+        let mut pair = part.splitn(2, '=');
+        match (pair.next(), pair.next()) {
+            (Some("t"), Some(v)) => timestamp = v.parse::<i64>().ok(),
+            (Some("v1"), Some(v)) => signature = hex::decode(v).ok(),
+            _ => {}
+        }
+    }
+
+    match (timestamp, signature) {
+        (Some(timestamp), Some(signature)) => {
+            if (Utc::now().timestamp() - timestamp).abs() > SIGNATURE_TOLERANCE_SECS {
+                return false;
+            }
+            signing_mac(secret, timestamp, body).verify(&signature).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// Returns HMAC-SHA256 of `<timestamp>.<body>` keyed with the secret.
+fn signing_mac(secret: &str, timestamp: i64, body: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    mac
+}
+
+/// The list without its items for detecting changes of the list itself.
+fn list_fields(list: &LdList) -> Value {
+    let mut value = to_json(list);
+    if let Some(v) = value.as_object_mut() {
+        v.remove("items");
+    }
+    value
+}
+
+fn to_json<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or_else(|e| {
+        error!("Cannot convert the webhook data into JSON: {}", e);
+        Value::Null
+    })
+}
@@ -0,0 +1,352 @@
+// Use cargo test -- --nocapture to get the full logging output
+#[cfg(test)]
+mod tests_webhooks {
+    use crate::stream::{ListChange, StreamEventName};
+    use crate::structures_ddb::{LdList, LdListItem};
+    use crate::structures_pg::{TListItem, TWebhook};
+    use crate::webhooks::*;
+    use chrono::Utc;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::net::IpAddr;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    /// A request received by the local webhook receiver: the signature and event headers and the body.
+    type Received = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    /// Starts a local HTTP server that responds with `statuses` in turn, then with 200.
+    /// Returns its URL and the requests it received.
+    fn start_receiver(statuses: Vec<u16>) -> (String, Received) {
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let statuses = Arc::new(Mutex::new(statuses.into_iter().collect::<VecDeque<u16>>()));
+
+        let requests = received.clone();
+        let make_service = make_service_fn(move |_| {
+            let requests = requests.clone();
+            let statuses = statuses.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let requests = requests.clone();
+                    let status = statuses.lock().unwrap().pop_front().unwrap_or(200);
+                    async move {
+                        let header = |name: &str| {
+                            request
+                                .headers()
+                                .get(name)
+                                .and_then(|v| v.to_str().ok())
+                                .unwrap_or_default()
+                                .to_string()
+                        };
+                        let signature = header(HEADER_SIGNATURE);
+                        let event = header(HEADER_EVENT);
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        let body = String::from_utf8(body.to_vec()).unwrap();
+                        requests.lock().unwrap().push((signature, event, body));
+
+                        Ok::<_, Infallible>(Response::builder().status(status).body(Body::empty()).unwrap())
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+
+        (url, received)
+    }
+
+    fn test_webhook(url: String) -> TWebhook {
+        TWebhook {
+            webhook_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            lid: Some(Uuid::new_v4()),
+            org_id: None,
+            url,
+            secret: "a-secret".to_string(),
+            created_on_utc: None,
+        }
+    }
+
+    fn test_policy() -> DeliveryPolicy {
+        DeliveryPolicy {
+            max_attempts: 3,
+            first_retry_delay: Duration::from_millis(10),
+            timeout: Duration::from_secs(2),
+            budget: Duration::from_secs(10),
+        }
+    }
+
+    /// The deadline for a single delivery in the tests.
+    fn test_deadline() -> Instant {
+        delivery_deadline(&test_policy())
+    }
+
+    fn test_item(lid: Uuid, title: &str) -> LdListItem {
+        LdListItem {
+            title: title.to_string(),
+            description: None,
+            due: None,
+            rel: TListItem::new(Uuid::new_v4(), lid),
+        }
+    }
+
+    #[test]
+    fn test_webhook_signature() {
+        let body = "{\"event\":\"list.created\"}";
+        let header = signature_header("a-secret", Utc::now().timestamp(), body);
+        assert!(header.starts_with("t="));
+        assert!(verify_signature("a-secret", &header, body));
+
+        // a different secret, a changed body or an old request
+        assert!(!verify_signature("another-secret", &header, body));
+        assert!(!verify_signature("a-secret", &header, "{\"event\":\"list.deleted\"}"));
+        let old = signature_header("a-secret", Utc::now().timestamp() - SIGNATURE_TOLERANCE_SECS - 1, body);
+        assert!(!verify_signature("a-secret", &old, body));
+        assert!(!verify_signature("a-secret", "v1=abc", body));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_url_validation() {
+        assert!(validate_url("https://93.184.215.14/hooks/lists").await.is_ok());
+        assert!(validate_url("https://[2606:2800:21f:cb07:6820:80da:af6b:8b2c]:8443/hook")
+            .await
+            .is_ok());
+        assert!(validate_url("http://93.184.215.14/hook").await.is_err());
+        assert!(validate_url("http://localhost:8080/hook").await.is_err());
+        assert!(validate_url("ftp://example.com/hook").await.is_err());
+        assert!(validate_url("not a url").await.is_err());
+        assert!(validate_url(&["https://example.com/", &"a".repeat(2048)].concat())
+            .await
+            .is_err());
+
+        // hosts on the internal network are rejected for https too
+        for url in [
+            "https://localhost/hook",
+            "https://127.0.0.1/hook",
+            "https://10.1.2.3/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/hook",
+            "https://[fd00::1]/hook",
+        ]
+        .iter()
+        {
+            assert!(validate_url(url).await.is_err(), "{} is rejected", url);
+        }
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        for ip in ["93.184.215.14", "8.8.8.8", "2606:4700::1111"].iter() {
+            assert!(is_public_ip(&ip.parse::<IpAddr>().unwrap()), "{} is public", ip);
+        }
+        for ip in [
+            "127.0.0.53",
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fc00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "198.18.0.1",
+            "198.19.255.254",
+            "192.0.0.8",
+            "240.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:a00:1::1",
+        ]
+        .iter()
+        {
+            assert!(!is_public_ip(&ip.parse::<IpAddr>().unwrap()), "{} is not public", ip);
+        }
+    }
+
+    #[test]
+    fn test_change_events() {
+        let lid = Uuid::new_v4();
+        let mut old = LdList::new(lid, "Groceries".to_string(), Uuid::new_v4());
+        old.items = Some(vec![test_item(lid, "Milk"), test_item(lid, "Bread")]);
+
+        // the new list has Milk changed, Bread removed and Eggs added
+        let mut new = LdList::new(lid, "Groceries".to_string(), old.rel.user_id.unwrap());
+        let mut milk = test_item(lid, "Milk, 2l");
+        milk.rel.liid = old.items.as_ref().unwrap()[0].rel.liid;
+        new.items = Some(vec![milk, test_item(lid, "Eggs")]);
+
+        let modify = ListChange {
+            event_id: "1".to_string(),
+            lid,
+            event_name: StreamEventName::Modify,
+            old: Some(old),
+            new: Some(new),
+        };
+        let events = change_events(&modify);
+        let kinds = events.iter().map(|e| e.event).collect::<Vec<WebhookEvent>>();
+        assert_eq!(
+            kinds,
+            vec![
+                WebhookEvent::ItemUpdated,
+                WebhookEvent::ItemCreated,
+                WebhookEvent::ItemDeleted
+            ]
+        );
+        assert_eq!(events[0].data["title"], "Milk, 2l");
+        assert_eq!(events[2].data["title"], "Bread");
+        assert!(events.iter().all(|e| e.lid == lid && e.liid.is_some()));
+
+        // a retried record gets the same event IDs, which differ per item
+        let retried = change_events(&modify);
+        assert!(events.iter().zip(retried.iter()).all(|(a, b)| a.event_id == b.event_id));
+        assert_ne!(events[0].event_id, events[1].event_id);
+
+        // a change of the list itself
+        let mut renamed = ListChange { ..modify };
+        renamed.new.as_mut().unwrap().title = "Weekly shop".to_string();
+        assert_eq!(change_events(&renamed)[0].event, WebhookEvent::ListUpdated);
+
+        // new and deleted lists have a single event without item events
+        let insert = ListChange {
+            event_name: StreamEventName::Insert,
+            old: None,
+            ..renamed
+        };
+        let events = change_events(&insert);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, WebhookEvent::ListCreated);
+        assert_eq!(events[0].data["items"].as_array().unwrap().len(), 2);
+
+        let remove = ListChange {
+            event_name: StreamEventName::Remove,
+            old: insert.new,
+            new: None,
+            ..insert
+        };
+        let events = change_events(&remove);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, WebhookEvent::ListDeleted);
+        assert_eq!(events[0].data["title"], "Weekly shop");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery_retries() {
+        let (url, received) = start_receiver(vec![500, 503]);
+        let webhook = test_webhook(url);
+        let lid = webhook.lid.unwrap();
+        let payload = change_events(&ListChange {
+            event_id: "1".to_string(),
+            lid,
+            event_name: StreamEventName::Insert,
+            old: None,
+            new: Some(LdList::new(lid, "Packing".to_string(), webhook.user_id)),
+        })
+        .remove(0);
+
+        // 2 failures are retried and the 3rd attempt succeeds
+        let attempts = send_with_retry(&http_client(), &webhook, &payload, &test_policy(), test_deadline()).await;
+        assert_eq!(attempts.len(), 3);
+        assert_eq!(attempts[0].status_code, Some(500));
+        assert!(attempts[0].error.is_some());
+        assert_eq!(attempts[2].attempt, 3);
+        assert_eq!(attempts[2].status_code, Some(200));
+        assert!(attempts[2].error.is_none());
+        assert!(attempts
+            .iter()
+            .all(|a| a.event_id == payload.event_id && a.event == "list.created"));
+
+        // every attempt is signed and carries the same payload
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        for (signature, event, body) in received.iter() {
+            assert_eq!(event, "list.created");
+            assert!(verify_signature(&webhook.secret, signature, body));
+            let sent: WebhookPayload = serde_json::from_str(body).unwrap();
+            assert_eq!(sent.event_id, payload.event_id);
+            assert_eq!(sent.data["title"], "Packing");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery_failures() {
+        let lid = Uuid::new_v4();
+        let payload = change_events(&ListChange {
+            event_id: "1".to_string(),
+            lid,
+            event_name: StreamEventName::Remove,
+            old: Some(LdList::new(lid, "Old list".to_string(), Uuid::new_v4())),
+            new: None,
+        })
+        .remove(0);
+
+        // rejections other than 429 are final
+        let (url, received) = start_receiver(vec![410]);
+        let attempts =
+            send_with_retry(&http_client(), &test_webhook(url), &payload, &test_policy(), test_deadline()).await;
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].status_code, Some(410));
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // too many requests is retried
+        let (url, _) = start_receiver(vec![429]);
+        let attempts =
+            send_with_retry(&http_client(), &test_webhook(url), &payload, &test_policy(), test_deadline()).await;
+        assert_eq!(attempts.len(), 2);
+
+        // a receiver that is down gets all attempts, none of them with a status
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let unreachable = format!("http://{}/hook", closed);
+        let attempts =
+            send_with_retry(&http_client(), &test_webhook(unreachable), &payload, &test_policy(), test_deadline())
+                .await;
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts.iter().all(|a| a.status_code.is_none() && a.error.is_some()));
+
+        // a receiver that never responds is given up on at the deadline and nothing is sent after it
+        let hanging = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", hanging.local_addr().unwrap());
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(200);
+        let attempts =
+            send_with_retry(&http_client(), &test_webhook(url.clone()), &payload, &test_policy(), deadline).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(attempts.len(), 1);
+        assert!(attempts[0].error.is_some());
+        let attempts = send_with_retry(&http_client(), &test_webhook(url), &payload, &test_policy(), deadline).await;
+        assert!(attempts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery_to_private_host() {
+        let lid = Uuid::new_v4();
+        let payload = change_events(&ListChange {
+            event_id: "1".to_string(),
+            lid,
+            event_name: StreamEventName::Remove,
+            old: Some(LdList::new(lid, "Old list".to_string(), Uuid::new_v4())),
+            new: None,
+        })
+        .remove(0);
+
+        // the client does not connect to a host name that resolves to a private address,
+        // even if the URL was not validated
+        let (url, received) = start_receiver(Vec::new());
+        let url = url.replace("127.0.0.1", "localhost");
+        let attempts =
+            send_with_retry(&http_client(), &test_webhook(url), &payload, &test_policy(), test_deadline()).await;
+        assert!(!attempts.is_empty());
+        assert!(attempts.iter().all(|a| a.status_code.is_none() && a.error.is_some()));
+        assert!(received.lock().unwrap().is_empty());
+    }
+}